@@ -1,12 +1,9 @@
 use std::net::{Ipv4Addr, Ipv6Addr};
 use std::str::FromStr;
-use regex::Regex;
-use log::{Level, LevelFilter, log, error, debug, info, trace, warn};
-use std::time::Duration;
-use std::time::Instant;
-use std::sync::{Arc, Mutex};
 use std::fmt;
 
+use log::debug;
+
 #[derive(Debug)]
 pub struct XAddr {
     pub af: u16,           // Address family
@@ -36,6 +33,8 @@ impl fmt::Debug for XAddrUnion {
 pub const AF_INET: u16 = 2;   // IPv4
 pub const AF_INET6: u16 = 10; // IPv6
 
+const INET6_ADDRSTRLEN: usize = 46;
+
 // Helper function to mimic C's addr_pton function
 pub fn addr_pton(addr: &str) -> Result<XAddr, &'static str> {
     if let Ok(v4) = Ipv4Addr::from_str(addr) {
@@ -56,117 +55,127 @@ pub fn addr_pton(addr: &str) -> Result<XAddr, &'static str> {
 
 // Helper function to mimic C's addr_pton_cidr function
 pub fn addr_pton_cidr(cidr: &str) -> Result<(XAddr, u32), &'static str> {
-    let parts: Vec<&str> = cidr.split('/').collect();
-    if parts.len() != 2 {
-        return Err("Invalid CIDR format");
+    pton_cidr(cidr).map_err(|r| if r == -2 { "Inconsistent mask length" } else { "Invalid network" })
+}
+
+// addr_pton_cidr() 的返回值约定：-1 不是网络地址，-2 掩码长度与地址不一致
+fn pton_cidr(cidr: &str) -> Result<(XAddr, u32), i32> {
+    let (addr, mask) = match cidr.split_once('/') {
+        Some((addr, mask)) => (addr, Some(mask)),
+        None => (cidr, None),
+    };
+    let masklen = match mask {
+        Some(m) if m.starts_with(|c: char| c.is_ascii_digit()) => m.parse::<u32>().map_err(|_| -1)?,
+        Some(_) => return Err(-1),
+        None => 0,
+    };
+    if masklen > 128 {
+        return Err(-1);
     }
 
-    let addr = addr_pton(parts[0])?;
-    let masklen = parts[1].parse::<u32>().map_err(|_| "Invalid mask length")?;
+    let addr = addr_pton(addr).map_err(|_| -1)?;
+    let max = if addr.af == AF_INET { 32 } else { 128 };
+    // 未给出掩码长度时视为单个地址
+    let masklen = if mask.is_some() { masklen } else { max };
+    if masklen > max {
+        return Err(-2);
+    }
+    // 主机部分必须全为零
+    let host_bits = match addr.af {
+        AF_INET => u32::from(unsafe { addr.xa.v4 }).checked_shl(masklen).unwrap_or(0) as u128,
+        _ => u128::from(unsafe { addr.xa.v6 }).checked_shl(masklen).unwrap_or(0),
+    };
+    if host_bits != 0 {
+        return Err(-2);
+    }
 
     Ok((addr, masklen))
 }
 
 // Helper function to match addresses
 pub fn addr_netmatch(addr: &XAddr, match_addr: &XAddr, masklen: u32) -> bool {
-    // We assume here that the addresses are of the same family (either both IPv4 or both IPv6)
+    // 地址族不同则不匹配
+    if addr.af != match_addr.af {
+        return false;
+    }
     match addr.af {
         AF_INET => {
-            // 使用 unsafe 块访问 union 的 v4 字段
-            if unsafe { addr.xa.v4 } != Ipv4Addr::new(0, 0, 0, 0) {
-                if unsafe { match_addr.xa.v4 } != Ipv4Addr::new(0, 0, 0, 0) {
-                    // Perform bitwise comparison for IPv4 address matching
-                    let mask = !((1 << (32 - masklen)) - 1);
-                    let addr_u32 = u32::from(unsafe { addr.xa.v4 });
-                    let match_u32 = u32::from(unsafe { match_addr.xa.v4 });
-                    return (addr_u32 & mask) == (match_u32 & mask);
-                }
-            }
+            // 掩码长度为 0 时匹配所有地址，移位不能溢出
+            let mask = u32::MAX.checked_shl(32 - masklen.min(32)).unwrap_or(0);
+            let addr_u32 = u32::from(unsafe { addr.xa.v4 });
+            let match_u32 = u32::from(unsafe { match_addr.xa.v4 });
+            (addr_u32 & mask) == (match_u32 & mask)
         }
         AF_INET6 => {
-            // 使用 unsafe 块访问 union 的 v6 字段
-            if unsafe { addr.xa.v6 } != Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0) {
-                if unsafe { match_addr.xa.v6 } != Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0) {
-                    // Perform bitwise comparison for IPv6 address matching
-                    let mask = !((1 << (128 - masklen)) - 1);
-                    let addr_u128 = u128::from(unsafe { addr.xa.v6 });
-                    let match_u128 = u128::from(unsafe { match_addr.xa.v6 });
-                    return (addr_u128 & mask) == (match_u128 & mask);
-                }
-            }
+            let mask = u128::MAX.checked_shl(128 - masklen.min(128)).unwrap_or(0);
+            let addr_u128 = u128::from(unsafe { addr.xa.v6 });
+            let match_u128 = u128::from(unsafe { match_addr.xa.v6 });
+            (addr_u128 & mask) == (match_u128 & mask)
         }
-        _ => {}
+        _ => false,
     }
-    false
 }
 
-// Add logging functionality
-pub fn addr_match_list(addr: Option<&str>, _list: &str) -> i32 {
-    let mut ret = 0;
-    let mut o = _list.to_string();
-    let mut list = o.split(',');
-
+/// Match `addr` against a comma-separated list of entries that are
+/// either CIDR blocks or wildcard patterns, any of them negated with `!`
+/// (addr_match_list()). Returns 1 on a match, 0 on no match, -1 if a
+/// negated entry matched and -2 on a malformed list; with `addr` of None
+/// only the list syntax is checked.
+pub fn addr_match_list(addr: Option<&str>, list: &str) -> i32 {
     let try_addr = match addr {
         Some(addr_str) => match addr_pton(addr_str) {
-            Ok(addr) => addr,
+            Ok(addr) => Some(addr),
             Err(_) => return 0,
         },
-        None => return 0,
+        None => None,
     };
 
-    for cp in list {
-        let neg = cp.starts_with('!');
-        let cp = if neg { &cp[1..] } else { cp };
-
+    let mut ret = 0;
+    for cp in list.split(',') {
+        let (neg, cp) = match cp.strip_prefix('!') {
+            Some(cp) => (true, cp),
+            None => (false, cp),
+        };
         if cp.is_empty() {
-            ret = -2;
-            break;
+            return -2;
         }
 
-        // Prefer CIDR address matching
-        match addr_pton_cidr(cp) {
-            Ok((match_addr, masklen)) => {
-                if addr_netmatch(&try_addr, &match_addr, masklen) {
-                    if neg {
-                        ret = -1;
-                        break;
-                    } else {
-                        ret = 1;
-                    }
-                }
+        // 优先按 CIDR 匹配，不是网络地址时退回通配符匹配
+        let found = match pton_cidr(cp) {
+            Ok((match_addr, masklen)) => try_addr.as_ref().is_some_and(|a| addr_netmatch(a, &match_addr, masklen)),
+            Err(-2) => {
+                debug!("inconsistent mask length for match network \"{:.100}\"", cp);
+                return -2;
             }
-            Err(_) => {
-                // Fallback to wildcard matching if CIDR fails
-                if addr_match_wildcard(addr.unwrap_or(""), cp) {
-                    if neg {
-                        ret = -1;
-                        break;
-                    } else {
-                        ret = 1;
-                    }
-                }
+            Err(_) => addr.is_some_and(|a| match_pattern(a, cp)),
+        };
+        if found {
+            if neg {
+                return -1;
             }
+            ret = 1;
         }
     }
-
     ret
 }
 
-pub fn addr_match_cidr_list(addr: Option<&str>, _list: &str) -> i32 {
+/// Match `addr` against a comma-separated list of CIDR entries. Returns 1
+/// on a match, 0 on no match and -1 on a malformed list; with `addr` of
+/// None only the list syntax is checked.
+pub fn addr_match_cidr_list(addr: Option<&str>, list: &str) -> i32 {
     let mut ret = 0;
-    let mut o = _list.to_string();
-    let mut list = o.split(',');
 
     let try_addr = match addr {
         Some(addr_str) => match addr_pton(addr_str) {
-            Ok(addr) => addr,
+            Ok(addr) => Some(addr),
             Err(_) => return 0,
         },
-        None => return 0,
+        None => None,
     };
 
-    for cp in list {
-        if cp.is_empty() {
+    for cp in list.split(',') {
+        // 地址加 "/128" 的最大长度，避免把垃圾交给解析函数
+        if cp.is_empty() || cp.len() > INET6_ADDRSTRLEN + 3 {
             return -1;
         }
 
@@ -177,7 +186,7 @@ pub fn addr_match_cidr_list(addr: Option<&str>, _list: &str) -> i32 {
 
         match addr_pton_cidr(cp) {
             Ok((match_addr, masklen)) => {
-                if addr_netmatch(&try_addr, &match_addr, masklen) {
+                if try_addr.as_ref().is_some_and(|a| addr_netmatch(a, &match_addr, masklen)) {
                     ret = 1;
                 }
             }
@@ -188,59 +197,73 @@ pub fn addr_match_cidr_list(addr: Option<&str>, _list: &str) -> i32 {
     ret
 }
 
-fn addr_match_wildcard(addr: &str, pattern: &str) -> bool {
-    // Implement a pattern matching for wildcards (e.g., using regex or basic string matching)
-    let regex_pattern = convert_wildcard_to_regex(pattern);
-    let re = Regex::new(&regex_pattern).unwrap();
-    re.is_match(addr)
-}
+/// Glob match in the style of OpenSSH's match_pattern(): `*` matches any
+/// run of characters and `?` matches exactly one.
+pub fn match_pattern(s: &str, pattern: &str) -> bool {
+    let s: Vec<char> = s.chars().collect();
+    let p: Vec<char> = pattern.chars().collect();
+    let (mut si, mut pi) = (0, 0);
+    // 最近一个 '*' 的位置以及它当时对应的字符串位置，用于回溯
+    let mut star: Option<(usize, usize)> = None;
 
-// Convert simple wildcard patterns to regex
-fn convert_wildcard_to_regex(pattern: &str) -> String {
-    pattern.replace('*', ".*").replace('?', ".")
+    while si < s.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == s[si]) {
+            si += 1;
+            pi += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, si));
+            pi += 1;
+        } else if let Some((sp, ss)) = star {
+            pi = sp + 1;
+            si = ss + 1;
+            star = Some((sp, ss + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
 }
 
-// Log initialization (simple log wrapper for simplicity)
-pub fn log_init(level: Level, threshold: Option<Duration>, facility: Option<String>) {
-    // Set up logging configuration (you can use env_logger or other logging crates)
-    std::env::set_var("RUST_LOG", level.to_string());
-    env_logger::init();
-    
-    // You could add more setup logic here (for example, logging to specific facilities)
-    debug!("Logging initialized with level: {:?}", level);
-}
+/// Match `s` against a comma-separated pattern list where entries may be
+/// negated with `!`. Returns 1 on a positive match, -1 if a negated
+/// pattern matched and 0 otherwise (match_pattern_list()).
+pub fn match_pattern_list(s: &str, list: &str, dolower: bool) -> i32 {
+    let s = if dolower { s.to_lowercase() } else { s.to_string() };
+    let mut got_positive = 0;
 
-// Log rate limiting context
-pub struct LogRateLimitCtx {
-    pub threshold: u32,
-    pub max_accum: u32,
-    pub hysteresis: u32,
-    pub log_every: u32,
-    
-    // 为 Instant 实现 Default，直接使用 `Instant::now()` 作为默认值
-    pub last_event: Instant,
-    pub accumulated_events: u32,
-    pub ratelimit_active: i32,
-    pub ratelimit_start: Instant,
-    pub last_log: Instant,
-    pub hysteresis_start: Instant,
-    pub ratelimited_events: u32,
+    for entry in list.split(',') {
+        let (negated, pattern) = match entry.strip_prefix('!') {
+            Some(p) => (true, p),
+            None => (false, entry),
+        };
+        let pattern = if dolower { pattern.to_lowercase() } else { pattern.to_string() };
+        if match_pattern(&s, &pattern) {
+            if negated {
+                return -1;
+            }
+            got_positive = 1;
+        }
+    }
+    got_positive
 }
 
-impl Default for LogRateLimitCtx {
-    fn default() -> Self {
-        LogRateLimitCtx {
-            threshold: 0,
-            max_accum: 0,
-            hysteresis: 0,
-            log_every: 0,
-            last_event: Instant::now(),
-            accumulated_events: 0,
-            ratelimit_active: 0,
-            ratelimit_start: Instant::now(),
-            last_log: Instant::now(),
-            hysteresis_start: Instant::now(),
-            ratelimited_events: 0,
-        }
+/// Match a client against a from= style list (match_host_and_ip()): the
+/// entries are tried against both its address and its host name. Returns
+/// 1 on a match, 0 on no match or a negated match and -1 on a malformed
+/// list.
+pub fn match_host_and_ip(host: Option<&str>, ipaddr: Option<&str>, patterns: &str) -> i32 {
+    let mip = addr_match_list(ipaddr, patterns);
+    if mip == -2 {
+        return -1;
+    }
+    let (host, _) = match (host, ipaddr) {
+        (Some(host), Some(ip)) if mip != -1 => (host, ip),
+        _ => return 0,
+    };
+    // 主机名匹配不区分大小写
+    match match_pattern_list(host, patterns, true) {
+        -1 => 0,
+        0 if mip == 0 => 0,
+        _ => 1,
     }
 }
@@ -1,26 +1,54 @@
-use std::io::{self, Read, Write};
-use std::vec::Vec;
-use std::ptr::read;
-use std::time::Duration;
-///use std::fmt::write;
-use std::fmt::{self, Write as FmtWrite};
-use std::os::unix::io::RawFd;
-use crate::atomicio::ptr::write;
-use std::os::fd::FromRawFd;
-
-/// Type alias for read/write function types.
-pub type AtomicioRead = fn(RawFd, &mut [u8]) -> io::Result<usize>;
-pub type AtomicioWrite = fn(RawFd, &[u8]) -> io::Result<usize>;
-
-/// Ensure all of data on socket comes through. f == read || vwrite.
-/// It reads/writes until all data is processed or an error occurs.
-use libc::{poll, pollfd, POLLIN, POLLOUT};
-///use std::os::unix::io::{RawFd, AsRawFd};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, IoSlice, IoSliceMut, Read, Write};
+use std::ops::ControlFlow;
+use std::os::unix::io::{AsRawFd, BorrowedFd, RawFd};
 use std::ptr;
+use std::time::{Duration, Instant};
 
-// 包装的安全 read 和 write 函数
-pub fn safe_read(fd: RawFd, buf: &mut [u8]) -> io::Result<usize> {
-    let res = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+use libc::{ppoll, pollfd, POLLIN, POLLOUT};
+
+/// Error from an atomic transfer, carrying the number of bytes that were
+/// moved before it occurred. A short read reports `UnexpectedEof` and a
+/// zero-length write `WriteZero`, like OpenSSH's atomicio() setting EPIPE.
+#[derive(Debug)]
+pub struct AtomicioError {
+    pub transferred: usize,
+    pub error: io::Error,
+}
+
+impl AtomicioError {
+    pub fn kind(&self) -> io::ErrorKind {
+        self.error.kind()
+    }
+
+    /// True if the transfer gave up because its deadline passed.
+    pub fn timed_out(&self) -> bool {
+        self.error.kind() == io::ErrorKind::TimedOut
+    }
+}
+
+impl fmt::Display for AtomicioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} after {} bytes", self.error, self.transferred)
+    }
+}
+
+impl Error for AtomicioError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<AtomicioError> for io::Error {
+    fn from(e: AtomicioError) -> Self {
+        io::Error::new(e.error.kind(), e)
+    }
+}
+
+// 包装的安全 read 和 write 函数，只借用 fd，不会关闭它
+pub fn safe_read(fd: BorrowedFd, buf: &mut [u8]) -> io::Result<usize> {
+    let res = unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
     if res == -1 {
         Err(io::Error::last_os_error())
     } else {
@@ -28,8 +56,8 @@ pub fn safe_read(fd: RawFd, buf: &mut [u8]) -> io::Result<usize> {
     }
 }
 
-pub fn safe_write(fd: RawFd, buf: &[u8]) -> io::Result<usize> {
-    let res = unsafe { libc::write(fd, buf.as_ptr() as *const libc::c_void, buf.len()) };
+pub fn safe_write(fd: BorrowedFd, buf: &[u8]) -> io::Result<usize> {
+    let res = unsafe { libc::write(fd.as_raw_fd(), buf.as_ptr() as *const libc::c_void, buf.len()) };
     if res == -1 {
         Err(io::Error::last_os_error())
     } else {
@@ -37,133 +65,306 @@ pub fn safe_write(fd: RawFd, buf: &[u8]) -> io::Result<usize> {
     }
 }
 
-// 定义一个简单的函数来模拟 poll 的行为
-fn poll_once(fd: RawFd, events: i16) -> io::Result<i32> {
+/// Deadline for a blocking transfer. While waiting, `sigmask` (if set)
+/// replaces the signal mask atomically, as with ppoll(2), so signals can
+/// be kept blocked except while the transfer sleeps.
+#[derive(Clone, Copy)]
+pub struct Deadline {
+    pub at: Instant,
+    pub sigmask: Option<libc::sigset_t>,
+}
+
+impl Deadline {
+    pub fn new(at: Instant) -> Self {
+        Deadline { at, sigmask: None }
+    }
+
+    /// Deadline `timeout` from now, e.g. LoginGraceTime or ConnectTimeout.
+    pub fn after(timeout: Duration) -> Self {
+        Self::new(Instant::now() + timeout)
+    }
+
+    pub fn with_sigmask(mut self, sigmask: libc::sigset_t) -> Self {
+        self.sigmask = Some(sigmask);
+        self
+    }
+}
+
+pub(crate) fn timed_out() -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, "deadline expired")
+}
+
+// 等待 fd 在给定方向上就绪，EINTR 时按剩余时间重试；无截止时间时无限等待
+fn poll_until(fd: RawFd, events: i16, deadline: Option<&Deadline>) -> io::Result<()> {
     let mut pfd = pollfd {
         fd,
         events,
         revents: 0,
     };
+    let sigmask = deadline.and_then(|d| d.sigmask.as_ref()).map_or(ptr::null(), |m| m as *const libc::sigset_t);
 
-    // 调用 poll 系统调用
-    let ret = unsafe { poll(&mut pfd as *mut pollfd, 1, -1) };
-
-    if ret == -1 {
-        Err(io::Error::last_os_error())
-    } else {
-        Ok(ret)
+    loop {
+        let ts = deadline.map(|d| {
+            let left = d.at.saturating_duration_since(Instant::now());
+            libc::timespec {
+                tv_sec: left.as_secs() as libc::time_t,
+                tv_nsec: left.subsec_nanos() as libc::c_long,
+            }
+        });
+        let tsp = ts.as_ref().map_or(ptr::null(), |t| t as *const libc::timespec);
+        let ret = unsafe { ppoll(&mut pfd as *mut pollfd, 1, tsp, sigmask) };
+        if ret > 0 {
+            return Ok(());
+        }
+        if ret == 0 {
+            return Err(timed_out());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
     }
 }
 
-/// Simplified version of `atomicio6` that does not use callbacks.
-pub fn atomicio_no_cb(
-    f: fn(RawFd, &mut [u8]) -> io::Result<usize>,
-    fd: RawFd,
-    buf: &mut [u8],
-    n: usize,
-) -> io::Result<usize> {
-    atomicio6(f, fd, buf, n, None, None)
-}
+/// Progress callback invoked with the byte count of every successful
+/// syscall. Returning `ControlFlow::Break` aborts the transfer.
+pub type AtomicioCallback<'a> = &'a mut dyn FnMut(usize) -> ControlFlow<()>;
 
-/// Ensure all of data on socket comes through. `f` == `read` || `vwrite`.
-/// It reads/writes until all data is processed or an error occurs.
-pub fn atomicio(
-    f: fn(RawFd, &mut [u8]) -> io::Result<usize>,
-    fd: RawFd,
-    buf: &mut [u8],
+/// Core loop shared by all atomic transfers. `op` moves data starting at
+/// the given offset; `wait` is called when it reports `WouldBlock`.
+fn transfer<F, W>(
     n: usize,
-) -> io::Result<usize> {
+    mut op: F,
+    mut wait: W,
+    zero: io::ErrorKind,
+    mut cb: Option<AtomicioCallback>,
+) -> Result<usize, AtomicioError>
+where
+    F: FnMut(usize) -> io::Result<usize>,
+    W: FnMut() -> io::Result<()>,
+{
     let mut pos = 0;
 
     while pos < n {
-        match f(fd, &mut buf[pos..]) {
-            Ok(res) => pos += res,
-            Err(e) => return Err(e),
+        match op(pos) {
+            Ok(0) => {
+                return Err(AtomicioError {
+                    transferred: pos,
+                    error: io::Error::from(zero),
+                })
+            }
+            Ok(res) => {
+                pos += res;
+                if let Some(cb) = cb.as_mut() {
+                    if cb(res).is_break() {
+                        return Err(aborted(pos));
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if let Err(error) = wait() {
+                    return Err(AtomicioError { transferred: pos, error });
+                }
+            }
+            Err(error) => return Err(AtomicioError { transferred: pos, error }),
         }
     }
 
     Ok(pos)
 }
 
-pub fn atomicio6(
-    f: fn(RawFd, &mut [u8]) -> io::Result<usize>,
-    fd: RawFd,
-    buf: &mut [u8],
-    n: usize,
-    cb: Option<fn(&mut u8, usize) -> i32>,
-    mut cb_arg: Option<&mut u8>,  // 这里添加了 `mut` 关键字
-) -> io::Result<usize> {
-    let mut pos = 0;
+/// Read exactly `buf.len()` bytes from `r`, retrying on EINTR. A reader
+/// without an fd cannot be polled, so `WouldBlock` is returned as an error.
+pub fn read_exact_atomic<R: Read + ?Sized>(r: &mut R, buf: &mut [u8]) -> Result<usize, AtomicioError> {
+    let n = buf.len();
+    transfer(n, |pos| r.read(&mut buf[pos..]), || Err(io::ErrorKind::WouldBlock.into()), io::ErrorKind::UnexpectedEof, None)
+}
 
-    while pos < n {
-        // 使用 poll 来等待数据就绪
-        let poll_result = poll_once(fd, POLLIN);
-        match poll_result {
-            Ok(_) => {
-                let res = f(fd, &mut buf[pos..])?;
-                pos += res;
+/// Write all of `buf` to `w`, retrying on EINTR.
+pub fn write_all_atomic<W: Write + ?Sized>(w: &mut W, buf: &[u8]) -> Result<usize, AtomicioError> {
+    transfer(buf.len(), |pos| w.write(&buf[pos..]), || Err(io::ErrorKind::WouldBlock.into()), io::ErrorKind::WriteZero, None)
+}
 
-                // 使用 ref mut 来借用 cb_arg，而不是移动它
-                if let Some(callback) = cb {
-                    if let Some(ref mut arg) = cb_arg {
-                        // 使用可变引用
-                        if callback(arg, res) == -1 {
-                            return Ok(pos); // 如果回调返回-1，退出
-                        }
-                    }
-                }
+/// Write all of `bufs` to `w` with `Write::write_vectored`, a single
+/// writev(2) for sockets, pipes and files, retrying on EINTR and
+/// advancing across buffers after partial writes.
+pub fn writev_all_atomic<W: Write + ?Sized>(w: &mut W, mut bufs: &mut [IoSlice<'_>]) -> Result<usize, AtomicioError> {
+    let mut pos = 0;
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        match w.write_vectored(bufs) {
+            Ok(0) => return Err(AtomicioError { transferred: pos, error: io::ErrorKind::WriteZero.into() }),
+            Ok(res) => {
+                pos += res;
+                IoSlice::advance_slices(&mut bufs, res);
             }
-            Err(e) => return Err(e),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(AtomicioError { transferred: pos, error }),
         }
     }
-
     Ok(pos)
 }
 
+/// Read exactly `buf.len()` bytes from `fd`, waiting for POLLIN when the
+/// descriptor is non-blocking. The fd is only borrowed.
+pub fn read_exact_fd(fd: BorrowedFd, buf: &mut [u8]) -> Result<usize, AtomicioError> {
+    atomicio6_read(fd, buf, None)
+}
 
+/// Write all of `buf` to `fd`, waiting for POLLOUT when the descriptor
+/// is non-blocking. The fd is only borrowed.
+pub fn write_all_fd(fd: BorrowedFd, buf: &[u8]) -> Result<usize, AtomicioError> {
+    atomicio6_write(fd, buf, None)
+}
 
+/// `read_exact_fd` with a progress/limit callback (OpenSSH's atomicio6).
+pub fn atomicio6_read(fd: BorrowedFd, buf: &mut [u8], cb: Option<AtomicioCallback>) -> Result<usize, AtomicioError> {
+    read_fd(fd, buf, None, cb)
+}
 
-/// Ensure all of data on socket comes through. f == readv || writev.
-/// This handles multiple buffers (iovec).
-pub fn atomiciov6(
-    f: fn(RawFd, &mut [u8]) -> io::Result<usize>,
-    fd: RawFd,
-    iov: &mut [Vec<u8>],
-    iovcnt: usize,
-    cb: Option<fn(&mut u8, usize) -> i32>,
-    mut cb_arg: Option<&mut u8>,  // 这里添加了 `mut` 关键字
-) -> Result<usize, io::Error> {
-    let mut pos = 0;
-    
-    // 确保 iovcnt 不大于 iov 的长度
-    let iov_len = iov.len();
-    if iovcnt > iov_len {
-        return Err(io::Error::new(io::ErrorKind::InvalidInput, "iovcnt exceeds the length of iov"));
-    }
+/// `write_all_fd` with a progress/limit callback (OpenSSH's atomicio6).
+pub fn atomicio6_write(fd: BorrowedFd, buf: &[u8], cb: Option<AtomicioCallback>) -> Result<usize, AtomicioError> {
+    write_fd(fd, buf, None, cb)
+}
 
-    while pos < iovcnt {
-        // 确保访问的是有效的 iov[pos]
-        if pos >= iov_len {
-            return Err(io::Error::new(io::ErrorKind::InvalidInput, "pos exceeds the length of iov"));
-        }
+/// `atomicio6_read` that gives up with a `TimedOut` error once `deadline`
+/// passes; the error carries the bytes read so far.
+pub fn read_exact_fd_until(
+    fd: BorrowedFd,
+    buf: &mut [u8],
+    deadline: &Deadline,
+    cb: Option<AtomicioCallback>,
+) -> Result<usize, AtomicioError> {
+    read_fd(fd, buf, Some(deadline), cb)
+}
 
-        let res = f(fd, &mut iov[pos])?;
+/// `atomicio6_write` that gives up with a `TimedOut` error once `deadline`
+/// passes; the error carries the bytes written so far. A blocking fd can
+/// still stall inside write(2), so strict deadlines need O_NONBLOCK.
+pub fn write_all_fd_until(
+    fd: BorrowedFd,
+    buf: &[u8],
+    deadline: &Deadline,
+    cb: Option<AtomicioCallback>,
+) -> Result<usize, AtomicioError> {
+    write_fd(fd, buf, Some(deadline), cb)
+}
 
-        match res {
-            0 => {
-                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "Broken pipe"));
+// 有截止时间时每次 read/write 前先 poll，这样阻塞 fd 也不会越过截止时间
+fn read_fd(
+    fd: BorrowedFd,
+    buf: &mut [u8],
+    deadline: Option<&Deadline>,
+    cb: Option<AtomicioCallback>,
+) -> Result<usize, AtomicioError> {
+    let n = buf.len();
+    let raw = fd.as_raw_fd();
+    transfer(
+        n,
+        |pos| {
+            if deadline.is_some() {
+                poll_until(raw, POLLIN, deadline)?;
             }
-            _ => {
-                pos += res;
+            safe_read(fd, &mut buf[pos..])
+        },
+        || poll_until(raw, POLLIN, deadline),
+        io::ErrorKind::UnexpectedEof,
+        cb,
+    )
+}
+
+fn write_fd(
+    fd: BorrowedFd,
+    buf: &[u8],
+    deadline: Option<&Deadline>,
+    cb: Option<AtomicioCallback>,
+) -> Result<usize, AtomicioError> {
+    let raw = fd.as_raw_fd();
+    transfer(
+        buf.len(),
+        |pos| {
+            if deadline.is_some() {
+                poll_until(raw, POLLOUT, deadline)?;
             }
-        }
+            safe_write(fd, &buf[pos..])
+        },
+        || poll_until(raw, POLLOUT, deadline),
+        io::ErrorKind::WriteZero,
+        cb,
+    )
+}
 
-        if let Some(callback) = cb {
-            // 使用 `as_mut()` 来借用 cb_arg 而不是移动它
-            if let Some(ref mut arg) = cb_arg.as_mut() {
-                if callback(arg, res) == -1 {
-                    return Err(io::Error::new(io::ErrorKind::Interrupted, "Interrupted by callback"));
+// 单次 readv/writev 最多传递的 iovec 数量
+pub(crate) const IOV_MAX: usize = 1024;
+
+pub(crate) fn aborted(transferred: usize) -> AtomicioError {
+    AtomicioError {
+        transferred,
+        error: io::Error::new(io::ErrorKind::Interrupted, "aborted by callback"),
+    }
+}
+
+/// Ensure all of data in `bufs` is written with writev(2), advancing across
+/// iovecs after partial writes. `cb` sees the byte count of each syscall
+/// and may abort the transfer. `bufs` is consumed as data is sent.
+pub fn writev_all_fd(
+    fd: BorrowedFd,
+    bufs: &mut [IoSlice<'_>],
+    cb: Option<AtomicioCallback>,
+) -> Result<usize, AtomicioError> {
+    writev_fd(fd, bufs, None, cb)
+}
+
+/// `writev_all_fd` that gives up with a `TimedOut` error once `deadline`
+/// passes.
+pub fn writev_all_fd_until(
+    fd: BorrowedFd,
+    bufs: &mut [IoSlice<'_>],
+    deadline: &Deadline,
+    cb: Option<AtomicioCallback>,
+) -> Result<usize, AtomicioError> {
+    writev_fd(fd, bufs, Some(deadline), cb)
+}
+
+fn writev_fd(
+    fd: BorrowedFd,
+    mut bufs: &mut [IoSlice<'_>],
+    deadline: Option<&Deadline>,
+    mut cb: Option<AtomicioCallback>,
+) -> Result<usize, AtomicioError> {
+    let mut pos = 0;
+    let wait = |pos| poll_until(fd.as_raw_fd(), POLLOUT, deadline).map_err(|error| AtomicioError { transferred: pos, error });
+
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        if deadline.is_some() {
+            wait(pos)?;
+        }
+        let cnt = bufs.len().min(IOV_MAX);
+        // IoSlice 与 struct iovec 的内存布局兼容
+        let res = unsafe { libc::writev(fd.as_raw_fd(), bufs.as_ptr() as *const libc::iovec, cnt as libc::c_int) };
+        if res < 0 {
+            let error = io::Error::last_os_error();
+            match error.kind() {
+                io::ErrorKind::Interrupted => continue,
+                io::ErrorKind::WouldBlock => {
+                    wait(pos)?;
+                    continue;
                 }
+                _ => return Err(AtomicioError { transferred: pos, error }),
+            }
+        }
+        if res == 0 {
+            return Err(AtomicioError { transferred: pos, error: io::ErrorKind::WriteZero.into() });
+        }
+
+        let res = res as usize;
+        pos += res;
+        IoSlice::advance_slices(&mut bufs, res);
+        if let Some(cb) = cb.as_mut() {
+            if cb(res).is_break() {
+                return Err(aborted(pos));
             }
         }
     }
@@ -171,27 +372,68 @@ pub fn atomiciov6(
     Ok(pos)
 }
 
-/// Simplified version of atomiciov6 that does not use callbacks.
-pub fn atomiciov(
-    f: fn(RawFd, &mut [u8]) -> io::Result<usize>,
-    fd: RawFd,
-    iov: &mut [Vec<u8>],
-    iovcnt: usize,
-) -> Result<usize, io::Error> {
-    atomiciov6(f, fd, iov, iovcnt, None, None)
+/// Fill every buffer in `bufs` with readv(2), advancing across iovecs
+/// after partial reads. EOF before all buffers are full is reported as
+/// `UnexpectedEof` together with the bytes already read.
+pub fn readv_exact_fd(
+    fd: BorrowedFd,
+    bufs: &mut [IoSliceMut<'_>],
+    cb: Option<AtomicioCallback>,
+) -> Result<usize, AtomicioError> {
+    readv_fd(fd, bufs, None, cb)
 }
 
-// 这是一个安全的 `write` 函数，接受可变引用
-pub fn safe_write_mut(fd: RawFd, buf: &mut [u8]) -> io::Result<usize> {
-    use std::os::unix::io::AsRawFd;
-    let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
-    file.write(buf)
+/// `readv_exact_fd` that gives up with a `TimedOut` error once `deadline`
+/// passes.
+pub fn readv_exact_fd_until(
+    fd: BorrowedFd,
+    bufs: &mut [IoSliceMut<'_>],
+    deadline: &Deadline,
+    cb: Option<AtomicioCallback>,
+) -> Result<usize, AtomicioError> {
+    readv_fd(fd, bufs, Some(deadline), cb)
 }
 
+fn readv_fd(
+    fd: BorrowedFd,
+    mut bufs: &mut [IoSliceMut<'_>],
+    deadline: Option<&Deadline>,
+    mut cb: Option<AtomicioCallback>,
+) -> Result<usize, AtomicioError> {
+    let mut pos = 0;
+    let wait = |pos| poll_until(fd.as_raw_fd(), POLLIN, deadline).map_err(|error| AtomicioError { transferred: pos, error });
 
-/// vwrite function equivalent in Rust
-pub fn vwrite(fd: RawFd, buf: &[u8]) -> io::Result<usize> {
-    use std::os::unix::io::AsRawFd;
-    let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
-    file.write(buf)
-}
\ No newline at end of file
+    IoSliceMut::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        if deadline.is_some() {
+            wait(pos)?;
+        }
+        let cnt = bufs.len().min(IOV_MAX);
+        let res = unsafe { libc::readv(fd.as_raw_fd(), bufs.as_ptr() as *const libc::iovec, cnt as libc::c_int) };
+        if res < 0 {
+            let error = io::Error::last_os_error();
+            match error.kind() {
+                io::ErrorKind::Interrupted => continue,
+                io::ErrorKind::WouldBlock => {
+                    wait(pos)?;
+                    continue;
+                }
+                _ => return Err(AtomicioError { transferred: pos, error }),
+            }
+        }
+        if res == 0 {
+            return Err(AtomicioError { transferred: pos, error: io::ErrorKind::UnexpectedEof.into() });
+        }
+
+        let res = res as usize;
+        pos += res;
+        IoSliceMut::advance_slices(&mut bufs, res);
+        if let Some(cb) = cb.as_mut() {
+            if cb(res).is_break() {
+                return Err(aborted(pos));
+            }
+        }
+    }
+
+    Ok(pos)
+}
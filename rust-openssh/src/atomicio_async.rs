@@ -0,0 +1,186 @@
+// atomicio_async.rs
+//
+// tokio counterparts of the atomicio primitives (feature "async"): the same
+// exact-length semantics, progress/limit callbacks and deadlines, on top of
+// AsyncRead/AsyncWrite so a packet codec can be shared with async services.
+
+use std::future::Future;
+use std::io::{self, IoSlice};
+use std::ops::ControlFlow;
+use std::time::Instant;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::atomicio::{aborted, timed_out, AtomicioError, IOV_MAX};
+
+/// Like `AtomicioCallback`, but `Send` so the transfer future can be
+/// spawned onto a multi-threaded runtime.
+pub type AsyncCallback<'a> = &'a mut (dyn FnMut(usize) -> ControlFlow<()> + Send);
+
+// 有截止时间时用 timeout_at 包装单次 I/O；read/write 均可安全取消，不会丢数据
+async fn with_deadline<F>(deadline: Option<Instant>, io: F) -> io::Result<usize>
+where
+    F: Future<Output = io::Result<usize>>,
+{
+    match deadline {
+        None => io.await,
+        Some(at) => tokio::time::timeout_at(at.into(), io).await.unwrap_or_else(|_| Err(timed_out())),
+    }
+}
+
+// 统计一次成功的 I/O 并调用回调；返回 Err 表示回调中止了传输
+fn account(pos: &mut usize, res: usize, cb: &mut Option<AsyncCallback>) -> Result<(), AtomicioError> {
+    *pos += res;
+    if let Some(cb) = cb.as_mut() {
+        if cb(res).is_break() {
+            return Err(aborted(*pos));
+        }
+    }
+    Ok(())
+}
+
+/// Read exactly `buf.len()` bytes from `r`. EOF before the buffer is full
+/// is reported as `UnexpectedEof` together with the bytes already read.
+pub async fn read_exact_async<R>(r: &mut R, buf: &mut [u8], cb: Option<AsyncCallback<'_>>) -> Result<usize, AtomicioError>
+where
+    R: AsyncRead + Unpin + ?Sized,
+{
+    read_async(r, buf, None, cb).await
+}
+
+/// `read_exact_async` that gives up with a `TimedOut` error once
+/// `deadline` passes.
+pub async fn read_exact_async_until<R>(
+    r: &mut R,
+    buf: &mut [u8],
+    deadline: Instant,
+    cb: Option<AsyncCallback<'_>>,
+) -> Result<usize, AtomicioError>
+where
+    R: AsyncRead + Unpin + ?Sized,
+{
+    read_async(r, buf, Some(deadline), cb).await
+}
+
+/// Write all of `buf` to `w`.
+pub async fn write_all_async<W>(w: &mut W, buf: &[u8], cb: Option<AsyncCallback<'_>>) -> Result<usize, AtomicioError>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    write_async(w, buf, None, cb).await
+}
+
+/// `write_all_async` that gives up with a `TimedOut` error once
+/// `deadline` passes.
+pub async fn write_all_async_until<W>(
+    w: &mut W,
+    buf: &[u8],
+    deadline: Instant,
+    cb: Option<AsyncCallback<'_>>,
+) -> Result<usize, AtomicioError>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    write_async(w, buf, Some(deadline), cb).await
+}
+
+/// Write every buffer in `bufs` with vectored writes, advancing across
+/// slices after partial writes. `bufs` is consumed as data is sent.
+pub async fn writev_all_async<W>(
+    w: &mut W,
+    bufs: &mut [IoSlice<'_>],
+    cb: Option<AsyncCallback<'_>>,
+) -> Result<usize, AtomicioError>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    writev_async(w, bufs, None, cb).await
+}
+
+/// `writev_all_async` that gives up with a `TimedOut` error once
+/// `deadline` passes.
+pub async fn writev_all_async_until<W>(
+    w: &mut W,
+    bufs: &mut [IoSlice<'_>],
+    deadline: Instant,
+    cb: Option<AsyncCallback<'_>>,
+) -> Result<usize, AtomicioError>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    writev_async(w, bufs, Some(deadline), cb).await
+}
+
+async fn read_async<R>(
+    r: &mut R,
+    buf: &mut [u8],
+    deadline: Option<Instant>,
+    mut cb: Option<AsyncCallback<'_>>,
+) -> Result<usize, AtomicioError>
+where
+    R: AsyncRead + Unpin + ?Sized,
+{
+    let mut pos = 0;
+
+    while pos < buf.len() {
+        match with_deadline(deadline, r.read(&mut buf[pos..])).await {
+            Ok(0) => return Err(AtomicioError { transferred: pos, error: io::ErrorKind::UnexpectedEof.into() }),
+            Ok(res) => account(&mut pos, res, &mut cb)?,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(AtomicioError { transferred: pos, error }),
+        }
+    }
+
+    Ok(pos)
+}
+
+async fn write_async<W>(
+    w: &mut W,
+    buf: &[u8],
+    deadline: Option<Instant>,
+    mut cb: Option<AsyncCallback<'_>>,
+) -> Result<usize, AtomicioError>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let mut pos = 0;
+
+    while pos < buf.len() {
+        match with_deadline(deadline, w.write(&buf[pos..])).await {
+            Ok(0) => return Err(AtomicioError { transferred: pos, error: io::ErrorKind::WriteZero.into() }),
+            Ok(res) => account(&mut pos, res, &mut cb)?,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(AtomicioError { transferred: pos, error }),
+        }
+    }
+
+    Ok(pos)
+}
+
+async fn writev_async<W>(
+    w: &mut W,
+    mut bufs: &mut [IoSlice<'_>],
+    deadline: Option<Instant>,
+    mut cb: Option<AsyncCallback<'_>>,
+) -> Result<usize, AtomicioError>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let mut pos = 0;
+
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        let cnt = bufs.len().min(IOV_MAX);
+        match with_deadline(deadline, w.write_vectored(&bufs[..cnt])).await {
+            Ok(0) => return Err(AtomicioError { transferred: pos, error: io::ErrorKind::WriteZero.into() }),
+            Ok(res) => {
+                IoSlice::advance_slices(&mut bufs, res);
+                account(&mut pos, res, &mut cb)?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(AtomicioError { transferred: pos, error }),
+        }
+    }
+
+    Ok(pos)
+}
@@ -1,8 +1,15 @@
 // audit.rs
 
 use libc::{geteuid};  // 获取有效用户 ID (UID)
-use std::ffi::CString;
-use std::ptr;
+use log::{debug, error};
+use std::io;
+use std::sync::Mutex;
+
+/// 审计命令的最大长度，与 OpenSSH 的 `audit_run_command` 保持一致
+pub const AUDIT_COMMAND_MAX: usize = 200;
+
+/// Name shown for records whose user is not known, as in audit_username().
+pub const AUDIT_UNKNOWN_USER: &str = "(unknown user)";
 
 #[derive(Debug, PartialEq, Copy, Clone)]  // 添加 Copy 和 Clone
 pub enum SshAuditEvent {
@@ -42,17 +49,109 @@ impl SshAuditEvent {
             SshAuditEvent::AuditUnknown => "AUDIT_UNKNOWN",
         }
     }
+
+    /// Numeric event code, matching the order of `ssh_audit_event_t` in audit.h.
+    pub fn code(&self) -> i32 {
+        *self as i32
+    }
 }
 
 pub struct AuthContext {
     pub user: Option<String>,
     pub valid: bool,
+    pub method: Option<String>,
 }
 
 pub struct LoginInfo {
     pub line: Option<String>,
 }
 
+/// A single audit event with everything a backend may want to record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditRecord {
+    pub event: &'static str,
+    pub code: Option<i32>,
+    pub euid: u32,
+    /// The authenticated user, if the connection has a valid one.
+    pub user: Option<String>,
+    pub remote_addr: Option<String>,
+    pub remote_port: Option<i32>,
+    pub method: Option<String>,
+    pub session_id: Option<String>,
+    pub tty: Option<String>,
+    pub command: Option<String>,
+}
+
+impl AuditRecord {
+    fn new(event: &'static str, code: Option<i32>, authctxt: Option<&AuthContext>) -> Self {
+        let state = AUDIT_STATE.lock().unwrap();
+        AuditRecord {
+            event,
+            code,
+            euid: unsafe { geteuid() },
+            user: audit_user(authctxt),
+            remote_addr: state.remote_addr.clone(),
+            remote_port: state.remote_port,
+            method: authctxt.and_then(|c| c.method.clone()),
+            session_id: state.session_id.clone(),
+            tty: None,
+            command: None,
+        }
+    }
+
+    /// The user for display, or `AUDIT_UNKNOWN_USER` when there is none.
+    pub fn username(&self) -> &str {
+        self.user.as_deref().unwrap_or(AUDIT_UNKNOWN_USER)
+    }
+}
+
+/// Destination for audit records; sshd installs one with `audit_set_backend`.
+pub trait AuditBackend: Send {
+    fn record(&mut self, rec: &AuditRecord) -> io::Result<()>;
+}
+
+// 每个 sshd 子进程只处理一个连接，因此连接信息可以放在进程级状态中
+struct AuditState {
+    backend: Option<Box<dyn AuditBackend>>,
+    remote_addr: Option<String>,
+    remote_port: Option<i32>,
+    session_id: Option<String>,
+}
+
+static AUDIT_STATE: Mutex<AuditState> = Mutex::new(AuditState {
+    backend: None,
+    remote_addr: None,
+    remote_port: None,
+    session_id: None,
+});
+
+/// Install the backend that receives all subsequent audit records,
+/// returning the previous one. `None` restores the default of logging
+/// events at debug level.
+pub fn audit_set_backend(backend: Option<Box<dyn AuditBackend>>) -> Option<Box<dyn AuditBackend>> {
+    std::mem::replace(&mut AUDIT_STATE.lock().unwrap().backend, backend)
+}
+
+/// Set the session identifier attached to subsequent records.
+pub fn audit_set_session_id(session_id: Option<&str>) {
+    AUDIT_STATE.lock().unwrap().session_id = session_id.map(str::to_string);
+}
+
+fn audit_emit(rec: AuditRecord, fallback: String) -> io::Result<()> {
+    let mut state = AUDIT_STATE.lock().unwrap();
+    match state.backend.as_mut() {
+        Some(backend) => backend.record(&rec).map_err(|e| {
+            error!("audit: failed to record {} event: {}", rec.event, e);
+            e
+        }),
+        None => {
+            // 与 OpenSSH audit.c 的默认实现一样只写调试日志
+            debug!("{}", fallback);
+            Ok(())
+        }
+    }
+}
+
 pub fn audit_classify_auth(method: &str) -> SshAuditEvent {
     match method {
         "none" => SshAuditEvent::AuthFailNone,
@@ -65,51 +164,79 @@ pub fn audit_classify_auth(method: &str) -> SshAuditEvent {
     }
 }
 
+fn audit_user(authctxt: Option<&AuthContext>) -> Option<String> {
+    authctxt.filter(|ctxt| ctxt.valid).and_then(|ctxt| ctxt.user.clone())
+}
+
 pub fn audit_username(authctxt: Option<&AuthContext>) -> String {
-    match authctxt {
-        Some(ctxt) if ctxt.valid => ctxt.user.clone().unwrap_or("(unknown user)".to_string()),
-        _ => "(unknown user)".to_string(),
+    audit_user(authctxt).unwrap_or_else(|| AUDIT_UNKNOWN_USER.to_string())
+}
+
+/// Limit a command to `AUDIT_COMMAND_MAX` bytes without splitting a character.
+pub fn audit_truncate_command(command: &str) -> &str {
+    if command.len() <= AUDIT_COMMAND_MAX {
+        return command;
     }
+    let mut end = AUDIT_COMMAND_MAX;
+    while !command.is_char_boundary(end) {
+        end -= 1;
+    }
+    &command[..end]
 }
 
 pub fn audit_connection_from(host: &str, port: i32) {
-    println!("audit connection from {} port {} euid {}", host, port, unsafe { geteuid() });
+    {
+        let mut state = AUDIT_STATE.lock().unwrap();
+        state.remote_addr = Some(host.to_string());
+        state.remote_port = Some(port);
+    }
+    let rec = AuditRecord::new("CONNECTION_FROM", None, None);
+    let fallback = format!("audit connection from {} port {} euid {}", host, port, rec.euid);
+    let _ = audit_emit(rec, fallback);
 }
 
-pub fn audit_event(event: &SshAuditEvent) {
-    // 使用引用方式打印枚举值，而不是尝试移动它
-    println!(
+pub fn audit_event(authctxt: Option<&AuthContext>, event: &SshAuditEvent) {
+    let rec = AuditRecord::new(event.lookup(), Some(event.code()), authctxt);
+    let fallback = format!(
         "audit event euid {} user {} event {} ({})",
-        unsafe { geteuid() },
-        audit_username(None),
-        (*event as i32),  // 这里解引用 event 来获取其值并转换为 i32
+        rec.euid,
+        rec.username(),
+        event.code(),
         event.lookup()
     );
+    let _ = audit_emit(rec, fallback);
 }
 
 
-pub fn audit_session_open(li: &LoginInfo) {
+/// Record the start of a login session. An error means the session must
+/// not be allowed to proceed, as with OpenSSH's fatal() on this path.
+pub fn audit_session_open(authctxt: Option<&AuthContext>, li: &LoginInfo) -> io::Result<()> {
     let tty = li.line.as_deref().unwrap_or("(no tty)");
-    println!("audit session open euid {} user {} tty name {}", unsafe { geteuid() }, audit_username(None), tty);
+    let mut rec = AuditRecord::new("SESSION_OPEN", None, authctxt);
+    rec.tty = li.line.clone();
+    let fallback = format!("audit session open euid {} user {} tty name {}", rec.euid, rec.username(), tty);
+    audit_emit(rec, fallback)
 }
 
-pub fn audit_session_close(li: &LoginInfo) {
+pub fn audit_session_close(authctxt: Option<&AuthContext>, li: &LoginInfo) {
     let tty = li.line.as_deref().unwrap_or("(no tty)");
-    println!("audit session close euid {} user {} tty name {}", unsafe { geteuid() }, audit_username(None), tty);
+    let mut rec = AuditRecord::new("SESSION_CLOSE", None, authctxt);
+    rec.tty = li.line.clone();
+    let fallback = format!("audit session close euid {} user {} tty name {}", rec.euid, rec.username(), tty);
+    let _ = audit_emit(rec, fallback);
 }
 
-pub fn audit_run_command(command: &str) {
+pub fn audit_run_command(authctxt: Option<&AuthContext>, command: &str) {
     // 限制命令字符串的最大长度为 200 个字符
-    let truncated_command = if command.len() > 200 {
-        &command[0..200]
-    } else {
-        command
-    };
+    let truncated_command = audit_truncate_command(command);
 
-    println!(
+    let mut rec = AuditRecord::new("RUN_COMMAND", None, authctxt);
+    rec.command = Some(truncated_command.to_string());
+    let fallback = format!(
         "audit run command euid {} user {} command '{}' ",
-        unsafe { geteuid() },
-        audit_username(None),
+        rec.euid,
+        rec.username(),
         truncated_command
     );
+    let _ = audit_emit(rec, fallback);
 }
@@ -1,137 +1,531 @@
-use std::ffi::CString;
+// audit_bsm.rs
+//
+// BSM (Basic Security Module) audit backend, as used on Solaris, FreeBSD
+// and macOS. Records are built from header, subject_ex, text, return and
+// trailer tokens in the on-disk BSM format and appended to a trail file.
+
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::fs::{File, OpenOptions};
 use std::io::{self, Write};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
-use std::process::Command;
-use std::ptr;
-use std::os::unix::io::RawFd;
-use std::string::String;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::audit::{AuditBackend, AuditRecord};
+
+// 事件编号，见 OpenBSM 的 audit_uevents.h
+pub const AUE_LOGOUT: u16 = 6153;
+pub const AUE_OPENSSH: u16 = 32800;
+
+// 审计类
+pub const AU_CLASS_LO: u32 = 0x0000_1000;
+pub const AU_CLASS_AA: u32 = 0x0000_2000;
+
+// token 类型
+const AUT_TRAILER: u8 = 0x13;
+const AUT_HEADER32: u8 = 0x14;
+const AUT_RETURN32: u8 = 0x27;
+const AUT_TEXT: u8 = 0x28;
+const AUT_SUBJECT32_EX: u8 = 0x7a;
+
+const AUDIT_HEADER_VERSION_OPENBSM: u8 = 11;
+const AUT_TRAILER_MAGIC: u16 = 0xb105;
+const AU_IPV4: u32 = 4;
+const AU_IPV6: u32 = 16;
 
-#[cfg(target_family = "unix")]
-extern crate libc;
+const AU_CLASSES: &[(&str, u32)] = &[
+    ("no", 0x0000_0000),
+    ("fr", 0x0000_0001),
+    ("fw", 0x0000_0002),
+    ("fa", 0x0000_0004),
+    ("fm", 0x0000_0008),
+    ("fc", 0x0000_0010),
+    ("fd", 0x0000_0020),
+    ("cl", 0x0000_0040),
+    ("pc", 0x0000_0080),
+    ("nt", 0x0000_0100),
+    ("ip", 0x0000_0200),
+    ("na", 0x0000_0400),
+    ("ad", 0x0000_0800),
+    ("lo", AU_CLASS_LO),
+    ("aa", AU_CLASS_AA),
+    ("ap", 0x0000_4000),
+    ("ex", 0x4000_0000),
+    ("ot", 0x8000_0000),
+    ("all", 0xffff_ffff),
+];
 
-#[derive(Debug)]
-struct AuditInfo {
-    auid: i32,
-    asid: i32,
-    mask: AuditMask,
-    termid: i32, // ssh_bsm_tid 相当于
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AuditMask {
+    pub am_success: u32,
+    pub am_failure: u32,
 }
 
-#[derive(Debug)]
-struct AuditMask {
-    am_success: u32,
-    am_failure: u32,
+impl AuditMask {
+    /// Parse an audit flags string such as `lo,+aa,-ex` (getauditflagsbin).
+    pub fn from_flags(flags: &str) -> Result<Self, String> {
+        let mut mask = AuditMask::default();
+        for flag in flags.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            let (success, failure, name) = match flag.as_bytes()[0] {
+                b'+' => (true, false, &flag[1..]),
+                b'-' => (false, true, &flag[1..]),
+                _ => (true, true, flag),
+            };
+            let class = AU_CLASSES
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, c)| *c)
+                .ok_or_else(|| format!("unknown audit class \"{}\"", name))?;
+            if success {
+                mask.am_success |= class;
+            }
+            if failure {
+                mask.am_failure |= class;
+            }
+        }
+        Ok(mask)
+    }
 }
 
-#[derive(Debug)]
-pub enum AuditEvent {
-    SuccessLogin,
-    FailedLogin,
-    SessionClosed,
-    // 你可以根据需要添加更多的事件类型
+/// Audit selection and output configuration (audit_control / audit_user).
+#[derive(Debug, Clone)]
+pub struct BsmConfig {
+    pub trail: PathBuf,
+    /// System-wide `flags:` from audit_control.
+    pub flags: AuditMask,
+    /// `naflags:` used for events that cannot be attributed to a user.
+    pub naflags: AuditMask,
+    /// Per-user (always, never) masks from audit_user.
+    pub users: HashMap<String, (AuditMask, AuditMask)>,
 }
 
-#[cfg(target_family = "unix")]
-extern "C" {
-    fn getaddrinfo(
-        node: *const libc::c_char,
-        service: *const libc::c_char,
-        hints: *const libc::addrinfo,
-        res: *mut *mut libc::addrinfo,
-    ) -> libc::c_int;
+impl BsmConfig {
+    pub fn new<P: AsRef<Path>>(trail: P) -> Self {
+        BsmConfig {
+            trail: trail.as_ref().to_path_buf(),
+            flags: AuditMask { am_success: AU_CLASS_LO, am_failure: AU_CLASS_LO },
+            naflags: AuditMask { am_success: AU_CLASS_LO, am_failure: AU_CLASS_LO },
+            users: HashMap::new(),
+        }
+    }
+
+    /// Apply `flags:` and `naflags:` lines from audit_control contents.
+    pub fn parse_audit_control(&mut self, text: &str) -> Result<(), String> {
+        for line in text.lines().map(str::trim).filter(|l| !l.starts_with('#')) {
+            if let Some(v) = line.strip_prefix("flags:") {
+                self.flags = AuditMask::from_flags(v)?;
+            } else if let Some(v) = line.strip_prefix("naflags:") {
+                self.naflags = AuditMask::from_flags(v)?;
+            }
+        }
+        Ok(())
+    }
 
-    fn freeaddrinfo(res: *mut libc::addrinfo);
-}
+    /// Load `user:always:never` lines from audit_user contents.
+    pub fn parse_audit_user(&mut self, text: &str) -> Result<(), String> {
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(':').collect();
+            if fields.len() != 3 {
+                return Err(format!("malformed audit_user line \"{}\"", line));
+            }
+            let always = AuditMask::from_flags(fields[1])?;
+            let never = AuditMask::from_flags(fields[2])?;
+            self.users.insert(fields[0].to_string(), (always, never));
+        }
+        Ok(())
+    }
+
+    /// Equivalent of au_user_mask(): system flags plus the user's
+    /// always-audit classes, minus the never-audit classes.
+    pub fn au_user_mask(&self, user: &str) -> AuditMask {
+        match self.users.get(user) {
+            Some((always, never)) => AuditMask {
+                am_success: (self.flags.am_success | always.am_success) & !never.am_success,
+                am_failure: (self.flags.am_failure | always.am_failure) & !never.am_failure,
+            },
+            None => self.flags,
+        }
+    }
 
-fn debug3(message: &str) {
-    eprintln!("{}", message);
+    /// Whether `event` with the given outcome is preselected for `user`.
+    pub fn selected(&self, user: Option<&str>, event: u16, success: bool) -> bool {
+        // 与 OpenSSH 相同：无法确定用户时使用 naflags
+        let mask = match user {
+            Some(user) => self.au_user_mask(user),
+            None => self.naflags,
+        };
+        let class = event_class(event);
+        if success {
+            mask.am_success & class != 0
+        } else {
+            mask.am_failure & class != 0
+        }
+    }
 }
 
-fn bsm_audit_record(typ: i32, string: &str, event_no: i32) {
-    debug3(&format!("BSM audit: typ {} rc {} \"{}\"", typ, 0, string));
-    // 这里应该是将审计记录发送出去，可以根据实际需要实现 au_write
+fn event_class(event: u16) -> u32 {
+    match event {
+        AUE_OPENSSH | AUE_LOGOUT => AU_CLASS_LO,
+        _ => 0,
+    }
 }
 
-fn bsm_audit_session_setup(user: &str) {
-    let mut mask = AuditMask { am_success: 0, am_failure: 0 };
-    // 设置审计标志等
-    debug3(&format!("BSM audit: session setup for user {}", user));
+/// Terminal ID of the remote peer (au_tid_addr_t).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TerminalId {
+    pub port: u32,
+    pub addr: IpAddr,
 }
 
-fn bsm_audit_bad_login(what: &str, user: &str) {
-    let text = format!("invalid {} for user {}", what, user);
-    bsm_audit_record(4, &text, 32800); // AUE_openssh = 32800
+/// One BSM token.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BsmToken {
+    Header32 { size: u32, version: u8, event: u16, modifier: u16, sec: u32, msec: u32 },
+    Subject32Ex {
+        auid: u32,
+        euid: u32,
+        egid: u32,
+        ruid: u32,
+        rgid: u32,
+        pid: u32,
+        sid: u32,
+        tid: TerminalId,
+    },
+    Text(String),
+    Return32 { status: u8, ret: u32 },
+    Trailer { size: u32 },
 }
 
-fn aug_get_machine(host: &str) -> io::Result<(IpAddr, u32)> {
-    let mut addr_info: *mut libc::addrinfo = std::ptr::null_mut(); // 可变指针
-    let c_host = CString::new(host).unwrap();
+impl BsmToken {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            BsmToken::Header32 { size, version, event, modifier, sec, msec } => {
+                out.push(AUT_HEADER32);
+                out.extend_from_slice(&size.to_be_bytes());
+                out.push(*version);
+                out.extend_from_slice(&event.to_be_bytes());
+                out.extend_from_slice(&modifier.to_be_bytes());
+                out.extend_from_slice(&sec.to_be_bytes());
+                out.extend_from_slice(&msec.to_be_bytes());
+            }
+            BsmToken::Subject32Ex { auid, euid, egid, ruid, rgid, pid, sid, tid } => {
+                out.push(AUT_SUBJECT32_EX);
+                for v in [auid, euid, egid, ruid, rgid, pid, sid, &tid.port] {
+                    out.extend_from_slice(&v.to_be_bytes());
+                }
+                match tid.addr {
+                    IpAddr::V4(a) => {
+                        out.extend_from_slice(&AU_IPV4.to_be_bytes());
+                        out.extend_from_slice(&a.octets());
+                    }
+                    IpAddr::V6(a) => {
+                        out.extend_from_slice(&AU_IPV6.to_be_bytes());
+                        out.extend_from_slice(&a.octets());
+                    }
+                }
+            }
+            BsmToken::Text(text) => {
+                // 长度包含结尾的 NUL
+                out.push(AUT_TEXT);
+                out.extend_from_slice(&((text.len() + 1) as u16).to_be_bytes());
+                out.extend_from_slice(text.as_bytes());
+                out.push(0);
+            }
+            BsmToken::Return32 { status, ret } => {
+                out.push(AUT_RETURN32);
+                out.push(*status);
+                out.extend_from_slice(&ret.to_be_bytes());
+            }
+            BsmToken::Trailer { size } => {
+                out.push(AUT_TRAILER);
+                out.extend_from_slice(&AUT_TRAILER_MAGIC.to_be_bytes());
+                out.extend_from_slice(&size.to_be_bytes());
+            }
+        }
+    }
 
-    unsafe {
-        let res = getaddrinfo(c_host.as_ptr(), std::ptr::null(), std::ptr::null(), &mut addr_info); // 使用 &mut addr_info
-        if res != 0 {
-            return Err(io::Error::new(io::ErrorKind::Other, "getaddrinfo failed"));
+    /// Render the token the way `praudit -l` prints it.
+    pub fn praudit(&self) -> String {
+        match self {
+            BsmToken::Header32 { size, version, event, modifier, sec, msec } => {
+                format!("header,{},{},{},{},{}.{:03}", size, version, event_name(*event), modifier, sec, msec)
+            }
+            BsmToken::Subject32Ex { auid, euid, egid, ruid, rgid, pid, sid, tid } => format!(
+                "subject_ex,{},{},{},{},{},{},{},{},{}",
+                auid, euid, egid, ruid, rgid, pid, sid, tid.port, tid.addr
+            ),
+            BsmToken::Text(text) => format!("text,{}", text),
+            BsmToken::Return32 { status: 0, ret } => format!("return,success,{}", ret),
+            BsmToken::Return32 { status, ret } => format!("return,failure : {},{}", status, ret),
+            BsmToken::Trailer { size } => format!("trailer,{}", size),
         }
+    }
+}
 
-        let ai = *addr_info; // 获取地址信息
-        let ip = match ai.ai_family {
-            libc::AF_INET => {
-                let sockaddr_in = &*(ai.ai_addr as *const libc::sockaddr_in);
-                IpAddr::V4(Ipv4Addr::from(u32::from_be(sockaddr_in.sin_addr.s_addr)))
+fn event_name(event: u16) -> String {
+    match event {
+        AUE_OPENSSH => "OpenSSH login".to_string(),
+        AUE_LOGOUT => "logout".to_string(),
+        _ => event.to_string(),
+    }
+}
+
+/// Assemble a complete record: header, the given body tokens and trailer.
+pub fn bsm_build_record(event: u16, when: SystemTime, body: &[BsmToken]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    for token in body {
+        token.encode(&mut payload);
+    }
+
+    // header 18 字节，trailer 7 字节
+    let size = (18 + payload.len() + 7) as u32;
+    let d = when.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut record = Vec::with_capacity(size as usize);
+    BsmToken::Header32 {
+        size,
+        version: AUDIT_HEADER_VERSION_OPENBSM,
+        event,
+        modifier: 0,
+        sec: d.as_secs() as u32,
+        msec: d.subsec_millis(),
+    }
+    .encode(&mut record);
+    record.extend_from_slice(&payload);
+    BsmToken::Trailer { size }.encode(&mut record);
+    record
+}
+
+/// Decode the tokens of one record, checking header and trailer sizes.
+pub fn bsm_parse_record(buf: &[u8]) -> io::Result<Vec<BsmToken>> {
+    fn bad(msg: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+    }
+    fn take<'a>(buf: &'a [u8], pos: &mut usize, n: usize) -> io::Result<&'a [u8]> {
+        let s = buf.get(*pos..*pos + n).ok_or_else(|| bad("truncated BSM token"))?;
+        *pos += n;
+        Ok(s)
+    }
+    fn u16_at(buf: &[u8], pos: &mut usize) -> io::Result<u16> {
+        Ok(u16::from_be_bytes(take(buf, pos, 2)?.try_into().unwrap()))
+    }
+    fn u32_at(buf: &[u8], pos: &mut usize) -> io::Result<u32> {
+        Ok(u32::from_be_bytes(take(buf, pos, 4)?.try_into().unwrap()))
+    }
+
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < buf.len() {
+        let id = take(buf, &mut pos, 1)?[0];
+        let token = match id {
+            AUT_HEADER32 => BsmToken::Header32 {
+                size: u32_at(buf, &mut pos)?,
+                version: take(buf, &mut pos, 1)?[0],
+                event: u16_at(buf, &mut pos)?,
+                modifier: u16_at(buf, &mut pos)?,
+                sec: u32_at(buf, &mut pos)?,
+                msec: u32_at(buf, &mut pos)?,
+            },
+            AUT_SUBJECT32_EX => {
+                let mut ids = [0u32; 8];
+                for v in ids.iter_mut() {
+                    *v = u32_at(buf, &mut pos)?;
+                }
+                let addr = match u32_at(buf, &mut pos)? {
+                    AU_IPV4 => {
+                        let b: [u8; 4] = take(buf, &mut pos, 4)?.try_into().unwrap();
+                        IpAddr::V4(Ipv4Addr::from(b))
+                    }
+                    AU_IPV6 => {
+                        let b: [u8; 16] = take(buf, &mut pos, 16)?.try_into().unwrap();
+                        IpAddr::V6(Ipv6Addr::from(b))
+                    }
+                    _ => return Err(bad("bad terminal address type")),
+                };
+                BsmToken::Subject32Ex {
+                    auid: ids[0],
+                    euid: ids[1],
+                    egid: ids[2],
+                    ruid: ids[3],
+                    rgid: ids[4],
+                    pid: ids[5],
+                    sid: ids[6],
+                    tid: TerminalId { port: ids[7], addr },
+                }
             }
-            libc::AF_INET6 => {
-                let sockaddr_in6 = &*(ai.ai_addr as *const libc::sockaddr_in6);
-                IpAddr::V6(Ipv6Addr::from(sockaddr_in6.sin6_addr.s6_addr))
+            AUT_TEXT => {
+                let len = u16_at(buf, &mut pos)? as usize;
+                let raw = take(buf, &mut pos, len)?;
+                let text = raw.strip_suffix(&[0]).ok_or_else(|| bad("unterminated text token"))?;
+                BsmToken::Text(String::from_utf8_lossy(text).into_owned())
             }
-            _ => return Err(io::Error::new(io::ErrorKind::Other, "Unknown address family")),
+            AUT_RETURN32 => BsmToken::Return32 {
+                status: take(buf, &mut pos, 1)?[0],
+                ret: u32_at(buf, &mut pos)?,
+            },
+            AUT_TRAILER => {
+                if u16_at(buf, &mut pos)? != AUT_TRAILER_MAGIC {
+                    return Err(bad("bad trailer magic"));
+                }
+                BsmToken::Trailer { size: u32_at(buf, &mut pos)? }
+            }
+            _ => return Err(bad("unknown BSM token")),
         };
+        tokens.push(token);
+    }
 
-        freeaddrinfo(addr_info); // 释放分配的内存
-        Ok((ip, ai.ai_family as u32))
+    match (tokens.first(), tokens.last()) {
+        (Some(BsmToken::Header32 { size: h, .. }), Some(BsmToken::Trailer { size: t }))
+            if *h == *t && *h as usize == buf.len() =>
+        {
+            Ok(tokens)
+        }
+        _ => Err(bad("record size mismatch")),
     }
 }
 
+/// Resolve `host` to the address recorded in the terminal ID. The
+/// addrinfo list is released on every path and the family is read
+/// before it is freed.
+pub fn aug_get_machine(host: &str) -> io::Result<IpAddr> {
+    let c_host = CString::new(host).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut res: *mut libc::addrinfo = std::ptr::null_mut();
 
-fn selected(username: Option<&str>, uid: i32, event: AuditEvent, sf: i32) -> i32 {
-    let mask = if let Some(username) = username {
-        // 获取用户的审计标志
-        AuditMask { am_success: 1, am_failure: 0 }
-    } else {
-        // 默认的标志
-        AuditMask { am_success: 0, am_failure: 0 }
+    let rc = unsafe { libc::getaddrinfo(c_host.as_ptr(), std::ptr::null(), std::ptr::null(), &mut res) };
+    if rc != 0 {
+        let msg = unsafe { CStr::from_ptr(libc::gai_strerror(rc)) };
+        return Err(io::Error::other(format!("getaddrinfo {}: {}", host, msg.to_string_lossy())));
+    }
+
+    let ip = unsafe {
+        let ai = &*res;
+        match ai.ai_family {
+            libc::AF_INET => {
+                let sin = &*(ai.ai_addr as *const libc::sockaddr_in);
+                Ok(IpAddr::V4(Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr))))
+            }
+            libc::AF_INET6 => {
+                let sin6 = &*(ai.ai_addr as *const libc::sockaddr_in6);
+                Ok(IpAddr::V6(Ipv6Addr::from(sin6.sin6_addr.s6_addr)))
+            }
+            family => Err(io::Error::other(format!("unsupported address family {}", family))),
+        }
     };
+    unsafe { libc::freeaddrinfo(res) };
+    ip
+}
 
-    let sorf = if sf == 0 { 1 } else { 0 };
-    // 检查事件是否已选择进行审计
-    if sorf == 1 { 1 } else { 0 }
+fn lookup_user(name: &str) -> Option<(u32, u32)> {
+    let c_name = CString::new(name).ok()?;
+    // sshd 的审计在单线程中执行，getpwnam 足够
+    let pw = unsafe { libc::getpwnam(c_name.as_ptr()) };
+    if pw.is_null() {
+        None
+    } else {
+        unsafe { Some(((*pw).pw_uid, (*pw).pw_gid)) }
+    }
 }
 
-// 将函数设置为 `pub`，这样它可以在 `main.rs` 中访问
-pub fn audit_connection_from(host: &str, port: i32) {
-    debug3(&format!("BSM audit: connection from {} port {}", host, port));
+/// BSM audit backend writing records to a trail file.
+pub struct BsmAuditBackend {
+    config: BsmConfig,
+    trail: File,
+    tid: Option<TerminalId>,
+    authenticated: bool,
+}
 
-    match aug_get_machine(host) {
-        Ok((ip, _)) => debug3(&format!("BSM audit: machine ID {}", ip)),
-        Err(_) => debug3("BSM audit: failed to get machine info"),
+impl BsmAuditBackend {
+    pub fn open(config: BsmConfig) -> io::Result<Self> {
+        use std::os::unix::fs::OpenOptionsExt;
+        let trail = OpenOptions::new().create(true).append(true).mode(0o600).open(&config.trail)?;
+        Ok(BsmAuditBackend { config, trail, tid: None, authenticated: false })
     }
-}
 
-pub fn audit_event(event: AuditEvent) {
-    let user = "(unknown user)"; // 示例，实际可以从上下文中获取
-    let text = match event {
-        AuditEvent::SuccessLogin => format!("successful login {}", user),
-        AuditEvent::FailedLogin => format!("failed login attempt {}", user),
-        AuditEvent::SessionClosed => format!("session closed for {}", user),
-    };
+    fn terminal_id(&mut self, rec: &AuditRecord) -> TerminalId {
+        if let Some(tid) = self.tid {
+            return tid;
+        }
+        let addr = rec
+            .remote_addr
+            .as_deref()
+            .and_then(|host| aug_get_machine(host).ok())
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        let tid = TerminalId { port: rec.remote_port.unwrap_or(0) as u32, addr };
+        if rec.remote_addr.is_some() {
+            self.tid = Some(tid);
+        }
+        tid
+    }
 
-    bsm_audit_record(0, &text, 32800);
-}
+    /// Equivalent of bsm_audit_record(): `typ` 0 is success, anything
+    /// else is a failure status carried in the return token.
+    fn bsm_audit_record(&mut self, rec: &AuditRecord, typ: u8, text: Option<&str>, event: u16) -> io::Result<()> {
+        let user = rec.user.as_deref();
+        let success = typ == 0;
+        if !self.config.selected(user, event, success) {
+            return Ok(());
+        }
+
+        let (uid, gid) = user.and_then(lookup_user).unwrap_or((u32::MAX, u32::MAX));
+        let pid = std::process::id();
+        let mut body = vec![BsmToken::Subject32Ex {
+            auid: uid,
+            euid: uid,
+            egid: gid,
+            ruid: uid,
+            rgid: gid,
+            pid,
+            sid: pid,
+            tid: self.terminal_id(rec),
+        }];
+        if let Some(text) = text {
+            body.push(BsmToken::Text(text.to_string()));
+        }
+        let ret = if success { 0 } else { u32::MAX };
+        body.push(BsmToken::Return32 { status: typ, ret });
 
-// 导出函数，供 `main.rs` 使用
-pub fn audit_connection_from_host(host: &str, port: i32) {
-    audit_connection_from(host, port);
+        let record = bsm_build_record(event, SystemTime::now(), &body);
+        self.trail.write_all(&record)?;
+        self.trail.flush()
+    }
+
+    fn bad_login(&mut self, rec: &AuditRecord, what: &str) -> io::Result<()> {
+        let text = format!("invalid {} for user {}", what, rec.username());
+        self.bsm_audit_record(rec, 4, Some(&text), AUE_OPENSSH)
+    }
 }
 
-pub fn audit_login_event(event: AuditEvent) {
-    audit_event(event);
+impl AuditBackend for BsmAuditBackend {
+    fn record(&mut self, rec: &AuditRecord) -> io::Result<()> {
+        match rec.event {
+            "CONNECTION_FROM" => {
+                self.tid = None;
+                self.terminal_id(rec);
+                Ok(())
+            }
+            "LOGIN_EXCEED_MAXTRIES" => {
+                let text = format!("too many tries for user {}", rec.username());
+                self.bsm_audit_record(rec, 1, Some(&text), AUE_OPENSSH)
+            }
+            "LOGIN_ROOT_DENIED" => self.bsm_audit_record(rec, 2, Some("not_console"), AUE_OPENSSH),
+            "AUTH_SUCCESS" => {
+                self.authenticated = true;
+                self.bsm_audit_record(rec, 0, None, AUE_OPENSSH)
+            }
+            "CONNECTION_CLOSE" if self.authenticated => {
+                // 只有认证成功过的连接才记录 logout
+                self.bsm_audit_record(rec, 0, Some(""), AUE_LOGOUT)
+            }
+            "AUTH_FAIL_PASSWD" => self.bad_login(rec, "password"),
+            "AUTH_FAIL_KBDINT" => self.bad_login(rec, "interactive password entry"),
+            "INVALID_USER" => self.bad_login(rec, "user"),
+            "AUTH_FAIL_NONE" | "AUTH_FAIL_PUBKEY" | "AUTH_FAIL_HOSTBASED" | "AUTH_FAIL_GSSAPI" => {
+                self.bad_login(rec, "authentication")
+            }
+            _ => Ok(()),
+        }
+    }
 }
@@ -0,0 +1,162 @@
+// audit_json.rs
+//
+// JSON-lines audit backend: every audit record becomes one JSON object on
+// its own line, so a SIEM can tail the file and ingest sshd events directly.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+use crate::audit::{AuditBackend, AuditRecord};
+
+/// Options controlling how the JSON audit file is written.
+#[derive(Debug, Clone)]
+pub struct JsonAuditOptions {
+    /// fsync the file after every record.
+    pub fsync: bool,
+    /// Rotate once the file grows beyond this many bytes.
+    pub max_size: Option<u64>,
+    /// Number of rotated files (`path.1` .. `path.N`) to keep.
+    pub keep: usize,
+}
+
+impl Default for JsonAuditOptions {
+    fn default() -> Self {
+        JsonAuditOptions {
+            fsync: false,
+            max_size: None,
+            keep: 5,
+        }
+    }
+}
+
+/// Appends records to the file at `path`. sshd forks a process per
+/// connection and each inherits the sink, so the file's size and identity
+/// are always taken from disk: when another process has rotated it, the
+/// sink reopens `path` instead of writing to the renamed file.
+pub struct JsonAuditSink {
+    path: PathBuf,
+    opts: JsonAuditOptions,
+    file: File,
+}
+
+impl JsonAuditSink {
+    pub fn open<P: AsRef<Path>>(path: P, opts: JsonAuditOptions) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = open_append(&path)?;
+        Ok(JsonAuditSink { path, opts, file })
+    }
+
+    // 打开的文件已不是 path 所指的文件（被其他进程轮转或删除）时重新打开，返回当前大小
+    fn reopen_if_moved(&mut self) -> io::Result<u64> {
+        let open = self.file.metadata()?;
+        match fs::metadata(&self.path) {
+            Ok(st) if st.dev() == open.dev() && st.ino() == open.ino() => return Ok(open.len()),
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        self.file = open_append(&self.path)?;
+        Ok(self.file.metadata()?.len())
+    }
+
+    // 将 path.N-1 依次移动到 path.N，最后把当前文件改名为 path.1
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.opts.keep == 0 {
+            self.file.set_len(0)?;
+            return Ok(());
+        }
+        for i in (1..self.opts.keep).rev() {
+            let from = rotated_name(&self.path, i);
+            if from.exists() {
+                fs::rename(&from, rotated_name(&self.path, i + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated_name(&self.path, 1))?;
+        self.file = open_append(&self.path)?;
+        Ok(())
+    }
+}
+
+impl AuditBackend for JsonAuditSink {
+    fn record(&mut self, rec: &AuditRecord) -> io::Result<()> {
+        let mut line = serde_json::to_vec(&audit_record_json(rec, SystemTime::now()))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        line.push(b'\n');
+
+        let size = self.reopen_if_moved()?;
+        if let Some(max) = self.opts.max_size {
+            if size > 0 && size + line.len() as u64 > max {
+                self.rotate()?;
+            }
+        }
+
+        // 整行一次写入，避免多个进程追加时记录交错
+        self.file.write_all(&line)?;
+        if self.opts.fsync {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    OpenOptions::new().create(true).append(true).mode(0o600).open(path)
+}
+
+fn rotated_name(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// Build the JSON object written for `rec`.
+pub fn audit_record_json(rec: &AuditRecord, now: SystemTime) -> Value {
+    json!({
+        "timestamp": format_timestamp(now),
+        "event": rec.event,
+        "code": rec.code,
+        "euid": rec.euid,
+        "user": rec.username(),
+        "remote_addr": rec.remote_addr,
+        "remote_port": rec.remote_port,
+        "method": rec.method,
+        "session_id": rec.session_id,
+        "tty": rec.tty,
+        "command": rec.command,
+    })
+}
+
+/// Format `t` as an RFC 3339 UTC timestamp with millisecond precision.
+pub fn format_timestamp(t: SystemTime) -> String {
+    let d = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = d.as_secs();
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // Howard Hinnant 的 civil_from_days 算法
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem / 60) % 60,
+        rem % 60,
+        d.subsec_millis()
+    )
+}
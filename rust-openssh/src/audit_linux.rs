@@ -1,153 +1,287 @@
-extern crate libc;
-use std::ffi::{CString, CStr};
-use std::io::{self, Write};
-use std::ptr;
-use libc::{geteuid, EINVAL, EPROTONOSUPPORT, EAFNOSUPPORT, EPERM};
-
-const AUDIT_USER_LOGIN: i32 = 1100; // 假设的审计事件类型
-
-// 将 C 中的审计函数封装为 Rust 函数
-extern "C" {
-    fn audit_open() -> i32;
-    fn audit_log_acct_message(
-        fd: i32,
-        type_: i32,
-        addr: *const libc::c_char,
-        msg: *const libc::c_char,
-        user: *const libc::c_char,
-        uid: i32,
-        hostname: *const libc::c_char,
-        ip: *const libc::c_char,
-        tty: *const libc::c_char,
-        success: i32,
-    ) -> i32;
-    fn close(fd: i32);
+// audit_linux.rs
+//
+// Linux audit backend talking to the kernel over NETLINK_AUDIT directly,
+// producing the same records as libaudit's audit_log_acct_message() without
+// linking against libaudit.
+
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+
+use libc::{geteuid, EAFNOSUPPORT, ECONNREFUSED, EINTR, EINVAL, EPERM, EPROTONOSUPPORT};
+
+use crate::audit::{AuditBackend, AuditRecord};
+
+// 来自 <linux/audit.h> 的消息类型
+pub const AUDIT_USER_AUTH: u16 = 1100;
+pub const AUDIT_USER_ACCT: u16 = 1101;
+pub const AUDIT_CRED_ACQ: u16 = 1103;
+pub const AUDIT_USER_START: u16 = 1105;
+pub const AUDIT_USER_END: u16 = 1106;
+pub const AUDIT_USER_LOGIN: u16 = 1112;
+
+const NETLINK_AUDIT: libc::c_int = 9;
+const NLMSG_HDRLEN: usize = 16;
+const NLMSG_ERROR: u16 = 2;
+const NLM_F_REQUEST: u16 = 0x01;
+const NLM_F_ACK: u16 = 0x04;
+const MAX_AUDIT_MESSAGE_LENGTH: usize = 8970;
+// libaudit waits this long for the kernel's acknowledgement
+const ACK_TIMEOUT_MS: libc::c_int = 500;
+
+/// A NETLINK_AUDIT connection to the kernel.
+pub struct LinuxAudit {
+    fd: OwnedFd,
+    seq: u32,
+    exe: String,
 }
 
-fn linux_audit_record_event(uid: i32, username: Option<&str>, hostname: Option<&str>, ip: Option<&str>, ttyn: Option<&str>, success: i32) -> Result<bool, io::Error> {
-    unsafe {
-        let audit_fd = audit_open();
-        if audit_fd < 0 {
-            // 捕获 `errno` 错误
-            let err = io::Error::last_os_error(); // 获取 `errno`
-            if err.raw_os_error() == Some(EINVAL) || err.raw_os_error() == Some(EPROTONOSUPPORT) || err.raw_os_error() == Some(EAFNOSUPPORT) {
-                return Ok(true); // No audit support in kernel
-            } else {
-                return Ok(false); // Prevent login
-            }
+impl LinuxAudit {
+    /// Open the audit socket. `Ok(None)` means the kernel has no audit
+    /// support, which OpenSSH treats as success rather than a reason to
+    /// refuse logins.
+    pub fn open() -> io::Result<Option<Self>> {
+        let fd = unsafe {
+            libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, NETLINK_AUDIT)
+        };
+        if fd < 0 {
+            let err = io::Error::last_os_error();
+            return match err.raw_os_error() {
+                Some(EINVAL) | Some(EPROTONOSUPPORT) | Some(EAFNOSUPPORT) => Ok(None),
+                _ => Err(err),
+            };
         }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
 
-        let username_cstr = CString::new(username.unwrap_or("(unknown)")).unwrap();
-        let hostname_cstr = CString::new(hostname.unwrap_or("")).unwrap();
-        let ip_cstr = CString::new(ip.unwrap_or("")).unwrap();
-        let ttyn_cstr = CString::new(ttyn.unwrap_or("")).unwrap();
-
-        let rc = audit_log_acct_message(
-            audit_fd,
-            AUDIT_USER_LOGIN,
-            ptr::null(),
-            CString::new("login").unwrap().as_ptr(),
-            username_cstr.as_ptr(),
-            if username.is_none() { uid } else { -1 },
-            hostname_cstr.as_ptr(),
-            ip_cstr.as_ptr(),
-            ttyn_cstr.as_ptr(),
-            success,
-        );
-
-        let saved_errno = io::Error::last_os_error(); // 保存当前的错误码
-        close(audit_fd);
-
-        if rc == -libc::EPERM && geteuid() != 0 {
-            return Ok(false); // Don't report error if it's due to non-root user
+        // 连接到内核 (nl_pid 0)，之后即可用 send/recv 收发
+        let mut sa: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        sa.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        let rc = unsafe {
+            libc::connect(
+                fd.as_raw_fd(),
+                &sa as *const libc::sockaddr_nl as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
         }
+        Ok(Some(Self::from_fd(fd)))
+    }
 
-        // 恢复错误码
-        if let Some(err) = saved_errno.raw_os_error() {
-            std::process::abort(); // 如果有错误，处理错误
-        }
+    /// Wrap an already connected socket; tests pass one end of a socketpair.
+    pub fn from_fd(fd: OwnedFd) -> Self {
+        let exe = std::fs::read_link("/proc/self/exe")
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| "?".to_string());
+        LinuxAudit { fd, seq: 0, exe }
+    }
 
-        return Ok(rc >= 0);
+    /// Send one user-space account message, waiting for the kernel's ACK.
+    /// Mirrors audit_log_acct_message(): `user` is recorded as `acct=`,
+    /// otherwise `uid` is recorded as `id=`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn log_acct_message(
+        &mut self,
+        msg_type: u16,
+        op: &str,
+        user: Option<&str>,
+        uid: u32,
+        hostname: Option<&str>,
+        addr: Option<&str>,
+        tty: Option<&str>,
+        success: bool,
+    ) -> io::Result<()> {
+        let msg = format_acct_message(op, user, uid, &self.exe, hostname, addr, tty, success);
+        self.send(msg_type, msg.as_bytes())
     }
-}
 
-pub fn audit_connection_from(host: &str, port: i32) {
-    // Not implemented in the C version
-}
+    fn send(&mut self, msg_type: u16, payload: &[u8]) -> io::Result<()> {
+        if payload.len() >= MAX_AUDIT_MESSAGE_LENGTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "audit message too long"));
+        }
+        self.seq = self.seq.wrapping_add(1);
 
-pub fn audit_run_command(command: &str) {
-    // Not implemented in the C version
-}
+        // 负载包含结尾的 NUL，并按 4 字节对齐
+        let len = NLMSG_HDRLEN + payload.len() + 1;
+        let mut buf = vec![0u8; (len + 3) & !3];
+        buf[0..4].copy_from_slice(&(len as u32).to_ne_bytes());
+        buf[4..6].copy_from_slice(&msg_type.to_ne_bytes());
+        buf[6..8].copy_from_slice(&(NLM_F_REQUEST | NLM_F_ACK).to_ne_bytes());
+        buf[8..12].copy_from_slice(&self.seq.to_ne_bytes());
+        buf[NLMSG_HDRLEN..NLMSG_HDRLEN + payload.len()].copy_from_slice(payload);
 
-pub fn audit_session_open(li: &LoginInfo) {
-    if let Err(e) = linux_audit_record_event(li.uid, None, Some(&li.hostname), None, Some(&li.line), 1) {
-        eprintln!("linux_audit_write_entry failed: {}", e);
+        loop {
+            let rc = unsafe {
+                libc::send(self.fd.as_raw_fd(), buf.as_ptr() as *const libc::c_void, buf.len(), 0)
+            };
+            if rc >= 0 {
+                break;
+            }
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(EINTR) {
+                return Err(err);
+            }
+        }
+        self.check_ack()
     }
-}
 
-pub fn audit_session_close(li: &LoginInfo) {
-    // Not implemented in the C version
-}
+    // 等待 NLMSG_ERROR 应答；错误码为 0 表示成功，超时按成功处理（与 libaudit 一致）
+    fn check_ack(&mut self) -> io::Result<()> {
+        let mut pfd = libc::pollfd { fd: self.fd.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        loop {
+            let rc = unsafe { libc::poll(&mut pfd, 1, ACK_TIMEOUT_MS) };
+            if rc == 0 {
+                return Ok(());
+            }
+            if rc < 0 {
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() == Some(EINTR) {
+                    continue;
+                }
+                return Err(err);
+            }
 
-pub fn audit_event(ssh: &Ssh, event: SshAuditEvent) {
-    match event {
-        SshAuditEvent::SSH_AUTH_SUCCESS |
-        SshAuditEvent::SSH_CONNECTION_CLOSE |
-        SshAuditEvent::SSH_NOLOGIN |
-        SshAuditEvent::SSH_LOGIN_EXCEED_MAXTRIES |
-        SshAuditEvent::SSH_LOGIN_ROOT_DENIED => {}
-        SshAuditEvent::SSH_AUTH_FAIL_NONE |
-        SshAuditEvent::SSH_AUTH_FAIL_PASSWD |
-        SshAuditEvent::SSH_AUTH_FAIL_KBDINT |
-        SshAuditEvent::SSH_AUTH_FAIL_PUBKEY |
-        SshAuditEvent::SSH_AUTH_FAIL_HOSTBASED |
-        SshAuditEvent::SSH_AUTH_FAIL_GSSAPI |
-        SshAuditEvent::SSH_INVALID_USER => {
-            let remote_ip = ssh.remote_ipaddr();
-            let username = audit_username();
-            linux_audit_record_event(-1, Some(&username), None, Some(&remote_ip), Some("sshd"), 0).unwrap();
-        }
-        _ => {
-            eprintln!("unhandled event {:?}", event);
+            let mut buf = [0u8; 512];
+            let n = unsafe {
+                libc::recv(self.fd.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0)
+            };
+            if n < 0 {
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() == Some(EINTR) {
+                    continue;
+                }
+                return Err(err);
+            }
+            let n = n as usize;
+            if n < NLMSG_HDRLEN + 4 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "short netlink reply"));
+            }
+            let msg_type = u16::from_ne_bytes([buf[4], buf[5]]);
+            let seq = u32::from_ne_bytes([buf[8], buf[9], buf[10], buf[11]]);
+            if msg_type != NLMSG_ERROR || seq != self.seq {
+                continue;
+            }
+            let error = i32::from_ne_bytes([buf[16], buf[17], buf[18], buf[19]]);
+            return if error == 0 { Ok(()) } else { Err(io::Error::from_raw_os_error(-error)) };
         }
     }
 }
 
-// 结构体 LoginInfo 和相关函数（例如 ssh_remote_ipaddr）需要根据实际的应用场景进一步实现
-// 假设的 LoginInfo 结构体和 SshAuditEvent 枚举
-#[derive(Debug)]
-pub struct LoginInfo {
-    pub uid: i32,
-    pub hostname: String,
-    pub line: String,
+/// Audit values that contain spaces, quotes or control characters are
+/// hex-encoded, everything else is quoted (audit_encode_nv_string()).
+pub fn audit_encode_value(value: &str) -> String {
+    let needs_encoding = value.bytes().any(|b| b == b'"' || !(0x21..=0x7e).contains(&b));
+    if needs_encoding {
+        value.bytes().map(|b| format!("{:02X}", b)).collect()
+    } else {
+        format!("\"{}\"", value)
+    }
 }
 
-#[derive(Debug)] // 让枚举实现 Debug
-pub enum SshAuditEvent {
-    SSH_AUTH_SUCCESS,
-    SSH_CONNECTION_CLOSE,
-    SSH_NOLOGIN,
-    SSH_LOGIN_EXCEED_MAXTRIES,
-    SSH_LOGIN_ROOT_DENIED,
-    SSH_AUTH_FAIL_NONE,
-    SSH_AUTH_FAIL_PASSWD,
-    SSH_AUTH_FAIL_KBDINT,
-    SSH_AUTH_FAIL_PUBKEY,
-    SSH_AUTH_FAIL_HOSTBASED,
-    SSH_AUTH_FAIL_GSSAPI,
-    SSH_INVALID_USER,
+#[allow(clippy::too_many_arguments)]
+pub fn format_acct_message(
+    op: &str,
+    user: Option<&str>,
+    uid: u32,
+    exe: &str,
+    hostname: Option<&str>,
+    addr: Option<&str>,
+    tty: Option<&str>,
+    success: bool,
+) -> String {
+    let acct = match user {
+        Some(name) => format!("acct={}", audit_encode_value(name)),
+        None => format!("id={}", uid),
+    };
+    format!(
+        "op={} {} exe={} hostname={} addr={} terminal={} res={}",
+        op,
+        acct,
+        audit_encode_value(exe),
+        hostname.unwrap_or("?"),
+        addr.unwrap_or("?"),
+        tty.unwrap_or("?"),
+        if success { "success" } else { "failed" }
+    )
 }
 
-pub struct Ssh {
-    // 假设有一个方法获取远程 IP 地址
+/// Audit backend emitting the Linux audit records used by sshd.
+pub struct LinuxAuditBackend {
+    audit: Option<LinuxAudit>,
 }
 
-impl Ssh {
-    pub fn remote_ipaddr(&self) -> String {
-        "192.168.1.1".to_string() // 示例
+impl LinuxAuditBackend {
+    pub fn open() -> io::Result<Self> {
+        Ok(LinuxAuditBackend { audit: LinuxAudit::open()? })
+    }
+
+    pub fn with_socket(audit: LinuxAudit) -> Self {
+        LinuxAuditBackend { audit: Some(audit) }
+    }
+
+    /// Record that credentials (e.g. forwarded Kerberos tickets) were
+    /// acquired for `user`.
+    pub fn cred_acquired(&mut self, user: &str, addr: Option<&str>) -> io::Result<()> {
+        self.record_event(AUDIT_CRED_ACQ, "PAM:setcred", Some(user), addr, Some("ssh"), true)
+    }
+
+    fn record_event(
+        &mut self,
+        msg_type: u16,
+        op: &str,
+        user: Option<&str>,
+        addr: Option<&str>,
+        tty: Option<&str>,
+        success: bool,
+    ) -> io::Result<()> {
+        let audit = match self.audit.as_mut() {
+            Some(audit) => audit,
+            None => return Ok(()),
+        };
+        let euid = unsafe { geteuid() };
+        match audit.log_acct_message(msg_type, op, user, euid, None, addr, tty, success) {
+            Ok(()) => Ok(()),
+            Err(e) => match e.raw_os_error() {
+                // auditd 未运行或内核未启用审计
+                Some(ECONNREFUSED) => Ok(()),
+                // 非 root 运行的 sshd 没有 CAP_AUDIT_WRITE，不视为错误
+                Some(EPERM) if euid != 0 => Ok(()),
+                _ => Err(e),
+            },
+        }
     }
 }
 
-pub fn audit_username() -> String {
-    "example_user".to_string() // 假设的用户名
+impl AuditBackend for LinuxAuditBackend {
+    fn record(&mut self, rec: &AuditRecord) -> io::Result<()> {
+        let user = rec.user.as_deref();
+        let addr = rec.remote_addr.as_deref();
+        let tty = rec.tty.as_deref().or(Some("ssh"));
+
+        match rec.event {
+            "AUTH_SUCCESS" => {
+                self.record_event(AUDIT_USER_AUTH, "PAM:authentication", user, addr, tty, true)?;
+                self.record_event(AUDIT_USER_ACCT, "PAM:accounting", user, addr, tty, true)
+            }
+            "AUTH_FAIL_NONE" | "AUTH_FAIL_PASSWD" | "AUTH_FAIL_KBDINT" | "AUTH_FAIL_PUBKEY"
+            | "AUTH_FAIL_HOSTBASED" | "AUTH_FAIL_GSSAPI" | "INVALID_USER" => {
+                // OpenSSH 在认证失败时记录 "login" 失败事件，user 为 audit_username()
+                let user = Some(rec.username());
+                self.record_event(AUDIT_USER_AUTH, "PAM:authentication", user, addr, Some("sshd"), false)?;
+                self.record_event(AUDIT_USER_LOGIN, "login", user, addr, Some("sshd"), false)
+            }
+            "LOGIN_EXCEED_MAXTRIES" | "LOGIN_ROOT_DENIED" | "NOLOGIN" => {
+                self.record_event(AUDIT_USER_ACCT, "PAM:accounting", user, addr, tty, false)
+            }
+            "SESSION_OPEN" => {
+                self.record_event(AUDIT_USER_LOGIN, "login", user, addr, tty, true)?;
+                self.record_event(AUDIT_USER_START, "PAM:session_open", user, addr, tty, true)
+            }
+            "SESSION_CLOSE" => {
+                self.record_event(AUDIT_USER_END, "PAM:session_close", user, addr, tty, true)
+            }
+            // 连接建立、关闭和执行命令在 Linux 审计中没有对应记录
+            _ => Ok(()),
+        }
+    }
 }
@@ -1,226 +1,405 @@
-use std::ffi::CString;
-use std::ptr;
-use std::vec::Vec;
-use std::alloc::{self, Layout};
-use std::collections::HashMap;
-use std::io::{self, Write};
-use std::str::FromStr;
-
-// Function to duplicate a list of strings
-fn dup_strings(src: &[&str]) -> Result<Vec<String>, std::io::Error> {
-    let mut dst: Vec<String> = Vec::with_capacity(src.len());
-
-    for &s in src {
-        match CString::new(s) {
-            Ok(cstr) => {
-                dst.push(cstr.into_string().unwrap());
-            }
-            Err(_) => {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    "Failed to duplicate string",
-                ));
-            }
-        }
-    }
+// auth_options.rs
+//
+// Restrictions attached to an accepted key, after OpenSSH's auth-options.c:
+// the critical options and extensions of a certificate and the options of
+// the authorized_keys line that let the key in, merged when both apply.
 
-    Ok(dst)
-}
+use std::error::Error;
+use std::fmt;
 
+use log::{debug, info};
 
-#[derive(Default)]
-struct SshAuthOpt {
-    no_require_user_presence: bool,
-    permit_x11_forwarding_flag: bool,
-    permit_agent_forwarding_flag: bool,
-    permit_port_forwarding_flag: bool,
-    permit_pty_flag: bool,
-    permit_user_rc: bool,
-    require_verify: bool,
-    force_command: Option<String>,
-    required_from_host_cert: Option<String>,
-}
+use crate::addrmatch::addr_match_cidr_list;
+use crate::misc::parse_absolute_time;
+use crate::sshbuf::SshBuf;
+use crate::sshkey::cert::encode_options;
+use crate::sshkey::Certificate;
 
-#[derive(Debug)]
+pub const OPTIONS_CRITICAL: u32 = 1;
+pub const OPTIONS_EXTENSIONS: u32 = 2;
+
+/// tunnel="any".
+pub const SSH_TUNID_ANY: i32 = 0x7fffffff;
+pub const SSH_TUNID_MAX: i32 = SSH_TUNID_ANY - 2;
+
+const SSH_AUTHOPT_PERMIT_MAX: usize = 4096;
+const SSH_AUTHOPT_ENV_MAX: usize = 1024;
+const NI_MAXHOST: usize = 1025;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ParseError {
     InvalidBuffer,
-    InvalidOption,
+    /// A critical option this implementation does not understand.
+    InvalidOption(String),
     MultipleOptions(String),
     SyntaxError(String),
-    OptionCorruption,
+    /// An option with data it should not have.
+    OptionCorruption(String),
 }
 
-const OPTIONS_CRITICAL: u32 = 1;
-const OPTIONS_EXTENSIONS: u32 = 2;
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::InvalidBuffer => f.write_str("unable to parse certificate options"),
+            ParseError::InvalidOption(name) => write!(f, "Certificate critical option \"{}\" is not supported", name),
+            ParseError::MultipleOptions(name) => write!(f, "Certificate has multiple {} options", name),
+            ParseError::SyntaxError(msg) => f.write_str(msg),
+            ParseError::OptionCorruption(name) => write!(f, "Certificate option \"{}\" corrupt (extra data)", name),
+        }
+    }
+}
 
-fn addr_match_cidr_list(_allowed: Option<String>) -> Result<(), ParseError> {
-    // Stub for CIDR address matching logic
-    Ok(())
+impl Error for ParseError {}
+
+/// Match a flag option at the start of `opts` case-insensitively,
+/// optionally negated with a "no-" prefix (opt_flag()). On a match `opts`
+/// is advanced past it and the flag's value returned.
+pub fn opt_flag(opt: &str, allow_negate: bool, opts: &mut &str) -> Option<bool> {
+    let mut s = *opts;
+    let mut negate = false;
+    if allow_negate && s.get(..3).is_some_and(|p| p.eq_ignore_ascii_case("no-")) {
+        s = &s[3..];
+        negate = true;
+    }
+    if s.get(..opt.len()).is_some_and(|p| p.eq_ignore_ascii_case(opt)) {
+        *opts = &s[opt.len()..];
+        return Some(!negate);
+    }
+    None
 }
 
-fn cert_option_list(
-    opts: &mut SshAuthOpt,
-    oblob: &[u8],
-    which: u32,
-    crit: bool,
-) -> Result<(), ParseError> {
-    let mut c = oblob.to_vec();
-    let mut found = false;
-    let mut i = 0;
-
-    while i < c.len() {
-        let mut name = String::new();
-        let mut data = Vec::new();
-
-        // Parse the option name (Assume length-prefixed for simplicity)
-        if i + 4 > c.len() {
-            return Err(ParseError::InvalidBuffer);
-        }
-        let len = u32::from_be_bytes(c[i..i + 4].try_into().unwrap()) as usize;
-        i += 4;
+/// Match "term=" at the start of `opts` case-insensitively and advance
+/// past it (opt_match()).
+pub fn opt_match(opts: &mut &str, term: &str) -> bool {
+    let s = *opts;
+    if s.get(..term.len()).is_some_and(|p| p.eq_ignore_ascii_case(term)) && s[term.len()..].starts_with('=') {
+        *opts = &s[term.len() + 1..];
+        return true;
+    }
+    false
+}
 
-        if i + len > c.len() {
-            return Err(ParseError::InvalidBuffer);
+/// Take a double-quoted value from the start of `s`, where \" stands for
+/// a quote, and advance past it (opt_dequote()).
+pub fn opt_dequote(s: &mut &str) -> Result<String, &'static str> {
+    let rest = s.strip_prefix('"').ok_or("missing start quote")?;
+    let mut out = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => {
+                *s = &rest[i + 1..];
+                return Ok(out);
+            }
+            '\\' if rest[i + 1..].starts_with('"') => {
+                chars.next();
+                out.push('"');
+            }
+            c => out.push(c),
         }
-        name = String::from_utf8_lossy(&c[i..i + len]).to_string();
-        i += len;
+    }
+    Err("missing end quote")
+}
 
-        // Parse the associated data
-        if i + 4 > c.len() {
-            return Err(ParseError::InvalidBuffer);
-        }
-        let data_len = u32::from_be_bytes(c[i..i + 4].try_into().unwrap()) as usize;
-        i += 4;
+/// struct sshauthopt.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SshAuthOpt {
+    pub permit_port_forwarding_flag: bool,
+    pub permit_agent_forwarding_flag: bool,
+    pub permit_x11_forwarding_flag: bool,
+    pub permit_pty_flag: bool,
+    pub permit_user_rc: bool,
+
+    /// "restrict" was given.
+    pub restricted: bool,
+    /// The line holds a CA key trusted for user certificates.
+    pub cert_authority: bool,
+    /// expiry-time, in seconds since the epoch; 0 when unset.
+    pub valid_before: u64,
+
+    /// -1 when unset, SSH_TUNID_ANY or a tun device number otherwise.
+    pub force_tun_device: i32,
+    /// principals= of a cert-authority line, a comma-separated list.
+    pub cert_principals: Option<String>,
+    pub force_command: Option<String>,
+    /// source-address of a certificate.
+    pub required_from_host_cert: Option<String>,
+    /// from= of an authorized_keys line.
+    pub required_from_host_keys: Option<String>,
+    pub env: Vec<String>,
+    pub permitopen: Vec<String>,
+    pub permitlisten: Vec<String>,
+
+    pub no_require_user_presence: bool,
+    pub require_verify: bool,
+}
 
-        if i + data_len > c.len() {
-            return Err(ParseError::InvalidBuffer);
+impl SshAuthOpt {
+    /// Everything denied (sshauthopt_new()).
+    pub fn new() -> Self {
+        SshAuthOpt { force_tun_device: -1, ..Default::default() }
+    }
+
+    /// The defaults for a key without options: all forwarding, ptys and
+    /// ~/.ssh/rc allowed (sshauthopt_new_with_keys_defaults()).
+    pub fn new_with_keys_defaults() -> Self {
+        SshAuthOpt {
+            permit_port_forwarding_flag: true,
+            permit_agent_forwarding_flag: true,
+            permit_x11_forwarding_flag: true,
+            permit_pty_flag: true,
+            permit_user_rc: true,
+            ..SshAuthOpt::new()
         }
-        data = c[i..i + data_len].to_vec();
-        i += data_len;
+    }
 
-        println!("Found certificate option \"{}\", len {}", name, data.len());
-        found = false;
+    /// Options granted by a certificate: its critical options, which must
+    /// all be understood, and its extensions, where unknown ones are
+    /// ignored (sshauthopt_from_cert()).
+    pub fn from_cert(cert: &Certificate) -> Result<SshAuthOpt, ParseError> {
+        let mut opts = SshAuthOpt::new();
+        cert_option_list(&mut opts, &encode_options(&cert.critical_options), OPTIONS_CRITICAL, true)?;
+        cert_option_list(&mut opts, &encode_options(&cert.extensions), OPTIONS_EXTENSIONS, false)?;
+        Ok(opts)
+    }
 
-        // Check for extensions
-        if (which & OPTIONS_EXTENSIONS) != 0 {
-            match name.as_str() {
-                "no-touch-required" => {
-                    opts.no_require_user_presence = true;
-                    found = true;
+    /// Parse the options field of an authorized_keys or principals file
+    /// line, up to the first unquoted space (sshauthopt_parse()). Starts
+    /// from the keys defaults, so an empty string allows everything.
+    pub fn parse(opts: &str) -> Result<SshAuthOpt, ParseError> {
+        let mut ret = SshAuthOpt::new_with_keys_defaults();
+        let mut opts = opts;
+
+        while !opts.is_empty() && !opts.starts_with([' ', '\t']) {
+            if opt_flag("restrict", false, &mut opts).is_some() {
+                ret.restricted = true;
+                ret.permit_port_forwarding_flag = false;
+                ret.permit_agent_forwarding_flag = false;
+                ret.permit_x11_forwarding_flag = false;
+                ret.permit_pty_flag = false;
+                ret.permit_user_rc = false;
+            } else if let Some(v) = opt_flag("cert-authority", false, &mut opts) {
+                ret.cert_authority = v;
+            } else if let Some(v) = opt_flag("port-forwarding", true, &mut opts) {
+                ret.permit_port_forwarding_flag = v;
+            } else if let Some(v) = opt_flag("agent-forwarding", true, &mut opts) {
+                ret.permit_agent_forwarding_flag = v;
+            } else if let Some(v) = opt_flag("x11-forwarding", true, &mut opts) {
+                ret.permit_x11_forwarding_flag = v;
+            } else if let Some(v) = opt_flag("touch-required", true, &mut opts) {
+                // 注意取反
+                ret.no_require_user_presence = !v;
+            } else if let Some(v) = opt_flag("verify-required", true, &mut opts) {
+                ret.require_verify = v;
+            } else if let Some(v) = opt_flag("pty", true, &mut opts) {
+                ret.permit_pty_flag = v;
+            } else if let Some(v) = opt_flag("user-rc", true, &mut opts) {
+                ret.permit_user_rc = v;
+            } else if opt_match(&mut opts, "command") {
+                if ret.force_command.is_some() {
+                    return Err(syntax("multiple \"command\" clauses"));
                 }
-                "permit-X11-forwarding" => {
-                    opts.permit_x11_forwarding_flag = true;
-                    found = true;
+                ret.force_command = Some(opt_dequote(&mut opts).map_err(syntax)?);
+            } else if opt_match(&mut opts, "principals") {
+                if ret.cert_principals.is_some() {
+                    return Err(syntax("multiple \"principals\" clauses"));
                 }
-                "permit-agent-forwarding" => {
-                    opts.permit_agent_forwarding_flag = true;
-                    found = true;
+                ret.cert_principals = Some(opt_dequote(&mut opts).map_err(syntax)?);
+            } else if opt_match(&mut opts, "from") {
+                if ret.required_from_host_keys.is_some() {
+                    return Err(syntax("multiple \"from\" clauses"));
                 }
-                "permit-port-forwarding" => {
-                    opts.permit_port_forwarding_flag = true;
-                    found = true;
+                ret.required_from_host_keys = Some(opt_dequote(&mut opts).map_err(syntax)?);
+            } else if opt_match(&mut opts, "expiry-time") {
+                let opt = opt_dequote(&mut opts).map_err(syntax)?;
+                let valid_before = match parse_absolute_time(&opt) {
+                    Some(t) if t != 0 => t,
+                    _ => return Err(syntax("invalid expires time")),
+                };
+                if ret.valid_before == 0 || valid_before < ret.valid_before {
+                    ret.valid_before = valid_before;
                 }
-                "permit-pty" => {
-                    opts.permit_pty_flag = true;
-                    found = true;
+            } else if opt_match(&mut opts, "environment") {
+                if ret.env.len() > SSH_AUTHOPT_ENV_MAX {
+                    return Err(syntax("too many environment strings"));
                 }
-                "permit-user-rc" => {
-                    opts.permit_user_rc = true;
-                    found = true;
+                let opt = opt_dequote(&mut opts).map_err(syntax)?;
+                // 变量名只能由字母、数字和下划线组成，后跟 '='
+                let name = match opt.split_once('=') {
+                    Some((name, _)) if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') => {
+                        name
+                    }
+                    _ => return Err(syntax("invalid environment string")),
+                };
+                // 同名变量以第一次出现的为准
+                if !ret.env.iter().any(|e| e.split_once('=').is_some_and(|(n, _)| n == name)) {
+                    ret.env.push(opt);
                 }
-                _ => {}
+            } else if opt_match(&mut opts, "permitopen") {
+                let opt = opt_dequote(&mut opts).map_err(syntax)?;
+                handle_permit(&opt, false, &mut ret.permitopen).map_err(|e| permit_error(e, "permitopen"))?;
+            } else if opt_match(&mut opts, "permitlisten") {
+                let opt = opt_dequote(&mut opts).map_err(syntax)?;
+                handle_permit(&opt, true, &mut ret.permitlisten).map_err(|e| permit_error(e, "permitlisten"))?;
+            } else if opt_match(&mut opts, "tunnel") {
+                let opt = opt_dequote(&mut opts).map_err(syntax)?;
+                ret.force_tun_device = match a2tun(&opt) {
+                    Some(tun) => tun,
+                    None => return Err(syntax("invalid tun device")),
+                };
+            }
+
+            // 跳过逗号，进入下一个选项
+            if opts.is_empty() || opts.starts_with([' ', '\t']) {
+                break;
+            }
+            // 其他任何字符都说明选项未知
+            opts = match opts.strip_prefix(',') {
+                Some(rest) => rest,
+                None => return Err(syntax("unknown key option")),
+            };
+            if opts.is_empty() {
+                return Err(syntax("unexpected end-of-options"));
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Combine the options of an authorized_keys or principals line
+    /// (`primary`) with those of the certificate it accepted
+    /// (`additional`) (sshauthopt_merge()). Flags must be allowed by both;
+    /// the earlier expiry wins; forced commands, if both are given, must
+    /// agree. cert_principals is deliberately not carried over.
+    pub fn merge(primary: &SshAuthOpt, additional: &SshAuthOpt) -> Result<SshAuthOpt, ParseError> {
+        let mut ret = SshAuthOpt::new();
+
+        ret.force_tun_device = match primary.force_tun_device {
+            -1 => additional.force_tun_device,
+            tun => tun,
+        };
+        let pick = |a: &Option<String>, b: &Option<String>| a.clone().or_else(|| b.clone());
+        ret.required_from_host_cert = pick(&primary.required_from_host_cert, &additional.required_from_host_cert);
+        ret.required_from_host_keys = pick(&primary.required_from_host_keys, &additional.required_from_host_keys);
+        // 列表不合并：primary 非空时优先
+        let pick_list = |a: &Vec<String>, b: &Vec<String>| if a.is_empty() { b.clone() } else { a.clone() };
+        ret.env = pick_list(&primary.env, &additional.env);
+        ret.permitopen = pick_list(&primary.permitopen, &additional.permitopen);
+        ret.permitlisten = pick_list(&primary.permitlisten, &additional.permitlisten);
+
+        ret.permit_port_forwarding_flag = primary.permit_port_forwarding_flag && additional.permit_port_forwarding_flag;
+        ret.permit_agent_forwarding_flag = primary.permit_agent_forwarding_flag && additional.permit_agent_forwarding_flag;
+        ret.permit_x11_forwarding_flag = primary.permit_x11_forwarding_flag && additional.permit_x11_forwarding_flag;
+        ret.permit_pty_flag = primary.permit_pty_flag && additional.permit_pty_flag;
+        ret.permit_user_rc = primary.permit_user_rc && additional.permit_user_rc;
+        ret.no_require_user_presence = primary.no_require_user_presence && additional.no_require_user_presence;
+        ret.require_verify = primary.require_verify || additional.require_verify;
+
+        ret.valid_before = match (primary.valid_before, additional.valid_before) {
+            (0, t) | (t, 0) => t,
+            (a, b) => a.min(b),
+        };
+
+        ret.force_command = match (&primary.force_command, &additional.force_command) {
+            (Some(a), Some(b)) if a != b => {
+                return Err(ParseError::SyntaxError("forced command options do not match".to_string()))
+            }
+            (a, b) => pick(a, b),
+        };
+        Ok(ret)
+    }
+}
+
+fn syntax(msg: &str) -> ParseError {
+    ParseError::SyntaxError(msg.to_string())
+}
+
+// handle_permit() 的错误信息
+fn permit_error(e: SshAuthError, which: &str) -> ParseError {
+    let msg = match e {
+        SshAuthError::TooManyPermissions => format!("too many {} entries", which),
+        SshAuthError::InvalidPermissionHostname => format!("invalid {} hostname", which),
+        SshAuthError::InvalidPermissionPort => format!("invalid {} port", which),
+        _ => format!("invalid {}", which),
+    };
+    ParseError::SyntaxError(msg)
+}
+
+// "any" 或设备号（a2tun()，不带远端部分）
+fn a2tun(s: &str) -> Option<i32> {
+    if s.eq_ignore_ascii_case("any") {
+        return Some(SSH_TUNID_ANY);
+    }
+    s.parse::<i32>().ok().filter(|tun| (0..=SSH_TUNID_MAX).contains(tun))
+}
+
+/// Apply the options in `oblob`, the contents of a certificate's critical
+/// options or extensions field, to `opts`. `which` selects the option
+/// names recognised; with `crit` an unrecognised option is an error.
+pub fn cert_option_list(opts: &mut SshAuthOpt, oblob: &[u8], which: u32, crit: bool) -> Result<(), ParseError> {
+    let mut c = SshBuf::from_vec(oblob.to_vec());
+
+    while !c.is_empty() {
+        let name = c.get_cstring().map_err(|_| ParseError::InvalidBuffer)?;
+        let mut data = SshBuf::from_vec(c.get_string().map_err(|_| ParseError::InvalidBuffer)?.to_vec());
+        debug!("found certificate option \"{}\" len {}", name, data.len());
+        let mut found = false;
+
+        if (which & OPTIONS_EXTENSIONS) != 0 {
+            found = true;
+            match name.as_str() {
+                "no-touch-required" => opts.no_require_user_presence = true,
+                "permit-X11-forwarding" => opts.permit_x11_forwarding_flag = true,
+                "permit-agent-forwarding" => opts.permit_agent_forwarding_flag = true,
+                "permit-port-forwarding" => opts.permit_port_forwarding_flag = true,
+                "permit-pty" => opts.permit_pty_flag = true,
+                "permit-user-rc" => opts.permit_user_rc = true,
+                _ => found = false,
             }
         }
 
         if !found && (which & OPTIONS_CRITICAL) != 0 {
+            found = true;
             match name.as_str() {
-                "verify-required" => {
-                    opts.require_verify = true;
-                    found = true;
-                }
+                "verify-required" => opts.require_verify = true,
                 "force-command" => {
-                    let command = String::from_utf8_lossy(&data).to_string();
+                    let command = data
+                        .get_cstring()
+                        .map_err(|_| ParseError::SyntaxError(format!("Unable to parse \"{}\" section", name)))?;
                     if opts.force_command.is_some() {
                         return Err(ParseError::MultipleOptions("force-command".to_string()));
                     }
                     opts.force_command = Some(command);
-                    found = true;
                 }
                 "source-address" => {
-                    let allowed = String::from_utf8_lossy(&data).to_string();
+                    let allowed = data
+                        .get_cstring()
+                        .map_err(|_| ParseError::SyntaxError(format!("Unable to parse \"{}\" section", name)))?;
                     if opts.required_from_host_cert.is_some() {
                         return Err(ParseError::MultipleOptions("source-address".to_string()));
                     }
-                    addr_match_cidr_list(Some(allowed.clone()))?; // Check syntax
+                    // 只检查语法
+                    if addr_match_cidr_list(None, &allowed) == -1 {
+                        return Err(ParseError::SyntaxError("Certificate source-address contents invalid".to_string()));
+                    }
                     opts.required_from_host_cert = Some(allowed);
-                    found = true;
                 }
-                _ => {}
+                _ => found = false,
             }
         }
 
         if !found {
             if crit {
-                return Err(ParseError::OptionCorruption);
-            } else {
-                println!("Certificate extension \"{}\" is not supported", name);
+                return Err(ParseError::InvalidOption(name));
             }
+            info!("Certificate extension \"{}\" is not supported", name);
         } else if !data.is_empty() {
-            return Err(ParseError::OptionCorruption);
+            return Err(ParseError::OptionCorruption(name));
         }
     }
 
     Ok(())
 }
 
-#[derive(Default)]
-pub struct SshAuthOpt {
-    force_tun_device: i32,
-    cert_principals: Option<Vec<String>>,
-    force_command: Option<String>,
-    required_from_host_cert: Option<String>,
-    required_from_host_keys: Option<Vec<String>>,
-    nenv: usize,
-    env: Option<Vec<String>>,
-    npermitopen: usize,
-    permitopen: Option<Vec<String>>,
-    npermitlisten: usize,
-    permitlisten: Option<Vec<String>>,
-    permit_port_forwarding_flag: bool,
-    permit_agent_forwarding_flag: bool,
-    permit_x11_forwarding_flag: bool,
-    permit_pty_flag: bool,
-    permit_user_rc: bool,
-}
-
-impl SshAuthOpt {
-    // Equivalent to sshauthopt_new() in C
-    pub fn new() -> Option<Box<Self>> {
-        let mut ret = Box::new(SshAuthOpt::default());
-        ret.force_tun_device = -1;
-        Some(ret)
-    }
-
-    // Equivalent to sshauthopt_free() in C
-    pub fn free(&mut self) {
-        // Automatically freed when the struct is dropped. No need to manually free memory in Rust.
-    }
-
-    // Equivalent to sshauthopt_new_with_keys_defaults() in C
-    pub fn new_with_keys_defaults() -> Option<Box<Self>> {
-        let mut ret = SshAuthOpt::new()?;
-
-        // Set defaults for the flags (same as in the C code)
-        ret.permit_port_forwarding_flag = true;
-        ret.permit_agent_forwarding_flag = true;
-        ret.permit_x11_forwarding_flag = true;
-        ret.permit_pty_flag = true;
-        ret.permit_user_rc = true;
-
-        Some(ret)
-    }
-}
-
 #[derive(Debug)]
 pub enum SshAuthError {
     TooManyPermissions,
@@ -230,57 +409,35 @@ pub enum SshAuthError {
     UnknownError,
 }
 
-pub fn handle_permit(
-    optsp: &mut Vec<String>,
-    allow_bare_port: bool,
-    permitsp: &mut Vec<String>,
-    npermitsp: &mut usize,
-) -> Result<(), SshAuthError> {
-    if *npermitsp > SSH_AUTHOPT_PERMIT_MAX {
+/// Validate and record a permitopen/permitlisten specification.
+pub fn handle_permit(opt: &str, allow_bare_port: bool, permits: &mut Vec<String>) -> Result<(), SshAuthError> {
+    if permits.len() >= SSH_AUTHOPT_PERMIT_MAX {
         return Err(SshAuthError::TooManyPermissions);
     }
 
-    let opt = optsp.pop().ok_or(SshAuthError::UnknownError)?;
-    let mut opt = opt.clone();
+    // Allow a bare port number in permitlisten to indicate a listen_host wildcard.
+    let opt = if allow_bare_port && !opt.contains(':') { format!("*:{}", opt) } else { opt.to_string() };
 
-    if allow_bare_port && !opt.contains(':') {
-        // Allow a bare port number in permitlisten to indicate a listen_host wildcard.
-        opt = format!("*:{opt}");
-    }
-
-    let mut tmp = opt.clone();
     // Validate syntax before recording it.
-    let host = match hpdelim2(&mut tmp) {
-        Some(h) => h,
-        None => return Err(SshAuthError::InvalidPermissionHostname),
-    };
-
-    if host.len() >= NI_MAXHOST {
+    let (host, port) = hpdelim2(&opt).ok_or(SshAuthError::InvalidPermissionHostname)?;
+    if host.is_empty() || host.len() >= NI_MAXHOST {
         return Err(SshAuthError::InvalidPermissionHostname);
     }
 
     // Validate the port.
-    let port = tmp.trim();
-    if port != "*" && port.parse::<u16>().is_err() {
+    if port != "*" && !matches!(port.parse::<u16>(), Ok(p) if p > 0) {
         return Err(SshAuthError::InvalidPermissionPort);
     }
 
-    // Record the permission.
-    permitsp.push(opt);
-
-    *npermitsp += 1;
-
+    permits.push(opt);
     Ok(())
 }
 
-// Helper function: mimicking `hpdelim2` from C
-fn hpdelim2(input: &mut String) -> Option<String> {
-    let parts: Vec<&str> = input.split(':').collect();
-    if parts.is_empty() {
-        return None;
+// 拆分 host:port，IPv6 地址写作 [addr]:port（hpdelim2()）
+fn hpdelim2(s: &str) -> Option<(&str, &str)> {
+    if let Some(rest) = s.strip_prefix('[') {
+        let (host, rest) = rest.split_once(']')?;
+        return Some((host, rest.strip_prefix(':')?));
     }
-    Some(parts[0].to_string())  // Return the hostname part.
+    s.split_once(':')
 }
-
-const SSH_AUTHOPT_PERMIT_MAX: usize = 100;  // Arbitrary value for max permission directives.
-const NI_MAXHOST: usize = 256;  // Maximum allowed hostname length (example).
\ No newline at end of file
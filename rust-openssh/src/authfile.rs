@@ -0,0 +1,762 @@
+// authfile.rs
+//
+// authorized_keys handling for public key authentication, after OpenSSH's
+// auth2-pubkeyfile.c and the file parts of auth2-pubkey.c: expanding
+// AuthorizedKeysFile templates, the StrictModes checks on the files found,
+// and matching an offered key or certificate against each line, whose
+// options decide whether and how it may be used. Keys may also come from
+// the output of AuthorizedKeysCommand. Certificates signed by a
+// TrustedUserCAKeys CA are checked against the principals listed in an
+// AuthorizedPrincipalsFile or by AuthorizedPrincipalsCommand.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use log::{debug, error, info};
+
+use crate::addrmatch::{addr_match_cidr_list, match_host_and_ip};
+use crate::auth_options::SshAuthOpt;
+use crate::krl::{self, KrlError};
+use crate::misc::{argv_split, format_absolute_time, percent_expand, safe_path, subprocess, Passwd};
+use crate::sshkey::{advance_past_options, Certificate, FpHash, KeyError, PublicKey};
+
+/// The sshd_config settings used to look up a user's keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthKeysConfig {
+    /// AuthorizedKeysFile: templates taking %h, %u, %U and %%, relative
+    /// to the home directory unless absolute; "none" is skipped.
+    pub authorized_keys_files: Vec<String>,
+    pub strict_modes: bool,
+    /// RevokedKeys: a KRL or a list of public keys.
+    pub revoked_keys_file: Option<PathBuf>,
+    /// TrustedUserCAKeys: CA keys whose user certificates are accepted
+    /// without an authorized_keys entry.
+    pub trusted_user_ca_keys: Option<PathBuf>,
+    /// AuthorizedPrincipalsFile: a template like AuthorizedKeysFile naming
+    /// the principals accepted for certificates from those CAs, instead of
+    /// the user name; "none" disables it.
+    pub authorized_principals_file: Option<String>,
+    /// AuthorizedKeysCommand: an absolute program and arguments taking
+    /// %U, %u, %h, %t, %f, %k, %C and %%, whose output is read as an
+    /// authorized_keys file. Given no arguments it is passed the user name.
+    pub authorized_keys_command: Option<String>,
+    /// AuthorizedKeysCommandUser: who runs it, taking %u; required.
+    pub authorized_keys_command_user: Option<String>,
+    /// AuthorizedPrincipalsCommand: as AuthorizedKeysCommand, also taking
+    /// the CA's %T, %F and %K and the certificate's %i and %s, whose output
+    /// lists the principals accepted for the user.
+    pub authorized_principals_command: Option<String>,
+    /// AuthorizedPrincipalsCommandUser: who runs it, taking %u; required.
+    pub authorized_principals_command_user: Option<String>,
+    /// How long either command may run.
+    pub command_timeout: Duration,
+    /// How much output either command may write.
+    pub command_max_output: usize,
+}
+
+impl Default for AuthKeysConfig {
+    fn default() -> Self {
+        AuthKeysConfig {
+            authorized_keys_files: vec![".ssh/authorized_keys".to_string(), ".ssh/authorized_keys2".to_string()],
+            strict_modes: true,
+            revoked_keys_file: None,
+            trusted_user_ca_keys: None,
+            authorized_principals_file: None,
+            authorized_keys_command: None,
+            authorized_keys_command_user: None,
+            authorized_principals_command: None,
+            authorized_principals_command_user: None,
+            command_timeout: Duration::from_secs(30),
+            command_max_output: 1 << 20,
+        }
+    }
+}
+
+/// The connection a key is offered on. The client is checked against
+/// from= and source-address; all four endpoints make up %C.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnInfo {
+    pub remote_ip: String,
+    pub remote_host: String,
+    pub remote_port: u16,
+    pub local_ip: String,
+    pub local_port: u16,
+}
+
+/// The authorized_keys line that accepted a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthorizedKey {
+    /// "file:line".
+    pub loc: String,
+    /// The key on the line: the offered key itself or, for a
+    /// certificate, its CA.
+    pub key: PublicKey,
+    /// The line's options, merged with the certificate's if there is one.
+    pub opts: SshAuthOpt,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Expand an AuthorizedKeysFile template for `pw`
+/// (expand_authorized_keys()). A relative result is taken to be relative
+/// to the home directory.
+pub fn expand_authorized_keys(template: &str, pw: &Passwd) -> Result<PathBuf, String> {
+    let uid = pw.uid.to_string();
+    let home = pw.dir.to_string_lossy();
+    let file = percent_expand(template, &[('h', &home), ('u', &pw.name), ('U', &uid)])?;
+    Ok(pw.dir.join(file))
+}
+
+// auth_openfile()：file_type 用于日志，如 "authorized keys"
+fn open_auth_file(
+    file: &Path,
+    pw: &Passwd,
+    strict_modes: bool,
+    file_type: &str,
+    auth_debug: &mut Vec<String>,
+) -> Option<File> {
+    let f = match File::open(file) {
+        Ok(f) => f,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            debug!("Could not open user '{}' {} '{}': {}", pw.name, file_type, file.display(), e);
+            return None;
+        }
+        Err(e) => {
+            info!("Could not open user '{}' {} '{}': {}", pw.name, file_type, file.display(), e);
+            return None;
+        }
+    };
+    let st = f.metadata().ok()?;
+    if !st.is_file() {
+        info!("User '{}' {} '{}' is not a regular file", pw.name, file_type, file.display());
+        return None;
+    }
+    if strict_modes {
+        if let Err(msg) = safe_path(file, &st, Some(&pw.dir), pw.uid) {
+            info!("Authentication refused: {}", msg);
+            auth_debug.push(format!("Ignored {}: {}", file_type, msg));
+            return None;
+        }
+    }
+    Some(f)
+}
+
+/// Open a user's key file (auth_openkeyfile()). A missing file is not
+/// worth more than a debug message; with `strict_modes` the file and the
+/// directories above it must pass safe_path().
+pub fn open_keyfile(file: &Path, pw: &Passwd, strict_modes: bool, auth_debug: &mut Vec<String>) -> Option<File> {
+    open_auth_file(file, pw, strict_modes, "authorized keys", auth_debug)
+}
+
+/// Open a user's principals file (auth_openprincipals()), checked as
+/// open_keyfile() does.
+pub fn open_principals_file(file: &Path, pw: &Passwd, strict_modes: bool, auth_debug: &mut Vec<String>) -> Option<File> {
+    open_auth_file(file, pw, strict_modes, "authorized principals", auth_debug)
+}
+
+/// Check the options of an accepted line, or of a certificate, against
+/// the connection (auth_authorise_keyopts()): expiry-time, from= and
+/// source-address, and that principals= and cert-authority are only used
+/// where they make sense.
+fn authorise_keyopts(
+    pw: &Passwd,
+    opts: &SshAuthOpt,
+    allow_cert_authority: bool,
+    conn: &ConnInfo,
+    loc: &str,
+    auth_debug: &mut Vec<String>,
+) -> bool {
+    let mut refuse = |msg: String| {
+        debug!("{}", msg);
+        auth_debug.push(msg);
+        false
+    };
+    // 证书自身的有效期在别处检查
+    if opts.valid_before != 0 && opts.valid_before < now() {
+        return refuse(format!("{}: entry expired at {}", loc, format_absolute_time(opts.valid_before)));
+    }
+    if opts.cert_principals.is_some() && !opts.cert_authority {
+        return refuse(format!("{}: principals on non-CA key", loc));
+    }
+    if !allow_cert_authority && opts.cert_authority {
+        return refuse(format!("{}: cert-authority flag invalid here", loc));
+    }
+
+    if let Some(from) = &opts.required_from_host_keys {
+        let m = match_host_and_ip(Some(&conn.remote_host), Some(&conn.remote_ip), from);
+        if m != 1 {
+            if m == -1 {
+                refuse(format!("{}: invalid from criteria", loc));
+            }
+            info!(
+                "{}: Authentication tried for {} with correct key but not from a permitted host \
+                 (host={}, ip={}, required={}).",
+                loc, pw.name, conn.remote_host, conn.remote_ip, from
+            );
+            return refuse(format!("{}: Your host '{}' is not permitted to use this key for login.", loc, conn.remote_host));
+        }
+    }
+    if let Some(source) = &opts.required_from_host_cert {
+        let m = addr_match_cidr_list(Some(&conn.remote_ip), source);
+        if m != 1 {
+            if m == -1 {
+                error!("{}: Certificate source-address invalid", loc);
+            }
+            info!(
+                "{}: Authentication tried for {} with valid certificate but not from a permitted source address ({}).",
+                loc, pw.name, conn.remote_ip
+            );
+            return refuse(format!(
+                "{}: Your address '{}' is not permitted to use this certificate for login.",
+                loc, conn.remote_ip
+            ));
+        }
+    }
+    true
+}
+
+// 匹配的密钥被拒绝：记录原因并告知客户端
+fn fail_reason(auth_debug: &mut Vec<String>, reason: &str) -> Option<AuthorizedKey> {
+    error!("{}", reason);
+    auth_debug.push(reason.to_string());
+    None
+}
+
+/// Check one authorized_keys line, "[options] key [comment]", against
+/// `key` (auth_check_authkey_line()). A plain key must be listed without
+/// cert-authority; a certificate must be signed by a key listed with it,
+/// and name the user among its principals, or one of principals= if
+/// given. Lines that cannot be parsed are reported with `loc` and skipped.
+pub fn check_authkey_line(
+    pw: &Passwd,
+    key: &PublicKey,
+    line: &str,
+    conn: &ConnInfo,
+    loc: &str,
+    auth_debug: &mut Vec<String>,
+) -> Option<AuthorizedKey> {
+    let mut bad_line = |reason: String| {
+        error!("{}: {}", loc, reason);
+        auth_debug.push(format!("{}: {}", loc, reason));
+        None
+    };
+    // 行首不是密钥时，前面是选项
+    let (found, key_options) = match PublicKey::from_openssh_line(line) {
+        Ok((found, _)) => (found, ""),
+        Err(_) => {
+            debug!("{}: check options: '{}'", loc, line);
+            let rest = match advance_past_options(line) {
+                Some(rest) => rest,
+                None => return bad_line("invalid key option string".to_string()),
+            };
+            match PublicKey::from_openssh_line(rest) {
+                Ok((found, _)) => (found, &line[..line.len() - rest.len()]),
+                Err(e) => return bad_line(format!("invalid key: {}", e)),
+            }
+        }
+    };
+    // 先解析选项：需要知道这是否为 CA 密钥
+    let keyopts = match SshAuthOpt::parse(key_options) {
+        Ok(opts) => opts,
+        Err(reason) => return bad_line(format!("bad key options: {}", reason)),
+    };
+
+    // 跳过不匹配的密钥，以及 cert-authority 标记不对的行
+    let cert = key.cert();
+    let candidate = match cert {
+        Some(cert) => keyopts.cert_authority && found == *cert.signature_key.plain(),
+        None => !keyopts.cert_authority && found == *key,
+    };
+    if !candidate {
+        return None;
+    }
+
+    let fp = found.fingerprint(FpHash::Sha256);
+    debug!("{}: matching {} found: {} {}", loc, if cert.is_some() { "CA" } else { "key" }, found.type_name(), fp);
+
+    if !authorise_keyopts(pw, &keyopts, cert.is_some(), conn, loc, auth_debug) {
+        return fail_reason(auth_debug, "Refused by key options");
+    }
+    // 普通密钥到此为止
+    let cert = match cert {
+        None => {
+            info!("Accepted key {} {} found at {}", found.type_name(), fp, loc);
+            return Some(AuthorizedKey { loc: loc.to_string(), key: found, opts: keyopts });
+        }
+        Some(cert) => cert,
+    };
+
+    // 证书还要检查证书自身的选项
+    let certopts = match SshAuthOpt::from_cert(cert) {
+        Ok(opts) => opts,
+        Err(_) => return fail_reason(auth_debug, "Invalid certificate options"),
+    };
+    if !authorise_keyopts(pw, &certopts, false, conn, loc, auth_debug) {
+        return fail_reason(auth_debug, "Refused by certificate options");
+    }
+    let finalopts = match SshAuthOpt::merge(&keyopts, &certopts) {
+        Ok(opts) => opts,
+        Err(reason) => return fail_reason(auth_debug, &reason.to_string()),
+    };
+    // 给出 principals= 时用它代替用户名去匹配证书中的 principal
+    if let Some(principals) = &keyopts.cert_principals {
+        if !cert.principals.iter().any(|p| principals.split(',').any(|q| q == p)) {
+            return fail_reason(auth_debug, "Certificate does not contain an authorized principal");
+        }
+    }
+    let name = keyopts.cert_principals.is_none().then_some(pw.name.as_str());
+    if let Err(e) = cert.check_authority(false, false, false, name, now()) {
+        return fail_reason(auth_debug, &e.to_string());
+    }
+    info!(
+        "Accepted certificate ID \"{}\" (serial {}) signed by CA {} {} found at {}",
+        cert.key_id,
+        cert.serial,
+        found.type_name(),
+        fp,
+        loc
+    );
+    Some(AuthorizedKey { loc: loc.to_string(), key: found, opts: finalopts })
+}
+
+/// Check the lines read from `f`, named `file` in diagnostics, until one
+/// accepts `key` (auth_check_authkeys_file()). Blank lines and comments
+/// are skipped.
+pub fn check_authkeys_file(
+    pw: &Passwd,
+    f: impl BufRead,
+    file: &str,
+    key: &PublicKey,
+    conn: &ConnInfo,
+    auth_debug: &mut Vec<String>,
+) -> Option<AuthorizedKey> {
+    for (i, line) in f.split(b'\n').enumerate() {
+        let line = match line {
+            Ok(line) => String::from_utf8_lossy(&line).into_owned(),
+            Err(e) => {
+                error!("{}: read: {}", file, e);
+                return None;
+            }
+        };
+        let line = line.trim_start_matches([' ', '\t']).trim_end_matches(['\r', '\n']);
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let loc = format!("{}:{}", file, i + 1);
+        if let Some(found) = check_authkey_line(pw, key, line, conn, &loc, auth_debug) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+/// Check one expanded AuthorizedKeysFile (user_key_allowed2()).
+pub fn check_authorized_keys_file(
+    cfg: &AuthKeysConfig,
+    pw: &Passwd,
+    file: &Path,
+    key: &PublicKey,
+    conn: &ConnInfo,
+    auth_debug: &mut Vec<String>,
+) -> Option<AuthorizedKey> {
+    debug!("trying public key file {}", file.display());
+    let f = open_keyfile(file, pw, cfg.strict_modes, auth_debug)?;
+    check_authkeys_file(pw, BufReader::new(f), &file.display().to_string(), key, conn, auth_debug)
+}
+
+// auth_key_is_revoked()：无法检查时也当作已吊销
+fn key_is_revoked(cfg: &AuthKeysConfig, key: &PublicKey) -> bool {
+    let path = match &cfg.revoked_keys_file {
+        Some(path) => path,
+        None => return false,
+    };
+    let fp = key.fingerprint(FpHash::Sha256);
+    match krl::check_revoked(key, path) {
+        Ok(()) => false,
+        Err(KrlError::Revoked) => {
+            error!("Authentication key {} {} revoked by file {}", key.type_name(), fp, path.display());
+            true
+        }
+        Err(e) => {
+            error!(
+                "Error checking authentication key {} {} in revoked keys file {}: {}",
+                key.type_name(),
+                fp,
+                path.display(),
+                e
+            );
+            true
+        }
+    }
+}
+
+// 运行命令的用户及展开后的参数；出错时已记录日志
+fn prepare_command(
+    tag: &str,
+    command: &str,
+    user: &str,
+    pw: &Passwd,
+    tokens: &[(char, &str)],
+) -> Option<(Passwd, Vec<String>)> {
+    let username = match percent_expand(user, &[('u', &pw.name)]) {
+        Ok(username) => username,
+        Err(e) => {
+            error!("{}User \"{}\": {}", tag, user, e);
+            return None;
+        }
+    };
+    let runas = match Passwd::from_name(&username) {
+        Some(runas) => runas,
+        None => {
+            error!("{}User \"{}\" not found", tag, username);
+            return None;
+        }
+    };
+    let mut av = match argv_split(command) {
+        Some(av) => av,
+        None => {
+            error!("{} \"{}\" contains invalid quotes", tag, command);
+            return None;
+        }
+    };
+    if av.is_empty() {
+        error!("{} \"{}\" yielded no arguments", tag, command);
+        return None;
+    }
+    for arg in &mut av[1..] {
+        match percent_expand(arg, tokens) {
+            Ok(expanded) => *arg = expanded,
+            Err(e) => {
+                error!("{} \"{}\": {}", tag, command, e);
+                return None;
+            }
+        }
+    }
+    Some((runas, av))
+}
+
+// %C：客户端地址、端口，服务器地址、端口
+fn conn_id(conn: &ConnInfo) -> String {
+    format!("{} {} {} {}", conn.remote_ip, conn.remote_port, conn.local_ip, conn.local_port)
+}
+
+/// Run AuthorizedKeysCommand and check its output as an authorized_keys
+/// file (user_key_command_allowed2()). Nothing is accepted if the command
+/// fails, times out or writes too much.
+pub fn user_key_command_allowed(
+    cfg: &AuthKeysConfig,
+    pw: &Passwd,
+    key: &PublicKey,
+    conn: &ConnInfo,
+    auth_debug: &mut Vec<String>,
+) -> Option<AuthorizedKey> {
+    let command = cfg.authorized_keys_command.as_deref()?;
+    let user = match &cfg.authorized_keys_command_user {
+        Some(user) => user,
+        None => {
+            error!("No user for AuthorizedKeysCommand specified, skipping");
+            return None;
+        }
+    };
+    let uid = pw.uid.to_string();
+    let home = pw.dir.to_string_lossy();
+    let fp = key.fingerprint(FpHash::Sha256);
+    let keytext = BASE64.encode(key.to_blob());
+    let conn_id = conn_id(conn);
+    let tokens = [
+        ('U', uid.as_str()),
+        ('u', pw.name.as_str()),
+        ('h', &home),
+        ('t', key.name()),
+        ('f', &fp),
+        ('k', &keytext),
+        ('C', &conn_id),
+    ];
+    let (runas, mut av) = prepare_command("AuthorizedKeysCommand", command, user, pw, &tokens)?;
+    // 没有参数时沿用旧行为：传入用户名
+    if av.len() == 1 {
+        av.push(pw.name.clone());
+    }
+    let out = match subprocess("AuthorizedKeysCommand", &av, &runas, cfg.command_timeout, cfg.command_max_output) {
+        Ok(out) => out,
+        Err(e) => {
+            error!("{}", e);
+            return None;
+        }
+    };
+    check_authkeys_file(pw, out.as_slice(), command, key, conn, auth_debug)
+}
+
+/// Check one authorized principals line, "[options] principal", against
+/// the certificate (auth_check_principals_line()). Returns the line's
+/// options if the certificate lists the principal.
+pub fn check_principals_line(
+    line: &str,
+    cert: &Certificate,
+    loc: &str,
+    auth_debug: &mut Vec<String>,
+) -> Option<SshAuthOpt> {
+    let line = line.trim_end_matches(['\n', ' ', '\t']);
+    // 行内有空白时，前面是选项
+    let principal = match line.rfind(' ').or_else(|| line.rfind('\t')) {
+        Some(i) => line[i..].trim_start_matches([' ', '\t']),
+        None => line,
+    };
+    let opts_text = if principal.len() == line.len() { "" } else { line };
+    let opts = match SshAuthOpt::parse(opts_text) {
+        Ok(opts) => opts,
+        Err(e) => {
+            debug!("{}: bad principals options: {}", loc, e);
+            auth_debug.push(format!("{}: bad principals options: {}", loc, e));
+            return None;
+        }
+    };
+    if !cert.principals.iter().any(|p| p == principal) {
+        return None;
+    }
+    debug!("{}: matched principal \"{}\"", loc, principal);
+    Some(opts)
+}
+
+/// Check the principals read from `f`, named `file` in diagnostics
+/// (auth_process_principals()). Anything after a '#' is a comment.
+pub fn process_principals(
+    f: impl BufRead,
+    file: &str,
+    cert: &Certificate,
+    auth_debug: &mut Vec<String>,
+) -> Option<SshAuthOpt> {
+    for (i, line) in f.split(b'\n').enumerate() {
+        let line = match line {
+            Ok(line) => String::from_utf8_lossy(&line).into_owned(),
+            Err(e) => {
+                error!("{}: read: {}", file, e);
+                return None;
+            }
+        };
+        let line = line.trim_start_matches([' ', '\t']);
+        let line = line.split('#').next().unwrap_or_default();
+        if line.is_empty() {
+            continue;
+        }
+        let loc = format!("{}:{}", file, i + 1);
+        if let Some(opts) = check_principals_line(line, cert, &loc, auth_debug) {
+            return Some(opts);
+        }
+    }
+    None
+}
+
+/// Run AuthorizedPrincipalsCommand for a certificate and check the
+/// principals it prints (match_principals_command()). Returns the options
+/// of the line that matched.
+pub fn match_principals_command(
+    cfg: &AuthKeysConfig,
+    pw: &Passwd,
+    key: &PublicKey,
+    conn: &ConnInfo,
+    auth_debug: &mut Vec<String>,
+) -> Option<SshAuthOpt> {
+    let command = cfg.authorized_principals_command.as_deref()?;
+    let user = match &cfg.authorized_principals_command_user {
+        Some(user) => user,
+        None => {
+            error!("No user for AuthorizedPrincipalsCommand specified, skipping");
+            return None;
+        }
+    };
+    let cert = key.cert()?;
+    let ca = &cert.signature_key;
+    let uid = pw.uid.to_string();
+    let home = pw.dir.to_string_lossy();
+    let (fp, ca_fp) = (key.fingerprint(FpHash::Sha256), ca.fingerprint(FpHash::Sha256));
+    let (keytext, catext) = (BASE64.encode(key.to_blob()), BASE64.encode(ca.to_blob()));
+    let serial = cert.serial.to_string();
+    let conn_id = conn_id(conn);
+    let tokens = [
+        ('C', conn_id.as_str()),
+        ('U', &uid),
+        ('u', &pw.name),
+        ('h', &home),
+        ('t', key.name()),
+        ('T', ca.name()),
+        ('f', &fp),
+        ('F', &ca_fp),
+        ('k', &keytext),
+        ('K', &catext),
+        ('i', &cert.key_id),
+        ('s', &serial),
+    ];
+    let (runas, av) = prepare_command("AuthorizedPrincipalsCommand", command, user, pw, &tokens)?;
+    let out = match subprocess("AuthorizedPrincipalsCommand", &av, &runas, cfg.command_timeout, cfg.command_max_output) {
+        Ok(out) => out,
+        Err(e) => {
+            error!("{}", e);
+            return None;
+        }
+    };
+    process_principals(out.as_slice(), "(command)", cert, auth_debug)
+}
+
+/// Check the expanded AuthorizedPrincipalsFile for a certificate
+/// (match_principals_file()), returning the options of the line that
+/// matched.
+pub fn match_principals_file(
+    cfg: &AuthKeysConfig,
+    pw: &Passwd,
+    file: &Path,
+    cert: &Certificate,
+    auth_debug: &mut Vec<String>,
+) -> Option<SshAuthOpt> {
+    debug!("trying authorized principals file {}", file.display());
+    let f = open_principals_file(file, pw, cfg.strict_modes, auth_debug)?;
+    process_principals(BufReader::new(f), &file.display().to_string(), cert, auth_debug)
+}
+
+// sshkey_in_file()：逐行读取公钥，长度不受支持的密钥被跳过
+fn key_in_file(key: &PublicKey, path: &Path) -> Result<bool, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_start_matches([' ', '\t']);
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match PublicKey::from_openssh_line(line) {
+            Ok((listed, _)) if listed == *key => return Ok(true),
+            Ok(_) | Err(KeyError::KeyLength(_)) => {}
+            Err(e) => return Err(format!("line {}: {}", i + 1, e)),
+        }
+    }
+    Ok(false)
+}
+
+/// Whether a user certificate is accepted because its CA is listed in
+/// TrustedUserCAKeys (user_cert_trusted_ca()). If AuthorizedPrincipalsFile
+/// or AuthorizedPrincipalsCommand is set, one of the certificate's
+/// principals must be listed there, and the options on that line are
+/// checked and merged with the certificate's; otherwise the certificate
+/// must name the user. `loc` in the result is the CA keys file.
+pub fn user_cert_trusted_ca(
+    cfg: &AuthKeysConfig,
+    pw: &Passwd,
+    key: &PublicKey,
+    conn: &ConnInfo,
+    auth_debug: &mut Vec<String>,
+) -> Option<AuthorizedKey> {
+    let (cert, ca_keys) = match (key.cert(), &cfg.trusted_user_ca_keys) {
+        (Some(cert), Some(ca_keys)) => (cert, ca_keys),
+        _ => return None,
+    };
+    let ca = &cert.signature_key;
+    let ca_fp = ca.fingerprint(FpHash::Sha256);
+    match key_in_file(ca, ca_keys) {
+        Ok(true) => {}
+        Ok(false) => {
+            debug!("CA {} {} is not listed in {}", ca.type_name(), ca_fp, ca_keys.display());
+            return None;
+        }
+        Err(e) => {
+            debug!("CA {} {} is not listed in {}: {}", ca.type_name(), ca_fp, ca_keys.display(), e);
+            return None;
+        }
+    }
+
+    // 配置了 AuthorizedPrincipals 时，用其中的名字代替用户名匹配证书 principal
+    let principals_file = match cfg.authorized_principals_file.as_deref() {
+        Some(template) if !template.eq_ignore_ascii_case("none") => match expand_authorized_keys(template, pw) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                error!("AuthorizedPrincipalsFile {}: {}", template, e);
+                return None;
+            }
+        },
+        _ => None,
+    };
+    let mut principals_opts = principals_file.as_ref().and_then(|file| match_principals_file(cfg, pw, file, cert, auth_debug));
+    if principals_opts.is_none() {
+        principals_opts = match_principals_command(cfg, pw, key, conn, auth_debug);
+    }
+    let use_authorized_principals = principals_file.is_some() || cfg.authorized_principals_command.is_some();
+    if principals_opts.is_none() && use_authorized_principals {
+        return fail_reason(auth_debug, "Certificate does not contain an authorized principal");
+    }
+    let name = (!use_authorized_principals).then_some(pw.name.as_str());
+    if let Err(e) = cert.check_authority(false, true, false, name, now()) {
+        return fail_reason(auth_debug, &e.to_string());
+    }
+
+    let certopts = match SshAuthOpt::from_cert(cert) {
+        Ok(opts) => opts,
+        Err(_) => return fail_reason(auth_debug, "Invalid certificate options"),
+    };
+    if !authorise_keyopts(pw, &certopts, false, conn, "cert", auth_debug) {
+        return fail_reason(auth_debug, "Refused by certificate options");
+    }
+    let finalopts = match principals_opts {
+        None => certopts,
+        Some(principals_opts) => {
+            if !authorise_keyopts(pw, &principals_opts, false, conn, "principals", auth_debug) {
+                return fail_reason(auth_debug, "Refused by certificate principals options");
+            }
+            match SshAuthOpt::merge(&principals_opts, &certopts) {
+                Ok(opts) => opts,
+                Err(reason) => return fail_reason(auth_debug, &reason.to_string()),
+            }
+        }
+    };
+    info!(
+        "Accepted certificate ID \"{}\" (serial {}) signed by {} CA {} via {}",
+        cert.key_id,
+        cert.serial,
+        ca.type_name(),
+        ca_fp,
+        ca_keys.display()
+    );
+    Some(AuthorizedKey { loc: ca_keys.display().to_string(), key: ca.clone(), opts: finalopts })
+}
+
+/// Whether `pw` may log in with `key` (user_key_allowed()): it must not
+/// be revoked, and must be a certificate from a TrustedUserCAKeys CA or be
+/// accepted by one of the AuthorizedKeysFiles or by AuthorizedKeysCommand.
+/// Reasons for refusing keys that matched are added to `auth_debug`.
+pub fn user_key_allowed(
+    cfg: &AuthKeysConfig,
+    pw: &Passwd,
+    key: &PublicKey,
+    conn: &ConnInfo,
+    auth_debug: &mut Vec<String>,
+) -> Option<AuthorizedKey> {
+    if key_is_revoked(cfg, key) {
+        return None;
+    }
+    if let Some(cert) = key.cert() {
+        if key_is_revoked(cfg, &cert.signature_key) {
+            return None;
+        }
+    }
+    if let Some(found) = user_cert_trusted_ca(cfg, pw, key, conn, auth_debug) {
+        return Some(found);
+    }
+    for template in &cfg.authorized_keys_files {
+        if template.eq_ignore_ascii_case("none") {
+            continue;
+        }
+        let file = match expand_authorized_keys(template, pw) {
+            Ok(file) => file,
+            Err(e) => {
+                error!("AuthorizedKeysFile {}: {}", template, e);
+                continue;
+            }
+        };
+        if let Some(found) = check_authorized_keys_file(cfg, pw, &file, key, conn, auth_debug) {
+            return Some(found);
+        }
+    }
+    user_key_command_allowed(cfg, pw, key, conn, auth_debug)
+}
@@ -0,0 +1,1048 @@
+// bin/ssh-keygen.rs
+//
+// Key generation and management, after OpenSSH's ssh-keygen.c: creating
+// keys (-t/-b/-C/-f/-N), printing the public half of a private key (-y),
+// fingerprints and randomart (-l/-E/-v), changing passphrases (-p),
+// converting public keys to and from RFC 4716 and PEM (-e/-i/-m),
+// signing certificates (-s/-I/-n/-V/-O/-z/-h), generating and checking
+// key revocation lists (-k/-Q) and signing and verifying files with SSHSIG
+// signatures (-Y).
+
+use std::env;
+use std::error::Error;
+use std::ffi::CStr;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rust_openssh::addrmatch::addr_match_cidr_list;
+use base64::engine::general_purpose::STANDARD_NO_PAD as BASE64_NO_PAD;
+use base64::Engine;
+use sha1::{Digest, Sha1};
+
+use rust_openssh::auth_options::opt_match;
+use rust_openssh::krl::{self, Krl};
+use rust_openssh::log::{log_init, LogConfig, LogLevel};
+use rust_openssh::misc::{convtime, format_absolute_time, parse_absolute_time};
+use rust_openssh::sshkey::cert::SSHKEY_CERT_MAX_PRINCIPALS;
+use rust_openssh::sshkey::file::{change_passphrase, load_private, load_public, public_path, save_private, save_public};
+use rust_openssh::sshkey::format::{private_format, DEFAULT_CIPHERNAME, DEFAULT_ROUNDS};
+use rust_openssh::sshkey::*;
+use rust_openssh::sshsig::{self, SigError};
+
+// 带参数的选项（getopt 的 optstring 中后跟冒号的字母）
+const OPTS_WITH_ARG: &str = "abCEfImNnOPrstVYzZ";
+const OPTS_FLAG: &str = "ehiklpQquvy";
+
+/// Command line state (the globals of ssh-keygen.c).
+#[derive(Default)]
+struct Options {
+    key_type_name: Option<String>,
+    bits: Option<usize>,
+    comment: Option<String>,
+    identity_file: Option<PathBuf>,
+    new_passphrase: Option<String>,
+    old_passphrase: Option<String>,
+    fingerprint_hash: Option<FpHash>,
+    format: Option<PrivateKeyFormat>,
+    rounds: Option<u32>,
+    ciphername: Option<String>,
+    quiet: bool,
+    print_fingerprint: bool,
+    print_randomart: bool,
+    print_public: bool,
+    change_passphrase: bool,
+    convert_to: bool,
+    convert_from: bool,
+    // 证书签发
+    ca_key_path: Option<PathBuf>,
+    cert_key_id: Option<String>,
+    cert_principals: Option<String>,
+    cert_serial: u64,
+    cert_host: bool,
+    validity: Option<String>,
+    cert_options: Vec<String>,
+    // KRL：-f 为 KRL 文件，-s 为 CA 公钥，-z 为 KRL 版本，-C 为注释
+    gen_krl: bool,
+    check_krl: bool,
+    update_krl: bool,
+    // -Y 的操作名；-f、-I、-n、-s 与证书签发共用
+    sign_op: Option<String>,
+    /// -r: RevokedKeys file checked by -Y verify.
+    revoked_keys: Option<PathBuf>,
+    args: Vec<String>,
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: ssh-keygen [-q] [-a rounds] [-b bits] [-C comment] [-f output_keyfile]\n\
+         \x20                 [-N new_passphrase] [-t ecdsa | ed25519 | rsa] [-Z cipher]\n\
+         \x20      ssh-keygen -p [-a rounds] [-f keyfile] [-N new_passphrase] [-P old_passphrase]\n\
+         \x20      ssh-keygen -i [-f input_keyfile] [-m key_format]\n\
+         \x20      ssh-keygen -e [-f input_keyfile] [-m key_format]\n\
+         \x20      ssh-keygen -y [-f input_keyfile] [-P passphrase]\n\
+         \x20      ssh-keygen -l [-v] [-E fingerprint_hash] [-f input_keyfile]\n\
+         \x20      ssh-keygen -s ca_key -I certificate_identity [-h] [-n principals]\n\
+         \x20                 [-O option] [-V validity_interval] [-z serial_number] file ...\n\
+         \x20      ssh-keygen -k -f krl_file [-u] [-s ca_public] [-z version_number] file ...\n\
+         \x20      ssh-keygen -Q [-l] -f krl_file [file ...]\n\
+         \x20      ssh-keygen -Y find-principals -s signature_file -f allowed_signers_file\n\
+         \x20      ssh-keygen -Y match-principals -I signer_identity -f allowed_signers_file\n\
+         \x20      ssh-keygen -Y check-novalidate -n namespace -s signature_file\n\
+         \x20      ssh-keygen -Y sign -f key_file -n namespace [-O option] file ...\n\
+         \x20      ssh-keygen -Y verify -f allowed_signers_file -I signer_identity\n\
+         \x20                 -n namespace -s signature_file [-r krl_file] [-O option]"
+    );
+    process::exit(1);
+}
+
+fn fatal(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    process::exit(255);
+}
+
+fn parse_args(argv: &[String]) -> Options {
+    let mut opts = Options::default();
+    let mut i = 0;
+    // 与 BSD getopt 一样，遇到第一个非选项参数即停止
+    while i < argv.len() {
+        let arg = &argv[i];
+        if arg == "--" {
+            i += 1;
+            break;
+        }
+        if !arg.starts_with('-') || arg.len() == 1 {
+            break;
+        }
+        for (at, c) in arg[1..].char_indices() {
+            if OPTS_WITH_ARG.contains(c) {
+                let rest = &arg[2 + at..];
+                let value = if !rest.is_empty() {
+                    rest.to_string()
+                } else {
+                    i += 1;
+                    argv.get(i).cloned().unwrap_or_else(|| usage())
+                };
+                set_option(&mut opts, c, Some(value));
+                break;
+            } else if OPTS_FLAG.contains(c) {
+                set_option(&mut opts, c, None);
+            } else {
+                usage();
+            }
+        }
+        i += 1;
+    }
+    opts.args = argv[i..].to_vec();
+    opts
+}
+
+fn set_option(opts: &mut Options, c: char, value: Option<String>) {
+    let v = value.unwrap_or_default();
+    match c {
+        'a' => opts.rounds = Some(v.parse().unwrap_or_else(|_| fatal(&format!("Invalid number: {}", v)))),
+        'b' => opts.bits = Some(v.parse().unwrap_or_else(|_| fatal(&format!("Bits has bad value {}", v)))),
+        'C' => opts.comment = Some(v),
+        'E' => {
+            opts.fingerprint_hash =
+                Some(FpHash::from_name(&v).unwrap_or_else(|| fatal("Invalid hash algorithm")))
+        }
+        'f' => opts.identity_file = Some(PathBuf::from(v)),
+        'I' => opts.cert_key_id = Some(v),
+        'm' => {
+            opts.format =
+                Some(PrivateKeyFormat::from_name(&v).unwrap_or_else(|| fatal(&format!("Unsupported conversion format \"{}\"", v))))
+        }
+        'N' => opts.new_passphrase = Some(v),
+        'n' => opts.cert_principals = Some(v),
+        'O' => opts.cert_options.push(v),
+        'P' => opts.old_passphrase = Some(v),
+        'r' => opts.revoked_keys = Some(PathBuf::from(v)),
+        's' => opts.ca_key_path = Some(PathBuf::from(v)),
+        't' => opts.key_type_name = Some(v),
+        'V' => opts.validity = Some(v),
+        'Y' => opts.sign_op = Some(v),
+        'z' => opts.cert_serial = v.parse().unwrap_or_else(|_| fatal(&format!("Invalid serial number \"{}\"", v))),
+        'Z' => opts.ciphername = Some(v),
+        'e' => opts.convert_to = true,
+        'h' => opts.cert_host = true,
+        'i' => opts.convert_from = true,
+        'k' => opts.gen_krl = true,
+        'l' => opts.print_fingerprint = true,
+        'p' => opts.change_passphrase = true,
+        'Q' => opts.check_krl = true,
+        'q' => opts.quiet = true,
+        'u' => opts.update_krl = true,
+        'v' => opts.print_randomart = true,
+        'y' => opts.print_public = true,
+        _ => usage(),
+    }
+}
+
+// 当前用户的用户名与主目录
+fn passwd_entry() -> (String, PathBuf) {
+    let pw = unsafe { libc::getpwuid(libc::getuid()) };
+    if pw.is_null() {
+        fatal("No user exists for uid");
+    }
+    let (name, dir) = unsafe { (CStr::from_ptr((*pw).pw_name), CStr::from_ptr((*pw).pw_dir)) };
+    (name.to_string_lossy().into_owned(), PathBuf::from(dir.to_string_lossy().into_owned()))
+}
+
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+        fatal("gethostname failed");
+    }
+    let end = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..end]).into_owned()
+}
+
+fn read_line(prompt: &str) -> String {
+    print!("{}", prompt);
+    let _ = io::stdout().flush();
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line).unwrap_or(0) == 0 {
+        process::exit(1);
+    }
+    line.trim_end_matches(['\r', '\n']).to_string()
+}
+
+/// Prompt on the terminal with echo turned off (read_passphrase()); falls
+/// back to stdin when there is no terminal.
+fn read_passphrase(prompt: &str) -> String {
+    let tty = match OpenOptions::new().read(true).write(true).open("/dev/tty") {
+        Ok(tty) => tty,
+        Err(_) => {
+            eprint!("{}", prompt);
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line).unwrap_or_else(|_| fatal("read_passphrase: failed"));
+            return line.trim_end_matches(['\r', '\n']).to_string();
+        }
+    };
+    let fd = tty.as_raw_fd();
+    let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+    let have_termios = unsafe { libc::tcgetattr(fd, &mut saved) } == 0;
+    if have_termios {
+        let mut noecho = saved;
+        noecho.c_lflag &= !libc::ECHO;
+        unsafe { libc::tcsetattr(fd, libc::TCSAFLUSH, &noecho) };
+    }
+    let _ = (&tty).write_all(prompt.as_bytes());
+    let mut line = String::new();
+    let _ = io::BufReader::new(&tty).read_line(&mut line);
+    if have_termios {
+        unsafe { libc::tcsetattr(fd, libc::TCSAFLUSH, &saved) };
+    }
+    let _ = (&tty).write_all(b"\n");
+    line.trim_end_matches(['\r', '\n']).to_string()
+}
+
+// 目标文件已存在时询问是否覆盖
+fn confirm_overwrite(path: &Path) -> bool {
+    if !path.exists() {
+        return true;
+    }
+    println!("{} already exists.", path.display());
+    read_line("Overwrite (y/n)? ").starts_with(['y', 'Y'])
+}
+
+fn ask_filename(opts: &mut Options, prompt: &str) -> PathBuf {
+    if let Some(path) = &opts.identity_file {
+        return path.clone();
+    }
+    let name = match opts.key_type_name.as_deref().and_then(key_type_from_name) {
+        Some(KeyType::Ecdsa(_)) => "id_ecdsa",
+        Some(KeyType::Rsa) => "id_rsa",
+        _ => "id_ed25519",
+    };
+    let default = passwd_entry().1.join(".ssh").join(name);
+    let answer = read_line(&format!("{} ({}): ", prompt, default.display()));
+    let path = if answer.is_empty() { default } else { PathBuf::from(answer) };
+    opts.identity_file = Some(path.clone());
+    path
+}
+
+// -t 的参数：短名称或完整的 ssh 名称
+fn key_type_from_name(name: &str) -> Option<KeyType> {
+    match name.to_ascii_lowercase().as_str() {
+        "ed25519" => Some(KeyType::Ed25519),
+        "ecdsa" => Some(KeyType::Ecdsa(EcdsaCurve::NistP256)),
+        "rsa" => Some(KeyType::Rsa),
+        "ed25519-sk" => Some(KeyType::SkEd25519),
+        "ecdsa-sk" => Some(KeyType::SkEcdsa),
+        _ => KeyType::from_name(name),
+    }
+}
+
+/// Load a private key, asking for its passphrase if -P was not given and
+/// the key turns out to be encrypted (load_identity()).
+fn load_identity(path: &Path, passphrase: Option<&str>) -> Result<(PrivateKey, String), KeyError> {
+    match load_private(path, passphrase.unwrap_or("")) {
+        Err(KeyError::WrongPassphrase) if passphrase.is_none() => {
+            load_private(path, &read_passphrase(&format!("Enter passphrase for \"{}\": ", path.display())))
+        }
+        r => r,
+    }
+}
+
+fn ask_new_passphrase(prompt: &str) -> String {
+    loop {
+        let first = read_passphrase(prompt);
+        let second = read_passphrase("Enter same passphrase again: ");
+        if first == second {
+            return first;
+        }
+        eprintln!("Passphrases do not match.  Try again.");
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn do_gen_key(opts: &mut Options) {
+    let type_name = opts.key_type_name.clone().unwrap_or_else(|| "ed25519".to_string());
+    let ktype = key_type_from_name(&type_name).unwrap_or_else(|| fatal(&format!("unknown key type {}", type_name)));
+    if ktype.is_sk() {
+        fatal("Key enrollment failed: security keys are not supported");
+    }
+    let bits = match ktype {
+        KeyType::Ed25519 => None,
+        _ => opts.bits,
+    };
+    if !opts.quiet {
+        println!("Generating public/private {} key pair.", type_name);
+    }
+    let key = PrivateKey::generate(ktype, bits).unwrap_or_else(|e| fatal(&format!("sshkey_generate failed: {}", e)));
+
+    let default_file = opts.identity_file.is_none();
+    let path = ask_filename(opts, "Enter file in which to save the key");
+    if default_file {
+        if let Some(dir) = path.parent().filter(|d| !d.exists()) {
+            match DirBuilder::new().mode(0o700).create(dir) {
+                Ok(()) if !opts.quiet => println!("Created directory '{}'.", dir.display()),
+                Ok(()) => {}
+                Err(e) => eprintln!("Could not create directory '{}': {}", dir.display(), e),
+            }
+        }
+    }
+    if !confirm_overwrite(&path) {
+        process::exit(1);
+    }
+    let passphrase = match &opts.new_passphrase {
+        Some(p) => p.clone(),
+        None => ask_new_passphrase("Enter passphrase (empty for no passphrase): "),
+    };
+    let comment = match &opts.comment {
+        Some(c) => c.clone(),
+        None => format!("{}@{}", passwd_entry().0, hostname()),
+    };
+
+    let cipher = opts.ciphername.as_deref().unwrap_or(DEFAULT_CIPHERNAME);
+    if let Err(e) = save_private(&path, &key, &passphrase, &comment, cipher, opts.rounds.unwrap_or(DEFAULT_ROUNDS)) {
+        fatal(&format!("Saving key \"{}\" failed: {}", path.display(), e));
+    }
+    let public = key.public();
+    let pub_path = public_path(&path);
+    if let Err(e) = save_public(&pub_path, &public, &comment) {
+        fatal(&format!("Unable to save public key to {}: {}", pub_path.display(), e));
+    }
+
+    if !opts.quiet {
+        let hash = opts.fingerprint_hash.unwrap_or(FpHash::Sha256);
+        println!("Your identification has been saved in {}", path.display());
+        println!("Your public key has been saved in {}", pub_path.display());
+        println!("The key fingerprint is:");
+        println!("{} {}", public.fingerprint(hash), comment);
+        println!("The key's randomart image is:");
+        println!("{}", public.randomart(hash));
+    }
+}
+
+fn do_print_public(opts: &mut Options) {
+    let path = ask_filename(opts, "Enter file in which the key is");
+    let (key, comment) = load_identity(&path, opts.old_passphrase.as_deref())
+        .unwrap_or_else(|e| fatal(&format!("Load key \"{}\": {}", path.display(), e)));
+    println!("{}", key.public().to_openssh_line(&comment));
+}
+
+fn fingerprint_one_key(opts: &Options, key: &PublicKey, comment: &str) {
+    let hash = opts.fingerprint_hash.unwrap_or(FpHash::Sha256);
+    let comment = if comment.is_empty() { "no comment" } else { comment };
+    println!("{} {} {} ({})", key.bits(), key.fingerprint(hash), comment, key.type_name());
+    if opts.print_randomart {
+        println!("{}", key.randomart(hash));
+    }
+}
+
+fn do_fingerprint(opts: &mut Options) {
+    let path = ask_filename(opts, "Enter file in which the key is");
+    let text = fs::read_to_string(&path).unwrap_or_else(|e| fatal(&format!("{}: {}", path.display(), e)));
+    if private_format(&text).is_some() {
+        let (key, comment) = load_public(&path).unwrap_or_else(|e| fatal(&format!("{}: {}", path.display(), e)));
+        fingerprint_one_key(opts, &key, &comment);
+        return;
+    }
+
+    let mut found = false;
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // 普通公钥行，或前面带有选项/主机名/标记的 authorized_keys 与 known_hosts 行
+        let mut rest = line;
+        for _ in 0..3 {
+            if let Ok((key, comment)) = PublicKey::from_openssh_line(rest) {
+                fingerprint_one_key(opts, &key, &comment);
+                found = true;
+                break;
+            }
+            rest = advance_past_options(rest).unwrap_or("").trim_start();
+        }
+    }
+    if !found {
+        fatal(&format!("{} is not a public key file.", path.display()));
+    }
+}
+
+fn do_change_passphrase(opts: &mut Options) {
+    let path = ask_filename(opts, "Enter file in which the key is");
+    let old = match opts.old_passphrase.clone() {
+        Some(p) => p,
+        None => match load_private(&path, "") {
+            Ok(_) => String::new(),
+            Err(KeyError::WrongPassphrase) => read_passphrase("Enter old passphrase: "),
+            Err(e) => fatal(&format!("Failed to load key {}: {}", path.display(), e)),
+        },
+    };
+    if let Err(e) = load_private(&path, &old) {
+        fatal(&format!("Failed to load key {}: {}", path.display(), e));
+    }
+    let new = match &opts.new_passphrase {
+        Some(p) => p.clone(),
+        None => ask_new_passphrase("Enter new passphrase (empty for no passphrase): "),
+    };
+    let cipher = opts.ciphername.as_deref().unwrap_or(DEFAULT_CIPHERNAME);
+    if let Err(e) = change_passphrase(&path, &old, &new, cipher, opts.rounds.unwrap_or(DEFAULT_ROUNDS)) {
+        fatal(&format!("Saving key \"{}\" failed: {}.", path.display(), e));
+    }
+    println!("Your identification has been saved with the new passphrase.");
+}
+
+fn do_convert_to(opts: &mut Options) {
+    let path = ask_filename(opts, "Enter file in which the key is");
+    let (key, _) = load_public(&path).unwrap_or_else(|e| fatal(&format!("{}: {}", path.display(), e)));
+    let out = match opts.format.unwrap_or(PrivateKeyFormat::OpenSsh) {
+        PrivateKeyFormat::OpenSsh => {
+            // 与 ssh-keygen 相同，注释最长 60 个字符
+            let mut comment = format!(
+                "{}-bit {}, converted by {}@{} from OpenSSH",
+                key.bits(),
+                key.type_name(),
+                passwd_entry().0,
+                hostname()
+            );
+            comment.truncate(60);
+            Ok(key.to_rfc4716(&comment))
+        }
+        format => key.to_pem_public(format),
+    };
+    match out {
+        Ok(text) => print!("{}", text),
+        Err(e) => fatal(&format!("{}: {}", path.display(), e)),
+    }
+}
+
+fn do_convert_from(opts: &mut Options) {
+    let path = ask_filename(opts, "Enter file in which the key is");
+    let text = fs::read_to_string(&path).unwrap_or_else(|e| fatal(&format!("{}: {}", path.display(), e)));
+    let key = match opts.format.unwrap_or(PrivateKeyFormat::OpenSsh) {
+        PrivateKeyFormat::OpenSsh => PublicKey::from_rfc4716(&text),
+        PrivateKeyFormat::Pem | PrivateKeyFormat::Pkcs8 => PublicKey::from_pem_public(&text),
+    };
+    match key {
+        Ok(key) => println!("{}", key.to_openssh_line("")),
+        Err(e) => fatal(&format!("{}: {}", path.display(), e)),
+    }
+}
+
+/// -V: "from:to", where each side is an absolute time, a relative time
+/// prefixed with + or -, "always" or "forever"; a lone "+interval" starts
+/// a minute ago (parse_cert_times()).
+fn parse_cert_times(spec: &str, now: u64) -> (u64, u64) {
+    let invalid = || -> u64 { fatal(&format!("Invalid certificate life specification {}", spec)) };
+    let relative = |s: &str| -> u64 {
+        let secs = convtime(&s[1..]).unwrap_or_else(invalid);
+        if s.starts_with('+') {
+            now.checked_add(secs).unwrap_or_else(invalid)
+        } else {
+            now.saturating_sub(secs)
+        }
+    };
+    let absolute = |s: &str| -> u64 {
+        if let Some(hex) = s.strip_prefix("0x") {
+            return u64::from_str_radix(hex, 16).unwrap_or_else(|_| invalid());
+        }
+        parse_absolute_time(s).unwrap_or_else(invalid)
+    };
+
+    if spec.starts_with('+') && !spec.contains(':') {
+        return (now.saturating_sub(59) / 60 * 60, relative(spec));
+    }
+    let (from, to) = match spec.split_once(':') {
+        Some((from, to)) if !from.is_empty() && !to.is_empty() => (from, to),
+        _ => fatal(&format!("Invalid certificate life specification {}", spec)),
+    };
+    let from = match from {
+        "always" => 0,
+        f if f.starts_with(['+', '-']) => relative(f),
+        f => absolute(f),
+    };
+    let to = match to {
+        "forever" => u64::MAX,
+        t if t.starts_with(['+', '-']) => relative(t),
+        t => absolute(t),
+    };
+    if to <= from {
+        fatal(&format!("Empty certificate validity interval {}", spec));
+    }
+    (from, to)
+}
+
+// permit-*/no-* 开关与对应的证书扩展
+const FLAG_EXTENSIONS: &[(&str, &str)] = &[
+    ("x11-forwarding", "permit-X11-forwarding"),
+    ("agent-forwarding", "permit-agent-forwarding"),
+    ("port-forwarding", "permit-port-forwarding"),
+    ("pty", "permit-pty"),
+    ("user-rc", "permit-user-rc"),
+];
+
+fn set_flag(options: &mut Vec<CertOption>, name: &str, on: bool) {
+    options.retain(|o| o.name != name);
+    if on {
+        options.push(CertOption::flag(name));
+    }
+}
+
+/// Apply the -O options to a user certificate's critical options and
+/// extensions (add_cert_option()). The names are those that
+/// auth_options::cert_option_list() understands, plus arbitrary
+/// "critical:name[=value]" and "extension:name[=value]" ones.
+fn apply_cert_options(options: &[String], critical: &mut Vec<CertOption>, extensions: &mut Vec<CertOption>) {
+    for opt in options {
+        let lower = opt.to_ascii_lowercase();
+        if lower == "clear" {
+            critical.clear();
+            extensions.clear();
+            continue;
+        }
+        let toggle = lower.strip_prefix("permit-").map(|s| (true, s)).or_else(|| lower.strip_prefix("no-").map(|s| (false, s)));
+        if let Some((on, ext)) = toggle.and_then(|(on, s)| FLAG_EXTENSIONS.iter().find(|(n, _)| *n == s).map(|(_, e)| (on, e))) {
+            set_flag(extensions, ext, on);
+            continue;
+        }
+
+        let (name, value) = match opt.split_once('=') {
+            Some((name, value)) => (name.to_ascii_lowercase(), Some(value)),
+            None => (lower.clone(), None),
+        };
+        match (name.as_str(), value) {
+            ("no-touch-required", None) => set_flag(extensions, "no-touch-required", true),
+            ("verify-required", None) => set_flag(critical, "verify-required", true),
+            ("force-command", Some(command)) => {
+                if critical.iter().any(|o| o.name == "force-command") {
+                    fatal("force-command already specified");
+                }
+                critical.push(CertOption::string("force-command", command));
+            }
+            ("source-address", Some(list)) => {
+                if critical.iter().any(|o| o.name == "source-address") {
+                    fatal("source-address already specified");
+                }
+                if addr_match_cidr_list(None, list) == -1 {
+                    fatal("Invalid source-address list");
+                }
+                critical.push(CertOption::string("source-address", list));
+            }
+            _ if lower.starts_with("critical:") || lower.starts_with("extension:") => {
+                let spec = &opt[opt.find(':').unwrap() + 1..];
+                let option = match spec.split_once('=') {
+                    Some((name, value)) => CertOption::string(name, value),
+                    None => CertOption::flag(spec),
+                };
+                if option.name.is_empty() {
+                    fatal(&format!("Invalid certificate option \"{}\"", opt));
+                }
+                let list = if lower.starts_with("critical:") { &mut *critical } else { &mut *extensions };
+                if list.iter().any(|o| o.name == option.name) {
+                    fatal(&format!("Duplicate certificate extension \"{}\"", option.name));
+                }
+                list.push(option);
+            }
+            _ => fatal(&format!("Unsupported certificate option \"{}\"", opt)),
+        }
+    }
+    // 证书中的选项按名称排序
+    critical.sort_by(|a, b| a.name.cmp(&b.name));
+    extensions.sort_by(|a, b| a.name.cmp(&b.name));
+}
+
+fn fmt_validity(from: u64, to: u64) -> String {
+    match (from, to) {
+        (0, u64::MAX) => "forever".to_string(),
+        (0, to) => format!("before {}", format_absolute_time(to)),
+        (from, u64::MAX) => format!("after {}", format_absolute_time(from)),
+        (from, to) => format!("from {} to {}", format_absolute_time(from), format_absolute_time(to)),
+    }
+}
+
+// id_ed25519.pub → id_ed25519-cert.pub
+fn cert_path(path: &Path) -> PathBuf {
+    let s = path.to_string_lossy();
+    PathBuf::from(format!("{}-cert.pub", s.strip_suffix(".pub").unwrap_or(&s)))
+}
+
+fn do_ca_sign(opts: &Options, ca_path: &Path) {
+    let key_id = opts.cert_key_id.as_deref().unwrap_or_else(|| fatal("Must specify key id (-I) when certifying"));
+    if opts.args.is_empty() {
+        usage();
+    }
+    let (ca, _) = load_identity(ca_path, opts.old_passphrase.as_deref())
+        .unwrap_or_else(|e| fatal(&format!("Load key \"{}\": {}", ca_path.display(), e)));
+    // -t 指定签名算法，例如 rsa-sha2-256
+    let alg = opts.key_type_name.as_deref();
+    if let Some(alg) = alg {
+        if KeyType::from_sig_alg(alg) != Some(ca.key_type()) {
+            fatal(&format!("CA key type {} doesn't match specified {}", ca.key_type().short_name(), alg));
+        }
+    }
+
+    let principals: Vec<String> = match &opts.cert_principals {
+        Some(list) => list.split(',').map(str::to_string).collect(),
+        None => Vec::new(),
+    };
+    if principals.len() > SSHKEY_CERT_MAX_PRINCIPALS || principals.iter().any(String::is_empty) {
+        fatal(&format!("Invalid principal list \"{}\"", opts.cert_principals.as_deref().unwrap_or("")));
+    }
+    let (valid_after, valid_before) = match &opts.validity {
+        Some(spec) => parse_cert_times(spec, now()),
+        None => (0, u64::MAX),
+    };
+    let cert_type = if opts.cert_host { CertType::Host } else { CertType::User };
+
+    for arg in &opts.args {
+        let path = Path::new(arg);
+        let (key, comment) = load_public(path).unwrap_or_else(|e| fatal(&format!("load pubkey \"{}\": {}", arg, e)));
+        let mut req = CertRequest::new(key, cert_type, key_id);
+        req.serial = opts.cert_serial;
+        req.principals = principals.clone();
+        req.valid_after = valid_after;
+        req.valid_before = valid_before;
+        // 主机证书不带任何选项
+        if cert_type == CertType::User {
+            apply_cert_options(&opts.cert_options, &mut req.critical_options, &mut req.extensions);
+        } else {
+            req.extensions.clear();
+        }
+        let cert = req.sign(&ca, alg).unwrap_or_else(|e| fatal(&format!("Couldn't certify key {}: {}", arg, e)));
+
+        let out = cert_path(path);
+        let line = PublicKey::Cert(Box::new(cert));
+        if let Err(e) = save_public(&out, &line, &comment) {
+            fatal(&format!("Could not write certificate to \"{}\": {}", out.display(), e));
+        }
+        if !opts.quiet {
+            let for_principals = opts.cert_principals.as_deref().map(|p| format!(" for {}", p)).unwrap_or_default();
+            eprintln!(
+                "Signed {} key {}: id \"{}\" serial {}{} valid {}",
+                cert_type.name(),
+                out.display(),
+                key_id,
+                opts.cert_serial,
+                for_principals,
+                fmt_validity(valid_after, valid_before)
+            );
+        }
+    }
+}
+
+fn load_krl(path: &Path) -> Krl {
+    let data = fs::read(path).unwrap_or_else(|e| fatal(&format!("Unable to load KRL {}: {}", path.display(), e)));
+    Krl::from_blob(&data, &[]).unwrap_or_else(|e| fatal(&format!("Invalid KRL file {}: {}", path.display(), e)))
+}
+
+// strtoull(s, &ep, 0)：0x 开头为十六进制，0 开头为八进制
+fn parse_serial(s: &str) -> Option<u64> {
+    let (digits, radix) = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => (hex, 16),
+        None if s.len() > 1 && s.starts_with('0') => (&s[1..], 8),
+        None => (s, 10),
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    u64::from_str_radix(digits, radix).ok()
+}
+
+/// Apply one revocation spec file to `krl` (update_krl_from_file()). Each
+/// line is "serial: N[-M]", "id: key_id", "hash: SHA256:...", or a public
+/// key optionally prefixed by "key:", "sha1:" or "sha256:".
+fn update_krl_from_file(opts: &Options, file: &str, wild_ca: bool, ca: Option<&PublicKey>, krl: &mut Krl) {
+    let (name, text) = if file == "-" {
+        let mut text = String::new();
+        io::Read::read_to_string(&mut io::stdin(), &mut text).unwrap_or_else(|e| fatal(&format!("read stdin: {}", e)));
+        ("(standard input)", text)
+    } else {
+        (file, fs::read_to_string(file).unwrap_or_else(|e| fatal(&format!("fopen {}: {}", file, e))))
+    };
+    if !opts.quiet {
+        println!("Revoking from {}", name);
+    }
+    let strip_prefix = |line: &str, prefix: &str| -> Option<String> {
+        let head = line.get(..prefix.len())?;
+        head.eq_ignore_ascii_case(prefix).then(|| line[prefix.len()..].trim_start_matches([' ', '\t']).to_string())
+    };
+    let need_ca = |what: &str| {
+        if ca.is_none() && !wild_ca {
+            fatal(&format!("revoking certificates by {} requires specification of a CA key", what));
+        }
+    };
+    for (i, line) in text.lines().enumerate() {
+        let lnum = i + 1;
+        // 去掉注释与首尾空白
+        let line = line.split('#').next().unwrap().trim_matches([' ', '\t', '\r']);
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(spec) = strip_prefix(line, "serial:") {
+            need_ca("serial number");
+            let (lo, hi) = match spec.split_once('-') {
+                Some((lo, hi)) => (lo, Some(hi)),
+                None => (spec.as_str(), None),
+            };
+            let lo_serial = parse_serial(lo).unwrap_or_else(|| fatal(&format!("{}:{}: invalid serial \"{}\"", name, lnum, spec)));
+            let hi_serial = match hi {
+                None => lo_serial,
+                Some(hi) => {
+                    let hi_serial =
+                        parse_serial(hi).unwrap_or_else(|| fatal(&format!("{}:{}: invalid serial \"{}\"", name, lnum, hi)));
+                    if hi_serial <= lo_serial {
+                        fatal(&format!("{}:{}: invalid serial range {}:{}", name, lnum, lo_serial, hi_serial));
+                    }
+                    hi_serial
+                }
+            };
+            if let Err(e) = krl.revoke_cert_by_serial_range(ca, lo_serial, hi_serial) {
+                fatal(&format!("{}:{}: revoke serial failed: {}", name, lnum, e));
+            }
+        } else if let Some(key_id) = strip_prefix(line, "id:") {
+            need_ca("key ID");
+            krl.revoke_cert_by_key_id(ca, &key_id);
+        } else if let Some(hash) = strip_prefix(line, "hash:") {
+            let b64 = hash
+                .strip_prefix("SHA256:")
+                .unwrap_or_else(|| fatal(&format!("{}:{}: unsupported hash algorithm", name, lnum)));
+            let blob = BASE64_NO_PAD
+                .decode(b64.trim_end_matches('='))
+                .unwrap_or_else(|_| fatal(&format!("{}:{}: decode hash failed", name, lnum)));
+            if let Err(e) = krl.revoke_key_sha256(&blob) {
+                fatal(&format!("{}:{}: revoke key failed: {}", name, lnum, e));
+            }
+        } else {
+            let (how, spec) = ["key:", "sha1:", "sha256:"]
+                .iter()
+                .find_map(|p| strip_prefix(line, p).map(|rest| (*p, rest)))
+                .unwrap_or(("", line.to_string()));
+            let (key, _) = PublicKey::from_openssh_line(&spec)
+                .unwrap_or_else(|e| fatal(&format!("{}:{}: invalid key: {}", name, lnum, e)));
+            let r = match how {
+                "key:" => {
+                    krl.revoke_key_explicit(&key);
+                    Ok(())
+                }
+                "sha1:" => krl.revoke_key_sha1(&Sha1::digest(key.plain().to_blob())),
+                "sha256:" => krl.revoke_key_sha256(&key.fingerprint_raw(FpHash::Sha256)),
+                _ => krl.revoke_key(&key),
+            };
+            if let Err(e) = r {
+                fatal(&format!("{}:{}: revoke key failed: {}", name, lnum, e));
+            }
+        }
+    }
+}
+
+/// -k: create, or with -u update, the KRL named by -f from the revocation
+/// specs given as arguments (do_gen_krl()).
+fn do_gen_krl(opts: &Options) {
+    let path = opts.identity_file.as_deref().unwrap_or_else(|| fatal("KRL generation requires an output file"));
+    if opts.update_krl && !path.exists() {
+        fatal(&format!("KRL \"{}\" does not exist", path.display()));
+    }
+    // -s none：撤销任意 CA 签发的证书
+    let (wild_ca, ca) = match opts.ca_key_path.as_deref() {
+        Some(p) if p.as_os_str().eq_ignore_ascii_case("none") => (true, None),
+        Some(p) => {
+            let (ca, _) =
+                load_public(p).unwrap_or_else(|e| fatal(&format!("Cannot load CA public key {}: {}", p.display(), e)));
+            (false, Some(ca))
+        }
+        None => (false, None),
+    };
+    let mut krl = if opts.update_krl { load_krl(path) } else { Krl::new() };
+    if opts.cert_serial != 0 {
+        krl.krl_version = opts.cert_serial;
+    }
+    if let Some(comment) = &opts.comment {
+        krl.comment = comment.clone();
+    }
+    for file in &opts.args {
+        update_krl_from_file(opts, file, wild_ca, ca.as_ref(), &mut krl);
+    }
+    let blob = krl.to_blob(&[]).unwrap_or_else(|e| fatal(&format!("Couldn't generate KRL: {}", e)));
+    if let Err(e) = fs::write(path, blob) {
+        fatal(&format!("write {}: {}", path.display(), e));
+    }
+}
+
+/// -Q: report whether each key given is revoked by the KRL named by -f,
+/// after listing the KRL with -l (do_check_krl()). Exits 1 if any is.
+fn do_check_krl(opts: &Options) {
+    let path = opts.identity_file.as_deref().unwrap_or_else(|| fatal("KRL checking requires an input file"));
+    let krl = load_krl(path);
+    if opts.print_fingerprint {
+        print!("{}", krl.dump());
+    }
+    let mut any_revoked = false;
+    for arg in &opts.args {
+        let (key, comment) =
+            load_public(Path::new(arg)).unwrap_or_else(|e| fatal(&format!("Cannot load public key {}: {}", arg, e)));
+        // 与 sshkey_load_public() 一样，没有注释时用文件名
+        let comment = if comment.is_empty() { arg.as_str() } else { &comment };
+        let revoked = krl.is_revoked(&key);
+        println!("{} ({}): {}", arg, comment, if revoked { "REVOKED" } else { "ok" });
+        any_revoked |= revoked;
+    }
+    process::exit(any_revoked as i32);
+}
+
+/// -O options accepted with -Y (sig_process_opts()).
+struct SigOptions {
+    hashalg: Option<String>,
+    verify_time: u64,
+    print_pubkey: bool,
+}
+
+fn sig_process_opts(opts: &Options) -> SigOptions {
+    let mut ret = SigOptions { hashalg: None, verify_time: 0, print_pubkey: false };
+    for opt in &opts.cert_options {
+        let mut value = opt.as_str();
+        if opt_match(&mut value, "verify-time") {
+            ret.verify_time = parse_absolute_time(value)
+                .filter(|&t| t != 0)
+                .unwrap_or_else(|| fatal("Invalid \"verify-time\" option"));
+        } else if opt.eq_ignore_ascii_case("print-pubkey") {
+            ret.print_pubkey = true;
+        } else if opt_match(&mut value, "hashalg") {
+            ret.hashalg = Some(value.to_string());
+        } else {
+            fatal(&format!("Invalid option \"{}\"", opt));
+        }
+    }
+    if ret.verify_time == 0 {
+        ret.verify_time = now();
+    }
+    ret
+}
+
+fn too_few_arguments(op: &str, what: &str) -> ! {
+    eprintln!("Too few arguments for {}: missing {}", op, what);
+    process::exit(1);
+}
+
+fn read_signature(path: &Path) -> Result<Vec<u8>, SigError> {
+    sshsig::dearmor(&fs::read_to_string(path).map_err(SigError::Io)?)
+}
+
+/// -Y sign: write file.sig next to each file, or sign stdin to stdout when
+/// there are none (sig_sign()). -f may name the private key, its .pub or a
+/// certificate for it; the public key is what the signature records.
+fn sig_sign(opts: &Options, sig_opts: &SigOptions, namespace: &str) {
+    let keypath = opts.identity_file.as_deref().unwrap_or_else(|| too_few_arguments("sign", "key"));
+    let name = keypath.to_string_lossy();
+    let privpath = PathBuf::from(name.strip_suffix("-cert.pub").or_else(|| name.strip_suffix(".pub")).unwrap_or(&name));
+    let (signer, _) = load_public(keypath).unwrap_or_else(|e| fatal(&format!("Couldn't load public key {}: {}", name, e)));
+    let (key, _) = load_identity(&privpath, opts.old_passphrase.as_deref())
+        .unwrap_or_else(|e| fatal(&format!("Couldn't load private key {}: {}", privpath.display(), e)));
+    if signer.plain() != &key.public() {
+        fatal(&format!("Public key {} doesn't match private {}", name, privpath.display()));
+    }
+
+    let files = if opts.args.is_empty() { vec!["-".to_string()] } else { opts.args.clone() };
+    for file in &files {
+        let signed = if file == "-" {
+            sshsig::sign(&key, Some(&signer), sig_opts.hashalg.as_deref(), namespace, io::stdin().lock())
+        } else {
+            if !opts.quiet {
+                eprintln!("Signing file {}", file);
+            }
+            let f = fs::File::open(file).unwrap_or_else(|e| fatal(&format!("Cannot open {} for signing: {}", file, e)));
+            sshsig::sign(&key, Some(&signer), sig_opts.hashalg.as_deref(), namespace, f)
+        };
+        let armored = sshsig::armor(&signed.unwrap_or_else(|e| fatal(&format!("Signing {} failed: {}", file, e))));
+        if file == "-" {
+            print!("{}", armored);
+            continue;
+        }
+        let sigpath = PathBuf::from(format!("{}.sig", file));
+        if !confirm_overwrite(&sigpath) {
+            continue;
+        }
+        if let Err(e) = fs::write(&sigpath, armored) {
+            fatal(&format!("Cannot write {}: {}", sigpath.display(), e));
+        }
+        if !opts.quiet {
+            eprintln!("Write signature to {}", sigpath.display());
+        }
+    }
+}
+
+/// -Y verify and -Y check-novalidate: check the signature in -s over
+/// stdin and, for verify, that the allowed signers file lets the key sign
+/// for `principal` (sig_verify()).
+fn sig_verify(opts: &Options, sig_opts: &SigOptions, namespace: &str, allowed: Option<(&Path, &str)>) {
+    let op = if allowed.is_some() { "verify" } else { "check-novalidate" };
+    let sig_path = opts.ca_key_path.as_deref().unwrap_or_else(|| too_few_arguments(op, "signature file"));
+    let checked = (|| -> Result<PublicKey, Box<dyn Error>> {
+        let blob = read_signature(sig_path)?;
+        let (key, _) = sshsig::verify(&blob, io::stdin().lock(), namespace)?;
+        if let Some(path) = &opts.revoked_keys {
+            krl::check_revoked(&key, path)?;
+        }
+        if let Some((path, principal)) = allowed {
+            sshsig::check_allowed_keys(path, &key, principal, namespace, sig_opts.verify_time)?;
+        }
+        Ok(key)
+    })();
+    match checked {
+        Ok(key) => {
+            if !opts.quiet {
+                let fp = key.fingerprint(opts.fingerprint_hash.unwrap_or(FpHash::Sha256));
+                match allowed {
+                    Some((_, principal)) => println!(
+                        "Good \"{}\" signature for {} with {} key {}",
+                        namespace,
+                        principal,
+                        key.type_name(),
+                        fp
+                    ),
+                    None => println!("Good \"{}\" signature with {} key {}", namespace, key.type_name(), fp),
+                }
+            }
+            if sig_opts.print_pubkey {
+                println!("{}", key.to_openssh_line(""));
+            }
+        }
+        Err(e) => {
+            if !matches!(e.downcast_ref(), Some(SigError::KeyNotFound)) {
+                eprintln!("Signature verification failed: {}", e);
+            }
+            if !opts.quiet {
+                println!("Could not verify signature.");
+            }
+            process::exit(255);
+        }
+    }
+}
+
+/// -Y find-principals: print the principals the allowed signers file
+/// accepts the signing key for; the signature itself is not checked
+/// (sig_find_principals()).
+fn sig_find_principals(opts: &Options, sig_opts: &SigOptions) {
+    let sig_path = opts.ca_key_path.as_deref().unwrap_or_else(|| too_few_arguments("find-principals", "signature file"));
+    let allowed =
+        opts.identity_file.as_deref().unwrap_or_else(|| too_few_arguments("find-principals", "allowed keys file"));
+    let found = read_signature(sig_path)
+        .and_then(|blob| sshsig::signature_key(&blob))
+        .and_then(|key| sshsig::find_principals(allowed, &key, sig_opts.verify_time));
+    match found {
+        // 每行输出一个 principal
+        Ok(principals) => principals.split(',').for_each(|p| println!("{}", p)),
+        Err(SigError::KeyNotFound) => fatal("No principal matched."),
+        Err(e) => fatal(&format!("sshsig_find_principals: {}", e)),
+    }
+}
+
+/// -Y match-principals: print the principals fields matching -I
+/// (sig_match_principals()).
+fn sig_match_principals(opts: &Options) {
+    let principal = opts.cert_key_id.as_deref().unwrap_or_else(|| too_few_arguments("match-principals", "principal ID"));
+    let allowed =
+        opts.identity_file.as_deref().unwrap_or_else(|| too_few_arguments("match-principals", "allowed keys file"));
+    match sshsig::match_principals(allowed, principal) {
+        Ok(found) => found.iter().for_each(|p| println!("{}", p)),
+        Err(SigError::KeyNotFound) => fatal("No principal matched."),
+        Err(e) => fatal(&format!("sshsig_match_principals: {}", e)),
+    }
+}
+
+fn do_sign_op(opts: &Options, op: &str) {
+    let sig_opts = sig_process_opts(opts);
+    let namespace = || opts.cert_principals.as_deref().unwrap_or_else(|| too_few_arguments(op, "namespace"));
+    match op {
+        "find-principals" => sig_find_principals(opts, &sig_opts),
+        "match-principals" => sig_match_principals(opts),
+        "sign" => sig_sign(opts, &sig_opts, namespace()),
+        "check-novalidate" => sig_verify(opts, &sig_opts, namespace(), None),
+        "verify" => {
+            let allowed = opts.identity_file.as_deref().unwrap_or_else(|| too_few_arguments(op, "allowed keys file"));
+            let principal = opts.cert_key_id.as_deref().unwrap_or_else(|| too_few_arguments(op, "principal ID"));
+            sig_verify(opts, &sig_opts, namespace(), Some((allowed, principal)));
+        }
+        _ => {
+            eprintln!("Unsupported operation for -Y: \"{}\"", op);
+            usage();
+        }
+    }
+}
+
+fn main() {
+    let argv: Vec<String> = env::args().skip(1).collect();
+    let mut opts = parse_args(&argv);
+    let config = LogConfig {
+        ident: "ssh-keygen".to_string(),
+        level: if opts.quiet { LogLevel::Quiet } else { LogLevel::Info },
+        on_stderr: true,
+        ..Default::default()
+    };
+    let _ = log_init(config);
+
+    if let Some(op) = opts.sign_op.clone() {
+        do_sign_op(&opts, &op);
+    } else if opts.gen_krl {
+        do_gen_krl(&opts);
+    } else if opts.check_krl {
+        do_check_krl(&opts);
+    } else if let Some(ca) = opts.ca_key_path.clone() {
+        do_ca_sign(&opts, &ca);
+    } else if !opts.args.is_empty() {
+        usage();
+    } else if opts.print_fingerprint {
+        do_fingerprint(&mut opts);
+    } else if opts.change_passphrase {
+        do_change_passphrase(&mut opts);
+    } else if opts.convert_to {
+        do_convert_to(&mut opts);
+    } else if opts.convert_from {
+        do_convert_from(&mut opts);
+    } else if opts.print_public {
+        do_print_public(&mut opts);
+    } else {
+        do_gen_key(&mut opts);
+    }
+}
@@ -0,0 +1,63 @@
+// bwlimit.rs
+//
+// Token-bucket bandwidth limiter for scp/sftp's `-l limit` (Kbit/s),
+// replacing misc.c's bandwidth_limit(). Meant to be driven from an
+// atomicio callback: each call accounts for the bytes just moved and
+// sleeps long enough to keep the average rate under the limit.
+
+use std::ops::ControlFlow;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Bandwidth limiter; `burst` bytes may pass before throttling starts.
+pub struct BwLimit {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Option<Instant>,
+}
+
+impl BwLimit {
+    /// `limit_kbps` is in Kbit/s as given to `-l`; scp multiplies it by
+    /// 1024 to get bits per second. `burst` is usually the copy buffer size.
+    pub fn new(limit_kbps: u64, burst: usize) -> Self {
+        let burst = burst.max(1) as f64;
+        BwLimit {
+            rate: (limit_kbps.max(1) * 1024) as f64 / 8.0,
+            burst,
+            tokens: burst,
+            last: None,
+        }
+    }
+
+    /// Rate in bytes per second.
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Account for `n` bytes transferred at `now` and return how long the
+    /// caller has to wait before continuing.
+    pub fn delay(&mut self, n: usize, now: Instant) -> Duration {
+        if let Some(last) = self.last {
+            let elapsed = now.saturating_duration_since(last).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        }
+        self.last = Some(now);
+        self.tokens -= n as f64;
+        if self.tokens < 0.0 {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    /// Account for `n` bytes and sleep as needed; suitable as an atomicio
+    /// callback. Never aborts the transfer.
+    pub fn update(&mut self, n: usize) -> ControlFlow<()> {
+        let wait = self.delay(n, Instant::now());
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+        ControlFlow::Continue(())
+    }
+}
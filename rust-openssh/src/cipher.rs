@@ -0,0 +1,291 @@
+// cipher.rs
+//
+// Transport ciphers, after OpenSSH's cipher.c, cipher-chachapoly.c and the
+// AES-GCM support in cipher.c: chacha20-poly1305@openssh.com,
+// aes128/256-gcm@openssh.com and aes128/192/256-ctr. Each context handles
+// one direction and implements `PacketCipher`.
+
+use aes::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
+use aes::{Aes128, Aes192, Aes256};
+use aes_gcm::aead::{AeadInPlace, KeyInit};
+use aes_gcm::{Aes128Gcm, Aes256Gcm, Nonce, Tag};
+use chacha20::ChaCha20Legacy;
+use ctr::Ctr128BE;
+use poly1305::Poly1305;
+
+use crate::packet::{timingsafe_eq, Mode, NoneCipher, PacketCipher, PacketError};
+
+// 加密算法的种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    None,
+    ChachaPoly,
+    AesGcm,
+    AesCtr,
+}
+
+/// Static description of a cipher (struct sshcipher).
+#[derive(Debug)]
+pub struct CipherInfo {
+    pub name: &'static str,
+    pub block_size: usize,
+    pub key_len: usize,
+    pub iv_len: usize,
+    /// AEAD tag length, 0 for ciphers that need a MAC.
+    pub auth_len: usize,
+    kind: Kind,
+}
+
+impl CipherInfo {
+    pub fn is_aead(&self) -> bool {
+        self.auth_len != 0
+    }
+
+    /// Effective key strength in bytes (cipher_seclen()): chacha20-poly1305
+    /// carries two 256-bit keys but is only as strong as one.
+    pub fn seclen(&self) -> usize {
+        if self.kind == Kind::ChachaPoly {
+            32
+        } else {
+            self.key_len
+        }
+    }
+}
+
+const CIPHERS: &[CipherInfo] = &[
+    CipherInfo { name: "aes128-ctr", block_size: 16, key_len: 16, iv_len: 16, auth_len: 0, kind: Kind::AesCtr },
+    CipherInfo { name: "aes192-ctr", block_size: 16, key_len: 24, iv_len: 16, auth_len: 0, kind: Kind::AesCtr },
+    CipherInfo { name: "aes256-ctr", block_size: 16, key_len: 32, iv_len: 16, auth_len: 0, kind: Kind::AesCtr },
+    CipherInfo {
+        name: "aes128-gcm@openssh.com",
+        block_size: 16,
+        key_len: 16,
+        iv_len: 12,
+        auth_len: 16,
+        kind: Kind::AesGcm,
+    },
+    CipherInfo {
+        name: "aes256-gcm@openssh.com",
+        block_size: 16,
+        key_len: 32,
+        iv_len: 12,
+        auth_len: 16,
+        kind: Kind::AesGcm,
+    },
+    CipherInfo {
+        name: "chacha20-poly1305@openssh.com",
+        block_size: 8,
+        key_len: 64,
+        iv_len: 0,
+        auth_len: 16,
+        kind: Kind::ChachaPoly,
+    },
+    CipherInfo { name: "none", block_size: 8, key_len: 0, iv_len: 0, auth_len: 0, kind: Kind::None },
+];
+
+pub fn cipher_by_name(name: &str) -> Option<&'static CipherInfo> {
+    CIPHERS.iter().find(|c| c.name == name)
+}
+
+/// Comma-separated list of supported ciphers (cipher_alg_list()).
+pub fn cipher_alg_list() -> String {
+    CIPHERS.iter().map(|c| c.name).collect::<Vec<_>>().join(",")
+}
+
+/// True if every name in the comma-separated `names` is a known cipher.
+pub fn ciphers_valid(names: &str) -> bool {
+    !names.is_empty() && names.split(',').all(|n| cipher_by_name(n).is_some())
+}
+
+/// Create a cipher context for one direction; `Mode::Out` encrypts.
+pub fn cipher_init(name: &str, key: &[u8], iv: &[u8], mode: Mode) -> Result<Box<dyn PacketCipher>, PacketError> {
+    let info = cipher_by_name(name).ok_or_else(|| PacketError::Protocol(format!("unknown cipher {}", name)))?;
+    if key.len() < info.key_len || iv.len() < info.iv_len {
+        return Err(PacketError::Protocol(format!("{}: key or IV too short", name)));
+    }
+    let (key, iv) = (&key[..info.key_len], &iv[..info.iv_len]);
+    let encrypt = mode == Mode::Out;
+    Ok(match info.kind {
+        Kind::None => Box::new(NoneCipher),
+        Kind::ChachaPoly => Box::new(ChachaPoly::new(key, encrypt)),
+        Kind::AesGcm => Box::new(AesGcm::new(key, iv, encrypt)),
+        Kind::AesCtr => Box::new(AesCtr::new(key, iv)),
+    })
+}
+
+/// chacha20-poly1305@openssh.com (PROTOCOL.chacha20poly1305). The first
+/// 32 bytes of the key encrypt the payload, the second 32 bytes encrypt
+/// the length; the sequence number is the nonce and the Poly1305 key is
+/// the first keystream block of the main key.
+pub struct ChachaPoly {
+    main_key: [u8; 32],
+    header_key: [u8; 32],
+    encrypt: bool,
+}
+
+const POLY1305_TAGLEN: usize = 16;
+
+impl ChachaPoly {
+    pub fn new(key: &[u8], encrypt: bool) -> ChachaPoly {
+        let mut main_key = [0u8; 32];
+        let mut header_key = [0u8; 32];
+        main_key.copy_from_slice(&key[..32]);
+        header_key.copy_from_slice(&key[32..64]);
+        ChachaPoly { main_key, header_key, encrypt }
+    }
+
+    fn stream(key: &[u8; 32], seqnr: u32) -> ChaCha20Legacy {
+        let nonce = u64::from(seqnr).to_be_bytes();
+        ChaCha20Legacy::new(key.into(), &nonce.into())
+    }
+}
+
+impl PacketCipher for ChachaPoly {
+    fn block_size(&self) -> usize {
+        8
+    }
+
+    fn auth_len(&self) -> usize {
+        POLY1305_TAGLEN
+    }
+
+    fn packet_length(&mut self, seqnr: u32, head: &[u8]) -> u32 {
+        let mut len = [head[0], head[1], head[2], head[3]];
+        Self::stream(&self.header_key, seqnr).apply_keystream(&mut len);
+        u32::from_be_bytes(len)
+    }
+
+    fn crypt(&mut self, seqnr: u32, buf: &mut [u8], aadlen: usize, len: usize) -> Result<(), PacketError> {
+        let end = aadlen + len;
+        let mut main = Self::stream(&self.main_key, seqnr);
+        let mut poly_key = [0u8; 32];
+        main.apply_keystream(&mut poly_key);
+        let poly = Poly1305::new((&poly_key).into());
+
+        // 解密前先校验标签
+        if !self.encrypt {
+            let expected = poly.clone().compute_unpadded(&buf[..end]);
+            if !timingsafe_eq(&expected[..], &buf[end..end + POLY1305_TAGLEN]) {
+                return Err(PacketError::MacInvalid);
+            }
+        }
+        if aadlen != 0 {
+            Self::stream(&self.header_key, seqnr).apply_keystream(&mut buf[..aadlen]);
+        }
+        // 负载从第 1 个分组开始，第 0 个分组用于生成 Poly1305 密钥
+        main.seek(64u64);
+        main.apply_keystream(&mut buf[aadlen..end]);
+        if self.encrypt {
+            let tag = poly.compute_unpadded(&buf[..end]);
+            buf[end..end + POLY1305_TAGLEN].copy_from_slice(&tag);
+        }
+        Ok(())
+    }
+}
+
+enum GcmKey {
+    Aes128(Box<Aes128Gcm>),
+    Aes256(Box<Aes256Gcm>),
+}
+
+/// aes128-gcm@openssh.com and aes256-gcm@openssh.com (RFC 5647 §7.1):
+/// packet_length is authenticated but not encrypted, and the low 64 bits
+/// of the IV count packets.
+pub struct AesGcm {
+    key: GcmKey,
+    iv: [u8; 12],
+    encrypt: bool,
+}
+
+impl AesGcm {
+    pub fn new(key: &[u8], iv: &[u8], encrypt: bool) -> AesGcm {
+        let key = match key.len() {
+            16 => GcmKey::Aes128(Box::new(Aes128Gcm::new_from_slice(key).expect("16-byte key"))),
+            _ => GcmKey::Aes256(Box::new(Aes256Gcm::new_from_slice(key).expect("32-byte key"))),
+        };
+        let mut fixed = [0u8; 12];
+        fixed.copy_from_slice(&iv[..12]);
+        AesGcm { key, iv: fixed, encrypt }
+    }
+
+    // 每个包之后调用计数器加一（EVP_CTRL_GCM_IV_GEN）
+    fn next_iv(&mut self) {
+        let mut counter = [0u8; 8];
+        counter.copy_from_slice(&self.iv[4..]);
+        let counter = u64::from_be_bytes(counter).wrapping_add(1);
+        self.iv[4..].copy_from_slice(&counter.to_be_bytes());
+    }
+}
+
+impl PacketCipher for AesGcm {
+    fn block_size(&self) -> usize {
+        16
+    }
+
+    fn auth_len(&self) -> usize {
+        16
+    }
+
+    fn crypt(&mut self, _seqnr: u32, buf: &mut [u8], aadlen: usize, len: usize) -> Result<(), PacketError> {
+        let nonce = Nonce::from(self.iv);
+        let (aad, rest) = buf.split_at_mut(aadlen);
+        let (data, tag) = rest.split_at_mut(len);
+        let tag = &mut tag[..16];
+        if self.encrypt {
+            let t = match &self.key {
+                GcmKey::Aes128(k) => k.encrypt_in_place_detached(&nonce, aad, data),
+                GcmKey::Aes256(k) => k.encrypt_in_place_detached(&nonce, aad, data),
+            }
+            .map_err(|_| PacketError::Protocol("AES-GCM encryption failed".to_string()))?;
+            tag.copy_from_slice(&t);
+        } else {
+            let t = Tag::from(<[u8; 16]>::try_from(&tag[..]).expect("16-byte tag"));
+            match &self.key {
+                GcmKey::Aes128(k) => k.decrypt_in_place_detached(&nonce, aad, data, &t),
+                GcmKey::Aes256(k) => k.decrypt_in_place_detached(&nonce, aad, data, &t),
+            }
+            .map_err(|_| PacketError::MacInvalid)?;
+        }
+        self.next_iv();
+        Ok(())
+    }
+}
+
+enum CtrState {
+    Aes128(Box<Ctr128BE<Aes128>>),
+    Aes192(Box<Ctr128BE<Aes192>>),
+    Aes256(Box<Ctr128BE<Aes256>>),
+}
+
+/// aes128/192/256-ctr (RFC 4344 §4): a keystream that runs on across
+/// packet boundaries, the IV being the initial 128-bit big-endian counter.
+pub struct AesCtr {
+    state: CtrState,
+}
+
+impl AesCtr {
+    pub fn new(key: &[u8], iv: &[u8]) -> AesCtr {
+        let state = match key.len() {
+            16 => CtrState::Aes128(Box::new(Ctr128BE::new_from_slices(key, iv).expect("key and IV length"))),
+            24 => CtrState::Aes192(Box::new(Ctr128BE::new_from_slices(key, iv).expect("key and IV length"))),
+            _ => CtrState::Aes256(Box::new(Ctr128BE::new_from_slices(key, iv).expect("key and IV length"))),
+        };
+        AesCtr { state }
+    }
+}
+
+impl PacketCipher for AesCtr {
+    fn block_size(&self) -> usize {
+        16
+    }
+
+    fn crypt(&mut self, _seqnr: u32, buf: &mut [u8], aadlen: usize, len: usize) -> Result<(), PacketError> {
+        let data = &mut buf[aadlen..aadlen + len];
+        match &mut self.state {
+            CtrState::Aes128(c) => c.apply_keystream(data),
+            CtrState::Aes192(c) => c.apply_keystream(data),
+            CtrState::Aes256(c) => c.apply_keystream(data),
+        }
+        Ok(())
+    }
+}
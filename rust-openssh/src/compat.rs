@@ -0,0 +1,89 @@
+// compat.rs
+//
+// Peer bug-compatibility flags keyed on the remote software version, as in
+// OpenSSH's compat.c. The first matching entry of the table wins.
+
+use log::debug;
+
+use crate::addrmatch::match_pattern_list;
+
+pub const SSH_BUG_UTF8TTYMODE: u32 = 0x0000_0001;
+pub const SSH_BUG_SIGTYPE: u32 = 0x0000_0002;
+pub const SSH_BUG_SIGTYPE74: u32 = 0x0000_0004;
+pub const SSH_OLD_SESSIONID: u32 = 0x0000_0010;
+pub const SSH_BUG_DEBUG: u32 = 0x0000_0040;
+pub const SSH_BUG_SCANNER: u32 = 0x0000_0800;
+pub const SSH_OLD_DHGEX: u32 = 0x0000_4000;
+pub const SSH_BUG_NOREKEY: u32 = 0x0000_8000;
+pub const SSH_BUG_EXTEOF: u32 = 0x0020_0000;
+pub const SSH_BUG_PROBE: u32 = 0x0040_0000;
+pub const SSH_OLD_FORWARD_ADDR: u32 = 0x0100_0000;
+pub const SSH_NEW_OPENSSH: u32 = 0x0400_0000;
+pub const SSH_BUG_DYNAMIC_RPORT: u32 = 0x0800_0000;
+pub const SSH_BUG_CURVE25519PAD: u32 = 0x1000_0000;
+pub const SSH_BUG_HOSTKEYS: u32 = 0x2000_0000;
+pub const SSH_BUG_DHGEX_LARGE: u32 = 0x4000_0000;
+
+// (模式列表, 标志)，顺序与 compat.c 一致
+const COMPAT_TABLE: &[(&str, u32)] = &[
+    (
+        "OpenSSH_2.*,OpenSSH_3.0*,OpenSSH_3.1*",
+        SSH_BUG_EXTEOF | SSH_OLD_FORWARD_ADDR | SSH_BUG_SIGTYPE,
+    ),
+    ("OpenSSH_3.*", SSH_OLD_FORWARD_ADDR | SSH_BUG_SIGTYPE),
+    ("Sun_SSH_1.0*", SSH_BUG_NOREKEY | SSH_BUG_EXTEOF | SSH_BUG_SIGTYPE),
+    ("OpenSSH_2*,OpenSSH_3*,OpenSSH_4*", SSH_BUG_SIGTYPE),
+    ("OpenSSH_5*", SSH_NEW_OPENSSH | SSH_BUG_DYNAMIC_RPORT | SSH_BUG_SIGTYPE),
+    ("OpenSSH_6.6.1*", SSH_NEW_OPENSSH | SSH_BUG_SIGTYPE),
+    (
+        "OpenSSH_6.5*,OpenSSH_6.6*",
+        SSH_NEW_OPENSSH | SSH_BUG_CURVE25519PAD | SSH_BUG_SIGTYPE,
+    ),
+    ("OpenSSH_7.4*", SSH_NEW_OPENSSH | SSH_BUG_SIGTYPE | SSH_BUG_SIGTYPE74),
+    (
+        "OpenSSH_7.0*,OpenSSH_7.1*,OpenSSH_7.2*,OpenSSH_7.3*,OpenSSH_7.5*,OpenSSH_7.6*,OpenSSH_7.7*",
+        SSH_NEW_OPENSSH | SSH_BUG_SIGTYPE,
+    ),
+    ("OpenSSH*", SSH_NEW_OPENSSH),
+    ("*MindTerm*", 0),
+    ("3.0.*", SSH_BUG_DEBUG),
+    ("3.0 SecureCRT*", SSH_OLD_SESSIONID),
+    ("1.7 SecureFX*", SSH_OLD_SESSIONID),
+    ("Cisco-1.*", SSH_BUG_DHGEX_LARGE | SSH_BUG_HOSTKEYS),
+    ("*SSH_Version_Mapper*", SSH_BUG_SCANNER),
+    (
+        "PuTTY_Local:*,PuTTY-Release-0.5*,PuTTY_Release_0.5*,PuTTY_Release_0.60*,\
+         PuTTY_Release_0.61*,PuTTY_Release_0.62*,PuTTY_Release_0.63*,PuTTY_Release_0.64*",
+        SSH_OLD_DHGEX,
+    ),
+    ("FuTTY*", SSH_OLD_DHGEX),
+    ("Probe-*", SSH_BUG_PROBE),
+    (
+        "TeraTerm SSH*,TTSSH/1.5.*,TTSSH/2.1*,TTSSH/2.2*,TTSSH/2.3*,TTSSH/2.4*,TTSSH/2.5*,\
+         TTSSH/2.6*,TTSSH/2.70*,TTSSH/2.71*,TTSSH/2.72*",
+        SSH_BUG_HOSTKEYS,
+    ),
+    (
+        "WinSCP_release_4*,WinSCP_release_5.0*,WinSCP_release_5.1,WinSCP_release_5.1.*,\
+         WinSCP_release_5.5,WinSCP_release_5.5.*,WinSCP_release_5.6,WinSCP_release_5.6.*,\
+         WinSCP_release_5.7,WinSCP_release_5.7.1,WinSCP_release_5.7.2,WinSCP_release_5.7.3,\
+         WinSCP_release_5.7.4",
+        SSH_OLD_DHGEX,
+    ),
+    ("ConfD-*", SSH_BUG_UTF8TTYMODE),
+    ("Twisted_*", 0),
+    ("Twisted*", SSH_BUG_DEBUG),
+];
+
+/// Compatibility flags for a remote software version string (the part of
+/// the banner after `SSH-2.0-`).
+pub fn compat_banner(version: &str) -> u32 {
+    for (pattern, bugs) in COMPAT_TABLE {
+        if match_pattern_list(version, pattern, false) == 1 {
+            debug!("match: {} pat {} compat 0x{:08x}", version, pattern, bugs);
+            return *bugs;
+        }
+    }
+    debug!("no match: {}", version);
+    0
+}
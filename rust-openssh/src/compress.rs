@@ -0,0 +1,165 @@
+// compress.rs
+//
+// Transport compression, after the zlib support in OpenSSH's packet.c:
+// "zlib" (RFC 4253 §6.2) starts with the new keys, "zlib@openssh.com"
+// waits until user authentication has succeeded. Each direction is one
+// zlib stream for the life of the connection, flushed after every packet
+// so the peer can decode it immediately.
+
+use flate2::{Compress, Decompress, FlushCompress, FlushDecompress, Status};
+
+use crate::packet::PacketError;
+
+const CHUNK: usize = 4096;
+// 与 Z_DEFAULT_COMPRESSION 一致
+const COMPRESSION_LEVEL: u32 = 6;
+
+/// Negotiated compression method for one direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    /// "zlib": active as soon as the keys are.
+    Zlib,
+    /// "zlib@openssh.com": active after user authentication.
+    Delayed,
+}
+
+impl Compression {
+    pub fn from_name(name: &str) -> Option<Compression> {
+        match name {
+            "none" => Some(Compression::None),
+            "zlib" => Some(Compression::Zlib),
+            "zlib@openssh.com" => Some(Compression::Delayed),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Zlib => "zlib",
+            Compression::Delayed => "zlib@openssh.com",
+        }
+    }
+
+    /// Whether the stream should run given the authentication state.
+    pub fn active(self, authenticated: bool) -> bool {
+        match self {
+            Compression::None => false,
+            Compression::Zlib => true,
+            Compression::Delayed => authenticated,
+        }
+    }
+}
+
+/// True if every name in the comma-separated `names` is a known method.
+pub fn compression_valid(names: &str) -> bool {
+    !names.is_empty() && names.split(',').all(|n| Compression::from_name(n).is_some())
+}
+
+/// Byte totals of one zlib stream: `raw` is the uncompressed side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    pub raw: u64,
+    pub compressed: u64,
+}
+
+impl CompressionStats {
+    /// Compressed size relative to the raw data, as in OpenSSH's
+    /// "compress outgoing: ... factor" debug line; 0.0 before any data.
+    pub fn factor(&self) -> f64 {
+        if self.raw == 0 {
+            0.0
+        } else {
+            self.compressed as f64 / self.raw as f64
+        }
+    }
+}
+
+/// Outgoing deflate stream (compress_buffer()).
+pub struct Compressor {
+    stream: Compress,
+}
+
+impl Default for Compressor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compressor {
+    pub fn new() -> Self {
+        Compressor { stream: Compress::new(flate2::Compression::new(COMPRESSION_LEVEL), true) }
+    }
+
+    /// Compress one payload with Z_PARTIAL_FLUSH.
+    pub fn compress(&mut self, input: &[u8]) -> Result<Vec<u8>, PacketError> {
+        let mut out = Vec::with_capacity(input.len() / 2 + 64);
+        let mut buf = [0u8; CHUNK];
+        let start = self.stream.total_in();
+        loop {
+            let consumed = (self.stream.total_in() - start) as usize;
+            let before = self.stream.total_out();
+            self.stream
+                .compress(&input[consumed..], &mut buf, FlushCompress::Partial)
+                .map_err(|e| PacketError::Protocol(format!("deflate: {}", e)))?;
+            let produced = (self.stream.total_out() - before) as usize;
+            out.extend_from_slice(&buf[..produced]);
+            // 输入已全部读入且输出未填满缓冲区，说明本次刷新已完成
+            if (self.stream.total_in() - start) as usize == input.len() && produced < CHUNK {
+                return Ok(out);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> CompressionStats {
+        CompressionStats { raw: self.stream.total_in(), compressed: self.stream.total_out() }
+    }
+}
+
+/// Incoming inflate stream (uncompress_buffer()). Output per packet is
+/// bounded so that a small compressed packet cannot expand without limit.
+pub struct Decompressor {
+    stream: Decompress,
+    max_output: usize,
+}
+
+impl Decompressor {
+    pub fn new(max_output: usize) -> Self {
+        Decompressor { stream: Decompress::new(true), max_output }
+    }
+
+    /// Inflate one packet payload with Z_SYNC_FLUSH.
+    pub fn decompress(&mut self, input: &[u8]) -> Result<Vec<u8>, PacketError> {
+        let mut out = Vec::new();
+        let mut buf = [0u8; CHUNK];
+        let start = self.stream.total_in();
+        loop {
+            let consumed = (self.stream.total_in() - start) as usize;
+            let before = self.stream.total_out();
+            let status = self
+                .stream
+                .decompress(&input[consumed..], &mut buf, FlushDecompress::Sync)
+                .map_err(|e| PacketError::Protocol(format!("inflate: {}", e)))?;
+            let produced = (self.stream.total_out() - before) as usize;
+            out.extend_from_slice(&buf[..produced]);
+            if out.len() > self.max_output {
+                return Err(PacketError::TooLarge(out.len()));
+            }
+            if status == Status::StreamEnd {
+                return Err(PacketError::Protocol("inflate: unexpected end of stream".to_string()));
+            }
+            let done = (self.stream.total_in() - start) as usize == input.len();
+            if done && produced < CHUNK {
+                return Ok(out);
+            }
+            if produced == 0 && self.stream.total_in() - start == consumed as u64 {
+                return Err(PacketError::Protocol("inflate: no progress".to_string()));
+            }
+        }
+    }
+
+    pub fn stats(&self) -> CompressionStats {
+        CompressionStats { raw: self.stream.total_out(), compressed: self.stream.total_in() }
+    }
+}
@@ -0,0 +1,465 @@
+// hostfile.rs
+//
+// The known_hosts database, after OpenSSH's hostfile.c. Each line lists
+// comma-separated host patterns, or a single "|1|salt|hash" HMAC-SHA1 of
+// the name when HashKnownHosts is on, followed by a public key, optionally
+// preceded by @cert-authority or @revoked. Non-standard ports are written
+// as "[host]:port". Lookups report whether a server's key is known, new,
+// changed or revoked; keys are appended as they are learnt, and
+// UpdateHostKeys rewrites a host's entries in place, keeping a backup.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use log::{debug, error, info};
+use sha1::Sha1;
+
+use crate::addrmatch::match_pattern_list;
+use crate::packet::random_bytes;
+use crate::sshkey::{FpHash, KeyType, PublicKey};
+
+/// Start of a hashed host name.
+pub const HASH_MAGIC: &str = "|1|";
+pub const HASH_DELIM: char = '|';
+/// Length of the salt and of the HMAC-SHA1 it keys.
+const HASH_LEN: usize = 20;
+
+/// The line matched the host name.
+pub const MATCH_HOST: u32 = 1;
+/// The line matched the address.
+pub const MATCH_IP: u32 = 1 << 1;
+/// The host name matched a hashed entry.
+pub const MATCH_HOST_HASHED: u32 = 1 << 2;
+/// The address matched a hashed entry.
+pub const MATCH_IP_HASHED: u32 = 1 << 3;
+
+/// Result of checking a server's key against the known keys (HostStatus).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostStatus {
+    /// The key, or the CA that signed the certificate, is known.
+    Ok,
+    /// Nothing is known for a key of this type.
+    New,
+    /// A different key of the same type is known.
+    Changed,
+    /// The key or its CA is marked @revoked.
+    Revoked,
+    /// A key of the wanted type was found (lookup by type).
+    Found,
+}
+
+/// The marker at the start of a line (HostkeyMarker).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Marker {
+    None,
+    /// @cert-authority: the key is a CA for host certificates.
+    CertAuthority,
+    /// @revoked: the key must never be accepted.
+    Revoked,
+}
+
+/// What hostkeys_foreach() made of a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineStatus {
+    /// A valid entry for another host, or read without a host.
+    Ok,
+    /// A valid entry matching the host or address.
+    Matched,
+    /// A line that could not be parsed.
+    Invalid,
+    /// A blank or comment line.
+    Comment,
+}
+
+/// One line of a known_hosts file (struct hostkey_foreach_line).
+#[derive(Debug, Clone)]
+pub struct HostKeyLine {
+    pub path: PathBuf,
+    pub linenum: usize,
+    pub status: LineStatus,
+    /// MATCH_* flags.
+    pub matched: u32,
+    /// The line as read, without its newline.
+    pub line: String,
+    pub marker: Marker,
+    /// The host field: patterns or a hashed name.
+    pub hosts: String,
+    pub key: Option<PublicKey>,
+    pub comment: String,
+}
+
+/// A known key for a host (struct hostkey_entry).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostKeyEntry {
+    /// The name it was looked up under.
+    pub host: String,
+    pub file: PathBuf,
+    pub line: usize,
+    pub key: PublicKey,
+    pub marker: Marker,
+}
+
+/// Keys known for a host, gathered from one or more files (struct hostkeys).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostKeys {
+    pub entries: Vec<HostKeyEntry>,
+}
+
+/// Hash `host` for a HashKnownHosts entry (host_hash()). A new random salt
+/// is used unless one is given; failing to obtain one is an error.
+pub fn host_hash(host: &str, salt: Option<&[u8]>) -> io::Result<String> {
+    match salt {
+        Some(salt) => Ok(hash_with_salt(host, salt)),
+        None => {
+            let mut fresh = [0u8; HASH_LEN];
+            random_bytes(&mut fresh)?;
+            Ok(hash_with_salt(host, &fresh))
+        }
+    }
+}
+
+fn hash_with_salt(host: &str, salt: &[u8]) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(salt).expect("HMAC takes any key length");
+    mac.update(host.as_bytes());
+    let hash = mac.finalize().into_bytes();
+    format!("{}{}{}{}", HASH_MAGIC, BASE64.encode(salt), HASH_DELIM, BASE64.encode(hash))
+}
+
+// extract_salt()：取出 "|1|salt|hash" 中的盐
+fn extract_salt(s: &str) -> Option<Vec<u8>> {
+    let rest = s.strip_prefix(HASH_MAGIC)?;
+    let (b64, _) = rest.split_once(HASH_DELIM)?;
+    let salt = BASE64.decode(b64).ok()?;
+    (salt.len() == HASH_LEN).then_some(salt)
+}
+
+/// The name known_hosts uses for a host and port (put_host_port()):
+/// "[host]:port" unless the port is the default.
+pub fn put_host_port(host: &str, port: u16) -> String {
+    if port == 0 || port == 22 {
+        host.to_string()
+    } else {
+        format!("[{}]:{}", host, port)
+    }
+}
+
+/// Match `host` against a line's host field (match_maybe_hashed()): a
+/// hashed name is compared after hashing with its salt, otherwise the
+/// field is a pattern list in which a negated match wins. None if the hash
+/// is malformed.
+pub fn match_maybe_hashed(host: &str, names: &str) -> Option<bool> {
+    if names.starts_with(HASH_DELIM) {
+        let salt = extract_salt(names)?;
+        return Some(hash_with_salt(host, &salt) == names);
+    }
+    Some(match_pattern_list(host, names, true) == 1)
+}
+
+// check_markers()：解析行首的 @ 标记并跳过其后的空白
+fn check_markers(line: &str) -> Option<(Marker, &str)> {
+    if !line.starts_with('@') {
+        return Some((Marker::None, line));
+    }
+    let (word, rest) = line.split_once([' ', '\t']).unwrap_or((line, ""));
+    let marker = match word {
+        "@cert-authority" => Marker::CertAuthority,
+        "@revoked" => Marker::Revoked,
+        _ => return None,
+    };
+    Some((marker, rest.trim_start_matches([' ', '\t'])))
+}
+
+/// Read every line of a known_hosts file (hostkeys_foreach()), passing
+/// each to `f`. With `host` (and `ip`), lines are matched against them
+/// and `status` and `matched` say which did.
+pub fn hostkeys_foreach(
+    path: &Path,
+    host: Option<&str>,
+    ip: Option<&str>,
+    mut f: impl FnMut(&HostKeyLine) -> io::Result<()>,
+) -> io::Result<()> {
+    let file = File::open(path)?;
+    for (i, line) in BufReader::new(file).split(b'\n').enumerate() {
+        let line = String::from_utf8_lossy(&line?).into_owned();
+        let mut l = HostKeyLine {
+            path: path.to_path_buf(),
+            linenum: i + 1,
+            status: LineStatus::Comment,
+            matched: 0,
+            line: line.trim_end_matches('\r').to_string(),
+            marker: Marker::None,
+            hosts: String::new(),
+            key: None,
+            comment: String::new(),
+        };
+        let text = l.line.trim_start_matches([' ', '\t']);
+        if text.is_empty() || text.starts_with('#') {
+            f(&l)?;
+            continue;
+        }
+        l.status = LineStatus::Invalid;
+        let (marker, text) = match check_markers(text) {
+            Some(m) => m,
+            None => {
+                info!("invalid marker at {}:{}", path.display(), l.linenum);
+                f(&l)?;
+                continue;
+            }
+        };
+        l.marker = marker;
+        let (hosts, text) = text.split_once([' ', '\t']).unwrap_or((text, ""));
+        l.hosts = hosts.to_string();
+
+        l.status = LineStatus::Ok;
+        if let Some(host) = host {
+            let wanted = [(Some(host), MATCH_HOST, MATCH_HOST_HASHED), (ip, MATCH_IP, MATCH_IP_HASHED)];
+            for (name, flag, hashed) in wanted {
+                let Some(name) = name else { continue };
+                match match_maybe_hashed(name, hosts) {
+                    Some(true) => {
+                        l.status = LineStatus::Matched;
+                        l.matched |= flag | if hosts.starts_with(HASH_DELIM) { hashed } else { 0 };
+                    }
+                    Some(false) => {}
+                    None => {
+                        debug!("{}:{}: bad host hash \"{:.32}\"", path.display(), l.linenum, hosts);
+                        l.status = LineStatus::Invalid;
+                        break;
+                    }
+                }
+            }
+        }
+        if l.status != LineStatus::Invalid {
+            match PublicKey::from_openssh_line(text.trim_start_matches([' ', '\t'])) {
+                Ok((key, comment)) => {
+                    l.key = Some(key);
+                    l.comment = comment;
+                }
+                Err(e) => {
+                    debug!("{}:{}: invalid key: {}", path.display(), l.linenum, e);
+                    l.status = LineStatus::Invalid;
+                }
+            }
+        }
+        f(&l)?;
+    }
+    Ok(())
+}
+
+// 比较密钥类型时不区分 ECDSA 曲线（sshkey_type_plain()）
+fn same_type(a: KeyType, b: KeyType) -> bool {
+    mem::discriminant(&a) == mem::discriminant(&b)
+}
+
+impl HostKeys {
+    pub fn new() -> HostKeys {
+        HostKeys::default()
+    }
+
+    /// Add the keys listed for `host` in the file at `path`
+    /// (load_hostkeys()). A missing file adds nothing.
+    pub fn load(&mut self, host: &str, path: &Path) -> io::Result<()> {
+        let r = hostkeys_foreach(path, Some(host), None, |l| {
+            if l.status != LineStatus::Matched {
+                return Ok(());
+            }
+            let key = l.key.clone().unwrap();
+            debug!("found key type {} in file {}:{}", key.type_name(), path.display(), l.linenum);
+            self.entries.push(HostKeyEntry {
+                host: host.to_string(),
+                file: path.to_path_buf(),
+                line: l.linenum,
+                key,
+                marker: l.marker,
+            });
+            Ok(())
+        });
+        match r {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                debug!("{}: {}", path.display(), e);
+                Ok(())
+            }
+            r => r,
+        }
+    }
+
+    // check_key_not_revoked()：吊销密钥本身，也吊销它签发的证书
+    fn is_revoked(&self, key: &PublicKey) -> bool {
+        self.entries.iter().filter(|e| e.marker == Marker::Revoked).any(|e| {
+            e.key.plain() == key.plain() || key.cert().is_some_and(|c| c.signature_key.plain() == e.key.plain())
+        })
+    }
+
+    /// Check a server's key (check_key_in_hostkeys()). A certificate is
+    /// known if its CA is listed with @cert-authority; a plain key if it
+    /// is listed without a marker, and changed if another key of its type
+    /// is. Also returns the entry that decided it, except for Revoked.
+    pub fn check_key(&self, key: &PublicKey) -> (HostStatus, Option<&HostKeyEntry>) {
+        let want_marker = if key.is_cert() { Marker::CertAuthority } else { Marker::None };
+        let mut status = HostStatus::New;
+        let mut found = None;
+        for e in self.entries.iter().filter(|e| e.marker == want_marker) {
+            if let Some(cert) = key.cert() {
+                if cert.signature_key.plain() == e.key.plain() {
+                    status = HostStatus::Ok;
+                    found = Some(e);
+                    break;
+                }
+            } else if same_type(e.key.key_type(), key.key_type()) {
+                if e.key == *key {
+                    status = HostStatus::Ok;
+                    found = Some(e);
+                    break;
+                }
+                status = HostStatus::Changed;
+                found = Some(e);
+            }
+        }
+        if self.is_revoked(key) {
+            return (HostStatus::Revoked, None);
+        }
+        (status, found)
+    }
+
+    /// Find a key of type `key_type` with `marker`
+    /// (lookup_key_in_hostkeys_by_type()); ECDSA keys must be on the same
+    /// curve.
+    pub fn lookup_by_type(&self, key_type: KeyType, marker: Marker) -> Option<&HostKeyEntry> {
+        self.entries.iter().find(|e| e.marker == marker && e.key.key_type() == key_type)
+    }
+
+    /// Whether any entry carries `marker` (lookup_marker_in_hostkeys()).
+    pub fn lookup_marker(&self, marker: Marker) -> Option<&HostKeyEntry> {
+        self.entries.iter().find(|e| e.marker == marker)
+    }
+}
+
+// write_host_entry()：散列时地址单独成行
+fn write_host_entry(out: &mut String, host: &str, ip: Option<&str>, key: &PublicKey, store_hash: bool) -> io::Result<()> {
+    let host = host.to_lowercase();
+    let name = match ip {
+        _ if store_hash => host_hash(&host, None)?,
+        Some(ip) => format!("{},{}", host, ip),
+        None => host,
+    };
+    out.push_str(&format!("{} {}\n", name, key.to_openssh_line("")));
+    if let (true, Some(ip)) = (store_hash, ip) {
+        write_host_entry(out, ip, None, key, true)?;
+    }
+    Ok(())
+}
+
+/// Append a key for `host` to a known_hosts file, creating it if needed
+/// (add_host_to_hostfile()). With `store_hash` the name is hashed.
+pub fn add_host_to_hostfile(path: &Path, host: &str, key: &PublicKey, store_hash: bool) -> io::Result<()> {
+    let mut f = OpenOptions::new().read(true).append(true).create(true).mode(0o644).open(path)?;
+    let mut entry = String::new();
+    // 确保文件以换行结尾
+    if f.seek(SeekFrom::End(-1)).is_ok() {
+        let mut last = [0u8];
+        f.read_exact(&mut last)?;
+        if last[0] != b'\n' {
+            entry.push('\n');
+        }
+    }
+    write_host_entry(&mut entry, host, None, key, store_hash)?;
+    f.write_all(entry.as_bytes())
+}
+
+/// Replace the keys known for `host` and `ip` with `keys`, as
+/// UpdateHostKeys does (hostfile_replace_entries()). Entries for the host
+/// with other keys are removed, though @cert-authority and @revoked lines
+/// are kept; keys not yet listed under both names are appended. A changed
+/// file is written to a temporary file and renamed into place, the old
+/// one being kept as "path.old". Returns whether anything changed.
+pub fn hostfile_replace_entries(
+    path: &Path,
+    host: &str,
+    ip: Option<&str>,
+    keys: &[PublicKey],
+    store_hash: bool,
+) -> io::Result<bool> {
+    let mut out = String::new();
+    let mut match_keys = vec![0u32; keys.len()];
+    let mut modified = false;
+    hostkeys_foreach(path, Some(host), ip, |l| {
+        match (&l.key, l.status, l.marker) {
+            (Some(key), LineStatus::Matched, Marker::None) => {
+                // 已有的密钥保留原行
+                if let Some(i) = keys.iter().position(|k| k == key) {
+                    match_keys[i] |= l.matched;
+                    out.push_str(&l.line);
+                    out.push('\n');
+                    debug!("{} key already at {}:{}", key.type_name(), l.path.display(), l.linenum);
+                } else {
+                    info!("{}:{}: Removed {} key for host {}", l.path.display(), l.linenum, key.type_name(), host);
+                    modified = true;
+                }
+            }
+            _ => {
+                if l.status == LineStatus::Invalid {
+                    info!("{}:{}: invalid known_hosts entry", l.path.display(), l.linenum);
+                }
+                out.push_str(&l.line);
+                out.push('\n');
+            }
+        }
+        Ok(())
+    })?;
+
+    let want = MATCH_HOST | if ip.is_some() { MATCH_IP } else { 0 };
+    for (key, &matched) in keys.iter().zip(&match_keys) {
+        let missing = want & !matched;
+        let what = if matched == 0 {
+            write_host_entry(&mut out, host, ip, key, store_hash)?;
+            "Adding new key"
+        } else if missing == MATCH_HOST {
+            write_host_entry(&mut out, host, None, key, store_hash)?;
+            "Fixing match (hostname)"
+        } else if missing == MATCH_IP {
+            write_host_entry(&mut out, ip.unwrap(), None, key, store_hash)?;
+            "Fixing match (address)"
+        } else {
+            continue;
+        };
+        let ip = ip.map(|ip| format!(",{}", ip)).unwrap_or_default();
+        let fp = key.fingerprint(FpHash::Sha256);
+        info!("{} for {}{} to {}: {} {}", what, host, ip, path.display(), key.name(), fp);
+        modified = true;
+    }
+    if !modified {
+        return Ok(false);
+    }
+
+    // 先写临时文件，保留旧文件为 .old，再原子地替换
+    let mut suffix = [0u8; 5];
+    random_bytes(&mut suffix)?;
+    let suffix: String = suffix.iter().map(|b| format!("{:02x}", b)).collect();
+    let temp = PathBuf::from(format!("{}.{}", path.display(), suffix));
+    let back = PathBuf::from(format!("{}.old", path.display()));
+    let mode = fs::metadata(path)?.permissions().mode() & 0o7777;
+    let r = (|| {
+        let mut f = OpenOptions::new().write(true).create_new(true).mode(0o600).open(&temp)?;
+        f.set_permissions(fs::Permissions::from_mode(mode))?;
+        f.write_all(out.as_bytes())?;
+        f.sync_all()?;
+        match fs::remove_file(&back) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        fs::hard_link(path, &back)?;
+        fs::rename(&temp, path)
+    })();
+    if let Err(e) = r {
+        error!("{}: {}", path.display(), e);
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    Ok(true)
+}
@@ -0,0 +1,192 @@
+// journald.rs
+//
+// systemd-journald backend for both log records and audit events, speaking
+// the native journal protocol over /run/systemd/journal/socket. When the
+// journal cannot be reached, entries are written to stderr instead.
+
+use std::io::{self, Write};
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::sync::Mutex;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::audit::{AuditBackend, AuditRecord};
+
+pub const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+const SYSLOG_IDENTIFIER: &str = "sshd";
+
+// syslog 优先级
+pub const LOG_ERR: u8 = 3;
+pub const LOG_WARNING: u8 = 4;
+pub const LOG_NOTICE: u8 = 5;
+pub const LOG_INFO: u8 = 6;
+pub const LOG_DEBUG: u8 = 7;
+
+/// Append one `KEY=value` field in journal native format. Values that
+/// contain a newline use the length-prefixed binary form.
+pub fn journal_append_field(buf: &mut Vec<u8>, key: &str, value: &str) {
+    buf.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(b'\n');
+}
+
+/// Connection to journald. Send failures fall back to stderr.
+pub struct JournalSink {
+    socket: Option<UnixDatagram>,
+}
+
+impl JournalSink {
+    /// Connect to the system journal socket.
+    pub fn open() -> Self {
+        Self::connect(JOURNAL_SOCKET)
+    }
+
+    /// Connect to a journal socket at `path`; tests bind their own.
+    pub fn connect<P: AsRef<Path>>(path: P) -> Self {
+        let socket = UnixDatagram::unbound().and_then(|s| s.connect(path).map(|_| s)).ok();
+        JournalSink { socket }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    /// Send one journal entry. `MESSAGE` should be among `fields`.
+    pub fn send(&self, fields: &[(&str, &str)]) -> io::Result<()> {
+        let mut buf = Vec::new();
+        for (key, value) in fields {
+            journal_append_field(&mut buf, key, value);
+        }
+        let sent = match &self.socket {
+            Some(socket) => socket.send(&buf).map(|_| ()),
+            None => Err(io::Error::new(io::ErrorKind::NotConnected, "journal socket unavailable")),
+        };
+        if sent.is_err() {
+            stderr_fallback(fields)?;
+        }
+        Ok(())
+    }
+}
+
+fn stderr_fallback(fields: &[(&str, &str)]) -> io::Result<()> {
+    let message = fields.iter().find(|(k, _)| *k == "MESSAGE").map(|(_, v)| *v).unwrap_or("");
+    let ident = fields
+        .iter()
+        .find(|(k, _)| *k == "SYSLOG_IDENTIFIER")
+        .map(|(_, v)| *v)
+        .unwrap_or(SYSLOG_IDENTIFIER);
+    writeln!(io::stderr().lock(), "{}[{}]: {}", ident, std::process::id(), message)
+}
+
+pub fn level_priority(level: Level) -> u8 {
+    match level {
+        Level::Error => LOG_ERR,
+        Level::Warn => LOG_WARNING,
+        Level::Info => LOG_INFO,
+        Level::Debug | Level::Trace => LOG_DEBUG,
+    }
+}
+
+/// `log` implementation forwarding records to journald.
+pub struct JournalLogger {
+    sink: Mutex<JournalSink>,
+    level: LevelFilter,
+}
+
+impl JournalLogger {
+    pub fn new(sink: JournalSink, level: LevelFilter) -> Self {
+        JournalLogger { sink: Mutex::new(sink), level }
+    }
+
+    /// Install a journald logger as the process-wide `log` backend.
+    pub fn init(level: LevelFilter) -> Result<(), log::SetLoggerError> {
+        log::set_boxed_logger(Box::new(JournalLogger::new(JournalSink::open(), level)))?;
+        log::set_max_level(level);
+        Ok(())
+    }
+}
+
+impl Log for JournalLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        let priority = level_priority(record.level()).to_string();
+        let line = record.line().map(|l| l.to_string()).unwrap_or_default();
+        let mut fields = vec![
+            ("MESSAGE", message.as_str()),
+            ("PRIORITY", priority.as_str()),
+            ("SYSLOG_IDENTIFIER", SYSLOG_IDENTIFIER),
+        ];
+        if let Some(file) = record.file() {
+            fields.push(("CODE_FILE", file));
+            fields.push(("CODE_LINE", line.as_str()));
+        }
+        if let Some(module) = record.module_path() {
+            fields.push(("CODE_FUNC", module));
+        }
+        let _ = self.sink.lock().unwrap().send(&fields);
+    }
+
+    fn flush(&self) {}
+}
+
+/// Audit backend emitting one structured journal entry per event.
+pub struct JournalAuditBackend {
+    sink: JournalSink,
+}
+
+impl JournalAuditBackend {
+    pub fn new(sink: JournalSink) -> Self {
+        JournalAuditBackend { sink }
+    }
+}
+
+fn audit_priority(event: &str) -> u8 {
+    match event {
+        "AUTH_SUCCESS" | "CONNECTION_FROM" | "CONNECTION_CLOSE" | "SESSION_OPEN" | "SESSION_CLOSE"
+        | "RUN_COMMAND" => LOG_INFO,
+        _ => LOG_NOTICE,
+    }
+}
+
+impl AuditBackend for JournalAuditBackend {
+    fn record(&mut self, rec: &AuditRecord) -> io::Result<()> {
+        let message = match rec.code {
+            Some(code) => format!("audit event euid {} user {} event {} ({})", rec.euid, rec.username(), code, rec.event),
+            None => format!("audit {} euid {} user {}", rec.event, rec.euid, rec.username()),
+        };
+        let priority = audit_priority(rec.event).to_string();
+        let port = rec.remote_port.map(|p| p.to_string());
+
+        let mut fields = vec![
+            ("MESSAGE", message.as_str()),
+            ("PRIORITY", priority.as_str()),
+            ("SYSLOG_IDENTIFIER", SYSLOG_IDENTIFIER),
+            ("SSH_EVENT", rec.event),
+            ("SSH_USER", rec.username()),
+        ];
+        let optional = [
+            ("SSH_REMOTE_ADDR", rec.remote_addr.as_deref()),
+            ("SSH_REMOTE_PORT", port.as_deref()),
+            ("SSH_AUTH_METHOD", rec.method.as_deref()),
+            ("SSH_SESSION_ID", rec.session_id.as_deref()),
+            ("SSH_TTY", rec.tty.as_deref()),
+            ("SSH_COMMAND", rec.command.as_deref()),
+        ];
+        fields.extend(optional.iter().filter_map(|(k, v)| v.map(|v| (*k, v))));
+        self.sink.send(&fields)
+    }
+}
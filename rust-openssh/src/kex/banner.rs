@@ -0,0 +1,234 @@
+// kex/banner.rs
+//
+// Protocol version exchange (RFC 4253 §4.2), after kex_exchange_identification()
+// in OpenSSH: send our `SSH-2.0-...` line, skip the peer's pre-banner lines,
+// parse its identification string and derive compatibility flags.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::os::unix::io::BorrowedFd;
+
+use log::debug;
+
+use crate::atomicio::{read_exact_atomic, read_exact_fd_until, write_all_atomic, AtomicioError, Deadline};
+use crate::compat::{compat_banner, SSH_BUG_PROBE, SSH_BUG_SCANNER};
+
+pub const SSH_VERSION: &str = "OpenSSH_9.9";
+pub const SSH_RELEASE: &str = "OpenSSH_9.9p1";
+/// Longest identification line, including the trailing CR LF.
+pub const SSH_MAX_BANNER_LEN: usize = 255;
+/// Lines a server may send before its identification string.
+pub const SSH_MAX_PRE_BANNER_LINES: usize = 1024;
+
+#[derive(Debug)]
+pub enum BannerError {
+    Io(io::Error),
+    ConnectionClosed,
+    TimedOut,
+    LineTooLong,
+    /// NUL byte, or CR not followed by LF.
+    InvalidCharacters,
+    TooManyPreambleLines,
+    BadIdentification(String),
+    ProtocolMismatch { major: u32, minor: u32 },
+    /// The peer was identified as a scanner or probe and is not served.
+    Rejected(&'static str),
+}
+
+impl fmt::Display for BannerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BannerError::Io(e) => write!(f, "banner exchange: {}", e),
+            BannerError::ConnectionClosed => f.write_str("Connection closed by remote host"),
+            BannerError::TimedOut => f.write_str("Connection timed out during banner exchange"),
+            BannerError::LineTooLong => f.write_str("banner line too long"),
+            BannerError::InvalidCharacters => f.write_str("banner line contains invalid characters"),
+            BannerError::TooManyPreambleLines => f.write_str("too many preamble lines"),
+            BannerError::BadIdentification(why) => write!(f, "Bad remote protocol version identification: {}", why),
+            BannerError::ProtocolMismatch { major, minor } => {
+                write!(f, "Protocol major versions differ: 2.0 vs. {}.{}", major, minor)
+            }
+            BannerError::Rejected(why) => f.write_str(why),
+        }
+    }
+}
+
+impl Error for BannerError {}
+
+impl From<io::Error> for BannerError {
+    fn from(e: io::Error) -> Self {
+        BannerError::Io(e)
+    }
+}
+
+impl From<AtomicioError> for BannerError {
+    fn from(e: AtomicioError) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => BannerError::ConnectionClosed,
+            io::ErrorKind::TimedOut => BannerError::TimedOut,
+            _ => BannerError::Io(e.into()),
+        }
+    }
+}
+
+/// Parsed peer identification string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerVersion {
+    /// The whole identification line without CR LF, as hashed by KEX.
+    pub banner: String,
+    pub major: u32,
+    pub minor: u32,
+    pub software: String,
+    pub comments: Option<String>,
+    /// `SSH_BUG_*` flags from the compat table.
+    pub compat: u32,
+}
+
+/// Our identification string without the trailing CR LF.
+pub fn local_version(comment: Option<&str>) -> String {
+    match comment {
+        Some(c) if !c.is_empty() => format!("SSH-2.0-{} {}", SSH_RELEASE, c),
+        _ => format!("SSH-2.0-{}", SSH_RELEASE),
+    }
+}
+
+/// Write an identification line followed by CR LF.
+pub fn send_banner<W: Write + ?Sized>(w: &mut W, version: &str) -> Result<(), BannerError> {
+    if version.len() + 2 > SSH_MAX_BANNER_LEN {
+        return Err(BannerError::LineTooLong);
+    }
+    write_all_atomic(w, format!("{}\r\n", version).as_bytes())?;
+    Ok(())
+}
+
+/// Parse `SSH-protoversion-softwareversion[ SP comments]`. Version 1.99
+/// is accepted as 2.0 (RFC 4253 §5.1).
+pub fn parse_banner(line: &str) -> Result<PeerVersion, BannerError> {
+    let bad = |why: &str| BannerError::BadIdentification(why.to_string());
+    let rest = line.strip_prefix("SSH-").ok_or_else(|| bad("missing SSH- prefix"))?;
+    let (proto, remote_version) = rest.split_once('-').ok_or_else(|| bad("missing software version"))?;
+    let (major, minor) = proto.split_once('.').ok_or_else(|| bad("malformed protocol version"))?;
+    let major: u32 = major.parse().map_err(|_| bad("malformed protocol version"))?;
+    let minor: u32 = minor.parse().map_err(|_| bad("malformed protocol version"))?;
+    if remote_version.is_empty() {
+        return Err(bad("missing software version"));
+    }
+
+    let (software, comments) = match remote_version.split_once(' ') {
+        Some((s, c)) => (s, Some(c.to_string())),
+        None => (remote_version, None),
+    };
+    debug!(
+        "Remote protocol version {}.{}, remote software version {:.100}",
+        major, minor, remote_version
+    );
+    if !(major == 2 || (major == 1 && minor == 99)) {
+        return Err(BannerError::ProtocolMismatch { major, minor });
+    }
+
+    // 注释部分也参与 compat 匹配，如 "3.0 SecureCRT*"
+    Ok(PeerVersion {
+        banner: line.to_string(),
+        major,
+        minor,
+        software: software.to_string(),
+        comments,
+        compat: compat_banner(remote_version),
+    })
+}
+
+// 读取一行（不含 CR LF），返回内容以及是否以 CR LF 结尾
+fn read_line(next: &mut dyn FnMut() -> Result<u8, BannerError>) -> Result<(Vec<u8>, bool), BannerError> {
+    let mut line = Vec::new();
+    let mut expect_nl = false;
+    loop {
+        let c = next()?;
+        match c {
+            b'\n' => return Ok((line, expect_nl)),
+            _ if expect_nl => return Err(BannerError::InvalidCharacters),
+            b'\r' => expect_nl = true,
+            0 => return Err(BannerError::InvalidCharacters),
+            _ => line.push(c),
+        }
+        if line.len() + 2 > SSH_MAX_BANNER_LEN {
+            return Err(BannerError::LineTooLong);
+        }
+    }
+}
+
+fn read_identification(
+    next: &mut dyn FnMut() -> Result<u8, BannerError>,
+    server: bool,
+) -> Result<PeerVersion, BannerError> {
+    for n in 0..SSH_MAX_PRE_BANNER_LINES {
+        let (line, crlf) = read_line(next)?;
+        let line = String::from_utf8_lossy(&line).into_owned();
+        if line.len() > 4 && line.starts_with("SSH-") {
+            // 其他行允许只以 LF 结尾，但标识行必须以 CR LF 结尾
+            if !crlf {
+                return Err(BannerError::BadIdentification("not terminated by CR LF".to_string()));
+            }
+            let peer = parse_banner(&line)?;
+            if server && peer.compat & SSH_BUG_PROBE != 0 {
+                return Err(BannerError::Rejected("probed by known SSH scanner"));
+            }
+            if peer.compat & SSH_BUG_SCANNER != 0 {
+                return Err(BannerError::Rejected("scanned by known SSH scanner"));
+            }
+            return Ok(peer);
+        }
+        // 客户端不得在标识行之前发送其他内容
+        if server {
+            return Err(BannerError::BadIdentification("client sent invalid protocol identifier".to_string()));
+        }
+        debug!("banner line {}: {}", n, line);
+    }
+    Err(BannerError::TooManyPreambleLines)
+}
+
+/// Read the peer's identification string from a blocking reader. A server
+/// rejects any line before it; a client skips up to
+/// `SSH_MAX_PRE_BANNER_LINES`.
+pub fn read_peer_banner<R: Read + ?Sized>(r: &mut R, server: bool) -> Result<PeerVersion, BannerError> {
+    let mut next = || {
+        let mut c = [0u8; 1];
+        read_exact_atomic(r, &mut c)?;
+        Ok(c[0])
+    };
+    read_identification(&mut next, server)
+}
+
+/// `read_peer_banner` on a descriptor, giving up with `TimedOut` at
+/// `deadline` (LoginGraceTime, ConnectTimeout).
+pub fn read_peer_banner_fd(fd: BorrowedFd, server: bool, deadline: &Deadline) -> Result<PeerVersion, BannerError> {
+    let mut next = || {
+        let mut c = [0u8; 1];
+        read_exact_fd_until(fd, &mut c, deadline, None)?;
+        Ok(c[0])
+    };
+    read_identification(&mut next, server)
+}
+
+/// Send `ours` and read the peer's identification. On a malformed or
+/// incompatible client banner a server tells the client why before
+/// returning the error.
+pub fn exchange_identification<S: Read + Write + ?Sized>(
+    s: &mut S,
+    server: bool,
+    ours: &str,
+) -> Result<PeerVersion, BannerError> {
+    send_banner(s, ours)?;
+    let res = read_peer_banner(s, server);
+    if server {
+        let notice: &[u8] = match &res {
+            Err(BannerError::BadIdentification(_)) => b"Invalid SSH identification string.\r\n",
+            Err(BannerError::ProtocolMismatch { .. }) => b"Protocol major versions differ.\r\n",
+            _ => b"",
+        };
+        if !notice.is_empty() {
+            let _ = write_all_atomic(s, notice);
+        }
+    }
+    res
+}
@@ -0,0 +1,65 @@
+// kex/c25519.rs
+//
+// curve25519-sha256 (RFC 8731), after OpenSSH's kexc25519.c. The X25519
+// half is shared with the post-quantum hybrids, which hash the raw shared
+// point instead of encoding it as an mpint.
+
+use std::io;
+
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use super::{encode_mpint, KexError};
+
+pub const CURVE25519_SIZE: usize = 32;
+
+pub struct C25519Key {
+    secret: StaticSecret,
+    pub public: [u8; CURVE25519_SIZE],
+}
+
+pub fn keypair(rng: &mut dyn FnMut(&mut [u8]) -> io::Result<()>) -> Result<C25519Key, KexError> {
+    let mut raw = [0u8; CURVE25519_SIZE];
+    rng(&mut raw).map_err(KexError::Rng)?;
+    let secret = StaticSecret::from(raw);
+    let public = PublicKey::from(&secret).to_bytes();
+    Ok(C25519Key { secret, public })
+}
+
+/// X25519 with the peer's point. An all-zero result means the peer sent a
+/// low-order point and is rejected.
+pub fn shared_key_raw(key: &C25519Key, peer: &[u8]) -> Result<[u8; CURVE25519_SIZE], KexError> {
+    let peer: [u8; CURVE25519_SIZE] = peer.try_into().map_err(|_| KexError::InvalidPublicKey)?;
+    let shared = key.secret.diffie_hellman(&PublicKey::from(peer)).to_bytes();
+    if shared == [0u8; CURVE25519_SIZE] {
+        return Err(KexError::InvalidPublicKey);
+    }
+    Ok(shared)
+}
+
+/// Client side: our ephemeral key, sent as Q_C.
+pub fn client_keypair(rng: &mut dyn FnMut(&mut [u8]) -> io::Result<()>) -> Result<(C25519Key, Vec<u8>), KexError> {
+    let key = keypair(rng)?;
+    let public = key.public.to_vec();
+    Ok((key, public))
+}
+
+/// Server side: returns Q_S and the encoded shared secret K.
+pub fn enc(
+    client_pub: &[u8],
+    rng: &mut dyn FnMut(&mut [u8]) -> io::Result<()>,
+) -> Result<(Vec<u8>, Vec<u8>), KexError> {
+    if client_pub.len() != CURVE25519_SIZE {
+        return Err(KexError::InvalidPublicKey);
+    }
+    let key = keypair(rng)?;
+    let shared = shared_key_raw(&key, client_pub)?;
+    Ok((key.public.to_vec(), encode_mpint(&shared)))
+}
+
+/// Client side: the encoded shared secret K from the server's Q_S.
+pub fn dec(key: &C25519Key, server_pub: &[u8]) -> Result<Vec<u8>, KexError> {
+    if server_pub.len() != CURVE25519_SIZE {
+        return Err(KexError::InvalidPublicKey);
+    }
+    Ok(encode_mpint(&shared_key_raw(key, server_pub)?))
+}
@@ -0,0 +1,300 @@
+// kex/dh.rs
+//
+// Finite-field Diffie-Hellman for the diffie-hellman-group*-sha* and
+// diffie-hellman-group-exchange-sha256 methods, after OpenSSH's dh.c: the
+// fixed RFC 3526 MODP groups, public value validation, the moduli file and
+// group selection for group exchange.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use log::{debug, warn};
+use num_bigint::BigUint;
+
+use super::KexError;
+
+/// Smallest group a client asks for or a server offers in group exchange.
+pub const DH_GRP_MIN: u32 = 2048;
+/// Preferred group size when nothing else says otherwise.
+pub const DH_GRP_MAX: u32 = 8192;
+pub const MODULI_PATH: &str = "/etc/ssh/moduli";
+
+// moduli 文件中的类型与测试位
+const MODULI_TYPE_SAFE: u32 = 2;
+const MODULI_TESTS_COMPOSITE: u32 = 0x01;
+
+// RFC 3526 2048-bit MODP group
+const GROUP14_PRIME: &str = concat!(
+    "FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74",
+    "020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F1437",
+    "4FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED",
+    "EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3DC2007CB8A163BF05",
+    "98DA48361C55D39A69163FA8FD24CF5F83655D23DCA3AD961C62F356208552BB",
+    "9ED529077096966D670C354E4ABC9804F1746C08CA18217C32905E462E36CE3B",
+    "E39E772C180E86039B2783A2EC07A28FB5C55DF06F4C52C9DE2BCBF695581718",
+    "3995497CEA956AE515D2261898FA051015728E5A8AACAA68FFFFFFFFFFFFFFFF",
+);
+
+// RFC 3526 4096-bit MODP group
+const GROUP16_PRIME: &str = concat!(
+    "FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74",
+    "020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F1437",
+    "4FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED",
+    "EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3DC2007CB8A163BF05",
+    "98DA48361C55D39A69163FA8FD24CF5F83655D23DCA3AD961C62F356208552BB",
+    "9ED529077096966D670C354E4ABC9804F1746C08CA18217C32905E462E36CE3B",
+    "E39E772C180E86039B2783A2EC07A28FB5C55DF06F4C52C9DE2BCBF695581718",
+    "3995497CEA956AE515D2261898FA051015728E5A8AAAC42DAD33170D04507A33",
+    "A85521ABDF1CBA64ECFB850458DBEF0A8AEA71575D060C7DB3970F85A6E1E4C7",
+    "ABF5AE8CDB0933D71E8C94E04A25619DCEE3D2261AD2EE6BF12FFA06D98A0864",
+    "D87602733EC86A64521F2B18177B200CBBE117577A615D6C770988C0BAD946E2",
+    "08E24FA074E5AB3143DB5BFCE0FD108E4B82D120A92108011A723C12A787E6D7",
+    "88719A10BDBA5B2699C327186AF4E23C1A946834B6150BDA2583E9CA2AD44CE8",
+    "DBBBC2DB04DE8EF92E8EFC141FBECAA6287C59474E6BC05D99B2964FA090C3A2",
+    "233BA186515BE7ED1F612970CEE2D7AFB81BDD762170481CD0069127D5B05AA9",
+    "93B4EA988D8FDDC186FFB7DC90A6C08F4DF435C934063199FFFFFFFFFFFFFFFF",
+);
+
+// RFC 3526 8192-bit MODP group
+const GROUP18_PRIME: &str = concat!(
+    "FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74",
+    "020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F1437",
+    "4FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED",
+    "EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3DC2007CB8A163BF05",
+    "98DA48361C55D39A69163FA8FD24CF5F83655D23DCA3AD961C62F356208552BB",
+    "9ED529077096966D670C354E4ABC9804F1746C08CA18217C32905E462E36CE3B",
+    "E39E772C180E86039B2783A2EC07A28FB5C55DF06F4C52C9DE2BCBF695581718",
+    "3995497CEA956AE515D2261898FA051015728E5A8AAAC42DAD33170D04507A33",
+    "A85521ABDF1CBA64ECFB850458DBEF0A8AEA71575D060C7DB3970F85A6E1E4C7",
+    "ABF5AE8CDB0933D71E8C94E04A25619DCEE3D2261AD2EE6BF12FFA06D98A0864",
+    "D87602733EC86A64521F2B18177B200CBBE117577A615D6C770988C0BAD946E2",
+    "08E24FA074E5AB3143DB5BFCE0FD108E4B82D120A92108011A723C12A787E6D7",
+    "88719A10BDBA5B2699C327186AF4E23C1A946834B6150BDA2583E9CA2AD44CE8",
+    "DBBBC2DB04DE8EF92E8EFC141FBECAA6287C59474E6BC05D99B2964FA090C3A2",
+    "233BA186515BE7ED1F612970CEE2D7AFB81BDD762170481CD0069127D5B05AA9",
+    "93B4EA988D8FDDC186FFB7DC90A6C08F4DF435C93402849236C3FAB4D27C7026",
+    "C1D4DCB2602646DEC9751E763DBA37BDF8FF9406AD9E530EE5DB382F413001AE",
+    "B06A53ED9027D831179727B0865A8918DA3EDBEBCF9B14ED44CE6CBACED4BB1B",
+    "DB7F1447E6CC254B332051512BD7AF426FB8F401378CD2BF5983CA01C64B92EC",
+    "F032EA15D1721D03F482D7CE6E74FEF6D55E702F46980C82B5A84031900B1C9E",
+    "59E7C97FBEC7E8F323A97A7E36CC88BE0F1D45B7FF585AC54BD407B22B4154AA",
+    "CC8F6D7EBF48E1D814CC5ED20F8037E0A79715EEF29BE32806A1D58BB7C5DA76",
+    "F550AA3D8A1FBFF0EB19CCB1A313D55CDA56C9EC2EF29632387FE8D76E3C0468",
+    "043E8F663F4860EE12BF2D5B0B7474D6E694F91E6DBE115974A3926F12FEE5E4",
+    "38777CB6A932DF8CD8BEC4D073B931BA3BC832B68D9DD300741FA7BF8AFC47ED",
+    "2576F6936BA424663AAB639C5AE4F5683423B4742BF1C978238F16CBE39D652D",
+    "E3FDB8BEFC848AD922222E04A4037C0713EB57A81A23F0C73473FC646CEA306B",
+    "4BCBC8862F8385DDFA9D4B7FA2C087E879683303ED5BDD3A062B3CF5B3A278A6",
+    "6D2A13F83F44F82DDF310EE074AB6A364597E899A0255DC164F31CC50846851D",
+    "F9AB48195DED7EA1B1D510BD7EE74D73FAF36BC31ECFA268359046F4EB879F92",
+    "4009438B481C6CD7889A002ED5EE382BC9190DA6FC026E479558E4475677E9AA",
+    "9E3050E2765694DFC81F56E880B96E7160C980DD98EDD3DFFFFFFFFFFFFFFFFF",
+);
+
+/// A prime modulus and generator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DhGroup {
+    pub p: BigUint,
+    pub g: BigUint,
+}
+
+impl DhGroup {
+    /// Group from a hex modulus and a small generator.
+    pub fn from_hex(p: &str, g: u32) -> DhGroup {
+        DhGroup {
+            p: BigUint::parse_bytes(p.as_bytes(), 16).expect("valid hex modulus"),
+            g: BigUint::from(g),
+        }
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.p.bits() as u32
+    }
+}
+
+pub fn group14() -> DhGroup {
+    DhGroup::from_hex(GROUP14_PRIME, 2)
+}
+
+pub fn group16() -> DhGroup {
+    DhGroup::from_hex(GROUP16_PRIME, 2)
+}
+
+pub fn group18() -> DhGroup {
+    DhGroup::from_hex(GROUP18_PRIME, 2)
+}
+
+/// Fixed group used when no moduli entry fits a group exchange request
+/// (dh_new_group_fallback()).
+pub fn fallback_group(max: u32) -> DhGroup {
+    if max < 3072 {
+        debug!("requested max size {}, using 2k bit group 14", max);
+        group14()
+    } else if max < 6144 {
+        debug!("requested max size {}, using 4k bit group 16", max);
+        group16()
+    } else {
+        debug!("using 8k bit group 18");
+        group18()
+    }
+}
+
+/// Reject public values outside 1 < pub < p-1 and values with too few
+/// bits set (dh_pub_is_valid()).
+pub fn dh_pub_is_valid(group: &DhGroup, public: &BigUint) -> bool {
+    let one = BigUint::from(1u32);
+    if *public <= one {
+        warn!("invalid public DH value: <= 1");
+        return false;
+    }
+    if *public >= &group.p - &one {
+        warn!("invalid public DH value: >= p-1");
+        return false;
+    }
+    let bits_set: u64 = public.to_u32_digits().iter().map(|d| u64::from(d.count_ones())).sum();
+    // 置位过少的公开值可能来自故意构造的弱密钥
+    if bits_set <= 1 {
+        warn!("invalid public DH value ({}/{})", bits_set, group.bits());
+        return false;
+    }
+    true
+}
+
+/// Private exponent length for `need` bytes of derived key material: twice
+/// the wanted bits (at least 256), capped just below the modulus size.
+pub fn exponent_bits(group: &DhGroup, need: usize) -> u32 {
+    let want = (need as u32).saturating_mul(8).max(256);
+    want.saturating_mul(2).min(group.bits() - 1)
+}
+
+/// Generate a key pair. `rng` fills the private exponent.
+pub fn dh_gen_key(
+    group: &DhGroup,
+    need: usize,
+    rng: &mut dyn FnMut(&mut [u8]) -> io::Result<()>,
+) -> Result<(BigUint, BigUint), KexError> {
+    let bits = exponent_bits(group, need);
+    let mut raw = vec![0u8; bits.div_ceil(8) as usize];
+    for _ in 0..16 {
+        rng(&mut raw).map_err(KexError::Rng)?;
+        let excess = raw.len() as u32 * 8 - bits;
+        raw[0] &= 0xff >> excess;
+        let private = BigUint::from_bytes_be(&raw);
+        let public = group.g.modpow(&private, &group.p);
+        if dh_pub_is_valid(group, &public) {
+            return Ok((private, public));
+        }
+    }
+    Err(KexError::Protocol("dh_gen_key: could not generate a valid key".to_string()))
+}
+
+/// Shared secret as an unsigned big-endian magnitude, after checking the
+/// peer's public value.
+pub fn dh_compute_key(group: &DhGroup, private: &BigUint, peer: &BigUint) -> Result<Vec<u8>, KexError> {
+    if !dh_pub_is_valid(group, peer) {
+        return Err(KexError::InvalidPublicKey);
+    }
+    Ok(peer.modpow(private, &group.p).to_bytes_be())
+}
+
+/// Group size a client should request so the exchange is about as strong
+/// as the derived keys (dh_estimate()).
+pub fn dh_estimate(bits: u32) -> u32 {
+    if bits <= 112 {
+        2048
+    } else if bits <= 128 {
+        3072
+    } else if bits <= 192 {
+        7680
+    } else {
+        8192
+    }
+}
+
+/// One usable line of a moduli file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Moduli {
+    /// Modulus size in bits.
+    pub size: u32,
+    pub group: DhGroup,
+}
+
+/// Parse one moduli line: `time type tests tries size generator modulus`.
+/// Comments, blank lines and entries that are not tested safe primes
+/// yield `None` (parse_prime()).
+pub fn parse_moduli_line(line: &str) -> Option<Moduli> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() != 7 {
+        return None;
+    }
+    let kind: u32 = fields[1].parse().ok()?;
+    let tests: u32 = fields[2].parse().ok()?;
+    let tries: u32 = fields[3].parse().ok()?;
+    let size: u32 = fields[4].parse().ok()?;
+    let g: u32 = fields[5].parse().ok()?;
+    if kind != MODULI_TYPE_SAFE || tests & MODULI_TESTS_COMPOSITE != 0 || tests & !MODULI_TESTS_COMPOSITE == 0 {
+        return None;
+    }
+    if tries == 0 || g < 2 {
+        return None;
+    }
+    let p = BigUint::parse_bytes(fields[6].as_bytes(), 16)?;
+    // 文件中记录的是位数减一
+    let size = size + 1;
+    if p.bits() as u32 != size {
+        debug!("moduli: prime has wrong size: actual {} listed {}", p.bits(), size - 1);
+        return None;
+    }
+    Some(Moduli { size, group: DhGroup { p, g: BigUint::from(g) } })
+}
+
+pub fn parse_moduli(text: &str) -> Vec<Moduli> {
+    text.lines().filter_map(parse_moduli_line).collect()
+}
+
+pub fn read_moduli<P: AsRef<Path>>(path: P) -> io::Result<Vec<Moduli>> {
+    Ok(parse_moduli(&fs::read_to_string(path)?))
+}
+
+/// Pick a group for a group exchange request (choose_dh()): the smallest
+/// size above `wantbits` within `[min, max]`, or failing that the largest
+/// below it. Ties are broken with `pick(count)`. Falls back to the fixed
+/// groups when nothing fits.
+pub fn choose_dh(
+    moduli: &[Moduli],
+    min: u32,
+    wantbits: u32,
+    max: u32,
+    pick: &mut dyn FnMut(usize) -> usize,
+) -> DhGroup {
+    let mut best = 0u32;
+    let mut bestcount = 0usize;
+    for m in moduli {
+        if m.size > max || m.size < min {
+            continue;
+        }
+        if (m.size > wantbits && m.size < best) || (m.size > best && best < wantbits) {
+            best = m.size;
+            bestcount = 0;
+        }
+        if m.size == best {
+            bestcount += 1;
+        }
+    }
+    if bestcount == 0 {
+        debug!("WARNING: no suitable primes in moduli list");
+        return fallback_group(max);
+    }
+    let which = pick(bestcount) % bestcount;
+    moduli
+        .iter()
+        .filter(|m| m.size == best)
+        .nth(which)
+        .map(|m| m.group.clone())
+        .unwrap_or_else(|| fallback_group(max))
+}
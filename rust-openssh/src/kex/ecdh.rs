@@ -0,0 +1,116 @@
+// kex/ecdh.rs
+//
+// ecdh-sha2-nistp256/384/521 (RFC 5656 §4), after OpenSSH's kexecdh.c.
+// Public values are uncompressed SEC1 points; K is the x coordinate of the
+// shared point, encoded as an mpint.
+
+use std::io;
+
+use p256::elliptic_curve::ecdh::diffie_hellman;
+use p256::elliptic_curve::sec1::{FromEncodedPoint, ModulusSize, ToEncodedPoint};
+use p256::elliptic_curve::{AffinePoint, CurveArithmetic, FieldBytesSize, PublicKey, SecretKey};
+
+use super::{encode_mpint, KexError};
+
+/// Ephemeral key for one of the NIST curves.
+pub enum EcdhKey {
+    P256(SecretKey<p256::NistP256>),
+    P384(SecretKey<p384::NistP384>),
+    P521(SecretKey<p521::NistP521>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Curve {
+    P256,
+    P384,
+    P521,
+}
+
+impl Curve {
+    pub fn bits(self) -> usize {
+        match self {
+            Curve::P256 => 256,
+            Curve::P384 => 384,
+            Curve::P521 => 521,
+        }
+    }
+}
+
+fn gen_secret<C>(bits: usize, rng: &mut dyn FnMut(&mut [u8]) -> io::Result<()>) -> Result<SecretKey<C>, KexError>
+where
+    C: CurveArithmetic,
+{
+    let mut raw = vec![0u8; bits.div_ceil(8)];
+    // 标量需落在 [1, n) 内，超出则重新取随机数
+    for _ in 0..64 {
+        rng(&mut raw).map_err(KexError::Rng)?;
+        raw[0] &= 0xff >> (raw.len() * 8 - bits);
+        if let Ok(secret) = SecretKey::<C>::from_slice(&raw) {
+            return Ok(secret);
+        }
+    }
+    Err(KexError::Protocol("could not generate EC key".to_string()))
+}
+
+fn public_point<C>(secret: &SecretKey<C>) -> Vec<u8>
+where
+    C: CurveArithmetic,
+    FieldBytesSize<C>: ModulusSize,
+    AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+{
+    secret.public_key().to_encoded_point(false).as_bytes().to_vec()
+}
+
+fn shared<C>(secret: &SecretKey<C>, peer: &[u8]) -> Result<Vec<u8>, KexError>
+where
+    C: CurveArithmetic,
+    FieldBytesSize<C>: ModulusSize,
+    AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+{
+    // from_sec1_bytes 会检查点是否在曲线上且不是无穷远点
+    let peer = PublicKey::<C>::from_sec1_bytes(peer).map_err(|_| KexError::InvalidPublicKey)?;
+    let k = diffie_hellman(secret.to_nonzero_scalar(), peer.as_affine());
+    Ok(encode_mpint(k.raw_secret_bytes()))
+}
+
+pub fn client_keypair(
+    curve: Curve,
+    rng: &mut dyn FnMut(&mut [u8]) -> io::Result<()>,
+) -> Result<(EcdhKey, Vec<u8>), KexError> {
+    Ok(match curve {
+        Curve::P256 => {
+            let s = gen_secret(curve.bits(), rng)?;
+            let q = public_point(&s);
+            (EcdhKey::P256(s), q)
+        }
+        Curve::P384 => {
+            let s = gen_secret(curve.bits(), rng)?;
+            let q = public_point(&s);
+            (EcdhKey::P384(s), q)
+        }
+        Curve::P521 => {
+            let s = gen_secret(curve.bits(), rng)?;
+            let q = public_point(&s);
+            (EcdhKey::P521(s), q)
+        }
+    })
+}
+
+/// Server side: returns Q_S and the encoded shared secret K.
+pub fn enc(
+    curve: Curve,
+    client_pub: &[u8],
+    rng: &mut dyn FnMut(&mut [u8]) -> io::Result<()>,
+) -> Result<(Vec<u8>, Vec<u8>), KexError> {
+    let (key, server_pub) = client_keypair(curve, rng)?;
+    Ok((server_pub, dec(&key, client_pub)?))
+}
+
+/// Client side: the encoded shared secret K from the server's Q_S.
+pub fn dec(key: &EcdhKey, server_pub: &[u8]) -> Result<Vec<u8>, KexError> {
+    match key {
+        EcdhKey::P256(s) => shared(s, server_pub),
+        EcdhKey::P384(s) => shared(s, server_pub),
+        EcdhKey::P521(s) => shared(s, server_pub),
+    }
+}
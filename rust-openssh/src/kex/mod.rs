@@ -0,0 +1,1222 @@
+// kex/mod.rs
+//
+// SSH transport setup: identification exchange and key exchange.
+//
+// Key exchange follows OpenSSH's kex.c/kexgen.c/kexgex*.c: both sides send
+// SSH2_MSG_KEXINIT, algorithms are negotiated by taking the first client
+// preference the server also supports, then the chosen method runs and
+// yields the exchange hash H and shared secret K from which the session
+// keys are derived (RFC 4253 §7.2). `Kex` is a message-in/messages-out
+// state machine; `run_kex` drives it over a `PacketStream`.
+
+pub mod banner;
+pub mod c25519;
+pub mod dh;
+pub mod ecdh;
+pub mod pq;
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use log::debug;
+use num_bigint::BigUint;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use crate::cipher::{cipher_by_name, cipher_init};
+use crate::compat::{SSH_BUG_CURVE25519PAD, SSH_BUG_DHGEX_LARGE, SSH_OLD_DHGEX};
+use crate::compress::Compression;
+use crate::mac::{mac_by_name, mac_init};
+use crate::packet::{
+    filter_transport_message, random_bytes, Mode, PacketCipher, PacketError, PacketMac, PacketState, PacketStream,
+};
+use crate::ssh2::*;
+use crate::sshbuf::{SshBuf, SshBufError};
+
+use self::c25519::C25519Key;
+use self::dh::{DhGroup, Moduli, DH_GRP_MAX, DH_GRP_MIN};
+use self::ecdh::{Curve, EcdhKey};
+use self::pq::{Mlkem768Key, Sntrup761Key};
+
+// KEXINIT 中各算法列表的下标
+pub const PROPOSAL_KEX_ALGS: usize = 0;
+pub const PROPOSAL_SERVER_HOST_KEY_ALGS: usize = 1;
+pub const PROPOSAL_ENC_ALGS_CTOS: usize = 2;
+pub const PROPOSAL_ENC_ALGS_STOC: usize = 3;
+pub const PROPOSAL_MAC_ALGS_CTOS: usize = 4;
+pub const PROPOSAL_MAC_ALGS_STOC: usize = 5;
+pub const PROPOSAL_COMP_ALGS_CTOS: usize = 6;
+pub const PROPOSAL_COMP_ALGS_STOC: usize = 7;
+pub const PROPOSAL_LANG_CTOS: usize = 8;
+pub const PROPOSAL_LANG_STOC: usize = 9;
+pub const PROPOSAL_MAX: usize = 10;
+
+/// Pseudo-algorithms appended to the KEX list of the initial KEXINIT.
+pub const KEX_STRICT_CLIENT: &str = "kex-strict-c-v00@openssh.com";
+pub const KEX_STRICT_SERVER: &str = "kex-strict-s-v00@openssh.com";
+pub const EXT_INFO_CLIENT: &str = "ext-info-c";
+pub const EXT_INFO_SERVER: &str = "ext-info-s";
+
+pub const KEX_DEFAULT_KEX: &str = "sntrup761x25519-sha512,sntrup761x25519-sha512@openssh.com,\
+mlkem768x25519-sha256,curve25519-sha256,curve25519-sha256@libssh.org,\
+ecdh-sha2-nistp256,ecdh-sha2-nistp384,ecdh-sha2-nistp521,\
+diffie-hellman-group-exchange-sha256,diffie-hellman-group16-sha512,\
+diffie-hellman-group18-sha512,diffie-hellman-group14-sha256";
+
+pub const KEX_DEFAULT_PK_ALG: &str = "ssh-ed25519-cert-v01@openssh.com,\
+ecdsa-sha2-nistp256-cert-v01@openssh.com,ecdsa-sha2-nistp384-cert-v01@openssh.com,\
+ecdsa-sha2-nistp521-cert-v01@openssh.com,sk-ssh-ed25519-cert-v01@openssh.com,\
+sk-ecdsa-sha2-nistp256-cert-v01@openssh.com,rsa-sha2-512-cert-v01@openssh.com,\
+rsa-sha2-256-cert-v01@openssh.com,ssh-ed25519,ecdsa-sha2-nistp256,ecdsa-sha2-nistp384,\
+ecdsa-sha2-nistp521,sk-ssh-ed25519@openssh.com,sk-ecdsa-sha2-nistp256@openssh.com,\
+rsa-sha2-512,rsa-sha2-256";
+
+pub const KEX_DEFAULT_ENCRYPT: &str = "chacha20-poly1305@openssh.com,\
+aes128-ctr,aes192-ctr,aes256-ctr,aes128-gcm@openssh.com,aes256-gcm@openssh.com";
+
+pub const KEX_DEFAULT_MAC: &str = "umac-64-etm@openssh.com,umac-128-etm@openssh.com,\
+hmac-sha2-256-etm@openssh.com,hmac-sha2-512-etm@openssh.com,hmac-sha1-etm@openssh.com,\
+umac-64@openssh.com,umac-128@openssh.com,hmac-sha2-256,hmac-sha2-512,hmac-sha1";
+
+pub const KEX_DEFAULT_COMP: &str = "none";
+
+pub type Proposal = [String; PROPOSAL_MAX];
+
+/// Default algorithm lists (myproposal.h).
+pub fn default_proposal() -> Proposal {
+    [
+        KEX_DEFAULT_KEX,
+        KEX_DEFAULT_PK_ALG,
+        KEX_DEFAULT_ENCRYPT,
+        KEX_DEFAULT_ENCRYPT,
+        KEX_DEFAULT_MAC,
+        KEX_DEFAULT_MAC,
+        KEX_DEFAULT_COMP,
+        KEX_DEFAULT_COMP,
+        "",
+        "",
+    ]
+    .map(String::from)
+}
+
+/// Fill a buffer with random bytes; replaceable for reproducible tests.
+pub type RandomFill = dyn FnMut(&mut [u8]) -> io::Result<()> + Send;
+
+#[derive(Debug)]
+pub enum KexError {
+    Packet(PacketError),
+    /// No algorithm in common for one of the proposal lists.
+    NoMatch { what: &'static str, client: String, server: String },
+    UnsupportedAlg(String),
+    /// The peer's ephemeral public value failed validation.
+    InvalidPublicKey,
+    /// The server has no host key for the negotiated algorithm.
+    NoHostKey(String),
+    /// Host key signature verification or host key check failed.
+    HostKey(String),
+    Rng(io::Error),
+    /// Strict KEX (Terrapin mitigation) violation.
+    Strict(String),
+    Protocol(String),
+}
+
+impl fmt::Display for KexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KexError::Packet(e) => write!(f, "{}", e),
+            KexError::NoMatch { what, client, server } => {
+                write!(f, "no matching {} found: client {} server {}", what, client, server)
+            }
+            KexError::UnsupportedAlg(name) => write!(f, "unsupported algorithm {}", name),
+            KexError::InvalidPublicKey => f.write_str("invalid ephemeral public key"),
+            KexError::NoHostKey(alg) => write!(f, "no hostkey for type {}", alg),
+            KexError::HostKey(why) => write!(f, "host key verification failed: {}", why),
+            KexError::Rng(e) => write!(f, "random number generator: {}", e),
+            KexError::Strict(why) => write!(f, "strict KEX violation: {}", why),
+            KexError::Protocol(why) => f.write_str(why),
+        }
+    }
+}
+
+impl Error for KexError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            KexError::Packet(e) => Some(e),
+            KexError::Rng(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<PacketError> for KexError {
+    fn from(e: PacketError) -> Self {
+        KexError::Packet(e)
+    }
+}
+
+impl From<SshBufError> for KexError {
+    fn from(e: SshBufError) -> Self {
+        KexError::Packet(PacketError::Format(e))
+    }
+}
+
+/// Hash function of a key exchange method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KexHash {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl KexHash {
+    pub fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            KexHash::Sha256 => Sha256::digest(data).to_vec(),
+            KexHash::Sha384 => Sha384::digest(data).to_vec(),
+            KexHash::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KexAlg {
+    DhGroup14Sha256,
+    DhGroup16Sha512,
+    DhGroup18Sha512,
+    DhGexSha256,
+    EcdhNistp256,
+    EcdhNistp384,
+    EcdhNistp521,
+    Curve25519Sha256,
+    Sntrup761x25519Sha512,
+    Mlkem768x25519Sha256,
+}
+
+impl KexAlg {
+    pub fn from_name(name: &str) -> Option<KexAlg> {
+        Some(match name {
+            "diffie-hellman-group14-sha256" => KexAlg::DhGroup14Sha256,
+            "diffie-hellman-group16-sha512" => KexAlg::DhGroup16Sha512,
+            "diffie-hellman-group18-sha512" => KexAlg::DhGroup18Sha512,
+            "diffie-hellman-group-exchange-sha256" => KexAlg::DhGexSha256,
+            "ecdh-sha2-nistp256" => KexAlg::EcdhNistp256,
+            "ecdh-sha2-nistp384" => KexAlg::EcdhNistp384,
+            "ecdh-sha2-nistp521" => KexAlg::EcdhNistp521,
+            "curve25519-sha256" | "curve25519-sha256@libssh.org" => KexAlg::Curve25519Sha256,
+            "sntrup761x25519-sha512" | "sntrup761x25519-sha512@openssh.com" => KexAlg::Sntrup761x25519Sha512,
+            "mlkem768x25519-sha256" => KexAlg::Mlkem768x25519Sha256,
+            _ => return None,
+        })
+    }
+
+    pub fn hash(self) -> KexHash {
+        match self {
+            KexAlg::DhGroup16Sha512 | KexAlg::DhGroup18Sha512 | KexAlg::Sntrup761x25519Sha512 => KexHash::Sha512,
+            KexAlg::EcdhNistp384 => KexHash::Sha384,
+            KexAlg::EcdhNistp521 => KexHash::Sha512,
+            _ => KexHash::Sha256,
+        }
+    }
+
+    fn fixed_group(self) -> Option<DhGroup> {
+        match self {
+            KexAlg::DhGroup14Sha256 => Some(dh::group14()),
+            KexAlg::DhGroup16Sha512 => Some(dh::group16()),
+            KexAlg::DhGroup18Sha512 => Some(dh::group18()),
+            _ => None,
+        }
+    }
+
+    fn curve(self) -> Option<Curve> {
+        match self {
+            KexAlg::EcdhNistp256 => Some(Curve::P256),
+            KexAlg::EcdhNistp384 => Some(Curve::P384),
+            KexAlg::EcdhNistp521 => Some(Curve::P521),
+            _ => None,
+        }
+    }
+}
+
+/// Parsed SSH2_MSG_KEXINIT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KexInit {
+    pub cookie: [u8; 16],
+    pub proposal: Proposal,
+    pub first_kex_follows: bool,
+}
+
+impl KexInit {
+    /// The full message, including the type byte, as hashed into H.
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut b = SshBuf::new();
+        b.put_u8(SSH2_MSG_KEXINIT);
+        b.put(&self.cookie);
+        for list in &self.proposal {
+            b.put_cstring(list);
+        }
+        b.put_bool(self.first_kex_follows);
+        b.put_u32(0);
+        b.into_vec()
+    }
+
+    pub fn parse(payload: &[u8]) -> Result<KexInit, KexError> {
+        let mut b = SshBuf::from_vec(payload.to_vec());
+        if b.get_u8()? != SSH2_MSG_KEXINIT {
+            return Err(KexError::Protocol("expected SSH2_MSG_KEXINIT".to_string()));
+        }
+        let mut cookie = [0u8; 16];
+        cookie.copy_from_slice(b.get(16)?);
+        let mut proposal = Proposal::default();
+        for list in proposal.iter_mut() {
+            *list = b.get_cstring()?;
+        }
+        let first_kex_follows = b.get_bool()?;
+        // reserved 字段忽略其取值
+        b.get_u32()?;
+        Ok(KexInit { cookie, proposal, first_kex_follows })
+    }
+}
+
+fn list_contains(list: &str, name: &str) -> bool {
+    list.split(',').any(|n| n == name)
+}
+
+fn first_name(list: &str) -> &str {
+    list.split(',').next().unwrap_or("")
+}
+
+/// First entry of `client` that also appears in `server` (match_list()).
+pub fn match_list(client: &str, server: &str) -> Option<String> {
+    client
+        .split(',')
+        .filter(|n| !n.is_empty())
+        .find(|n| list_contains(server, n))
+        .map(String::from)
+}
+
+/// Drop algorithms a buggy peer mishandles from our KEX list
+/// (compat_kex_proposal()).
+pub fn compat_kex_proposal(list: &str, compat: u32) -> String {
+    let mut deny: Vec<&str> = Vec::new();
+    if compat & SSH_BUG_CURVE25519PAD != 0 {
+        deny.push("curve25519-sha256@libssh.org");
+    }
+    if compat & SSH_OLD_DHGEX != 0 {
+        deny.extend(["diffie-hellman-group-exchange-sha256", "diffie-hellman-group-exchange-sha1"]);
+    }
+    list.split(',').filter(|n| !deny.contains(n)).collect::<Vec<_>>().join(",")
+}
+
+/// Algorithms negotiated for one direction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewKeys {
+    pub enc: String,
+    /// `None` for AEAD ciphers, which authenticate packets themselves.
+    pub mac: Option<String>,
+    pub comp: String,
+}
+
+impl NewKeys {
+    /// Key length of the negotiated MAC, 0 when the cipher is AEAD.
+    pub fn mac_key_len(&self) -> usize {
+        self.mac.as_deref().and_then(mac_by_name).map_or(0, |m| m.key_len)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Negotiated {
+    pub kex: KexAlg,
+    pub kex_name: String,
+    pub hostkey_alg: String,
+    pub c2s: NewKeys,
+    pub s2c: NewKeys,
+    /// Both sides offered the kex-strict pseudo-algorithm.
+    pub strict: bool,
+    /// The client offered ext-info-c: the server may send SSH2_MSG_EXT_INFO.
+    pub ext_info_c: bool,
+    /// The server offered ext-info-s.
+    pub ext_info_s: bool,
+    /// Longest key, IV, block or MAC key needed, in bytes.
+    pub we_need: usize,
+    /// Like `we_need` but using the ciphers' security strength; sizes the
+    /// group exchange request.
+    pub dh_need: usize,
+}
+
+fn choose(what: &'static str, client: &str, server: &str) -> Result<String, KexError> {
+    match_list(client, server).ok_or_else(|| KexError::NoMatch {
+        what,
+        client: client.to_string(),
+        server: server.to_string(),
+    })
+}
+
+fn choose_newkeys(client: &Proposal, server: &Proposal, enc: usize, mac: usize, comp: usize) -> Result<NewKeys, KexError> {
+    let enc = choose("cipher", &client[enc], &server[enc])?;
+    let info = cipher_by_name(&enc).ok_or_else(|| KexError::UnsupportedAlg(enc.clone()))?;
+    // AEAD 模式下 MAC 列表被忽略
+    let mac = if info.is_aead() {
+        None
+    } else {
+        let mac = choose("MAC", &client[mac], &server[mac])?;
+        mac_by_name(&mac).ok_or_else(|| KexError::UnsupportedAlg(mac.clone()))?;
+        Some(mac)
+    };
+    let comp = choose("compression method", &client[comp], &server[comp])?;
+    if Compression::from_name(&comp).is_none() {
+        return Err(KexError::UnsupportedAlg(comp));
+    }
+    Ok(NewKeys { enc, mac, comp })
+}
+
+/// Negotiate algorithms from both KEXINITs (kex_choose_conf()).
+pub fn kex_choose(client: &KexInit, server: &KexInit) -> Result<Negotiated, KexError> {
+    let (cp, sp) = (&client.proposal, &server.proposal);
+    let kex_name = choose("key exchange method", &cp[PROPOSAL_KEX_ALGS], &sp[PROPOSAL_KEX_ALGS])?;
+    let kex = KexAlg::from_name(&kex_name).ok_or_else(|| KexError::UnsupportedAlg(kex_name.clone()))?;
+    let hostkey_alg = choose(
+        "host key type",
+        &cp[PROPOSAL_SERVER_HOST_KEY_ALGS],
+        &sp[PROPOSAL_SERVER_HOST_KEY_ALGS],
+    )?;
+    let c2s = choose_newkeys(cp, sp, PROPOSAL_ENC_ALGS_CTOS, PROPOSAL_MAC_ALGS_CTOS, PROPOSAL_COMP_ALGS_CTOS)?;
+    let s2c = choose_newkeys(cp, sp, PROPOSAL_ENC_ALGS_STOC, PROPOSAL_MAC_ALGS_STOC, PROPOSAL_COMP_ALGS_STOC)?;
+
+    let mut we_need = 0;
+    let mut dh_need = 0;
+    for nk in [&c2s, &s2c] {
+        let enc = cipher_by_name(&nk.enc).expect("checked above");
+        let mac_len = nk.mac_key_len();
+        we_need = we_need.max(enc.key_len).max(enc.block_size).max(enc.iv_len).max(mac_len);
+        dh_need = dh_need.max(enc.seclen()).max(enc.block_size).max(enc.iv_len).max(mac_len);
+    }
+    debug!("kex: algorithm: {}", kex_name);
+    debug!("kex: host key algorithm: {}", hostkey_alg);
+
+    Ok(Negotiated {
+        kex,
+        kex_name,
+        hostkey_alg,
+        c2s,
+        s2c,
+        strict: list_contains(&cp[PROPOSAL_KEX_ALGS], KEX_STRICT_CLIENT)
+            && list_contains(&sp[PROPOSAL_KEX_ALGS], KEX_STRICT_SERVER),
+        ext_info_c: list_contains(&cp[PROPOSAL_KEX_ALGS], EXT_INFO_CLIENT),
+        ext_info_s: list_contains(&sp[PROPOSAL_KEX_ALGS], EXT_INFO_SERVER),
+        we_need,
+        dh_need,
+    })
+}
+
+/// An mpint with its length prefix, as K is hashed for classical methods.
+pub fn encode_mpint(v: &[u8]) -> Vec<u8> {
+    let mut b = SshBuf::new();
+    b.put_bignum2_bytes(v);
+    b.into_vec()
+}
+
+// mpint 去掉长度前缀后的内容，DH 的 Q_C/Q_S 以 string 形式承载它
+fn mpint_body(v: &BigUint) -> Vec<u8> {
+    encode_mpint(&v.to_bytes_be()).split_off(4)
+}
+
+fn parse_mpint_body(v: &[u8]) -> Result<BigUint, KexError> {
+    if v.first().is_some_and(|b| b & 0x80 != 0) {
+        return Err(KexError::InvalidPublicKey);
+    }
+    Ok(BigUint::from_bytes_be(v))
+}
+
+/// Group exchange request sizes, in bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GexRequest {
+    pub min: u32,
+    pub nbits: u32,
+    pub max: u32,
+}
+
+/// The inputs to H that every method shares.
+#[derive(Debug, Clone, Copy)]
+pub struct HashContext<'a> {
+    /// Identification strings without CR LF.
+    pub client_version: &'a str,
+    pub server_version: &'a str,
+    /// KEXINIT payloads including the message type.
+    pub client_kexinit: &'a [u8],
+    pub server_kexinit: &'a [u8],
+    pub server_host_key: &'a [u8],
+}
+
+impl HashContext<'_> {
+    fn put_common(&self, b: &mut SshBuf) {
+        b.put_cstring(self.client_version);
+        b.put_cstring(self.server_version);
+        b.put_string(self.client_kexinit);
+        b.put_string(self.server_kexinit);
+        b.put_string(self.server_host_key);
+    }
+
+    /// H for the fixed-group, ECDH, curve25519 and hybrid methods
+    /// (kex_gen_hash()). `shared_secret` is the already-encoded K.
+    pub fn kexgen_hash(&self, hash: KexHash, client_pub: &[u8], server_pub: &[u8], shared_secret: &[u8]) -> Vec<u8> {
+        let mut b = SshBuf::new();
+        self.put_common(&mut b);
+        b.put_string(client_pub);
+        b.put_string(server_pub);
+        b.put(shared_secret);
+        hash.digest(b.as_slice())
+    }
+
+    /// H for diffie-hellman-group-exchange (kexgex_hash(), RFC 4419 §3).
+    pub fn gex_hash(
+        &self,
+        hash: KexHash,
+        req: &GexRequest,
+        group: &DhGroup,
+        client_pub: &BigUint,
+        server_pub: &BigUint,
+        shared_secret: &[u8],
+    ) -> Vec<u8> {
+        let mut b = SshBuf::new();
+        self.put_common(&mut b);
+        b.put_u32(req.min);
+        b.put_u32(req.nbits);
+        b.put_u32(req.max);
+        for v in [&group.p, &group.g, client_pub, server_pub] {
+            b.put_bignum2_bytes(&v.to_bytes_be());
+        }
+        b.put(shared_secret);
+        hash.digest(b.as_slice())
+    }
+}
+
+/// HASH(K || H || id || session_id), extended by HASH(K || H || key so far)
+/// until `need` bytes are available (derive_key()).
+pub fn derive_key(hash: KexHash, shared_secret: &[u8], h: &[u8], session_id: &[u8], id: u8, need: usize) -> Vec<u8> {
+    let mut b = SshBuf::new();
+    b.put(shared_secret);
+    b.put(h);
+    b.put_u8(id);
+    b.put(session_id);
+    let mut key = hash.digest(b.as_slice());
+    while key.len() < need {
+        let mut b = SshBuf::new();
+        b.put(shared_secret);
+        b.put(h);
+        b.put(&key);
+        let more = hash.digest(b.as_slice());
+        key.extend_from_slice(&more);
+    }
+    key.truncate(need);
+    key
+}
+
+/// Key material for one direction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectionKeys {
+    pub iv: Vec<u8>,
+    pub key: Vec<u8>,
+    pub mac_key: Vec<u8>,
+}
+
+/// Cipher and optional MAC context for one direction.
+pub type PacketKeys = (Box<dyn PacketCipher>, Option<Box<dyn PacketMac>>);
+
+/// Result of a completed key exchange.
+#[derive(Debug, Clone)]
+pub struct KexOutput {
+    pub negotiated: Negotiated,
+    pub hash: KexHash,
+    /// This exchange's H.
+    pub exchange_hash: Vec<u8>,
+    /// H of the first exchange on the connection.
+    pub session_id: Vec<u8>,
+    /// K, encoded as it is hashed.
+    pub shared_secret: Vec<u8>,
+    pub server_host_key: Vec<u8>,
+    server: bool,
+}
+
+impl KexOutput {
+    pub fn derive_key(&self, id: u8, need: usize) -> Vec<u8> {
+        derive_key(self.hash, &self.shared_secret, &self.exchange_hash, &self.session_id, id, need)
+    }
+
+    /// Keys for our inbound or outbound direction: 'A', 'C', 'E' for
+    /// client to server and 'B', 'D', 'F' for server to client.
+    pub fn keys(&self, mode: Mode) -> DirectionKeys {
+        let c2s = (mode == Mode::Out) != self.server;
+        let (nk, base) = if c2s { (&self.negotiated.c2s, b'A') } else { (&self.negotiated.s2c, b'B') };
+        let enc = cipher_by_name(&nk.enc).expect("negotiated cipher");
+        DirectionKeys {
+            iv: self.derive_key(base, enc.iv_len),
+            key: self.derive_key(base + 2, enc.key_len),
+            mac_key: self.derive_key(base + 4, nk.mac_key_len()),
+        }
+    }
+
+    /// Algorithms for our inbound or outbound direction.
+    pub fn newkeys(&self, mode: Mode) -> &NewKeys {
+        if (mode == Mode::Out) != self.server {
+            &self.negotiated.c2s
+        } else {
+            &self.negotiated.s2c
+        }
+    }
+
+    /// Cipher and MAC contexts for one direction, keyed from this exchange.
+    pub fn packet_keys(&self, mode: Mode) -> Result<PacketKeys, PacketError> {
+        let nk = self.newkeys(mode);
+        let keys = self.keys(mode);
+        let cipher = cipher_init(&nk.enc, &keys.key, &keys.iv, mode)?;
+        let mac = match &nk.mac {
+            Some(name) => Some(mac_init(name, &keys.mac_key)?),
+            None => None,
+        };
+        Ok((cipher, mac))
+    }
+
+    /// Install the new keys and compression for both directions
+    /// (ssh_set_newkeys()).
+    pub fn install(&self, state: &mut PacketState) -> Result<(), PacketError> {
+        for mode in [Mode::Out, Mode::In] {
+            let (cipher, mac) = self.packet_keys(mode)?;
+            state.set_newkeys(mode, cipher, mac);
+            state.set_compression(mode, Compression::from_name(&self.newkeys(mode).comp).expect("negotiated"));
+        }
+        Ok(())
+    }
+}
+
+/// Server side access to host keys.
+pub trait HostKeySigner: Send {
+    /// Public key blob for `alg`, or `None` if we have no such key.
+    fn public_key(&self, alg: &str) -> Option<Vec<u8>>;
+    /// Signature blob over `data` (the exchange hash) using `alg`.
+    fn sign(&mut self, alg: &str, data: &[u8]) -> Result<Vec<u8>, KexError>;
+}
+
+/// Client side host key check: verify `signature` over `data` with
+/// `host_key` and decide whether the key is trusted for this host.
+pub trait HostKeyVerifier: Send {
+    fn verify(&mut self, alg: &str, host_key: &[u8], signature: &[u8], data: &[u8]) -> Result<(), KexError>;
+}
+
+enum Role {
+    Client(Box<dyn HostKeyVerifier>),
+    Server(Box<dyn HostKeySigner>),
+}
+
+// 客户端在等待服务器回复期间持有的临时私钥
+enum ClientKey {
+    C25519(C25519Key),
+    Ecdh(EcdhKey),
+    Dh(DhGroup, BigUint),
+    Sntrup761(Box<Sntrup761Key>),
+    Mlkem768(Box<Mlkem768Key>),
+}
+
+enum State {
+    /// Waiting for the peer's KEXINIT.
+    KexInit,
+    /// Server: waiting for KEXDH/ECDH INIT.
+    AwaitInit,
+    /// Client: waiting for KEXDH/ECDH REPLY.
+    AwaitReply(ClientKey, Vec<u8>),
+    AwaitGexRequest,
+    AwaitGexGroup(GexRequest),
+    AwaitGexInit(GexRequest, DhGroup),
+    AwaitGexReply(GexRequest, DhGroup, BigUint, BigUint),
+    AwaitNewKeys(Box<KexOutput>),
+    Done(Box<KexOutput>),
+}
+
+/// Key exchange state for one side of a connection.
+pub struct Kex {
+    role: Role,
+    client_version: String,
+    server_version: String,
+    proposal: Proposal,
+    compat: u32,
+    moduli: Vec<Moduli>,
+    rng: Box<RandomFill>,
+    my_kexinit: Option<Vec<u8>>,
+    peer_kexinit: Option<Vec<u8>>,
+    negotiated: Option<Negotiated>,
+    state: State,
+    session_id: Option<Vec<u8>>,
+    initial: bool,
+    strict: bool,
+    skip_next: bool,
+}
+
+impl Kex {
+    fn new(role: Role, client_version: &str, server_version: &str, proposal: Proposal) -> Kex {
+        Kex {
+            role,
+            client_version: client_version.to_string(),
+            server_version: server_version.to_string(),
+            proposal,
+            compat: 0,
+            moduli: Vec::new(),
+            rng: Box::new(random_bytes),
+            my_kexinit: None,
+            peer_kexinit: None,
+            negotiated: None,
+            state: State::KexInit,
+            session_id: None,
+            initial: true,
+            strict: false,
+            skip_next: false,
+        }
+    }
+
+    /// Client side; versions are the identification strings without CR LF.
+    pub fn client(client_version: &str, server_version: &str, proposal: Proposal, verifier: Box<dyn HostKeyVerifier>) -> Kex {
+        Kex::new(Role::Client(verifier), client_version, server_version, proposal)
+    }
+
+    /// Server side. The host key list in `proposal` should name only
+    /// algorithms `signer` has keys for.
+    pub fn server(client_version: &str, server_version: &str, proposal: Proposal, signer: Box<dyn HostKeySigner>) -> Kex {
+        Kex::new(Role::Server(signer), client_version, server_version, proposal)
+    }
+
+    fn is_server(&self) -> bool {
+        matches!(self.role, Role::Server(_))
+    }
+
+    /// Peer `SSH_BUG_*` flags; set before the first `kexinit()`.
+    pub fn set_compat(&mut self, compat: u32) {
+        self.compat = compat;
+    }
+
+    /// Groups a server offers for group exchange (see `dh::read_moduli`).
+    pub fn set_moduli(&mut self, moduli: Vec<Moduli>) {
+        self.moduli = moduli;
+    }
+
+    pub fn set_rng(&mut self, rng: Box<RandomFill>) {
+        self.rng = rng;
+    }
+
+    pub fn negotiated(&self) -> Option<&Negotiated> {
+        self.negotiated.as_ref()
+    }
+
+    /// Strict KEX was agreed in the initial exchange; sequence numbers are
+    /// reset at every NEWKEYS.
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    pub fn session_id(&self) -> Option<&[u8]> {
+        self.session_id.as_deref()
+    }
+
+    /// Our KEXINIT for this exchange has been built; `input` and `run_kex`
+    /// send it as soon as it is.
+    pub fn kexinit_sent(&self) -> bool {
+        self.my_kexinit.is_some()
+    }
+
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, State::Done(_))
+    }
+
+    /// Take the result once the peer's NEWKEYS has been processed.
+    pub fn take_output(&mut self) -> Option<KexOutput> {
+        match std::mem::replace(&mut self.state, State::KexInit) {
+            State::Done(out) => Some(*out),
+            other => {
+                self.state = other;
+                None
+            }
+        }
+    }
+
+    /// Prepare for a rekey; the session id and strict mode carry over.
+    pub fn rekey(&mut self) {
+        self.initial = false;
+        self.my_kexinit = None;
+        self.peer_kexinit = None;
+        self.negotiated = None;
+        self.state = State::KexInit;
+        self.skip_next = false;
+    }
+
+    /// Build and remember our SSH2_MSG_KEXINIT payload.
+    pub fn kexinit(&mut self) -> Result<Vec<u8>, KexError> {
+        if let Some(p) = &self.my_kexinit {
+            return Ok(p.clone());
+        }
+        let mut proposal = self.proposal.clone();
+        let mut kex = compat_kex_proposal(&proposal[PROPOSAL_KEX_ALGS], self.compat);
+        // 伪算法只出现在首次 KEXINIT 中
+        if self.initial {
+            let extra = if self.is_server() {
+                [EXT_INFO_SERVER, KEX_STRICT_SERVER]
+            } else {
+                [EXT_INFO_CLIENT, KEX_STRICT_CLIENT]
+            };
+            for name in extra {
+                if !list_contains(&kex, name) {
+                    kex.push(',');
+                    kex.push_str(name);
+                }
+            }
+        }
+        proposal[PROPOSAL_KEX_ALGS] = kex;
+        let mut cookie = [0u8; 16];
+        (self.rng)(&mut cookie).map_err(KexError::Rng)?;
+        let payload = KexInit { cookie, proposal, first_kex_follows: false }.to_payload();
+        self.my_kexinit = Some(payload.clone());
+        Ok(payload)
+    }
+
+    fn hash_context<'a>(&'a self, host_key: &'a [u8]) -> HashContext<'a> {
+        let mine = self.my_kexinit.as_deref().unwrap_or_default();
+        let peer = self.peer_kexinit.as_deref().unwrap_or_default();
+        let (client_kexinit, server_kexinit) = if self.is_server() { (peer, mine) } else { (mine, peer) };
+        HashContext {
+            client_version: &self.client_version,
+            server_version: &self.server_version,
+            client_kexinit,
+            server_kexinit,
+            server_host_key: host_key,
+        }
+    }
+
+    fn finish(&mut self, h: Vec<u8>, shared_secret: Vec<u8>, host_key: Vec<u8>, out: &mut Vec<Vec<u8>>) {
+        let negotiated = self.negotiated.clone().expect("negotiated before method messages");
+        let session_id = self.session_id.get_or_insert_with(|| h.clone()).clone();
+        let output = KexOutput {
+            hash: negotiated.kex.hash(),
+            negotiated,
+            exchange_hash: h,
+            session_id,
+            shared_secret,
+            server_host_key: host_key,
+            server: self.is_server(),
+        };
+        out.push(vec![SSH2_MSG_NEWKEYS]);
+        self.state = State::AwaitNewKeys(Box::new(output));
+    }
+
+    /// Process one message received during key exchange. `seqnr` is its
+    /// inbound sequence number. Returns the messages to send in order;
+    /// NEWKEYS is among them once the method has completed.
+    pub fn input(&mut self, seqnr: u32, payload: &[u8]) -> Result<Vec<Vec<u8>>, KexError> {
+        let msg = *payload.first().ok_or_else(|| KexError::Protocol("empty packet".to_string()))?;
+        let mut out = Vec::new();
+        let kex_msg = msg == SSH2_MSG_KEXINIT || msg == SSH2_MSG_NEWKEYS || (30..=49).contains(&msg);
+        if !kex_msg {
+            if msg == SSH2_MSG_DISCONNECT {
+                filter_transport_message(payload)?;
+            }
+            // 严格模式下首次密钥交换期间不允许出现任何其他消息
+            if self.strict && self.initial {
+                return Err(KexError::Strict(format!("unexpected packet type {} (seqnr {})", msg, seqnr)));
+            }
+            if matches!(msg, SSH2_MSG_IGNORE | SSH2_MSG_DEBUG | SSH2_MSG_UNIMPLEMENTED) {
+                filter_transport_message(payload)?;
+            } else {
+                debug!("kex protocol error: type {} seq {}", msg, seqnr);
+                let mut b = SshBuf::new();
+                b.put_u8(SSH2_MSG_UNIMPLEMENTED);
+                b.put_u32(seqnr);
+                out.push(b.into_vec());
+            }
+            return Ok(out);
+        }
+        if self.skip_next && msg != SSH2_MSG_KEXINIT {
+            debug!("skipping guessed KEX packet type {}", msg);
+            self.skip_next = false;
+            return Ok(out);
+        }
+
+        let state = std::mem::replace(&mut self.state, State::KexInit);
+        match (state, msg) {
+            (State::KexInit, SSH2_MSG_KEXINIT) => self.input_kexinit(seqnr, payload, &mut out)?,
+            (State::AwaitInit, SSH2_MSG_KEX_ECDH_INIT) => self.server_input_init(payload, &mut out)?,
+            (State::AwaitReply(key, client_pub), SSH2_MSG_KEX_ECDH_REPLY) => {
+                self.client_input_reply(key, &client_pub, payload, &mut out)?
+            }
+            (State::AwaitGexRequest, SSH2_MSG_KEX_DH_GEX_REQUEST) => self.server_input_gex_request(payload, &mut out)?,
+            (State::AwaitGexGroup(req), SSH2_MSG_KEX_DH_GEX_GROUP) => {
+                self.client_input_gex_group(req, payload, &mut out)?
+            }
+            (State::AwaitGexInit(req, group), SSH2_MSG_KEX_DH_GEX_INIT) => {
+                self.server_input_gex_init(req, group, payload, &mut out)?
+            }
+            (State::AwaitGexReply(req, group, x, e), SSH2_MSG_KEX_DH_GEX_REPLY) => {
+                self.client_input_gex_reply(req, group, x, e, payload, &mut out)?
+            }
+            (State::AwaitNewKeys(output), SSH2_MSG_NEWKEYS) => {
+                debug!("SSH2_MSG_NEWKEYS received");
+                self.state = State::Done(output);
+            }
+            (state, _) => {
+                self.state = state;
+                if self.strict && self.initial {
+                    return Err(KexError::Strict(format!("unexpected packet type {} (seqnr {})", msg, seqnr)));
+                }
+                return Err(KexError::Protocol(format!("protocol error: unexpected message type {}", msg)));
+            }
+        }
+        Ok(out)
+    }
+
+    fn input_kexinit(&mut self, seqnr: u32, payload: &[u8], out: &mut Vec<Vec<u8>>) -> Result<(), KexError> {
+        debug!("SSH2_MSG_KEXINIT received");
+        let peer = KexInit::parse(payload)?;
+        if self.my_kexinit.is_none() {
+            out.push(self.kexinit()?);
+        }
+        let mine = KexInit::parse(self.my_kexinit.as_deref().expect("sent above"))?;
+        self.peer_kexinit = Some(payload.to_vec());
+
+        let (client, server) = if self.is_server() { (&peer, &mine) } else { (&mine, &peer) };
+        let negotiated = kex_choose(client, server)?;
+        if self.initial {
+            self.strict = negotiated.strict;
+            if self.strict && seqnr != 0 {
+                return Err(KexError::Strict("KEXINIT was not the first packet".to_string()));
+            }
+        }
+        // 对方猜错了算法时，丢弃其紧随 KEXINIT 发出的那个包
+        if peer.first_kex_follows
+            && (first_name(&peer.proposal[PROPOSAL_KEX_ALGS]) != first_name(&mine.proposal[PROPOSAL_KEX_ALGS])
+                || first_name(&peer.proposal[PROPOSAL_SERVER_HOST_KEY_ALGS])
+                    != first_name(&mine.proposal[PROPOSAL_SERVER_HOST_KEY_ALGS]))
+        {
+            self.skip_next = true;
+        }
+        let alg = negotiated.kex;
+        self.negotiated = Some(negotiated);
+
+        if self.is_server() {
+            self.state = if alg == KexAlg::DhGexSha256 { State::AwaitGexRequest } else { State::AwaitInit };
+            return Ok(());
+        }
+        if alg == KexAlg::DhGexSha256 {
+            let need = self.negotiated.as_ref().map_or(0, |n| n.dh_need);
+            let mut max = DH_GRP_MAX;
+            if self.compat & SSH_BUG_DHGEX_LARGE != 0 {
+                max = max.min(4096);
+            }
+            let req = GexRequest { min: DH_GRP_MIN, nbits: dh::dh_estimate(need as u32 * 8).min(max), max };
+            let mut b = SshBuf::new();
+            b.put_u8(SSH2_MSG_KEX_DH_GEX_REQUEST);
+            b.put_u32(req.min);
+            b.put_u32(req.nbits);
+            b.put_u32(req.max);
+            debug!("SSH2_MSG_KEX_DH_GEX_REQUEST({}<{}<{}) sent", req.min, req.nbits, req.max);
+            out.push(b.into_vec());
+            self.state = State::AwaitGexGroup(req);
+            return Ok(());
+        }
+
+        let (key, client_pub) = self.client_keypair(alg)?;
+        let mut b = SshBuf::new();
+        b.put_u8(SSH2_MSG_KEX_ECDH_INIT);
+        b.put_string(&client_pub);
+        out.push(b.into_vec());
+        self.state = State::AwaitReply(key, client_pub);
+        Ok(())
+    }
+
+    fn client_keypair(&mut self, alg: KexAlg) -> Result<(ClientKey, Vec<u8>), KexError> {
+        let rng = &mut *self.rng;
+        Ok(match alg {
+            KexAlg::Curve25519Sha256 => {
+                let (k, q) = c25519::client_keypair(rng)?;
+                (ClientKey::C25519(k), q)
+            }
+            KexAlg::EcdhNistp256 | KexAlg::EcdhNistp384 | KexAlg::EcdhNistp521 => {
+                let (k, q) = ecdh::client_keypair(alg.curve().expect("ecdh method"), rng)?;
+                (ClientKey::Ecdh(k), q)
+            }
+            KexAlg::DhGroup14Sha256 | KexAlg::DhGroup16Sha512 | KexAlg::DhGroup18Sha512 => {
+                let group = alg.fixed_group().expect("fixed group method");
+                let need = self.negotiated.as_ref().map_or(0, |n| n.we_need);
+                let (x, e) = dh::dh_gen_key(&group, need, rng)?;
+                let q = mpint_body(&e);
+                (ClientKey::Dh(group, x), q)
+            }
+            KexAlg::Sntrup761x25519Sha512 => {
+                let (k, q) = pq::sntrup761x25519_keypair(rng)?;
+                (ClientKey::Sntrup761(Box::new(k)), q)
+            }
+            KexAlg::Mlkem768x25519Sha256 => {
+                let (k, q) = pq::mlkem768x25519_keypair(rng)?;
+                (ClientKey::Mlkem768(Box::new(k)), q)
+            }
+            KexAlg::DhGexSha256 => unreachable!("group exchange has its own messages"),
+        })
+    }
+
+    // 服务器端：由客户端公钥得到 (Q_S, K)
+    fn server_enc(&mut self, alg: KexAlg, client_pub: &[u8]) -> Result<(Vec<u8>, Vec<u8>), KexError> {
+        let rng = &mut *self.rng;
+        match alg {
+            KexAlg::Curve25519Sha256 => c25519::enc(client_pub, rng),
+            KexAlg::EcdhNistp256 | KexAlg::EcdhNistp384 | KexAlg::EcdhNistp521 => {
+                ecdh::enc(alg.curve().expect("ecdh method"), client_pub, rng)
+            }
+            KexAlg::DhGroup14Sha256 | KexAlg::DhGroup16Sha512 | KexAlg::DhGroup18Sha512 => {
+                let group = alg.fixed_group().expect("fixed group method");
+                let e = parse_mpint_body(client_pub)?;
+                let need = self.negotiated.as_ref().map_or(0, |n| n.we_need);
+                let (y, f) = dh::dh_gen_key(&group, need, rng)?;
+                let k = dh::dh_compute_key(&group, &y, &e)?;
+                Ok((mpint_body(&f), encode_mpint(&k)))
+            }
+            KexAlg::Sntrup761x25519Sha512 => pq::sntrup761x25519_enc(client_pub, rng),
+            KexAlg::Mlkem768x25519Sha256 => pq::mlkem768x25519_enc(client_pub, rng),
+            KexAlg::DhGexSha256 => unreachable!("group exchange has its own messages"),
+        }
+    }
+
+    fn host_key_and_alg(&self) -> Result<(String, Vec<u8>), KexError> {
+        let alg = self.negotiated.as_ref().map(|n| n.hostkey_alg.clone()).unwrap_or_default();
+        match &self.role {
+            Role::Server(signer) => {
+                let blob = signer.public_key(&alg).ok_or_else(|| KexError::NoHostKey(alg.clone()))?;
+                Ok((alg, blob))
+            }
+            Role::Client(_) => unreachable!("server only"),
+        }
+    }
+
+    fn sign(&mut self, alg: &str, h: &[u8]) -> Result<Vec<u8>, KexError> {
+        match &mut self.role {
+            Role::Server(signer) => signer.sign(alg, h),
+            Role::Client(_) => unreachable!("server only"),
+        }
+    }
+
+    fn verify(&mut self, host_key: &[u8], signature: &[u8], h: &[u8]) -> Result<(), KexError> {
+        let alg = self.negotiated.as_ref().map(|n| n.hostkey_alg.clone()).unwrap_or_default();
+        match &mut self.role {
+            Role::Client(verifier) => verifier.verify(&alg, host_key, signature, h),
+            Role::Server(_) => unreachable!("client only"),
+        }
+    }
+
+    fn server_input_init(&mut self, payload: &[u8], out: &mut Vec<Vec<u8>>) -> Result<(), KexError> {
+        let mut b = SshBuf::from_vec(payload.to_vec());
+        b.get_u8()?;
+        let client_pub = b.get_string()?.to_vec();
+        let alg = self.negotiated.as_ref().expect("negotiated").kex;
+        let (hostkey_alg, host_key) = self.host_key_and_alg()?;
+        let (server_pub, shared_secret) = self.server_enc(alg, &client_pub)?;
+        let h = self.hash_context(&host_key).kexgen_hash(alg.hash(), &client_pub, &server_pub, &shared_secret);
+        let signature = self.sign(&hostkey_alg, &h)?;
+
+        let mut b = SshBuf::new();
+        b.put_u8(SSH2_MSG_KEX_ECDH_REPLY);
+        b.put_string(&host_key);
+        b.put_string(&server_pub);
+        b.put_string(&signature);
+        out.push(b.into_vec());
+        self.finish(h, shared_secret, host_key, out);
+        Ok(())
+    }
+
+    fn client_input_reply(
+        &mut self,
+        key: ClientKey,
+        client_pub: &[u8],
+        payload: &[u8],
+        out: &mut Vec<Vec<u8>>,
+    ) -> Result<(), KexError> {
+        let mut b = SshBuf::from_vec(payload.to_vec());
+        b.get_u8()?;
+        let host_key = b.get_string()?.to_vec();
+        let server_pub = b.get_string()?.to_vec();
+        let signature = b.get_string()?.to_vec();
+        let shared_secret = match &key {
+            ClientKey::C25519(k) => c25519::dec(k, &server_pub)?,
+            ClientKey::Ecdh(k) => ecdh::dec(k, &server_pub)?,
+            ClientKey::Dh(group, x) => encode_mpint(&dh::dh_compute_key(group, x, &parse_mpint_body(&server_pub)?)?),
+            ClientKey::Sntrup761(k) => pq::sntrup761x25519_dec(k, &server_pub)?,
+            ClientKey::Mlkem768(k) => pq::mlkem768x25519_dec(k, &server_pub)?,
+        };
+        let hash = self.negotiated.as_ref().expect("negotiated").kex.hash();
+        let h = self.hash_context(&host_key).kexgen_hash(hash, client_pub, &server_pub, &shared_secret);
+        self.verify(&host_key, &signature, &h)?;
+        self.finish(h, shared_secret, host_key, out);
+        Ok(())
+    }
+
+    fn server_input_gex_request(&mut self, payload: &[u8], out: &mut Vec<Vec<u8>>) -> Result<(), KexError> {
+        let mut b = SshBuf::from_vec(payload.to_vec());
+        b.get_u8()?;
+        let min = b.get_u32()?;
+        let nbits = b.get_u32()?;
+        let max = b.get_u32()?;
+        if max < min || nbits < min || max < nbits || max < DH_GRP_MIN {
+            return Err(KexError::Protocol(format!(
+                "DH_GEX_REQUEST, bad parameters: {} !< {} !< {}",
+                min, nbits, max
+            )));
+        }
+        let req = GexRequest { min, nbits, max };
+        let nbits = nbits.clamp(DH_GRP_MIN, DH_GRP_MAX);
+        let (min, max) = (min.max(DH_GRP_MIN), max.min(DH_GRP_MAX));
+        let rng = &mut *self.rng;
+        let mut pick = |n: usize| {
+            let mut r = [0u8; 4];
+            // 随机数失败时退回第一个候选
+            if rng(&mut r).is_err() {
+                return 0;
+            }
+            u32::from_be_bytes(r) as usize % n
+        };
+        let group = dh::choose_dh(&self.moduli, min, nbits, max, &mut pick);
+        debug!("SSH2_MSG_KEX_DH_GEX_GROUP sent ({} bits)", group.bits());
+
+        let mut b = SshBuf::new();
+        b.put_u8(SSH2_MSG_KEX_DH_GEX_GROUP);
+        b.put_bignum2_bytes(&group.p.to_bytes_be());
+        b.put_bignum2_bytes(&group.g.to_bytes_be());
+        out.push(b.into_vec());
+        self.state = State::AwaitGexInit(req, group);
+        Ok(())
+    }
+
+    fn client_input_gex_group(&mut self, req: GexRequest, payload: &[u8], out: &mut Vec<Vec<u8>>) -> Result<(), KexError> {
+        let mut b = SshBuf::from_vec(payload.to_vec());
+        b.get_u8()?;
+        let p = BigUint::from_bytes_be(b.get_bignum2_bytes()?);
+        let g = BigUint::from_bytes_be(b.get_bignum2_bytes()?);
+        let group = DhGroup { p, g };
+        let bits = group.bits();
+        if bits < req.min || bits > req.max {
+            return Err(KexError::Protocol(format!(
+                "DH_GEX group out of range: {} !< {} !< {}",
+                req.min, bits, req.max
+            )));
+        }
+        let need = self.negotiated.as_ref().map_or(0, |n| n.we_need);
+        let (x, e) = dh::dh_gen_key(&group, need, &mut *self.rng)?;
+        let mut b = SshBuf::new();
+        b.put_u8(SSH2_MSG_KEX_DH_GEX_INIT);
+        b.put_bignum2_bytes(&e.to_bytes_be());
+        out.push(b.into_vec());
+        self.state = State::AwaitGexReply(req, group, x, e);
+        Ok(())
+    }
+
+    fn server_input_gex_init(
+        &mut self,
+        req: GexRequest,
+        group: DhGroup,
+        payload: &[u8],
+        out: &mut Vec<Vec<u8>>,
+    ) -> Result<(), KexError> {
+        let mut b = SshBuf::from_vec(payload.to_vec());
+        b.get_u8()?;
+        let e = BigUint::from_bytes_be(b.get_bignum2_bytes()?);
+        let (hostkey_alg, host_key) = self.host_key_and_alg()?;
+        let need = self.negotiated.as_ref().map_or(0, |n| n.we_need);
+        let (y, f) = dh::dh_gen_key(&group, need, &mut *self.rng)?;
+        let shared_secret = encode_mpint(&dh::dh_compute_key(&group, &y, &e)?);
+        let h = self.hash_context(&host_key).gex_hash(KexHash::Sha256, &req, &group, &e, &f, &shared_secret);
+        let signature = self.sign(&hostkey_alg, &h)?;
+
+        let mut b = SshBuf::new();
+        b.put_u8(SSH2_MSG_KEX_DH_GEX_REPLY);
+        b.put_string(&host_key);
+        b.put_bignum2_bytes(&f.to_bytes_be());
+        b.put_string(&signature);
+        out.push(b.into_vec());
+        self.finish(h, shared_secret, host_key, out);
+        Ok(())
+    }
+
+    fn client_input_gex_reply(
+        &mut self,
+        req: GexRequest,
+        group: DhGroup,
+        x: BigUint,
+        e: BigUint,
+        payload: &[u8],
+        out: &mut Vec<Vec<u8>>,
+    ) -> Result<(), KexError> {
+        let mut b = SshBuf::from_vec(payload.to_vec());
+        b.get_u8()?;
+        let host_key = b.get_string()?.to_vec();
+        let f = BigUint::from_bytes_be(b.get_bignum2_bytes()?);
+        let signature = b.get_string()?.to_vec();
+        let shared_secret = encode_mpint(&dh::dh_compute_key(&group, &x, &f)?);
+        let h = self.hash_context(&host_key).gex_hash(KexHash::Sha256, &req, &group, &e, &f, &shared_secret);
+        self.verify(&host_key, &signature, &h)?;
+        self.finish(h, shared_secret, host_key, out);
+        Ok(())
+    }
+}
+
+/// Run a key exchange to completion over `conn`: send our KEXINIT unless
+/// already sent, answer the peer, exchange NEWKEYS and apply strict KEX
+/// sequence number resets. The caller installs the new keys from the
+/// returned output (`KexOutput::install`) before sending or reading
+/// anything else.
+///
+/// Only KEX messages are expected while this runs; during a rekey with
+/// application traffic in flight, feed packets to `Kex::input` directly.
+pub fn run_kex<S: Read + Write>(conn: &mut PacketStream<S>, kex: &mut Kex) -> Result<KexOutput, KexError> {
+    if !kex.kexinit_sent() {
+        let payload = kex.kexinit()?;
+        conn.send(&payload)?;
+    }
+    loop {
+        let payload = conn.read_raw()?;
+        let seqnr = conn.state().seqnr(Mode::In).wrapping_sub(1);
+        for msg in kex.input(seqnr, &payload)? {
+            conn.send(&msg)?;
+            if msg == [SSH2_MSG_NEWKEYS] {
+                debug!("SSH2_MSG_NEWKEYS sent");
+                if kex.strict() {
+                    conn.state_mut().reset_seqnr(Mode::Out);
+                }
+            }
+        }
+        if kex.is_done() {
+            if kex.strict() {
+                conn.state_mut().reset_seqnr(Mode::In);
+            }
+            return Ok(kex.take_output().expect("done"));
+        }
+    }
+}
+
+/// Build SSH2_MSG_EXT_INFO (RFC 8308 §2.3).
+pub fn ext_info_payload(extensions: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut b = SshBuf::new();
+    b.put_u8(SSH2_MSG_EXT_INFO);
+    b.put_u32(extensions.len() as u32);
+    for (name, value) in extensions {
+        b.put_cstring(name);
+        b.put_string(value);
+    }
+    b.into_vec()
+}
+
+/// Parse SSH2_MSG_EXT_INFO into (name, value) pairs.
+pub fn parse_ext_info(payload: &[u8]) -> Result<Vec<(String, Vec<u8>)>, KexError> {
+    let mut b = SshBuf::from_vec(payload.to_vec());
+    if b.get_u8()? != SSH2_MSG_EXT_INFO {
+        return Err(KexError::Protocol("expected SSH2_MSG_EXT_INFO".to_string()));
+    }
+    let n = b.get_u32()?;
+    let mut exts = Vec::new();
+    for _ in 0..n {
+        let name = b.get_cstring()?;
+        let value = b.get_string()?.to_vec();
+        debug!("SSH2_MSG_EXT_INFO: {}", name);
+        exts.push((name, value));
+    }
+    Ok(exts)
+}
@@ -0,0 +1,138 @@
+// kex/pq.rs
+//
+// Post-quantum hybrid key exchange: sntrup761x25519-sha512 (kexsntrup761x25519.c)
+// and mlkem768x25519-sha256 (kexmlkem768x25519.c). The client sends its KEM
+// public key followed by an X25519 point; the server answers with the KEM
+// ciphertext followed by its own point. K is HASH(kem_secret || x25519_secret),
+// encoded as a string rather than an mpint.
+
+use std::io;
+
+use ml_kem::array::Array;
+use ml_kem::{Decapsulate, KeyExport, MlKem768};
+use sha2::{Digest, Sha256, Sha512};
+
+use super::c25519::{self, C25519Key, CURVE25519_SIZE};
+use super::KexError;
+
+pub const SNTRUP761_PUBLICKEY_SIZE: usize = sntrup761::PUBLIC_KEY_SIZE;
+pub const SNTRUP761_CIPHERTEXT_SIZE: usize = sntrup761::CIPHERTEXT_SIZE;
+pub const MLKEM768_PUBLICKEY_SIZE: usize = 1184;
+pub const MLKEM768_CIPHERTEXT_SIZE: usize = 1088;
+
+pub struct Sntrup761Key {
+    kem: sntrup761::DecapsulationKey,
+    x25519: C25519Key,
+}
+
+pub struct Mlkem768Key {
+    kem: ml_kem::DecapsulationKey<MlKem768>,
+    x25519: C25519Key,
+}
+
+fn encode_string(v: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(4 + v.len());
+    out.extend_from_slice(&(v.len() as u32).to_be_bytes());
+    out.extend_from_slice(v);
+    out
+}
+
+// 拆分 KEM 部分与末尾的 X25519 公钥
+fn split_pub(blob: &[u8], kem_len: usize) -> Result<(&[u8], &[u8]), KexError> {
+    if blob.len() != kem_len + CURVE25519_SIZE {
+        return Err(KexError::InvalidPublicKey);
+    }
+    Ok(blob.split_at(kem_len))
+}
+
+pub fn sntrup761x25519_keypair(
+    rng: &mut dyn FnMut(&mut [u8]) -> io::Result<()>,
+) -> Result<(Sntrup761Key, Vec<u8>), KexError> {
+    let mut seed = [0u8; 32];
+    rng(&mut seed).map_err(KexError::Rng)?;
+    let (pk, kem) = sntrup761::generate_key_from_seed(seed);
+    let x25519 = c25519::keypair(rng)?;
+    let mut public = pk.as_ref().to_vec();
+    public.extend_from_slice(&x25519.public);
+    Ok((Sntrup761Key { kem, x25519 }, public))
+}
+
+/// Server side: returns the ciphertext plus our point, and the encoded K.
+pub fn sntrup761x25519_enc(
+    client_pub: &[u8],
+    rng: &mut dyn FnMut(&mut [u8]) -> io::Result<()>,
+) -> Result<(Vec<u8>, Vec<u8>), KexError> {
+    let (kem_pub, x_pub) = split_pub(client_pub, SNTRUP761_PUBLICKEY_SIZE)?;
+    let pk = sntrup761::EncapsulationKey::try_from(kem_pub).map_err(|_| KexError::InvalidPublicKey)?;
+    let mut seed = [0u8; 32];
+    rng(&mut seed).map_err(KexError::Rng)?;
+    let (ct, kem_key) = pk.encapsulate_deterministic(seed);
+    let x25519 = c25519::keypair(rng)?;
+    let x_key = c25519::shared_key_raw(&x25519, x_pub)?;
+
+    let mut h = Sha512::new();
+    h.update(kem_key.as_ref());
+    h.update(x_key);
+    let mut server_pub = ct.as_ref().to_vec();
+    server_pub.extend_from_slice(&x25519.public);
+    Ok((server_pub, encode_string(&h.finalize())))
+}
+
+pub fn sntrup761x25519_dec(key: &Sntrup761Key, server_pub: &[u8]) -> Result<Vec<u8>, KexError> {
+    let (ct, x_pub) = split_pub(server_pub, SNTRUP761_CIPHERTEXT_SIZE)?;
+    let ct = sntrup761::Ciphertext::try_from(ct).map_err(|_| KexError::InvalidPublicKey)?;
+    let kem_key = key.kem.decapsulate(&ct);
+    let x_key = c25519::shared_key_raw(&key.x25519, x_pub)?;
+
+    let mut h = Sha512::new();
+    h.update(kem_key.as_ref());
+    h.update(x_key);
+    Ok(encode_string(&h.finalize()))
+}
+
+pub fn mlkem768x25519_keypair(
+    rng: &mut dyn FnMut(&mut [u8]) -> io::Result<()>,
+) -> Result<(Mlkem768Key, Vec<u8>), KexError> {
+    let mut seed = [0u8; 64];
+    rng(&mut seed).map_err(KexError::Rng)?;
+    let kem = ml_kem::DecapsulationKey::<MlKem768>::from_seed(Array::from(seed));
+    let x25519 = c25519::keypair(rng)?;
+    let mut public = kem.encapsulation_key().to_bytes().to_vec();
+    public.extend_from_slice(&x25519.public);
+    Ok((Mlkem768Key { kem, x25519 }, public))
+}
+
+/// Server side: returns the ciphertext plus our point, and the encoded K.
+pub fn mlkem768x25519_enc(
+    client_pub: &[u8],
+    rng: &mut dyn FnMut(&mut [u8]) -> io::Result<()>,
+) -> Result<(Vec<u8>, Vec<u8>), KexError> {
+    let (kem_pub, x_pub) = split_pub(client_pub, MLKEM768_PUBLICKEY_SIZE)?;
+    let kem_pub = Array::try_from(kem_pub).map_err(|_| KexError::InvalidPublicKey)?;
+    // new() 同时完成 FIPS 203 要求的公钥模数检查
+    let pk = ml_kem::EncapsulationKey::<MlKem768>::new(&kem_pub).map_err(|_| KexError::InvalidPublicKey)?;
+    let mut m = [0u8; 32];
+    rng(&mut m).map_err(KexError::Rng)?;
+    let (ct, kem_key) = pk.encapsulate_deterministic(&Array::from(m));
+    let x25519 = c25519::keypair(rng)?;
+    let x_key = c25519::shared_key_raw(&x25519, x_pub)?;
+
+    let mut h = Sha256::new();
+    h.update(kem_key.as_slice());
+    h.update(x_key);
+    let mut server_pub = ct.to_vec();
+    server_pub.extend_from_slice(&x25519.public);
+    Ok((server_pub, encode_string(&h.finalize())))
+}
+
+pub fn mlkem768x25519_dec(key: &Mlkem768Key, server_pub: &[u8]) -> Result<Vec<u8>, KexError> {
+    let (ct, x_pub) = split_pub(server_pub, MLKEM768_CIPHERTEXT_SIZE)?;
+    let ct = Array::try_from(ct).map_err(|_| KexError::InvalidPublicKey)?;
+    let kem_key = key.kem.decapsulate(&ct);
+    let x_key = c25519::shared_key_raw(&key.x25519, x_pub)?;
+
+    let mut h = Sha256::new();
+    h.update(kem_key.as_slice());
+    h.update(x_key);
+    Ok(encode_string(&h.finalize()))
+}
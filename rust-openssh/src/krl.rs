@@ -0,0 +1,689 @@
+// krl.rs
+//
+// Key revocation lists, after OpenSSH's krl.c (PROTOCOL.krl): the compact
+// binary KRL that revokes certificates by serial number or key ID and
+// plain keys explicitly or by SHA1/SHA256 fingerprint, optionally signed,
+// and the older RevokedKeys form that is just a list of public keys.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{debug, error};
+use sha1::{Digest, Sha1};
+
+use crate::misc::format_absolute_time;
+use crate::sshbuf::{SshBuf, SshBufError};
+use crate::sshkey::{FpHash, KeyError, PrivateKey, PublicKey};
+
+pub const KRL_MAGIC: &[u8] = b"SSHKRL\n\0";
+pub const KRL_FORMAT_VERSION: u32 = 1;
+
+const KRL_SECTION_CERTIFICATES: u8 = 1;
+const KRL_SECTION_EXPLICIT_KEY: u8 = 2;
+const KRL_SECTION_FINGERPRINT_SHA1: u8 = 3;
+const KRL_SECTION_SIGNATURE: u8 = 4;
+const KRL_SECTION_FINGERPRINT_SHA256: u8 = 5;
+
+const KRL_SECTION_CERT_SERIAL_LIST: u8 = 0x20;
+const KRL_SECTION_CERT_SERIAL_RANGE: u8 = 0x21;
+const KRL_SECTION_CERT_SERIAL_BITMAP: u8 = 0x22;
+const KRL_SECTION_CERT_KEY_ID: u8 = 0x23;
+
+const SHA1_LEN: usize = 20;
+const SHA256_LEN: usize = 32;
+
+#[derive(Debug)]
+pub enum KrlError {
+    /// Not a binary KRL (SSH_ERR_KRL_BAD_MAGIC).
+    BadMagic,
+    Format(SshBufError),
+    InvalidFormat(String),
+    /// A revocation that makes no sense, e.g. serial number zero.
+    InvalidArgument(String),
+    Key(KeyError),
+    /// The key is revoked (SSH_ERR_KEY_REVOKED).
+    Revoked,
+    Io(io::Error),
+}
+
+impl fmt::Display for KrlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KrlError::BadMagic => f.write_str("KRL file has invalid magic number"),
+            KrlError::Format(e) => write!(f, "invalid KRL encoding: {}", e),
+            KrlError::InvalidFormat(msg) => write!(f, "invalid format: {}", msg),
+            KrlError::InvalidArgument(msg) => write!(f, "invalid argument: {}", msg),
+            KrlError::Key(e) => write!(f, "{}", e),
+            KrlError::Revoked => f.write_str("Key is revoked"),
+            KrlError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for KrlError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            KrlError::Format(e) => Some(e),
+            KrlError::Key(e) => Some(e),
+            KrlError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<SshBufError> for KrlError {
+    fn from(e: SshBufError) -> Self {
+        KrlError::Format(e)
+    }
+}
+
+impl From<KeyError> for KrlError {
+    fn from(e: KeyError) -> Self {
+        KrlError::Key(e)
+    }
+}
+
+fn invalid<T>(msg: &str) -> Result<T, KrlError> {
+    error!("{}", msg);
+    Err(KrlError::InvalidFormat(msg.to_string()))
+}
+
+/// Certificates revoked under one CA, or under any CA when `ca_key` is
+/// None (struct revoked_certs).
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RevokedCerts {
+    ca_key: Option<PublicKey>,
+    /// Disjoint serial ranges, first serial → last serial. Overlapping and
+    /// adjacent ranges are merged on insertion.
+    serials: BTreeMap<u64, u64>,
+    key_ids: BTreeSet<String>,
+}
+
+impl RevokedCerts {
+    fn new(ca_key: Option<PublicKey>) -> Self {
+        RevokedCerts { ca_key, serials: BTreeMap::new(), key_ids: BTreeSet::new() }
+    }
+
+    // insert_serial_range()
+    fn insert_serials(&mut self, mut lo: u64, mut hi: u64) {
+        if let Some((&plo, &phi)) = self.serials.range(..=lo).next_back() {
+            if phi.saturating_add(1) >= lo {
+                if phi >= hi {
+                    return;
+                }
+                self.serials.remove(&plo);
+                lo = plo;
+            }
+        }
+        // 吞并之后与之重叠或相邻的区间
+        while let Some((&nlo, &nhi)) = self.serials.range(lo..).next() {
+            if nlo > hi.saturating_add(1) {
+                break;
+            }
+            self.serials.remove(&nlo);
+            hi = hi.max(nhi);
+        }
+        self.serials.insert(lo, hi);
+    }
+
+    fn serial_revoked(&self, serial: u64) -> bool {
+        self.serials.range(..=serial).next_back().is_some_and(|(_, &hi)| serial <= hi)
+    }
+
+    // is_cert_revoked()
+    fn cert_revoked(&self, key_id: &str, serial: u64) -> bool {
+        if self.key_ids.contains(key_id) {
+            debug!("revoked by key ID");
+            return true;
+        }
+        // 序列号 0 从不被吊销
+        serial != 0 && self.serial_revoked(serial)
+    }
+}
+
+/// A key revocation list (struct ssh_krl).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Krl {
+    pub krl_version: u64,
+    /// Seconds since the epoch; to_blob() uses the current time while
+    /// this is zero.
+    pub generated_date: u64,
+    pub flags: u64,
+    pub comment: String,
+    /// Plain public key blobs.
+    revoked_keys: BTreeSet<Vec<u8>>,
+    revoked_sha1s: BTreeSet<Vec<u8>>,
+    revoked_sha256s: BTreeSet<Vec<u8>>,
+    /// In the order the CAs were first used.
+    revoked_certs: Vec<RevokedCerts>,
+}
+
+impl Krl {
+    pub fn new() -> Self {
+        Krl::default()
+    }
+
+    // revoked_certs_for_ca_key()
+    fn certs_for_ca(&mut self, ca_key: Option<&PublicKey>) -> &mut RevokedCerts {
+        let ca_key = ca_key.map(|k| k.plain().clone());
+        let at = match self.revoked_certs.iter().position(|rc| rc.ca_key == ca_key) {
+            Some(at) => at,
+            None => {
+                self.revoked_certs.push(RevokedCerts::new(ca_key));
+                self.revoked_certs.len() - 1
+            }
+        };
+        &mut self.revoked_certs[at]
+    }
+
+    /// Revoke certificates with serial numbers `lo` to `hi` inclusive
+    /// signed by `ca_key`, or by any CA if it is None.
+    pub fn revoke_cert_by_serial_range(&mut self, ca_key: Option<&PublicKey>, lo: u64, hi: u64) -> Result<(), KrlError> {
+        if lo > hi || lo == 0 {
+            return Err(KrlError::InvalidArgument(format!("serial range {}-{}", lo, hi)));
+        }
+        self.certs_for_ca(ca_key).insert_serials(lo, hi);
+        Ok(())
+    }
+
+    pub fn revoke_cert_by_serial(&mut self, ca_key: Option<&PublicKey>, serial: u64) -> Result<(), KrlError> {
+        self.revoke_cert_by_serial_range(ca_key, serial, serial)
+    }
+
+    pub fn revoke_cert_by_key_id(&mut self, ca_key: Option<&PublicKey>, key_id: &str) {
+        self.certs_for_ca(ca_key).key_ids.insert(key_id.to_string());
+    }
+
+    /// Revoke a key by its public key blob; certificates are reduced to the
+    /// key they certify.
+    pub fn revoke_key_explicit(&mut self, key: &PublicKey) {
+        self.revoked_keys.insert(key.plain().to_blob());
+    }
+
+    pub fn revoke_key_sha1(&mut self, hash: &[u8]) -> Result<(), KrlError> {
+        if hash.len() != SHA1_LEN {
+            return Err(KrlError::InvalidArgument("bad SHA1 hash length".to_string()));
+        }
+        self.revoked_sha1s.insert(hash.to_vec());
+        Ok(())
+    }
+
+    pub fn revoke_key_sha256(&mut self, hash: &[u8]) -> Result<(), KrlError> {
+        if hash.len() != SHA256_LEN {
+            return Err(KrlError::InvalidArgument("bad SHA256 hash length".to_string()));
+        }
+        self.revoked_sha256s.insert(hash.to_vec());
+        Ok(())
+    }
+
+    /// Revoke a plain key explicitly, or a certificate by serial number
+    /// under its CA, or by key ID when its serial is zero
+    /// (ssh_krl_revoke_key()).
+    pub fn revoke_key(&mut self, key: &PublicKey) -> Result<(), KrlError> {
+        match key.cert() {
+            None => {
+                self.revoke_key_explicit(key);
+                Ok(())
+            }
+            Some(cert) if cert.serial == 0 => {
+                self.revoke_cert_by_key_id(Some(&cert.signature_key), &cert.key_id);
+                Ok(())
+            }
+            Some(cert) => self.revoke_cert_by_serial(Some(&cert.signature_key), cert.serial),
+        }
+    }
+
+    // is_key_revoked()：只看密钥本身，不看证书
+    fn plain_key_revoked(&self, key: &PublicKey) -> bool {
+        if self.revoked_sha1s.contains(&Sha1::digest(key.plain().to_blob())[..]) {
+            debug!("revoked by key SHA1");
+            return true;
+        }
+        if self.revoked_sha256s.contains(&key.fingerprint_raw(FpHash::Sha256)) {
+            debug!("revoked by key SHA256");
+            return true;
+        }
+        if self.revoked_keys.contains(&key.plain().to_blob()) {
+            debug!("revoked explicitly");
+            return true;
+        }
+        false
+    }
+
+    /// Whether `key` is revoked (ssh_krl_check_key()). A certificate is
+    /// revoked if its CA key or the key it certifies is, or if its serial
+    /// number or key ID is revoked under its CA or under any CA.
+    pub fn is_revoked(&self, key: &PublicKey) -> bool {
+        let cert = match key.cert() {
+            None => return self.plain_key_revoked(key),
+            Some(cert) => cert,
+        };
+        if self.plain_key_revoked(&cert.signature_key) || self.plain_key_revoked(key) {
+            return true;
+        }
+        self.revoked_certs
+            .iter()
+            .filter(|rc| rc.ca_key.is_none() || rc.ca_key.as_ref() == Some(cert.signature_key.plain()))
+            .any(|rc| rc.cert_revoked(&cert.key_id, cert.serial))
+    }
+
+    /// Encode as a binary KRL (ssh_krl_to_blob()), signed by each of
+    /// `sign_keys` in turn.
+    pub fn to_blob(&self, sign_keys: &[PrivateKey]) -> Result<Vec<u8>, KrlError> {
+        let mut buf = SshBuf::new();
+        buf.put(KRL_MAGIC);
+        buf.put_u32(KRL_FORMAT_VERSION);
+        buf.put_u64(self.krl_version);
+        let date = match self.generated_date {
+            0 => SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            date => date,
+        };
+        buf.put_u64(date);
+        buf.put_u64(self.flags);
+        buf.put_string(b"");
+        buf.put_cstring(&self.comment);
+
+        for rc in &self.revoked_certs {
+            buf.put_u8(KRL_SECTION_CERTIFICATES);
+            buf.put_string(&certs_section(rc));
+        }
+        for (section, blobs) in [
+            (KRL_SECTION_EXPLICIT_KEY, &self.revoked_keys),
+            (KRL_SECTION_FINGERPRINT_SHA1, &self.revoked_sha1s),
+            (KRL_SECTION_FINGERPRINT_SHA256, &self.revoked_sha256s),
+        ] {
+            if blobs.is_empty() {
+                continue;
+            }
+            let mut sect = SshBuf::new();
+            blobs.iter().for_each(|b| sect.put_string(b));
+            buf.put_u8(section);
+            buf.put_stringb(&sect);
+        }
+        // 每个签名覆盖它之前的全部内容，包括签名密钥本身
+        for key in sign_keys {
+            buf.put_u8(KRL_SECTION_SIGNATURE);
+            buf.put_string(&key.public().to_blob());
+            let sig = key.sign(buf.as_slice(), None)?;
+            buf.put_string(&sig);
+        }
+        Ok(buf.into_vec())
+    }
+
+    /// Parse a binary KRL (ssh_krl_from_blob()). Signatures present are
+    /// always verified; if `sign_ca_keys` is not empty a signed KRL must
+    /// also be signed by one of them.
+    pub fn from_blob(blob: &[u8], sign_ca_keys: &[PublicKey]) -> Result<Krl, KrlError> {
+        if !blob.starts_with(KRL_MAGIC) {
+            return Err(KrlError::BadMagic);
+        }
+        let mut b = SshBuf::from_vec(blob[KRL_MAGIC.len()..].to_vec());
+        let format_version = b.get_u32()?;
+        if format_version != KRL_FORMAT_VERSION {
+            return invalid(&format!("unsupported KRL format version {}", format_version));
+        }
+        let mut krl = Krl::new();
+        krl.krl_version = b.get_u64()?;
+        krl.generated_date = b.get_u64()?;
+        krl.flags = b.get_u64()?;
+        b.get_string()?;
+        krl.comment = b.get_cstring()?;
+
+        let mut signers: Vec<PublicKey> = Vec::new();
+        while !b.is_empty() {
+            let section_start = blob.len() - b.len();
+            let section = b.get_u8()?;
+            let data = b.get_string()?.to_vec();
+            if section == KRL_SECTION_SIGNATURE {
+                let key = PublicKey::from_blob(&data)?;
+                let signed = &blob[..blob.len() - b.len()];
+                let sig = b.get_string()?;
+                key.verify(sig, signed, None)?;
+                if signers.contains(&key) {
+                    return invalid("KRL signed more than once with the same key");
+                }
+                signers.push(key);
+                continue;
+            }
+            if !signers.is_empty() {
+                return invalid("KRL contains non-signature section after signature");
+            }
+            let mut sect = SshBuf::from_vec(data);
+            match section {
+                KRL_SECTION_CERTIFICATES => krl.parse_revoked_certs(&mut sect)?,
+                KRL_SECTION_EXPLICIT_KEY => blob_section(&mut sect, &mut krl.revoked_keys, None)?,
+                KRL_SECTION_FINGERPRINT_SHA1 => blob_section(&mut sect, &mut krl.revoked_sha1s, Some(SHA1_LEN))?,
+                KRL_SECTION_FINGERPRINT_SHA256 => {
+                    blob_section(&mut sect, &mut krl.revoked_sha256s, Some(SHA256_LEN))?
+                }
+                t => return invalid(&format!("Unsupported KRL section {} at offset {}", t, section_start)),
+            }
+            if !sect.is_empty() {
+                return invalid("KRL section contains unparsed data");
+            }
+        }
+
+        // 签名密钥本身被吊销的签名不算数
+        let signed = !signers.is_empty();
+        signers.retain(|k| !krl.is_revoked(k));
+        if signed && signers.is_empty() {
+            error!("All keys used to sign KRL were revoked");
+            return Err(KrlError::Revoked);
+        }
+        // 与 OpenSSH 相同：未签名的 KRL 不受 sign_ca_keys 约束
+        if signed && !sign_ca_keys.is_empty() && !signers.iter().any(|k| sign_ca_keys.contains(k)) {
+            error!("KRL not signed with any trusted key");
+            return Err(KrlError::Key(KeyError::SignatureInvalid));
+        }
+        Ok(krl)
+    }
+
+    fn parse_revoked_certs(&mut self, b: &mut SshBuf) -> Result<(), KrlError> {
+        let ca_blob = b.get_string()?.to_vec();
+        b.get_string()?; // reserved
+        let ca_key = match ca_blob.is_empty() {
+            true => None,
+            false => Some(PublicKey::from_blob(&ca_blob)?),
+        };
+        let ca_key = ca_key.as_ref();
+        while !b.is_empty() {
+            let kind = b.get_u8()?;
+            let mut sub = SshBuf::from_vec(b.get_string()?.to_vec());
+            match kind {
+                KRL_SECTION_CERT_SERIAL_LIST => {
+                    while !sub.is_empty() {
+                        let serial = sub.get_u64()?;
+                        self.revoke_cert_by_serial(ca_key, serial)?;
+                    }
+                }
+                KRL_SECTION_CERT_SERIAL_RANGE => {
+                    let (lo, hi) = (sub.get_u64()?, sub.get_u64()?);
+                    self.revoke_cert_by_serial_range(ca_key, lo, hi)?;
+                }
+                KRL_SECTION_CERT_SERIAL_BITMAP => {
+                    let serial_lo = sub.get_u64()?;
+                    let bitmap = sub.get_bignum2_bytes()?.to_vec();
+                    // 位 i 对应序列号 serial_lo + i，最低位在最后一个字节
+                    for i in 0..bitmap.len() as u64 * 8 {
+                        if bitmap[bitmap.len() - 1 - (i / 8) as usize] & (1 << (i % 8)) == 0 {
+                            continue;
+                        }
+                        match serial_lo.checked_add(i) {
+                            Some(serial) => self.revoke_cert_by_serial(ca_key, serial)?,
+                            None => return invalid("KRL serial bitmap wraps u64"),
+                        }
+                    }
+                }
+                KRL_SECTION_CERT_KEY_ID => {
+                    while !sub.is_empty() {
+                        let key_id = sub.get_cstring()?;
+                        self.revoke_cert_by_key_id(ca_key, &key_id);
+                    }
+                }
+                t => return invalid(&format!("Unsupported KRL certificate section {}", t)),
+            }
+            if !sub.is_empty() {
+                return invalid("KRL certificate section contains unparsed data");
+            }
+        }
+        Ok(())
+    }
+
+    /// The contents in the "ssh-keygen -Q -l" listing format (krl_dump()).
+    pub fn dump(&self) -> String {
+        let mut out = format!("# KRL version {}\n", self.krl_version);
+        // strftime("%Y%m%dT%H%M%S")
+        out += &format!("# Generated at {}\n", format_absolute_time(self.generated_date).replace(['-', ':'], ""));
+        if !self.comment.is_empty() {
+            out += &format!("# Comment: {}\n", printable(&self.comment));
+        }
+        out.push('\n');
+
+        for blob in &self.revoked_keys {
+            match PublicKey::from_blob(blob) {
+                Ok(key) => out += &format!("hash: {} # {}\n", key.fingerprint(FpHash::Sha256), key.name()),
+                Err(e) => error!("parse KRL key: {}", e),
+            }
+        }
+        // 与 OpenSSH 一样以十六进制输出
+        for hash in &self.revoked_sha256s {
+            out += &format!("hash: SHA256:{}\n", hex(hash));
+        }
+        // KRL 规范中没有 SHA1 的关键字，只能作为注释输出
+        for hash in &self.revoked_sha1s {
+            out += &format!("# hash SHA1:{}\n", hex(hash));
+        }
+
+        for rc in &self.revoked_certs {
+            out.push('\n');
+            match &rc.ca_key {
+                None => out += "# Wildcard CA\n",
+                Some(ca) => out += &format!("# CA key {} {}\n", ca.name(), ca.fingerprint(FpHash::Sha256)),
+            }
+            for (&lo, &hi) in &rc.serials {
+                if lo == hi {
+                    out += &format!("serial: {}\n", lo);
+                } else {
+                    out += &format!("serial: {}-{}\n", lo, hi);
+                }
+            }
+            for key_id in &rc.key_ids {
+                out += &format!("id: {}\n", printable(key_id));
+            }
+        }
+        out
+    }
+}
+
+/// Pick the cheapest encoding for the next run of `contig` serials
+/// (choose_next_state()). Costs are in bits: a list costs 64 per serial, a
+/// range always 128 but only ever holds one range, and a bitmap one bit
+/// per serial plus the gap since the previous run. Returns the section
+/// type and whether a new bitmap section must be started.
+fn choose_next_state(current: u8, contig: u64, last: bool, last_gap: u64, next_gap: u64) -> (u8, bool) {
+    // 限制取值，避免下面的计算溢出
+    let contig = contig.min(1 << 31);
+    let last_gap = last_gap.min(1 << 31);
+    let next_gap = next_gap.min(1 << 31);
+
+    // 切换到各候选类型的代价
+    let mut cost_range = 8;
+    let mut cost_bitmap_restart = 8 + 64;
+    let (mut cost_list, mut cost_bitmap) = match current {
+        KRL_SECTION_CERT_SERIAL_LIST => (0, 8 + 64),
+        KRL_SECTION_CERT_SERIAL_BITMAP => (8, 0),
+        _ => (8, 8 + 64),
+    };
+    let tail = if last { 0 } else { 8 + 64 };
+    cost_list += 64 * contig + tail;
+    cost_range += 2 * 64 + tail;
+    cost_bitmap += last_gap + contig + if last { 0 } else { next_gap.min(8 + 64) };
+    cost_bitmap_restart += contig + if last { 0 } else { next_gap.min(8 + 64) };
+
+    let bytes = |bits: u64| bits.div_ceil(8);
+    let mut state = (KRL_SECTION_CERT_SERIAL_BITMAP, false);
+    let mut cost = bytes(cost_bitmap);
+    if bytes(cost_range) < cost {
+        state = (KRL_SECTION_CERT_SERIAL_RANGE, false);
+        cost = bytes(cost_range);
+    }
+    if bytes(cost_list) < cost {
+        state = (KRL_SECTION_CERT_SERIAL_LIST, false);
+        cost = bytes(cost_list);
+    }
+    if bytes(cost_bitmap_restart) < cost {
+        state = (KRL_SECTION_CERT_SERIAL_BITMAP, true);
+    }
+    state
+}
+
+/// A serial number section being built.
+struct SerialSection {
+    kind: u8,
+    data: SshBuf,
+    bitmap_start: u64,
+    /// Big-endian bytes, bit i for serial bitmap_start + i.
+    bitmap: Vec<u8>,
+}
+
+impl SerialSection {
+    fn new(kind: u8, start: u64) -> Self {
+        let mut data = SshBuf::new();
+        if kind == KRL_SECTION_CERT_SERIAL_BITMAP {
+            data.put_u64(start);
+        }
+        SerialSection { kind, data, bitmap_start: start, bitmap: Vec::new() }
+    }
+
+    fn set_bit(&mut self, serial: u64) {
+        let bit = (serial - self.bitmap_start) as usize;
+        let need = bit / 8 + 1;
+        if need > self.bitmap.len() {
+            let grow = need - self.bitmap.len();
+            self.bitmap.splice(0..0, std::iter::repeat_n(0, grow));
+        }
+        let at = self.bitmap.len() - 1 - bit / 8;
+        self.bitmap[at] |= 1 << (bit % 8);
+    }
+
+    fn finish(mut self, out: &mut SshBuf) {
+        if self.kind == KRL_SECTION_CERT_SERIAL_BITMAP {
+            self.data.put_bignum2_bytes(&self.bitmap);
+        }
+        out.put_u8(self.kind);
+        out.put_stringb(&self.data);
+    }
+}
+
+/// Body of a certificates section (revoked_certs_generate()).
+fn certs_section(rc: &RevokedCerts) -> Vec<u8> {
+    let mut buf = SshBuf::new();
+    match &rc.ca_key {
+        Some(ca) => buf.put_string(&ca.to_blob()),
+        None => buf.put_string(b""),
+    }
+    buf.put_string(b"");
+
+    let mut section: Option<SerialSection> = None;
+    let mut last = 0;
+    let mut ranges = rc.serials.iter().peekable();
+    while let Some((&lo, &hi)) = ranges.next() {
+        let contig = 1 + (hi - lo);
+        let next = ranges.peek().map(|(&nlo, _)| nlo);
+        let current = section.as_ref().map_or(0, |s| s.kind);
+        let last_gap = if current == 0 { 0 } else { lo - last };
+        let (kind, force_new) =
+            choose_next_state(current, contig, next.is_none(), last_gap, next.map_or(0, |n| n - hi));
+        // 范围段只放一个范围
+        if force_new || kind != current || current == KRL_SECTION_CERT_SERIAL_RANGE {
+            if let Some(s) = section.take() {
+                s.finish(&mut buf);
+            }
+            section = Some(SerialSection::new(kind, lo));
+        }
+        let s = section.as_mut().unwrap();
+        match kind {
+            KRL_SECTION_CERT_SERIAL_LIST => (lo..=hi).for_each(|serial| s.data.put_u64(serial)),
+            KRL_SECTION_CERT_SERIAL_RANGE => {
+                s.data.put_u64(lo);
+                s.data.put_u64(hi);
+            }
+            _ => (lo..=hi).for_each(|serial| s.set_bit(serial)),
+        }
+        last = hi;
+    }
+    if let Some(s) = section {
+        s.finish(&mut buf);
+    }
+
+    if !rc.key_ids.is_empty() {
+        let mut sect = SshBuf::new();
+        rc.key_ids.iter().for_each(|id| sect.put_cstring(id));
+        buf.put_u8(KRL_SECTION_CERT_KEY_ID);
+        buf.put_stringb(&sect);
+    }
+    buf.into_vec()
+}
+
+fn hex(b: &[u8]) -> String {
+    b.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+// 不让嵌入的换行等控制字符弄乱输出
+fn printable(s: &str) -> String {
+    s.chars().map(|c| if c.is_control() { '?' } else { c }).collect()
+}
+
+fn blob_section(b: &mut SshBuf, tree: &mut BTreeSet<Vec<u8>>, expected_len: Option<usize>) -> Result<(), KrlError> {
+    while !b.is_empty() {
+        let blob = b.get_string()?;
+        if expected_len.is_some_and(|len| len != blob.len()) {
+            return invalid("KRL fingerprint has bad length");
+        }
+        tree.insert(blob.to_vec());
+    }
+    Ok(())
+}
+
+/// The contents of a RevokedKeys file: a binary KRL, or else a list of
+/// public keys, one per line, as written by older versions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RevokedKeys {
+    Krl(Krl),
+    Keys(Vec<PublicKey>),
+}
+
+impl RevokedKeys {
+    /// Read a RevokedKeys file. A key list line that cannot be parsed is
+    /// an error rather than being skipped, so that a damaged file fails
+    /// closed; only keys of unsupported sizes are ignored (sshkey_in_file()).
+    pub fn load(path: &Path) -> Result<RevokedKeys, KrlError> {
+        let data = fs::read(path).map_err(KrlError::Io)?;
+        match Krl::from_blob(&data, &[]) {
+            Err(KrlError::BadMagic) => {}
+            r => return r.map(RevokedKeys::Krl),
+        }
+        let text = String::from_utf8(data)
+            .map_err(|_| KrlError::InvalidFormat(format!("{}: not a KRL or a key list", path.display())))?;
+        let mut keys = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim_start_matches([' ', '\t']);
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match PublicKey::from_openssh_line(line) {
+                Ok((key, _)) => keys.push(key),
+                Err(KeyError::KeyLength(_)) => continue,
+                Err(e) => return Err(KrlError::InvalidFormat(format!("{}:{}: {}", path.display(), i + 1, e))),
+            }
+        }
+        Ok(RevokedKeys::Keys(keys))
+    }
+
+    /// Whether `key` is revoked. A key list revokes a listed key, any
+    /// certificate for it, and any certificate signed by it.
+    pub fn is_revoked(&self, key: &PublicKey) -> bool {
+        match self {
+            RevokedKeys::Krl(krl) => krl.is_revoked(key),
+            RevokedKeys::Keys(keys) => keys.iter().any(|k| {
+                k.plain() == key.plain() || key.cert().is_some_and(|c| c.signature_key.plain() == k.plain())
+            }),
+        }
+    }
+}
+
+/// Check `key` against the RevokedKeys file at `path`
+/// (sshkey_check_revoked()): Err(KrlError::Revoked) if it is revoked, or
+/// another error if the file cannot be used, which callers must also
+/// treat as a refusal.
+pub fn check_revoked(key: &PublicKey, path: &Path) -> Result<(), KrlError> {
+    if RevokedKeys::load(path)?.is_revoked(key) {
+        return Err(KrlError::Revoked);
+    }
+    Ok(())
+}
@@ -0,0 +1,29 @@
+pub mod addrmatch;
+pub mod atomicio;
+#[cfg(feature = "async")]
+pub mod atomicio_async;
+pub mod audit;
+pub mod audit_bsm;
+pub mod audit_json;
+#[cfg(target_os = "linux")]
+pub mod audit_linux;
+pub mod auth_options;
+pub mod authfile;
+pub mod bwlimit;
+pub mod cipher;
+pub mod compat;
+pub mod compress;
+pub mod hostfile;
+pub mod journald;
+pub mod kex;
+pub mod krl;
+pub mod log;
+pub mod mac;
+pub mod misc;
+pub mod packet;
+pub mod progressmeter;
+pub mod ssh2;
+pub mod sshsig;
+pub mod sshbuf;
+pub mod sshkey;
+pub mod umac;
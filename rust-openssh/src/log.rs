@@ -0,0 +1,487 @@
+// log.rs
+//
+// OpenSSH-compatible logging: LogLevel/SyslogFacility as understood by
+// sshd_config, syslog output over /dev/log (RFC 3164 or RFC 5424),
+// LogVerbose overrides and log_ratelimit().
+
+use std::fmt;
+use std::io::{self, Write};
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::addrmatch::match_pattern_list;
+
+pub const PATH_LOG: &str = "/dev/log";
+
+/// Verbosity levels in increasing order (SYSLOG_LEVEL_*).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Quiet,
+    Fatal,
+    Error,
+    Info,
+    Verbose,
+    Debug1,
+    Debug2,
+    Debug3,
+}
+
+const LOG_LEVELS: &[(&str, LogLevel)] = &[
+    ("QUIET", LogLevel::Quiet),
+    ("FATAL", LogLevel::Fatal),
+    ("ERROR", LogLevel::Error),
+    ("INFO", LogLevel::Info),
+    ("VERBOSE", LogLevel::Verbose),
+    ("DEBUG", LogLevel::Debug1),
+    ("DEBUG1", LogLevel::Debug1),
+    ("DEBUG2", LogLevel::Debug2),
+    ("DEBUG3", LogLevel::Debug3),
+];
+
+impl LogLevel {
+    /// Parse a `LogLevel` keyword, case-insensitively (log_level_number()).
+    pub fn from_name(name: &str) -> Option<Self> {
+        LOG_LEVELS.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, l)| *l)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogLevel::Debug1 => "DEBUG1",
+            _ => LOG_LEVELS.iter().find(|(_, l)| l == self).map(|(n, _)| *n).unwrap(),
+        }
+    }
+
+    /// Map a `log` crate level: warn!() behaves like logit() and info!()
+    /// like verbose(); debug2 is only reachable through `sshlog`.
+    pub fn from_log_level(level: ::log::Level) -> Self {
+        match level {
+            ::log::Level::Error => LogLevel::Error,
+            ::log::Level::Warn => LogLevel::Info,
+            ::log::Level::Info => LogLevel::Verbose,
+            ::log::Level::Debug => LogLevel::Debug1,
+            ::log::Level::Trace => LogLevel::Debug3,
+        }
+    }
+
+    fn syslog_severity(&self) -> u8 {
+        match self {
+            LogLevel::Quiet | LogLevel::Fatal => 2,
+            LogLevel::Error => 3,
+            LogLevel::Info | LogLevel::Verbose => 6,
+            LogLevel::Debug1 | LogLevel::Debug2 | LogLevel::Debug3 => 7,
+        }
+    }
+
+    /// The label put before a message. As in OpenSSH, errors are only
+    /// labelled when not logging to stderr.
+    pub fn prefix(&self, on_stderr: bool) -> &'static str {
+        match self {
+            LogLevel::Fatal if !on_stderr => "fatal: ",
+            LogLevel::Error if !on_stderr => "error: ",
+            LogLevel::Debug1 => "debug1: ",
+            LogLevel::Debug2 => "debug2: ",
+            LogLevel::Debug3 => "debug3: ",
+            _ => "",
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Syslog facilities accepted by `SyslogFacility`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyslogFacility {
+    Daemon,
+    User,
+    Auth,
+    AuthPriv,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+}
+
+const LOG_FACILITIES: &[(&str, SyslogFacility, u8)] = &[
+    ("DAEMON", SyslogFacility::Daemon, 3),
+    ("USER", SyslogFacility::User, 1),
+    ("AUTH", SyslogFacility::Auth, 4),
+    ("AUTHPRIV", SyslogFacility::AuthPriv, 10),
+    ("LOCAL0", SyslogFacility::Local0, 16),
+    ("LOCAL1", SyslogFacility::Local1, 17),
+    ("LOCAL2", SyslogFacility::Local2, 18),
+    ("LOCAL3", SyslogFacility::Local3, 19),
+    ("LOCAL4", SyslogFacility::Local4, 20),
+    ("LOCAL5", SyslogFacility::Local5, 21),
+    ("LOCAL6", SyslogFacility::Local6, 22),
+    ("LOCAL7", SyslogFacility::Local7, 23),
+];
+
+impl SyslogFacility {
+    pub fn from_name(name: &str) -> Option<Self> {
+        LOG_FACILITIES.iter().find(|(n, _, _)| n.eq_ignore_ascii_case(name)).map(|(_, f, _)| *f)
+    }
+
+    pub fn name(&self) -> &'static str {
+        LOG_FACILITIES.iter().find(|(_, f, _)| f == self).map(|(n, _, _)| *n).unwrap()
+    }
+
+    /// Facility number as used in the syslog PRI field.
+    pub fn code(&self) -> u8 {
+        LOG_FACILITIES.iter().find(|(_, f, _)| f == self).map(|(_, _, c)| *c).unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyslogFormat {
+    Rfc3164,
+    Rfc5424,
+}
+
+/// Logging configuration, as assembled from sshd_config and the command line.
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    pub ident: String,
+    pub level: LogLevel,
+    pub facility: SyslogFacility,
+    pub format: SyslogFormat,
+    /// Write to stderr instead of syslog (sshd -D -e).
+    pub on_stderr: bool,
+    /// `LogVerbose` pattern lists, matched against `file:function():line`.
+    /// For messages from the `log` macros and `log_ratelimit!` the function
+    /// is the caller's module path.
+    pub verbose: Vec<String>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            ident: "sshd".to_string(),
+            level: LogLevel::Info,
+            facility: SyslogFacility::Auth,
+            format: SyslogFormat::Rfc3164,
+            on_stderr: false,
+            verbose: Vec::new(),
+        }
+    }
+}
+
+impl LogConfig {
+    /// Whether a message from `file`/`func`/`line` is forced by LogVerbose.
+    /// As in OpenSSH, each entry is matched as a whole against the tag
+    /// "basename:func():line".
+    pub fn log_verbose_match(&self, file: &str, func: &str, line: u32) -> bool {
+        if self.verbose.is_empty() {
+            return false;
+        }
+        let base = file.rsplit('/').next().unwrap_or(file);
+        let tag = format!("{:.48}:{:.48}():{}", base, func, line);
+        self.verbose.iter().any(|entry| match_pattern_list(&tag, entry, false) == 1)
+    }
+}
+
+/// Format one syslog datagram.
+pub fn syslog_format(
+    config: &LogConfig,
+    level: LogLevel,
+    hostname: &str,
+    now: SystemTime,
+    msg: &str,
+) -> String {
+    let pri = config.facility.code() as u32 * 8 + level.syslog_severity() as u32;
+    let pid = std::process::id();
+    let tm = broken_down_time(now, config.format == SyslogFormat::Rfc3164);
+    match config.format {
+        SyslogFormat::Rfc3164 => {
+            const MONTHS: [&str; 12] =
+                ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+            format!(
+                "<{}>{} {:2} {:02}:{:02}:{:02} {}[{}]: {}",
+                pri, MONTHS[tm.month as usize - 1], tm.day, tm.hour, tm.min, tm.sec, config.ident, pid, msg
+            )
+        }
+        SyslogFormat::Rfc5424 => format!(
+            "<{}>1 {:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z {} {} {} - - {}",
+            pri, tm.year, tm.month, tm.day, tm.hour, tm.min, tm.sec, tm.usec, hostname, config.ident, pid, msg
+        ),
+    }
+}
+
+struct Tm {
+    year: i32,
+    month: u32,
+    day: u32,
+    hour: u32,
+    min: u32,
+    sec: u32,
+    usec: u32,
+}
+
+fn broken_down_time(t: SystemTime, local: bool) -> Tm {
+    let d = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = d.as_secs() as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        if local {
+            libc::localtime_r(&secs, &mut tm);
+        } else {
+            libc::gmtime_r(&secs, &mut tm);
+        }
+    }
+    Tm {
+        year: tm.tm_year + 1900,
+        month: tm.tm_mon as u32 + 1,
+        day: tm.tm_mday as u32,
+        hour: tm.tm_hour as u32,
+        min: tm.tm_min as u32,
+        sec: tm.tm_sec as u32,
+        usec: d.subsec_micros(),
+    }
+}
+
+fn local_hostname() -> String {
+    let mut buf = [0u8; 256];
+    let rc = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if rc != 0 {
+        return "-".to_string();
+    }
+    let end = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..end]).into_owned()
+}
+
+/// The process-wide logger.
+pub struct SshLogger {
+    config: LogConfig,
+    hostname: String,
+    syslog: Mutex<Option<UnixDatagram>>,
+}
+
+impl SshLogger {
+    pub fn new(config: LogConfig) -> Self {
+        SshLogger { config, hostname: local_hostname(), syslog: Mutex::new(None) }
+    }
+
+    /// Log to a syslog socket other than /dev/log.
+    pub fn with_syslog_socket<P: AsRef<Path>>(config: LogConfig, path: P) -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        let logger = SshLogger::new(config);
+        *logger.syslog.lock().unwrap() = Some(socket);
+        Ok(logger)
+    }
+
+    pub fn config(&self) -> &LogConfig {
+        &self.config
+    }
+
+    /// Core logging entry point (sshlog()).
+    pub fn sshlog(&self, file: &str, func: &str, line: u32, level: LogLevel, msg: &str) {
+        let forced = self.config.log_verbose_match(file, func, line);
+        if level > self.config.level && !forced {
+            return;
+        }
+        // 被 LogVerbose 强制输出的调试信息按 INFO 级别写入 syslog
+        let level = if forced && level > LogLevel::Verbose { LogLevel::Info } else { level };
+        // 去掉控制字符，防止日志注入
+        let msg: String = msg.chars().map(|c| if c.is_control() { '?' } else { c }).collect();
+
+        if self.config.on_stderr {
+            let _ = write!(io::stderr().lock(), "{}{}\r\n", level.prefix(true), msg);
+            return;
+        }
+        let text = format!("{}{}", level.prefix(false), msg);
+        let datagram = syslog_format(&self.config, level, &self.hostname, SystemTime::now(), &text);
+        let mut syslog = self.syslog.lock().unwrap();
+        if syslog.is_none() {
+            *syslog = UnixDatagram::unbound().and_then(|s| s.connect(PATH_LOG).map(|_| s)).ok();
+        }
+        let sent = syslog.as_ref().map(|s| s.send(datagram.as_bytes()));
+        if !matches!(sent, Some(Ok(_))) {
+            // syslog 不可用时退回到 stderr
+            *syslog = None;
+            let _ = write!(io::stderr().lock(), "{}\r\n", text);
+        }
+    }
+}
+
+impl ::log::Log for SshLogger {
+    fn enabled(&self, _metadata: &::log::Metadata) -> bool {
+        // LogVerbose 可能强制输出任意级别，过滤在 sshlog 中进行
+        true
+    }
+
+    fn log(&self, record: &::log::Record) {
+        let level = LogLevel::from_log_level(record.level());
+        let func = record.module_path().unwrap_or("");
+        let msg = record.args().to_string();
+        self.sshlog(record.file().unwrap_or(""), func, record.line().unwrap_or(0), level, &msg);
+    }
+
+    fn flush(&self) {}
+}
+
+/// Install the OpenSSH-style logger as the `log` crate backend.
+pub fn log_init(config: LogConfig) -> Result<(), ::log::SetLoggerError> {
+    let max = if config.verbose.is_empty() {
+        match config.level {
+            LogLevel::Quiet | LogLevel::Fatal | LogLevel::Error => ::log::LevelFilter::Error,
+            LogLevel::Info => ::log::LevelFilter::Warn,
+            LogLevel::Verbose => ::log::LevelFilter::Info,
+            LogLevel::Debug1 | LogLevel::Debug2 => ::log::LevelFilter::Debug,
+            LogLevel::Debug3 => ::log::LevelFilter::Trace,
+        }
+    } else {
+        ::log::LevelFilter::Trace
+    };
+    ::log::set_boxed_logger(Box::new(SshLogger::new(config)))?;
+    ::log::set_max_level(max);
+    Ok(())
+}
+
+/// Outcome of `LogRateLimitCtx::consider`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimit {
+    /// Below threshold: log normally.
+    Log,
+    /// Threshold just crossed: log this event and announce rate limiting.
+    LogStart,
+    /// Periodic message while limited, with the count suppressed since
+    /// the previous one.
+    LogSummary(u32),
+    /// Hysteresis expired: rate limiting ends after this many suppressed.
+    LogEnd(u32),
+    /// Drop this event.
+    Suppress,
+}
+
+/// State for log_ratelimit(). Events accumulate one per call and decay one
+/// per second; above `threshold` only one event every `log_every` seconds
+/// is logged until the rate stays below threshold for `hysteresis` seconds.
+#[derive(Debug, Clone)]
+pub struct LogRateLimitCtx {
+    pub threshold: u32,
+    pub max_accum: u32,
+    pub hysteresis: u32,
+    pub log_every: u32,
+
+    pub last_event: Option<Instant>,
+    pub accumulated_events: u32,
+    pub ratelimit_active: bool,
+    pub ratelimit_start: Option<Instant>,
+    pub last_log: Option<Instant>,
+    pub hysteresis_start: Option<Instant>,
+    pub ratelimited_events: u32,
+}
+
+impl LogRateLimitCtx {
+    pub fn new(threshold: u32, max_accum: u32, hysteresis: u32, log_every: u32) -> Self {
+        LogRateLimitCtx {
+            threshold,
+            max_accum,
+            hysteresis,
+            log_every,
+            last_event: None,
+            accumulated_events: 0,
+            ratelimit_active: false,
+            ratelimit_start: None,
+            last_log: None,
+            hysteresis_start: None,
+            ratelimited_events: 0,
+        }
+    }
+
+    /// Account for one event at `now` and decide whether to log it.
+    pub fn consider(&mut self, now: Instant) -> RateLimit {
+        if self.threshold == 0 {
+            return RateLimit::Log;
+        }
+
+        // 按经过的秒数衰减累计值，再计入本次事件
+        if let Some(last) = self.last_event {
+            let elapsed = now.saturating_duration_since(last).as_secs();
+            let decay = u32::try_from(elapsed).unwrap_or(u32::MAX);
+            self.accumulated_events = self.accumulated_events.saturating_sub(decay);
+        }
+        self.accumulated_events = (self.accumulated_events + 1).min(self.max_accum.max(1));
+        self.last_event = Some(now);
+
+        let over = self.accumulated_events > self.threshold;
+        if !self.ratelimit_active {
+            if !over {
+                return RateLimit::Log;
+            }
+            self.ratelimit_active = true;
+            self.ratelimit_start = Some(now);
+            self.last_log = Some(now);
+            self.hysteresis_start = None;
+            self.ratelimited_events = 0;
+            return RateLimit::LogStart;
+        }
+
+        if over {
+            self.hysteresis_start = None;
+        } else {
+            let start = *self.hysteresis_start.get_or_insert(now);
+            if now.saturating_duration_since(start) >= Duration::from_secs(self.hysteresis as u64) {
+                self.ratelimit_active = false;
+                self.hysteresis_start = None;
+                return RateLimit::LogEnd(std::mem::take(&mut self.ratelimited_events));
+            }
+        }
+
+        let due = self.last_log.is_none_or(|last| {
+            now.saturating_duration_since(last) >= Duration::from_secs(self.log_every as u64)
+        });
+        if self.log_every > 0 && due {
+            self.last_log = Some(now);
+            return RateLimit::LogSummary(std::mem::take(&mut self.ratelimited_events));
+        }
+        self.ratelimited_events += 1;
+        RateLimit::Suppress
+    }
+}
+
+/// Log `msg` through `logger` subject to rate limiting, annotating it with
+/// the number of suppressed events as OpenSSH does. `file`/`func`/`line`
+/// name the caller for LogVerbose; use the `log_ratelimit!` macro to fill
+/// them in.
+#[allow(clippy::too_many_arguments)]
+pub fn log_ratelimit(
+    logger: &SshLogger,
+    ctx: &mut LogRateLimitCtx,
+    now: Instant,
+    file: &str,
+    func: &str,
+    line: u32,
+    level: LogLevel,
+    msg: &str,
+) {
+    let text = match ctx.consider(now) {
+        RateLimit::Log => msg.to_string(),
+        RateLimit::LogStart => format!("{} (rate limiting messages)", msg),
+        RateLimit::LogSummary(0) => msg.to_string(),
+        RateLimit::LogSummary(n) => format!("{} ({} events suppressed)", msg, n),
+        RateLimit::LogEnd(n) => format!("{} (rate limiting ended, {} events suppressed)", msg, n),
+        RateLimit::Suppress => return,
+    };
+    logger.sshlog(file, func, line, level, &text);
+}
+
+/// `log_ratelimit(logger, ctx, now, level, msg)` attributed to the calling
+/// source location. Rust has no equivalent of `__func__`, so the caller's
+/// `module_path!()` stands in for the function name that LogVerbose sees.
+#[macro_export]
+macro_rules! log_ratelimit {
+    ($logger:expr, $ctx:expr, $now:expr, $level:expr, $msg:expr) => {
+        $crate::log::log_ratelimit($logger, $ctx, $now, file!(), module_path!(), line!(), $level, $msg)
+    };
+}
@@ -0,0 +1,134 @@
+// mac.rs
+//
+// Transport MACs, after OpenSSH's mac.c: hmac-sha1, hmac-sha2-256/512 and
+// umac-64/128@openssh.com, each also in its encrypt-then-MAC variant
+// (`*-etm@openssh.com`). Contexts implement `PacketMac`.
+
+use hmac::digest::{KeyInit, OutputSizeUser};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+use crate::packet::{PacketError, PacketMac};
+use crate::umac::{Umac, UMAC_KEY_LEN};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    HmacSha1,
+    HmacSha256,
+    HmacSha512,
+    Umac64,
+    Umac128,
+}
+
+/// Static description of a MAC (struct macalg).
+#[derive(Debug)]
+pub struct MacInfo {
+    pub name: &'static str,
+    pub key_len: usize,
+    pub mac_len: usize,
+    pub etm: bool,
+    kind: Kind,
+}
+
+const MACS: &[MacInfo] = &[
+    MacInfo { name: "hmac-sha1", key_len: 20, mac_len: 20, etm: false, kind: Kind::HmacSha1 },
+    MacInfo { name: "hmac-sha2-256", key_len: 32, mac_len: 32, etm: false, kind: Kind::HmacSha256 },
+    MacInfo { name: "hmac-sha2-512", key_len: 64, mac_len: 64, etm: false, kind: Kind::HmacSha512 },
+    MacInfo { name: "umac-64@openssh.com", key_len: 16, mac_len: 8, etm: false, kind: Kind::Umac64 },
+    MacInfo { name: "umac-128@openssh.com", key_len: 16, mac_len: 16, etm: false, kind: Kind::Umac128 },
+    MacInfo { name: "hmac-sha1-etm@openssh.com", key_len: 20, mac_len: 20, etm: true, kind: Kind::HmacSha1 },
+    MacInfo { name: "hmac-sha2-256-etm@openssh.com", key_len: 32, mac_len: 32, etm: true, kind: Kind::HmacSha256 },
+    MacInfo { name: "hmac-sha2-512-etm@openssh.com", key_len: 64, mac_len: 64, etm: true, kind: Kind::HmacSha512 },
+    MacInfo { name: "umac-64-etm@openssh.com", key_len: 16, mac_len: 8, etm: true, kind: Kind::Umac64 },
+    MacInfo { name: "umac-128-etm@openssh.com", key_len: 16, mac_len: 16, etm: true, kind: Kind::Umac128 },
+];
+
+pub fn mac_by_name(name: &str) -> Option<&'static MacInfo> {
+    MACS.iter().find(|m| m.name == name)
+}
+
+/// Comma-separated list of supported MACs (mac_alg_list()).
+pub fn mac_alg_list() -> String {
+    MACS.iter().map(|m| m.name).collect::<Vec<_>>().join(",")
+}
+
+/// True if every name in the comma-separated `names` is a known MAC.
+pub fn mac_valid(names: &str) -> bool {
+    !names.is_empty() && names.split(',').all(|n| mac_by_name(n).is_some())
+}
+
+/// Create a MAC context keyed with the first `key_len` bytes of `key`.
+pub fn mac_init(name: &str, key: &[u8]) -> Result<Box<dyn PacketMac>, PacketError> {
+    let info = mac_by_name(name).ok_or_else(|| PacketError::Protocol(format!("unknown MAC {}", name)))?;
+    if key.len() < info.key_len {
+        return Err(PacketError::Protocol(format!("{}: key too short", name)));
+    }
+    let key = &key[..info.key_len];
+    Ok(match info.kind {
+        Kind::HmacSha1 => Box::new(HmacMac::<Hmac<Sha1>>::new(key, info.etm)),
+        Kind::HmacSha256 => Box::new(HmacMac::<Hmac<Sha256>>::new(key, info.etm)),
+        Kind::HmacSha512 => Box::new(HmacMac::<Hmac<Sha512>>::new(key, info.etm)),
+        Kind::Umac64 | Kind::Umac128 => Box::new(UmacMac::new(key, info.mac_len, info.etm)),
+    })
+}
+
+/// HMAC over `uint32 seqnr || packet` (RFC 4253 §6.4, RFC 6668).
+pub struct HmacMac<M> {
+    ctx: M,
+    etm: bool,
+}
+
+impl<M: Mac + KeyInit> HmacMac<M> {
+    pub fn new(key: &[u8], etm: bool) -> HmacMac<M> {
+        let ctx = <M as KeyInit>::new_from_slice(key).expect("HMAC accepts any key length");
+        HmacMac { ctx, etm }
+    }
+}
+
+impl<M: Mac + Clone + Send> PacketMac for HmacMac<M> {
+    fn mac_len(&self) -> usize {
+        <M as OutputSizeUser>::output_size()
+    }
+
+    fn is_etm(&self) -> bool {
+        self.etm
+    }
+
+    fn compute(&mut self, seqnr: u32, data: &[u8]) -> Vec<u8> {
+        let mut ctx = self.ctx.clone();
+        ctx.update(&seqnr.to_be_bytes());
+        ctx.update(data);
+        ctx.finalize().into_bytes().to_vec()
+    }
+}
+
+/// umac-64@openssh.com and umac-128@openssh.com: the nonce is the
+/// sequence number as a 64-bit big-endian integer.
+pub struct UmacMac {
+    ctx: Umac,
+    mac_len: usize,
+    etm: bool,
+}
+
+impl UmacMac {
+    pub fn new(key: &[u8], mac_len: usize, etm: bool) -> UmacMac {
+        let mut k = [0u8; UMAC_KEY_LEN];
+        k.copy_from_slice(&key[..UMAC_KEY_LEN]);
+        UmacMac { ctx: Umac::new(&k, mac_len), mac_len, etm }
+    }
+}
+
+impl PacketMac for UmacMac {
+    fn mac_len(&self) -> usize {
+        self.mac_len
+    }
+
+    fn is_etm(&self) -> bool {
+        self.etm
+    }
+
+    fn compute(&mut self, seqnr: u32, data: &[u8]) -> Vec<u8> {
+        self.ctx.mac(data, &u64::from(seqnr).to_be_bytes())
+    }
+}
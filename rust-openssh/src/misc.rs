@@ -0,0 +1,395 @@
+// misc.rs
+//
+// Small helpers shared by the tools, after OpenSSH's misc.c: time
+// intervals such as "1h30m", absolute times such as "20240101" or
+// "202401011200Z", and the ISO-like timestamps printed for them; %-token
+// expansion and command line splitting; user account lookups; the
+// StrictModes path check; and running helper commands as another user.
+
+use std::env;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, error};
+
+/// _PATH_STDPATH: the PATH given to helper commands.
+pub const PATH_STDPATH: &str = "/usr/bin:/bin:/usr/sbin:/sbin";
+
+/// Parse a time interval (convtime()): a sequence of numbers, each
+/// optionally followed by one of s, m, h, d or w, which are summed. A
+/// bare number is seconds.
+pub fn convtime(s: &str) -> Option<u64> {
+    if s.is_empty() {
+        return None;
+    }
+    let mut total: u64 = 0;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if digits == 0 {
+            return None;
+        }
+        let value: u64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let multiplier = match rest.chars().next() {
+            None => 1,
+            Some(unit) => {
+                rest = &rest[1..];
+                match unit.to_ascii_lowercase() {
+                    's' => 1,
+                    'm' => 60,
+                    'h' => 60 * 60,
+                    'd' => 24 * 60 * 60,
+                    'w' => 7 * 24 * 60 * 60,
+                    _ => return None,
+                }
+            }
+        };
+        total = total.checked_add(value.checked_mul(multiplier)?)?;
+    }
+    // 与 OpenSSH 一样不超过 INT_MAX
+    (total <= i32::MAX as u64).then_some(total)
+}
+
+/// Parse YYYYMMDD, YYYYMMDDHHMM or YYYYMMDDHHMMSS as local time, or as
+/// UTC with a trailing "Z" (parse_absolute_time()). Returns seconds
+/// since the epoch.
+pub fn parse_absolute_time(s: &str) -> Option<u64> {
+    let (digits, utc) = match s.strip_suffix(['Z', 'z']) {
+        Some(d) => (d, true),
+        None => (s, false),
+    };
+    if !matches!(digits.len(), 8 | 12 | 14) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |at: usize, len: usize| digits.get(at..at + len).map_or(0, |f| f.parse::<i32>().unwrap());
+    let (year, month, day) = (field(0, 4), field(4, 2), field(6, 2));
+    let (hour, min, sec) = (field(8, 2), field(10, 2), field(12, 2));
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    tm.tm_year = year - 1900;
+    tm.tm_mon = month - 1;
+    tm.tm_mday = day;
+    tm.tm_hour = hour;
+    tm.tm_min = min;
+    tm.tm_sec = sec;
+    tm.tm_isdst = -1;
+    let t = unsafe {
+        if utc {
+            libc::timegm(&mut tm)
+        } else {
+            libc::mktime(&mut tm)
+        }
+    };
+    // mktime() 会把 2 月 30 日之类的日期规范化，这里视为无效
+    if t < 0 || tm.tm_mday != day || tm.tm_mon != month - 1 {
+        return None;
+    }
+    Some(t as u64)
+}
+
+/// Format a time as local "YYYY-MM-DDTHH:MM:SS" (format_absolute_time()).
+pub fn format_absolute_time(t: u64) -> String {
+    let secs = t.min(i64::MAX as u64) as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&secs, &mut tm) };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+/// Expand %-tokens in `s` (percent_expand()): "%%" is a literal percent
+/// and "%x" is replaced by the value for key 'x' in `keys`. An unknown
+/// key or a trailing '%' is an error.
+pub fn percent_expand(s: &str, keys: &[(char, &str)]) -> Result<String, String> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => out.push('%'),
+            Some(k) => match keys.iter().find(|(key, _)| *key == k) {
+                Some((_, value)) => out.push_str(value),
+                None => return Err(format!("percent_expand: unknown key %{}", k)),
+            },
+            None => return Err("percent_expand: invalid format".to_string()),
+        }
+    }
+    Ok(out)
+}
+
+/// A user account (struct passwd, as kept by pwcopy()).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Passwd {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub dir: PathBuf,
+    pub shell: PathBuf,
+}
+
+impl Passwd {
+    /// Look up a user by name (getpwnam()).
+    pub fn from_name(name: &str) -> Option<Passwd> {
+        let name = CString::new(name).ok()?;
+        Self::lookup(|pw, buf, result| unsafe {
+            libc::getpwnam_r(name.as_ptr(), pw, buf.as_mut_ptr(), buf.len(), result)
+        })
+    }
+
+    /// Look up a user by uid (getpwuid()).
+    pub fn from_uid(uid: u32) -> Option<Passwd> {
+        Self::lookup(|pw, buf, result| unsafe { libc::getpwuid_r(uid, pw, buf.as_mut_ptr(), buf.len(), result) })
+    }
+
+    fn lookup(
+        getpw: impl Fn(*mut libc::passwd, &mut [libc::c_char], *mut *mut libc::passwd) -> libc::c_int,
+    ) -> Option<Passwd> {
+        let mut buf = vec![0 as libc::c_char; 1024];
+        loop {
+            let mut pw: libc::passwd = unsafe { std::mem::zeroed() };
+            let mut result = std::ptr::null_mut();
+            match getpw(&mut pw, &mut buf, &mut result) {
+                // 缓冲区不够大时加倍重试
+                libc::ERANGE if buf.len() < 1 << 20 => buf.resize(buf.len() * 2, 0),
+                0 if !result.is_null() => {
+                    let string = |p: *const libc::c_char| unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned();
+                    return Some(Passwd {
+                        name: string(pw.pw_name),
+                        uid: pw.pw_uid,
+                        gid: pw.pw_gid,
+                        dir: PathBuf::from(string(pw.pw_dir)),
+                        shell: PathBuf::from(string(pw.pw_shell)),
+                    });
+                }
+                _ => return None,
+            }
+        }
+    }
+}
+
+/// Check that `name`, a file whose metadata is `st`, and every directory
+/// above it up to `pw_dir` (or the root) are owned by `uid` or root and
+/// not writable by group or others (safe_path(), used for StrictModes).
+/// The error is the message OpenSSH logs.
+pub fn safe_path(name: &Path, st: &fs::Metadata, pw_dir: Option<&Path>, uid: u32) -> Result<(), String> {
+    let mut buf = fs::canonicalize(name).map_err(|e| format!("realpath {} failed: {}", name.display(), e))?;
+    let homedir = pw_dir.and_then(|d| fs::canonicalize(d).ok());
+    let bad_owner = |st: &fs::Metadata| (st.uid() != 0 && st.uid() != uid) || st.permissions().mode() & 0o022 != 0;
+
+    if !st.is_file() {
+        return Err(format!("{} is not a regular file", buf.display()));
+    }
+    if bad_owner(st) {
+        return Err(format!("bad ownership or modes for file {}", buf.display()));
+    }
+    // 沿规范化路径逐级向上检查
+    while let Some(parent) = buf.parent() {
+        buf = parent.to_path_buf();
+        if fs::metadata(&buf).map_or(true, |st| bad_owner(&st)) {
+            return Err(format!("bad ownership or modes for directory {}", buf.display()));
+        }
+        // 到达用户主目录即可停止
+        if homedir.as_deref() == Some(buf.as_path()) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Split a command line into arguments (argv_split()). Arguments are
+/// separated by spaces or tabs and may be quoted with ' or "; a backslash
+/// escapes a quote, a backslash or, outside quotes, a space. None if a
+/// quote is left open.
+pub fn argv_split(s: &str) -> Option<Vec<String>> {
+    let mut argv = Vec::new();
+    let mut chars = s.chars().peekable();
+    loop {
+        // 跳过前导空白
+        while chars.next_if(|&c| c == ' ' || c == '\t').is_some() {}
+        if chars.peek().is_none() {
+            return Some(argv);
+        }
+        let mut arg = String::new();
+        let mut quote = None;
+        while let Some(c) = chars.next() {
+            match (c, quote) {
+                ('\\', _) => match chars.peek() {
+                    Some(&n) if n == '\'' || n == '"' || n == '\\' || (quote.is_none() && n == ' ') => {
+                        arg.push(n);
+                        chars.next();
+                    }
+                    // 无法识别的转义原样保留
+                    _ => arg.push(c),
+                },
+                (' ' | '\t', None) => break,
+                ('"' | '\'', None) => quote = Some(c),
+                (c, Some(q)) if c == q => quote = None,
+                (c, _) => arg.push(c),
+            }
+        }
+        if quote.is_some() {
+            return None;
+        }
+        argv.push(arg);
+    }
+}
+
+/// Join arguments into a printable command line for logs
+/// (argv_assemble()), quoting those with whitespace.
+pub fn argv_assemble(argv: &[String]) -> String {
+    let quoted: Vec<String> = argv
+        .iter()
+        .map(|a| {
+            let mut arg = String::new();
+            for c in a.chars() {
+                if matches!(c, '\\' | '\'' | '"') {
+                    arg.push('\\');
+                }
+                arg.push(c);
+            }
+            if a.contains([' ', '\t']) {
+                format!("\"{}\"", arg)
+            } else {
+                arg
+            }
+        })
+        .collect();
+    quoted.join(" ")
+}
+
+// getgrouplist()：用户所属的全部组，包括 gid
+fn group_list(name: &str, gid: u32) -> io::Result<Vec<libc::gid_t>> {
+    let name = CString::new(name).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let mut groups: Vec<libc::gid_t> = vec![0; 32];
+    loop {
+        let mut n = groups.len() as libc::c_int;
+        let rc = unsafe { libc::getgrouplist(name.as_ptr(), gid as _, groups.as_mut_ptr() as *mut _, &mut n) };
+        if rc != -1 {
+            groups.truncate(n as usize);
+            return Ok(groups);
+        }
+        if groups.len() >= 1 << 16 {
+            return Err(io::Error::other("too many groups"));
+        }
+        // glibc 会在 n 中给出所需的数量，其他系统只能逐步加倍
+        let want = (n as usize).max(groups.len() * 2);
+        groups.resize(want, 0);
+    }
+}
+
+/// Run `av` as `pw` and return what it writes to stdout (subprocess()
+/// with SSH_SUBPROCESS_STDOUT_CAPTURE, then exited_cleanly()). The program
+/// must be an absolute path that passes safe_path() for root; it gets
+/// /dev/null as stdin and only PATH, USER, LOGNAME, HOME and LANG in its
+/// environment, and its stderr is forwarded to the log. It is killed,
+/// with anything it started, if it runs longer than `timeout` or writes
+/// more than `max_output` bytes. The error is the message to log.
+pub fn subprocess(tag: &str, av: &[String], pw: &Passwd, timeout: Duration, max_output: usize) -> Result<Vec<u8>, String> {
+    let command = argv_assemble(av);
+    debug!("{} command \"{}\" running as {}", tag, command, pw.name);
+    let program = Path::new(av.first().ok_or_else(|| format!("{}: empty command", tag))?);
+    if !program.is_absolute() {
+        return Err(format!("{} path is not absolute", tag));
+    }
+    let st = fs::metadata(program).map_err(|e| format!("Could not stat {} \"{}\": {}", tag, program.display(), e))?;
+    safe_path(program, &st, None, 0).map_err(|e| format!("Unsafe {} \"{}\": {}", tag, program.display(), e))?;
+
+    let mut cmd = Command::new(program);
+    cmd.args(&av[1..])
+        .env_clear()
+        .env("PATH", PATH_STDPATH)
+        .env("USER", &pw.name)
+        .env("LOGNAME", &pw.name)
+        .env("HOME", &pw.dir);
+    if let Some(lang) = env::var_os("LANG") {
+        cmd.env("LANG", lang);
+    }
+    // 放在单独的进程组里，超时时连同它启动的进程一起杀掉
+    cmd.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).process_group(0);
+    // initgroups() 在 fork 之后不安全（会取 NSS 与 malloc 的锁），故先在父进程中查好
+    let groups = if unsafe { libc::geteuid() } == 0 {
+        Some(group_list(&pw.name, pw.gid).map_err(|e| format!("{}: getgrouplist {}: {}", tag, pw.name, e))?)
+    } else {
+        None
+    };
+    let (uid, gid) = (pw.uid, pw.gid);
+    unsafe {
+        cmd.pre_exec(move || {
+            if let Some(groups) = &groups {
+                if libc::setgroups(groups.len() as _, groups.as_ptr()) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if libc::setgid(gid) == -1 || libc::setuid(uid) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = cmd.spawn().map_err(|e| format!("{} execve \"{}\": {}", tag, command, e))?;
+    debug!("{} pid {}", tag, child.id());
+
+    let stderr = child.stderr.take().unwrap();
+    let err_tag = tag.to_string();
+    thread::spawn(move || {
+        for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+            error!("{}: {}", err_tag, line);
+        }
+    });
+    let stdout = child.stdout.take().unwrap();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut out = Vec::new();
+        let r = stdout.take(max_output as u64 + 1).read_to_end(&mut out);
+        let _ = tx.send(r.map(|_| out));
+    });
+
+    let deadline = Instant::now() + timeout;
+    let kill = |child: &mut std::process::Child, msg: String| {
+        unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+        let _ = child.wait();
+        Err(msg)
+    };
+    let timed_out = || format!("{} \"{}\" timed out after {:?}", tag, command, timeout);
+    let out = match rx.recv_timeout(timeout) {
+        Ok(Ok(out)) if out.len() <= max_output => out,
+        Ok(Ok(_)) => return kill(&mut child, format!("{} \"{}\" output exceeds {} bytes", tag, command, max_output)),
+        Ok(Err(e)) => return kill(&mut child, format!("{} \"{}\": read: {}", tag, command, e)),
+        Err(_) => return kill(&mut child, timed_out()),
+    };
+    // 输出结束后仍在同一时限内等待退出
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            Ok(None) => return kill(&mut child, timed_out()),
+            Err(e) => return kill(&mut child, format!("{} \"{}\": wait: {}", tag, command, e)),
+        }
+    };
+    match (status.code(), status.signal()) {
+        (Some(0), _) => Ok(out),
+        (Some(code), _) => Err(format!("{} {} failed, status {}", tag, command, code)),
+        (None, signal) => Err(format!("{} {} exited on signal {}", tag, command, signal.unwrap_or(0))),
+    }
+}
@@ -0,0 +1,756 @@
+// packet.rs
+//
+// SSH binary packet protocol (RFC 4253 §6), after OpenSSH's packet.c:
+// packet_length/padding_length framing, random padding, sequence numbers,
+// MAC and encrypt-then-MAC, compression, packet size checks and rekey
+// limits.
+//
+// `PacketState` is pure framing over byte buffers so the same codec can be
+// driven from blocking or async I/O; `PacketStream` runs it over any
+// blocking `Read + Write` (sockets, pipes, in-memory streams in tests).
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, IoSlice, Read, Write};
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+
+use crate::atomicio::writev_all_atomic;
+use crate::compress::{Compression, CompressionStats, Compressor, Decompressor};
+use crate::ssh2::*;
+use crate::sshbuf::{SshBuf, SshBufError};
+
+/// Largest packet_length accepted or produced (PACKET_MAX_SIZE).
+pub const PACKET_MAX_SIZE: usize = 256 * 1024;
+// 无论使用何种密码，任一方向收发这么多包后都必须重新协商密钥
+pub const MAX_PACKETS: u64 = 1 << 31;
+const MIN_PADDING: usize = 4;
+const READ_CHUNK: usize = 8192;
+
+#[derive(Debug)]
+pub enum PacketError {
+    Io(io::Error),
+    /// The peer closed the connection.
+    ConnectionClosed,
+    /// packet_length is out of range or not a multiple of the block size.
+    BadLength(u32),
+    /// padding_length is below the minimum or exceeds the packet.
+    BadPadding,
+    /// MAC or AEAD tag verification failed.
+    MacInvalid,
+    /// Outgoing packet exceeds the configured maximum size.
+    TooLarge(usize),
+    /// Malformed message payload.
+    Format(SshBufError),
+    /// The peer sent SSH2_MSG_DISCONNECT.
+    Disconnected { reason: u32, description: String },
+    /// Protocol violation detected locally (e.g. strict KEX).
+    Protocol(String),
+}
+
+impl fmt::Display for PacketError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PacketError::Io(e) => write!(f, "{}", e),
+            PacketError::ConnectionClosed => f.write_str("connection closed"),
+            PacketError::BadLength(len) => write!(f, "bad packet length {}", len),
+            PacketError::BadPadding => f.write_str("invalid padding length"),
+            PacketError::MacInvalid => f.write_str("message authentication code incorrect"),
+            PacketError::TooLarge(len) => write!(f, "packet too large ({} bytes)", len),
+            PacketError::Format(e) => write!(f, "invalid message format: {}", e),
+            PacketError::Disconnected { reason, description } => {
+                write!(f, "Received disconnect: {}: {}", reason, description)
+            }
+            PacketError::Protocol(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl Error for PacketError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PacketError::Io(e) => Some(e),
+            PacketError::Format(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PacketError {
+    fn from(e: io::Error) -> Self {
+        PacketError::Io(e)
+    }
+}
+
+impl From<SshBufError> for PacketError {
+    fn from(e: SshBufError) -> Self {
+        PacketError::Format(e)
+    }
+}
+
+/// A transport cipher context for one direction (cipher_crypt() in
+/// OpenSSH). The direction is fixed when the context is created.
+pub trait PacketCipher: Send {
+    fn block_size(&self) -> usize;
+
+    /// Length of the AEAD tag following the ciphertext, 0 for plain ciphers.
+    fn auth_len(&self) -> usize {
+        0
+    }
+
+    /// True for AEAD modes, which leave packet_length unencrypted (or
+    /// encrypt it separately) and authenticate it as associated data.
+    fn is_aead(&self) -> bool {
+        self.auth_len() != 0
+    }
+
+    /// True for the "none" cipher; padding is then left zeroed.
+    fn is_plaintext(&self) -> bool {
+        false
+    }
+
+    /// Recover packet_length from the first four bytes of an AEAD or EtM
+    /// packet without consuming them (cipher_get_length()).
+    fn packet_length(&mut self, _seqnr: u32, head: &[u8]) -> u32 {
+        u32::from_be_bytes([head[0], head[1], head[2], head[3]])
+    }
+
+    /// En/decrypt `buf[aadlen..aadlen + len]` in place. The first `aadlen`
+    /// bytes are associated data; AEAD ciphers write or verify a tag in
+    /// `buf[aadlen + len..]`.
+    fn crypt(&mut self, seqnr: u32, buf: &mut [u8], aadlen: usize, len: usize) -> Result<(), PacketError>;
+}
+
+/// A MAC context for one direction.
+pub trait PacketMac: Send {
+    fn mac_len(&self) -> usize;
+
+    /// True for encrypt-then-MAC modes (`*-etm@openssh.com`).
+    fn is_etm(&self) -> bool;
+
+    /// MAC over `uint32 seqnr || data`.
+    fn compute(&mut self, seqnr: u32, data: &[u8]) -> Vec<u8>;
+}
+
+/// The "none" cipher used before the first key exchange.
+pub struct NoneCipher;
+
+impl PacketCipher for NoneCipher {
+    fn block_size(&self) -> usize {
+        8
+    }
+
+    fn is_plaintext(&self) -> bool {
+        true
+    }
+
+    fn crypt(&mut self, _seqnr: u32, _buf: &mut [u8], _aadlen: usize, _len: usize) -> Result<(), PacketError> {
+        Ok(())
+    }
+}
+
+/// Constant-time comparison for MACs (timingsafe_bcmp()).
+pub fn timingsafe_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Fill `buf` from the kernel CSPRNG.
+pub fn random_bytes(buf: &mut [u8]) -> io::Result<()> {
+    let mut pos = 0;
+    while pos < buf.len() {
+        let rc = unsafe { libc::getrandom(buf[pos..].as_mut_ptr() as *mut libc::c_void, buf.len() - pos, 0) };
+        if rc < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        pos += rc as usize;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    In,
+    Out,
+}
+
+// 单个方向的密钥与计数器
+struct Direction {
+    cipher: Box<dyn PacketCipher>,
+    mac: Option<Box<dyn PacketMac>>,
+    comp: Compression,
+    seqnr: u32,
+    packets: u64,
+    blocks: u64,
+    bytes: u64,
+    max_blocks: u64,
+}
+
+impl Direction {
+    fn new() -> Self {
+        Direction {
+            cipher: Box::new(NoneCipher),
+            mac: None,
+            comp: Compression::None,
+            seqnr: 0,
+            packets: 0,
+            blocks: 0,
+            bytes: 0,
+            max_blocks: 0,
+        }
+    }
+
+    fn block_size(&self) -> usize {
+        self.cipher.block_size().max(8)
+    }
+
+    fn mac_len(&self) -> usize {
+        self.mac.as_ref().map_or(0, |m| m.mac_len())
+    }
+
+    fn is_etm(&self) -> bool {
+        self.mac.as_ref().is_some_and(|m| m.is_etm())
+    }
+
+    // AEAD 与 EtM 模式下 packet_length 不参与分组加密
+    fn aad_len(&self) -> usize {
+        if self.cipher.is_aead() || self.is_etm() {
+            4
+        } else {
+            0
+        }
+    }
+
+    fn account(&mut self, total: usize) -> bool {
+        self.seqnr = self.seqnr.wrapping_add(1);
+        self.packets += 1;
+        self.blocks += (total / self.block_size()) as u64;
+        self.bytes += total as u64;
+        self.seqnr == 0
+    }
+}
+
+/// Snapshot of one direction for `ConnectionInfo`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectionInfo {
+    pub seqnr: u32,
+    /// Packets and bytes since the last key change.
+    pub packets: u64,
+    pub bytes: u64,
+    pub compression: Compression,
+    /// Totals of the zlib stream once it has started.
+    pub compression_stats: Option<CompressionStats>,
+}
+
+/// Connection statistics (the packet.c counters behind `ssh -v`'s
+/// "Transferred" and "compress outgoing/incoming" lines).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionInfo {
+    pub inbound: DirectionInfo,
+    pub outbound: DirectionInfo,
+    pub authenticated: bool,
+}
+
+/// Framing state for both directions of one connection.
+pub struct PacketState {
+    send: Direction,
+    recv: Direction,
+    // zlib 流一旦启动便贯穿整个连接，重新协商密钥时不重置
+    compressor: Option<Compressor>,
+    decompressor: Option<Decompressor>,
+    authenticated: bool,
+    input: SshBuf,
+    // 已解密的首个分组（非 AEAD/EtM 模式）及其 packet_length
+    incoming: Vec<u8>,
+    packlen: Option<u32>,
+    max_packet_size: usize,
+    rekey_limit: u64,
+    rekey_interval: Option<Duration>,
+    rekey_time: Instant,
+}
+
+impl Default for PacketState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PacketState {
+    pub fn new() -> Self {
+        PacketState {
+            send: Direction::new(),
+            recv: Direction::new(),
+            compressor: None,
+            decompressor: None,
+            authenticated: false,
+            input: SshBuf::new(),
+            incoming: Vec::new(),
+            packlen: None,
+            max_packet_size: PACKET_MAX_SIZE,
+            rekey_limit: 0,
+            rekey_interval: None,
+            rekey_time: Instant::now(),
+        }
+    }
+
+    fn dir(&self, mode: Mode) -> &Direction {
+        match mode {
+            Mode::In => &self.recv,
+            Mode::Out => &self.send,
+        }
+    }
+
+    /// Install new keys for one direction after SSH2_MSG_NEWKEYS. Block and
+    /// packet counters restart; the sequence number does not.
+    pub fn set_newkeys(&mut self, mode: Mode, cipher: Box<dyn PacketCipher>, mac: Option<Box<dyn PacketMac>>) {
+        let rekey_limit = self.rekey_limit;
+        let dir = match mode {
+            Mode::In => &mut self.recv,
+            Mode::Out => &mut self.send,
+        };
+        // AEAD 模式自带认证，不使用单独的 MAC
+        dir.mac = if cipher.is_aead() { None } else { mac };
+        dir.cipher = cipher;
+        dir.packets = 0;
+        dir.blocks = 0;
+        dir.bytes = 0;
+
+        // 对 128 位分组的密码使用 RFC 4344 §3.2 建议的 2^(L/4) 个分组
+        let bs = dir.block_size() as u64;
+        dir.max_blocks = if bs >= 16 { 1u64.checked_shl((bs * 2) as u32).unwrap_or(u64::MAX) } else { (1 << 30) / bs };
+        if rekey_limit != 0 {
+            dir.max_blocks = dir.max_blocks.min(rekey_limit / bs);
+        }
+        self.rekey_time = Instant::now();
+    }
+
+    /// Set the compression method negotiated for one direction, starting
+    /// its stream now unless it is delayed until authentication.
+    pub fn set_compression(&mut self, mode: Mode, comp: Compression) {
+        match mode {
+            Mode::In => self.recv.comp = comp,
+            Mode::Out => self.send.comp = comp,
+        }
+        self.start_compression();
+    }
+
+    /// Mark user authentication as complete, starting any delayed
+    /// compression (ssh_packet_enable_delayed_compress()). The server calls
+    /// this right after sending SSH2_MSG_USERAUTH_SUCCESS, the client right
+    /// after receiving it.
+    pub fn set_authenticated(&mut self) {
+        self.authenticated = true;
+        self.start_compression();
+    }
+
+    fn start_compression(&mut self) {
+        if self.compressor.is_none() && self.send.comp.active(self.authenticated) {
+            debug!("Enabling compression on outgoing stream ({})", self.send.comp.name());
+            self.compressor = Some(Compressor::new());
+        }
+        if self.decompressor.is_none() && self.recv.comp.active(self.authenticated) {
+            debug!("Enabling compression on incoming stream ({})", self.recv.comp.name());
+            self.decompressor = Some(Decompressor::new(PACKET_MAX_SIZE));
+        }
+    }
+
+    pub fn info(&self) -> ConnectionInfo {
+        let dir = |d: &Direction, stats: Option<CompressionStats>| DirectionInfo {
+            seqnr: d.seqnr,
+            packets: d.packets,
+            bytes: d.bytes,
+            compression: d.comp,
+            compression_stats: stats,
+        };
+        ConnectionInfo {
+            inbound: dir(&self.recv, self.decompressor.as_ref().map(|c| c.stats())),
+            outbound: dir(&self.send, self.compressor.as_ref().map(|c| c.stats())),
+            authenticated: self.authenticated,
+        }
+    }
+
+    /// RekeyLimit: rekey after `bytes` (0 for the cipher default) or after
+    /// `interval` has elapsed since the last key exchange.
+    pub fn set_rekey_limits(&mut self, bytes: u64, interval: Option<Duration>) {
+        self.rekey_limit = bytes;
+        self.rekey_interval = interval;
+    }
+
+    /// Cap outgoing packets below PACKET_MAX_SIZE, e.g. for slow links.
+    pub fn set_max_packet_size(&mut self, size: usize) -> Result<(), PacketError> {
+        if !(4 * 1024..=PACKET_MAX_SIZE).contains(&size) {
+            return Err(PacketError::Protocol(format!("bad maximum packet size {}", size)));
+        }
+        self.max_packet_size = size;
+        Ok(())
+    }
+
+    pub fn seqnr(&self, mode: Mode) -> u32 {
+        self.dir(mode).seqnr
+    }
+
+    /// Reset a sequence number to zero after NEWKEYS (strict KEX).
+    pub fn reset_seqnr(&mut self, mode: Mode) {
+        match mode {
+            Mode::In => self.recv.seqnr = 0,
+            Mode::Out => self.send.seqnr = 0,
+        }
+    }
+
+    /// (packets, blocks, bytes) processed since the last key change.
+    pub fn counters(&self, mode: Mode) -> (u64, u64, u64) {
+        let dir = self.dir(mode);
+        (dir.packets, dir.blocks, dir.bytes)
+    }
+
+    /// Whether a rekey is due before sending a packet of `outbound_len`
+    /// bytes (ssh_packet_need_rekeying()).
+    pub fn need_rekeying(&self, outbound_len: usize, now: Instant) -> bool {
+        // 首次密钥交换之前不需要
+        if self.send.packets == 0 && self.recv.packets == 0 {
+            return false;
+        }
+        if let Some(interval) = self.rekey_interval {
+            if now.duration_since(self.rekey_time) >= interval {
+                return true;
+            }
+        }
+        if self.send.packets > MAX_PACKETS || self.recv.packets > MAX_PACKETS {
+            return true;
+        }
+        let bs = self.send.block_size();
+        let out_blocks = outbound_len.div_ceil(bs) as u64;
+        (self.send.max_blocks != 0 && self.send.blocks + out_blocks > self.send.max_blocks)
+            || (self.recv.max_blocks != 0 && self.recv.blocks > self.recv.max_blocks)
+    }
+
+    /// Compress, frame, pad, encrypt and MAC one message payload.
+    pub fn encode(&mut self, payload: &[u8]) -> Result<EncodedPacket, PacketError> {
+        let compressed;
+        let payload = match self.compressor.as_mut() {
+            Some(c) => {
+                compressed = c.compress(payload)?;
+                &compressed[..]
+            }
+            None => payload,
+        };
+        let dir = &mut self.send;
+        let bs = dir.block_size();
+        let aadlen = dir.aad_len();
+        let authlen = dir.cipher.auth_len();
+
+        // packet_length || padding_length || payload || padding
+        let len = 4 + 1 + payload.len();
+        let mut padlen = bs - (len - aadlen) % bs;
+        if padlen < MIN_PADDING {
+            padlen += bs;
+        }
+        let total = len + padlen;
+        if total - 4 > self.max_packet_size {
+            return Err(PacketError::TooLarge(total - 4));
+        }
+
+        let mut buf = Vec::with_capacity(total + authlen + dir.mac_len());
+        buf.extend_from_slice(&((total - 4) as u32).to_be_bytes());
+        buf.push(padlen as u8);
+        buf.extend_from_slice(payload);
+        buf.resize(total, 0);
+        if !dir.cipher.is_plaintext() {
+            random_bytes(&mut buf[len..])?;
+        }
+
+        let seqnr = dir.seqnr;
+        let mut mac = None;
+        if let Some(m) = dir.mac.as_mut() {
+            if !m.is_etm() {
+                mac = Some(m.compute(seqnr, &buf));
+            }
+        }
+        buf.resize(total + authlen, 0);
+        dir.cipher.crypt(seqnr, &mut buf, aadlen, total - aadlen)?;
+        if let Some(m) = dir.mac.as_mut() {
+            if m.is_etm() {
+                mac = Some(m.compute(seqnr, &buf));
+            }
+        }
+        if dir.account(total) {
+            warn!("outgoing seqnr wraps around");
+        }
+        Ok(EncodedPacket { buf, mac: mac.unwrap_or_default() })
+    }
+
+    /// Queue received bytes for `decode`.
+    pub fn feed(&mut self, data: &[u8]) {
+        self.input.put(data);
+    }
+
+    /// Bytes received but not yet consumed as a packet.
+    pub fn pending_input(&self) -> usize {
+        self.input.len()
+    }
+
+    /// Take the next complete packet from the input, returning its payload
+    /// (message type first), or `None` if more data is needed. Errors are
+    /// fatal for the connection.
+    pub fn decode(&mut self) -> Result<Option<Vec<u8>>, PacketError> {
+        let dir = &mut self.recv;
+        let bs = dir.block_size();
+        let aadlen = dir.aad_len();
+        let authlen = dir.cipher.auth_len();
+        let maclen = dir.mac_len();
+        let seqnr = dir.seqnr;
+
+        let packlen = match self.packlen {
+            Some(len) => len,
+            None => {
+                let len = if aadlen != 0 {
+                    if self.input.len() < 4 {
+                        return Ok(None);
+                    }
+                    dir.cipher.packet_length(seqnr, &self.input.as_slice()[..4])
+                } else {
+                    if self.input.len() < bs {
+                        return Ok(None);
+                    }
+                    // 先解密首个分组以得到 packet_length
+                    let mut first = self.input.get(bs)?.to_vec();
+                    dir.cipher.crypt(seqnr, &mut first, 0, bs)?;
+                    let len = u32::from_be_bytes([first[0], first[1], first[2], first[3]]);
+                    self.incoming = first;
+                    len
+                };
+                if len < 1 + 4 || len as usize > PACKET_MAX_SIZE || (aadlen == 0 && 4 + (len as usize) < bs) {
+                    return Err(PacketError::BadLength(len));
+                }
+                self.packlen = Some(len);
+                len
+            }
+        };
+
+        // 还需读取的密文长度（不含已解密的首个分组）
+        let need = if aadlen != 0 { packlen as usize } else { 4 + packlen as usize - bs };
+        if need % bs != 0 {
+            return Err(PacketError::BadLength(packlen));
+        }
+        if self.input.len() < aadlen + need + authlen + maclen {
+            return Ok(None);
+        }
+
+        let mut body = self.input.get(aadlen + need + authlen)?.to_vec();
+        let mac = self.input.get(maclen)?.to_vec();
+        if let Some(m) = dir.mac.as_mut() {
+            if m.is_etm() && !timingsafe_eq(&m.compute(seqnr, &body[..aadlen + need]), &mac) {
+                return Err(PacketError::MacInvalid);
+            }
+        }
+        dir.cipher.crypt(seqnr, &mut body, aadlen, need)?;
+        body.truncate(aadlen + need);
+        self.incoming.extend_from_slice(&body);
+        if let Some(m) = dir.mac.as_mut() {
+            if !m.is_etm() && !timingsafe_eq(&m.compute(seqnr, &self.incoming), &mac) {
+                return Err(PacketError::MacInvalid);
+            }
+        }
+
+        if dir.account(4 + packlen as usize) {
+            warn!("incoming seqnr wraps around");
+        }
+        self.packlen = None;
+        let packet = std::mem::take(&mut self.incoming);
+
+        let padlen = packet[4] as usize;
+        if padlen < MIN_PADDING || padlen > packlen as usize - 1 {
+            return Err(PacketError::BadPadding);
+        }
+        let payload = &packet[5..packet.len() - padlen];
+        match self.decompressor.as_mut() {
+            Some(d) => Ok(Some(d.decompress(payload)?)),
+            None => Ok(Some(payload.to_vec())),
+        }
+    }
+}
+
+/// Handle transport-level messages that never reach the caller
+/// (ssh_packet_read_poll_seqnr()). Returns `Ok(false)` if `payload` was
+/// consumed here.
+pub(crate) fn filter_transport_message(payload: &[u8]) -> Result<bool, PacketError> {
+    let mut b = SshBuf::from_vec(payload.to_vec());
+    match b.get_u8()? {
+        SSH2_MSG_IGNORE => {
+            debug!("Received SSH2_MSG_IGNORE");
+            Ok(false)
+        }
+        SSH2_MSG_DEBUG => {
+            b.get_bool()?;
+            let msg = String::from_utf8_lossy(b.get_string()?).into_owned();
+            b.get_string()?;
+            debug!("Remote: {:.900}", msg);
+            Ok(false)
+        }
+        SSH2_MSG_UNIMPLEMENTED => {
+            debug!("Received SSH2_MSG_UNIMPLEMENTED for {}", b.get_u32()?);
+            Ok(false)
+        }
+        SSH2_MSG_DISCONNECT => {
+            let reason = b.get_u32()?;
+            let description = String::from_utf8_lossy(b.get_string()?).into_owned();
+            Err(PacketError::Disconnected { reason, description })
+        }
+        _ => Ok(true),
+    }
+}
+
+/// Build an SSH2_MSG_DISCONNECT payload.
+pub fn disconnect_payload(reason: u32, description: &str) -> Vec<u8> {
+    let mut b = SshBuf::new();
+    b.put_u8(SSH2_MSG_DISCONNECT);
+    b.put_u32(reason);
+    b.put_cstring(description);
+    b.put_cstring("");
+    b.into_vec()
+}
+
+/// A packet as produced by `PacketState::encode`, kept in the parts that
+/// are sent together with one writev(2).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodedPacket {
+    // 加密后的 packet_length || padding_length || payload || padding，以及 AEAD 标签
+    buf: Vec<u8>,
+    mac: Vec<u8>,
+}
+
+impl EncodedPacket {
+    /// The (possibly encrypted) packet_length field.
+    pub fn header(&self) -> &[u8] {
+        &self.buf[..4]
+    }
+
+    /// Everything after the length up to the MAC: padding length, payload
+    /// and padding, followed by the tag of an AEAD cipher.
+    pub fn body(&self) -> &[u8] {
+        &self.buf[4..]
+    }
+
+    /// The MAC; empty without one or with an AEAD cipher.
+    pub fn mac(&self) -> &[u8] {
+        &self.mac
+    }
+
+    /// Bytes on the wire.
+    pub fn len(&self) -> usize {
+        self.buf.len() + self.mac.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn io_slices(&self) -> [IoSlice<'_>; 3] {
+        [IoSlice::new(self.header()), IoSlice::new(self.body()), IoSlice::new(&self.mac)]
+    }
+
+    /// The packet as one contiguous buffer.
+    pub fn into_vec(self) -> Vec<u8> {
+        let mut buf = self.buf;
+        buf.extend_from_slice(&self.mac);
+        buf
+    }
+}
+
+/// Packet connection over a blocking byte stream.
+pub struct PacketStream<S: Read + Write> {
+    stream: S,
+    state: PacketState,
+}
+
+impl<S: Read + Write> PacketStream<S> {
+    pub fn new(stream: S) -> Self {
+        PacketStream { stream, state: PacketState::new() }
+    }
+
+    pub fn state(&self) -> &PacketState {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut PacketState {
+        &mut self.state
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Send one message; `payload` starts with the message type. Length,
+    /// body and MAC go out in a single vectored write.
+    pub fn send(&mut self, payload: &[u8]) -> Result<(), PacketError> {
+        let packet = self.state.encode(payload)?;
+        writev_all_atomic(&mut self.stream, &mut packet.io_slices()).map_err(io::Error::from)?;
+        Ok(())
+    }
+
+    /// Send SSH2_MSG_IGNORE with `data` as padding, e.g. to obscure
+    /// keystroke timing.
+    pub fn send_ignore(&mut self, data: &[u8]) -> Result<(), PacketError> {
+        let mut b = SshBuf::new();
+        b.put_u8(SSH2_MSG_IGNORE);
+        b.put_string(data);
+        self.send(b.as_slice())
+    }
+
+    pub fn send_debug(&mut self, always_display: bool, msg: &str) -> Result<(), PacketError> {
+        let mut b = SshBuf::new();
+        b.put_u8(SSH2_MSG_DEBUG);
+        b.put_bool(always_display);
+        b.put_cstring(msg);
+        b.put_cstring("");
+        self.send(b.as_slice())
+    }
+
+    pub fn send_disconnect(&mut self, reason: u32, description: &str) -> Result<(), PacketError> {
+        self.send(&disconnect_payload(reason, description))
+    }
+
+    /// Read the next message, skipping IGNORE, DEBUG and UNIMPLEMENTED. A
+    /// DISCONNECT from the peer is returned as `PacketError::Disconnected`.
+    pub fn read_packet(&mut self) -> Result<Vec<u8>, PacketError> {
+        loop {
+            let payload = self.read_raw()?;
+            if payload.is_empty() {
+                return Err(PacketError::Protocol("empty packet".to_string()));
+            }
+            if filter_transport_message(&payload)? {
+                return Ok(payload);
+            }
+        }
+    }
+
+    /// Read the next packet without any message filtering.
+    pub fn read_raw(&mut self) -> Result<Vec<u8>, PacketError> {
+        let mut buf = [0u8; READ_CHUNK];
+        loop {
+            if let Some(payload) = self.state.decode()? {
+                return Ok(payload);
+            }
+            let n = match self.stream.read(&mut buf) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if n == 0 {
+                return Err(PacketError::ConnectionClosed);
+            }
+            self.state.feed(&buf[..n]);
+        }
+    }
+}
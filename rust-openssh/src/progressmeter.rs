@@ -0,0 +1,200 @@
+// progressmeter.rs
+//
+// scp/sftp style transfer progress display (progressmeter.c): file name,
+// percentage, bytes transferred, smoothed rate and ETA, redrawn at most
+// once per second. Meant to be driven from an atomicio callback.
+
+use std::io::{self, Write};
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+// 刷新间隔与速率平滑系数，与 progressmeter.c 相同
+const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+const AGE_FACTOR: f64 = 0.9;
+const DEFAULT_WINSIZE: usize = 80;
+const MAX_WINSIZE: usize = 512;
+
+const UNITS: &[u8] = b" KMGT";
+
+/// Format a byte count in at most four digits plus a unit, e.g. `"1234KB"`.
+pub fn format_size(mut bytes: u64) -> String {
+    let mut i = 0;
+    while bytes >= 10000 && UNITS[i] != b'T' {
+        bytes = (bytes + 512) / 1024;
+        i += 1;
+    }
+    format!("{:4}{}{}", bytes, UNITS[i] as char, if i > 0 { "B" } else { " " })
+}
+
+/// Format a transfer rate with one decimal, always at least in KB.
+pub fn format_rate(bytes: f64) -> String {
+    let mut bytes = (bytes * 100.0) as u64;
+    let mut i = 0;
+    while bytes >= 100 * 1000 && UNITS[i] != b'T' {
+        bytes = (bytes + 512) / 1024;
+        i += 1;
+    }
+    if i == 0 {
+        i += 1;
+        bytes = (bytes + 512) / 1024;
+    }
+    format!("{:3}.{}{}B", (bytes + 5) / 100, (bytes + 5) / 10 % 10, UNITS[i] as char)
+}
+
+fn format_eta(seconds: u64, bytes_per_second: f64, done: bool) -> String {
+    if bytes_per_second == 0.0 || seconds > 100 * 3600 {
+        return "    --:-- ETA".to_string();
+    }
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    let time = if hours != 0 {
+        format!("{:2}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("   {:02}:{:02}", minutes, seconds)
+    };
+    format!("{}{}", time, if done { "    " } else { " ETA" })
+}
+
+// 仅当 stdout 是终端且本进程位于前台进程组时才输出 (can_output())
+fn can_output() -> bool {
+    unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 && libc::getpgrp() == libc::tcgetpgrp(libc::STDOUT_FILENO) }
+}
+
+fn window_width() -> usize {
+    let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+    let rc = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) };
+    if rc == -1 || ws.ws_col == 0 {
+        DEFAULT_WINSIZE
+    } else {
+        (ws.ws_col as usize).min(MAX_WINSIZE)
+    }
+}
+
+/// Progress meter for a single file transfer.
+pub struct ProgressMeter<W: Write = io::Stdout> {
+    file: String,
+    size: u64,
+    done: u64,
+    start: Instant,
+    last_update: Instant,
+    last_done: u64,
+    bytes_per_second: f64,
+    width: usize,
+    out: Option<W>,
+}
+
+impl ProgressMeter<io::Stdout> {
+    /// Start a meter on stdout. Nothing is drawn unless stdout is a
+    /// terminal owned by the foreground process group.
+    pub fn new(file: &str, size: u64) -> Self {
+        let out = if can_output() { Some(io::stdout()) } else { None };
+        let mut meter = Self::build(file, size, out, window_width(), Instant::now());
+        meter.refresh(meter.start);
+        meter
+    }
+}
+
+impl<W: Write> ProgressMeter<W> {
+    /// Start a meter drawing to `out` with a fixed terminal width.
+    pub fn with_writer(file: &str, size: u64, out: W, width: usize, start: Instant) -> Self {
+        Self::build(file, size, Some(out), width.min(MAX_WINSIZE), start)
+    }
+
+    fn build(file: &str, size: u64, out: Option<W>, width: usize, start: Instant) -> Self {
+        ProgressMeter {
+            file: file.to_string(),
+            size,
+            done: 0,
+            start,
+            last_update: start,
+            last_done: 0,
+            bytes_per_second: 0.0,
+            width,
+            out,
+        }
+    }
+
+    pub fn transferred(&self) -> u64 {
+        self.done
+    }
+
+    /// Account for `n` more bytes; suitable as an atomicio callback.
+    pub fn update(&mut self, n: usize) -> ControlFlow<()> {
+        self.update_at(n, Instant::now())
+    }
+
+    pub fn update_at(&mut self, n: usize, now: Instant) -> ControlFlow<()> {
+        self.done += n as u64;
+        if now.duration_since(self.last_update) >= UPDATE_INTERVAL {
+            self.refresh(now);
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Draw the final line with the total elapsed time and end it.
+    pub fn finish(&mut self) {
+        self.finish_at(Instant::now())
+    }
+
+    pub fn finish_at(&mut self, now: Instant) {
+        self.refresh(now);
+        if let Some(out) = self.out.as_mut() {
+            let _ = out.write_all(b"\n");
+            let _ = out.flush();
+        }
+    }
+
+    pub fn into_inner(self) -> Option<W> {
+        self.out
+    }
+
+    fn refresh(&mut self, now: Instant) {
+        let line = self.format_line(now);
+        self.last_update = now;
+        self.last_done = self.done;
+        if let Some(out) = self.out.as_mut() {
+            let _ = out.write_all(line.as_bytes());
+            let _ = out.flush();
+        }
+    }
+
+    // 更新平滑速率并生成一行输出（以 \r 开头）
+    fn format_line(&mut self, now: Instant) -> String {
+        let bytes_left = self.size.saturating_sub(self.done);
+        let (elapsed, transferred) = if bytes_left > 0 {
+            (now.duration_since(self.last_update).as_secs_f64(), self.done - self.last_done)
+        } else {
+            // 传输完成时计算整体平均速率
+            self.bytes_per_second = 0.0;
+            (now.duration_since(self.start).as_secs_f64(), self.done)
+        };
+        let cur_speed = if elapsed != 0.0 { transferred as f64 / elapsed } else { transferred as f64 };
+        self.bytes_per_second = if self.bytes_per_second != 0.0 {
+            self.bytes_per_second * AGE_FACTOR + cur_speed * (1.0 - AGE_FACTOR)
+        } else {
+            cur_speed
+        };
+
+        let percent = (self.done.min(self.size) * 100).checked_div(self.size).unwrap_or(100);
+        let seconds = if bytes_left > 0 {
+            if self.bytes_per_second > 0.0 {
+                (bytes_left as f64 / self.bytes_per_second) as u64
+            } else {
+                0
+            }
+        } else {
+            elapsed as u64
+        };
+        let stats = format!(
+            " {:3}% {} {}/s {}",
+            percent,
+            format_size(self.done),
+            format_rate(self.bytes_per_second),
+            format_eta(seconds, self.bytes_per_second, bytes_left == 0)
+        );
+
+        // 文件名占据剩余宽度，过长时截断
+        let name_width = self.width.saturating_sub(stats.len() + 1).max(1);
+        let name: String = self.file.chars().take(name_width).collect();
+        format!("\r{:<width$}{}", name, stats, width = name_width)
+    }
+}
@@ -0,0 +1,60 @@
+// ssh2.rs
+//
+// SSH protocol message numbers and disconnect reason codes (ssh2.h,
+// RFC 4250 §4.1 and §4.2.2).
+
+// 传输层通用消息
+pub const SSH2_MSG_DISCONNECT: u8 = 1;
+pub const SSH2_MSG_IGNORE: u8 = 2;
+pub const SSH2_MSG_UNIMPLEMENTED: u8 = 3;
+pub const SSH2_MSG_DEBUG: u8 = 4;
+pub const SSH2_MSG_SERVICE_REQUEST: u8 = 5;
+pub const SSH2_MSG_SERVICE_ACCEPT: u8 = 6;
+pub const SSH2_MSG_EXT_INFO: u8 = 7;
+pub const SSH2_MSG_NEWCOMPRESS: u8 = 8;
+
+// 算法协商
+pub const SSH2_MSG_KEXINIT: u8 = 20;
+pub const SSH2_MSG_NEWKEYS: u8 = 21;
+
+// 密钥交换方法相关消息 (30-49)
+pub const SSH2_MSG_KEXDH_INIT: u8 = 30;
+pub const SSH2_MSG_KEXDH_REPLY: u8 = 31;
+pub const SSH2_MSG_KEX_ECDH_INIT: u8 = 30;
+pub const SSH2_MSG_KEX_ECDH_REPLY: u8 = 31;
+pub const SSH2_MSG_KEX_DH_GEX_REQUEST_OLD: u8 = 30;
+pub const SSH2_MSG_KEX_DH_GEX_GROUP: u8 = 31;
+pub const SSH2_MSG_KEX_DH_GEX_INIT: u8 = 32;
+pub const SSH2_MSG_KEX_DH_GEX_REPLY: u8 = 33;
+pub const SSH2_MSG_KEX_DH_GEX_REQUEST: u8 = 34;
+
+// 用户认证
+pub const SSH2_MSG_USERAUTH_REQUEST: u8 = 50;
+pub const SSH2_MSG_USERAUTH_FAILURE: u8 = 51;
+pub const SSH2_MSG_USERAUTH_SUCCESS: u8 = 52;
+pub const SSH2_MSG_USERAUTH_BANNER: u8 = 53;
+
+// 连接协议
+pub const SSH2_MSG_GLOBAL_REQUEST: u8 = 80;
+pub const SSH2_MSG_REQUEST_SUCCESS: u8 = 81;
+pub const SSH2_MSG_REQUEST_FAILURE: u8 = 82;
+pub const SSH2_MSG_CHANNEL_OPEN: u8 = 90;
+pub const SSH2_MSG_CHANNEL_DATA: u8 = 94;
+pub const SSH2_MSG_CHANNEL_CLOSE: u8 = 97;
+
+// 断开连接原因码
+pub const SSH2_DISCONNECT_HOST_NOT_ALLOWED_TO_CONNECT: u32 = 1;
+pub const SSH2_DISCONNECT_PROTOCOL_ERROR: u32 = 2;
+pub const SSH2_DISCONNECT_KEY_EXCHANGE_FAILED: u32 = 3;
+pub const SSH2_DISCONNECT_HOST_AUTHENTICATION_FAILED: u32 = 4;
+pub const SSH2_DISCONNECT_MAC_ERROR: u32 = 5;
+pub const SSH2_DISCONNECT_COMPRESSION_ERROR: u32 = 6;
+pub const SSH2_DISCONNECT_SERVICE_NOT_AVAILABLE: u32 = 7;
+pub const SSH2_DISCONNECT_PROTOCOL_VERSION_NOT_SUPPORTED: u32 = 8;
+pub const SSH2_DISCONNECT_HOST_KEY_NOT_VERIFIABLE: u32 = 9;
+pub const SSH2_DISCONNECT_CONNECTION_LOST: u32 = 10;
+pub const SSH2_DISCONNECT_BY_APPLICATION: u32 = 11;
+pub const SSH2_DISCONNECT_TOO_MANY_CONNECTIONS: u32 = 12;
+pub const SSH2_DISCONNECT_AUTH_CANCELLED_BY_USER: u32 = 13;
+pub const SSH2_DISCONNECT_NO_MORE_AUTH_METHODS_AVAILABLE: u32 = 14;
+pub const SSH2_DISCONNECT_ILLEGAL_USER_NAME: u32 = 15;
@@ -0,0 +1,217 @@
+// sshbuf.rs
+//
+// Buffer for SSH wire encoding (RFC 4251 §5): big-endian integers,
+// length-prefixed strings and mpints. Data is appended at the end and
+// consumed from the front, like OpenSSH's sshbuf.
+
+use std::error::Error;
+use std::fmt;
+
+// 单个 string 字段的上限，与 SSHBUF_SIZE_MAX 同量级
+pub const SSHBUF_MAX_STRING: usize = 0x800_0000;
+pub const SSHBUF_MAX_BIGNUM: usize = 16384 / 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SshBufError {
+    /// Not enough data left for the requested field.
+    MessageIncomplete,
+    /// A string or mpint exceeds its size limit.
+    StringTooLarge,
+    /// A C string contains an embedded NUL or is not UTF-8.
+    InvalidFormat,
+    /// An mpint that must be unsigned has its sign bit set.
+    BignumIsNegative,
+}
+
+impl fmt::Display for SshBufError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            SshBufError::MessageIncomplete => "message incomplete",
+            SshBufError::StringTooLarge => "string is too large",
+            SshBufError::InvalidFormat => "invalid format",
+            SshBufError::BignumIsNegative => "bignum is negative",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl Error for SshBufError {}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SshBuf {
+    data: Vec<u8>,
+    off: usize,
+}
+
+impl SshBuf {
+    pub fn new() -> Self {
+        SshBuf::default()
+    }
+
+    pub fn from_vec(data: Vec<u8>) -> Self {
+        SshBuf { data, off: 0 }
+    }
+
+    /// Number of bytes not yet consumed.
+    pub fn len(&self) -> usize {
+        self.data.len() - self.off
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The unconsumed bytes.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data[self.off..]
+    }
+
+    pub fn into_vec(mut self) -> Vec<u8> {
+        self.data.drain(..self.off);
+        self.data
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.off = 0;
+    }
+
+    // 写入
+
+    pub fn put(&mut self, v: &[u8]) {
+        // 已消费部分过半时整理缓冲区，避免长连接上无限增长
+        if self.off > 0 && self.off >= self.data.len() / 2 {
+            self.data.drain(..self.off);
+            self.off = 0;
+        }
+        self.data.extend_from_slice(v);
+    }
+
+    pub fn put_u8(&mut self, v: u8) {
+        self.data.push(v);
+    }
+
+    pub fn put_bool(&mut self, v: bool) {
+        self.data.push(v as u8);
+    }
+
+    pub fn put_u32(&mut self, v: u32) {
+        self.data.extend_from_slice(&v.to_be_bytes());
+    }
+
+    pub fn put_u64(&mut self, v: u64) {
+        self.data.extend_from_slice(&v.to_be_bytes());
+    }
+
+    pub fn put_string(&mut self, v: &[u8]) {
+        self.put_u32(v.len() as u32);
+        self.put(v);
+    }
+
+    pub fn put_cstring(&mut self, v: &str) {
+        self.put_string(v.as_bytes());
+    }
+
+    /// Append another buffer's unconsumed contents as a string.
+    pub fn put_stringb(&mut self, v: &SshBuf) {
+        self.put_string(v.as_slice());
+    }
+
+    /// Append an unsigned big-endian magnitude as an mpint: leading zeros
+    /// are stripped and a zero byte is prepended when the top bit is set.
+    pub fn put_bignum2_bytes(&mut self, v: &[u8]) {
+        let start = v.iter().position(|&b| b != 0).unwrap_or(v.len());
+        let v = &v[start..];
+        let prepend = !v.is_empty() && v[0] & 0x80 != 0;
+        self.put_u32((v.len() + prepend as usize) as u32);
+        if prepend {
+            self.put_u8(0);
+        }
+        self.put(v);
+    }
+
+    // 读取
+
+    fn take(&mut self, n: usize) -> Result<&[u8], SshBufError> {
+        if self.len() < n {
+            return Err(SshBufError::MessageIncomplete);
+        }
+        let start = self.off;
+        self.off += n;
+        Ok(&self.data[start..start + n])
+    }
+
+    /// Consume `n` bytes without looking at them.
+    pub fn consume(&mut self, n: usize) -> Result<(), SshBufError> {
+        self.take(n).map(|_| ())
+    }
+
+    pub fn get(&mut self, n: usize) -> Result<&[u8], SshBufError> {
+        self.take(n)
+    }
+
+    pub fn get_u8(&mut self) -> Result<u8, SshBufError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn get_bool(&mut self) -> Result<bool, SshBufError> {
+        Ok(self.get_u8()? != 0)
+    }
+
+    pub fn get_u32(&mut self) -> Result<u32, SshBufError> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn get_u64(&mut self) -> Result<u64, SshBufError> {
+        let b = self.take(8)?;
+        let mut v = [0u8; 8];
+        v.copy_from_slice(b);
+        Ok(u64::from_be_bytes(v))
+    }
+
+    pub fn peek_u32(&self) -> Result<u32, SshBufError> {
+        let b = self.as_slice();
+        if b.len() < 4 {
+            return Err(SshBufError::MessageIncomplete);
+        }
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn get_string(&mut self) -> Result<&[u8], SshBufError> {
+        let len = self.peek_u32()? as usize;
+        if len > SSHBUF_MAX_STRING {
+            return Err(SshBufError::StringTooLarge);
+        }
+        if self.len() - 4 < len {
+            return Err(SshBufError::MessageIncomplete);
+        }
+        self.off += 4;
+        self.take(len)
+    }
+
+    /// Read a string that must not contain NUL bytes and must be UTF-8.
+    pub fn get_cstring(&mut self) -> Result<String, SshBufError> {
+        let s = self.get_string()?;
+        if s.contains(&0) {
+            return Err(SshBufError::InvalidFormat);
+        }
+        String::from_utf8(s.to_vec()).map_err(|_| SshBufError::InvalidFormat)
+    }
+
+    /// Read an unsigned mpint, returning its magnitude without leading
+    /// zero bytes.
+    pub fn get_bignum2_bytes(&mut self) -> Result<&[u8], SshBufError> {
+        let len = self.peek_u32()? as usize;
+        if len > SSHBUF_MAX_BIGNUM + 1 {
+            return Err(SshBufError::StringTooLarge);
+        }
+        let v = self.get_string()?;
+        if !v.is_empty() && v[0] & 0x80 != 0 {
+            return Err(SshBufError::BignumIsNegative);
+        }
+        // 与 OpenSSH 一样容忍多余的前导零
+        let start = v.iter().position(|&b| b != 0).unwrap_or(v.len());
+        Ok(&v[start..])
+    }
+}
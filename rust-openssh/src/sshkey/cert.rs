@@ -0,0 +1,333 @@
+// sshkey/cert.rs
+//
+// OpenSSH certificates (PROTOCOL.certkeys): a public key signed by a CA
+// together with a serial, key id, principals, validity window, critical
+// options and extensions. Parsing checks the CA signature; whether the CA
+// is trusted and the certificate usable for a login is decided later by
+// check_authority() or validate().
+
+use super::{get_cstring, KeyError, KeyType, PrivateKey, PublicKey};
+use crate::addrmatch::match_pattern;
+use crate::packet::random_bytes;
+use crate::sshbuf::SshBuf;
+
+/// SSHKEY_CERT_MAX_PRINCIPALS: longer principal lists are rejected.
+pub const SSHKEY_CERT_MAX_PRINCIPALS: usize = 256;
+const CERT_NONCE_LEN: usize = 32;
+
+/// Extensions ssh-keygen puts in user certificates unless told otherwise.
+pub const DEFAULT_USER_EXTENSIONS: &[&str] = &[
+    "permit-X11-forwarding",
+    "permit-agent-forwarding",
+    "permit-port-forwarding",
+    "permit-pty",
+    "permit-user-rc",
+];
+
+/// SSH2_CERT_TYPE_USER or SSH2_CERT_TYPE_HOST.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertType {
+    User = 1,
+    Host = 2,
+}
+
+impl CertType {
+    pub fn from_u32(v: u32) -> Option<CertType> {
+        match v {
+            1 => Some(CertType::User),
+            2 => Some(CertType::Host),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CertType::User => "user",
+            CertType::Host => "host",
+        }
+    }
+}
+
+/// One critical option or extension. `data` is the raw contents of the
+/// option's data string: empty for flags, an inner string for options
+/// that carry a value such as force-command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertOption {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+impl CertOption {
+    pub fn flag(name: &str) -> CertOption {
+        CertOption { name: name.to_string(), data: Vec::new() }
+    }
+
+    pub fn string(name: &str, value: &str) -> CertOption {
+        let mut b = SshBuf::new();
+        b.put_cstring(value);
+        CertOption { name: name.to_string(), data: b.into_vec() }
+    }
+
+    /// The string value of an option made by `string`, if that is what
+    /// `data` holds.
+    pub fn value(&self) -> Option<String> {
+        let mut b = SshBuf::from_vec(self.data.clone());
+        let v = b.get_cstring().ok()?;
+        b.is_empty().then_some(v)
+    }
+}
+
+/// Encode an option list as the contents of the critical options or
+/// extensions field.
+pub fn encode_options(options: &[CertOption]) -> Vec<u8> {
+    let mut b = SshBuf::new();
+    for o in options {
+        b.put_cstring(&o.name);
+        b.put_string(&o.data);
+    }
+    b.into_vec()
+}
+
+fn decode_options(blob: &[u8]) -> Result<Vec<CertOption>, KeyError> {
+    let mut b = SshBuf::from_vec(blob.to_vec());
+    let mut options = Vec::new();
+    while !b.is_empty() {
+        let name = get_cstring(&mut b)?;
+        let data = b.get_string()?.to_vec();
+        options.push(CertOption { name, data });
+    }
+    Ok(options)
+}
+
+/// The fields of a certificate before it is signed (the inputs of
+/// ssh-keygen -s).
+#[derive(Debug, Clone)]
+pub struct CertRequest {
+    pub key: PublicKey,
+    pub cert_type: CertType,
+    pub key_id: String,
+    pub serial: u64,
+    pub principals: Vec<String>,
+    pub valid_after: u64,
+    pub valid_before: u64,
+    pub critical_options: Vec<CertOption>,
+    pub extensions: Vec<CertOption>,
+    /// Left empty, a random nonce is chosen when signing.
+    pub nonce: Vec<u8>,
+}
+
+impl CertRequest {
+    /// A request valid forever for any principal, carrying ssh-keygen's
+    /// default extensions for user certificates and none for host ones.
+    pub fn new(key: PublicKey, cert_type: CertType, key_id: &str) -> CertRequest {
+        let extensions = match cert_type {
+            CertType::User => DEFAULT_USER_EXTENSIONS.iter().map(|n| CertOption::flag(n)).collect(),
+            CertType::Host => Vec::new(),
+        };
+        CertRequest {
+            key,
+            cert_type,
+            key_id: key_id.to_string(),
+            serial: 0,
+            principals: Vec::new(),
+            valid_after: 0,
+            valid_before: u64::MAX,
+            critical_options: Vec::new(),
+            extensions,
+            nonce: Vec::new(),
+        }
+    }
+
+    /// Sign with the CA key (sshkey_certify()). `alg` picks the signature
+    /// algorithm for RSA CAs as in PrivateKey::sign.
+    pub fn sign(self, ca: &PrivateKey, alg: Option<&str>) -> Result<Certificate, KeyError> {
+        if self.key.is_cert() {
+            return Err(KeyError::InvalidKey("cannot certify a certificate".to_string()));
+        }
+        if self.principals.len() > SSHKEY_CERT_MAX_PRINCIPALS {
+            return Err(KeyError::InvalidFormat("too many principals".to_string()));
+        }
+        let mut nonce = self.nonce;
+        if nonce.is_empty() {
+            nonce = vec![0u8; CERT_NONCE_LEN];
+            random_bytes(&mut nonce).map_err(KeyError::Rng)?;
+        }
+        let mut cert = Certificate {
+            nonce,
+            key: self.key,
+            serial: self.serial,
+            cert_type: self.cert_type,
+            key_id: self.key_id,
+            principals: self.principals,
+            valid_after: self.valid_after,
+            valid_before: self.valid_before,
+            critical_options: self.critical_options,
+            extensions: self.extensions,
+            reserved: Vec::new(),
+            signature_key: ca.public(),
+            signature: Vec::new(),
+        };
+        cert.signature = ca.sign(&cert.signed_data(), alg)?;
+        Ok(cert)
+    }
+}
+
+/// A signed certificate (struct sshkey_cert plus the certified key).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Certificate {
+    pub nonce: Vec<u8>,
+    /// The certified key; never itself a certificate.
+    pub key: PublicKey,
+    pub serial: u64,
+    pub cert_type: CertType,
+    pub key_id: String,
+    pub principals: Vec<String>,
+    pub valid_after: u64,
+    pub valid_before: u64,
+    pub critical_options: Vec<CertOption>,
+    pub extensions: Vec<CertOption>,
+    pub reserved: Vec<u8>,
+    pub signature_key: PublicKey,
+    pub signature: Vec<u8>,
+}
+
+impl Certificate {
+    pub fn key_type(&self) -> KeyType {
+        self.key.key_type()
+    }
+
+    /// Everything up to and including the CA key: the data the CA signs.
+    fn signed_data(&self) -> Vec<u8> {
+        let mut b = SshBuf::new();
+        b.put_cstring(self.key_type().cert_name());
+        b.put_string(&self.nonce);
+        self.key.put_body(&mut b);
+        b.put_u64(self.serial);
+        b.put_u32(self.cert_type as u32);
+        b.put_cstring(&self.key_id);
+        let mut principals = SshBuf::new();
+        for p in &self.principals {
+            principals.put_cstring(p);
+        }
+        b.put_stringb(&principals);
+        b.put_u64(self.valid_after);
+        b.put_u64(self.valid_before);
+        b.put_string(&encode_options(&self.critical_options));
+        b.put_string(&encode_options(&self.extensions));
+        b.put_string(&self.reserved);
+        b.put_string(&self.signature_key.to_blob());
+        b.into_vec()
+    }
+
+    pub fn to_blob(&self) -> Vec<u8> {
+        let mut b = SshBuf::from_vec(self.signed_data());
+        b.put_string(&self.signature);
+        b.into_vec()
+    }
+
+    /// Read the fields following the type name and check the CA
+    /// signature (cert_parse()).
+    pub(super) fn decode_body(ktype: KeyType, b: &mut SshBuf) -> Result<Certificate, KeyError> {
+        let nonce = b.get_string()?.to_vec();
+        let key = PublicKey::decode_body(ktype, b)?;
+        let serial = b.get_u64()?;
+        let raw_type = b.get_u32()?;
+        let cert_type = CertType::from_u32(raw_type)
+            .ok_or_else(|| KeyError::CertInvalid(format!("unknown certificate type {}", raw_type)))?;
+        let key_id = get_cstring(b)?;
+        let mut pb = SshBuf::from_vec(b.get_string()?.to_vec());
+        let valid_after = b.get_u64()?;
+        let valid_before = b.get_u64()?;
+        let critical_options = decode_options(b.get_string()?)?;
+        let extensions = decode_options(b.get_string()?)?;
+        let reserved = b.get_string()?.to_vec();
+        let signature_key = PublicKey::from_blob(b.get_string()?)?;
+        let signature = b.get_string()?.to_vec();
+
+        let mut principals = Vec::new();
+        while !pb.is_empty() {
+            if principals.len() >= SSHKEY_CERT_MAX_PRINCIPALS {
+                return Err(KeyError::InvalidFormat("too many principals".to_string()));
+            }
+            principals.push(get_cstring(&mut pb)?);
+        }
+        if signature_key.is_cert() {
+            return Err(KeyError::CertInvalid("certificate signed by a certificate".to_string()));
+        }
+
+        let cert = Certificate {
+            nonce,
+            key,
+            serial,
+            cert_type,
+            key_id,
+            principals,
+            valid_after,
+            valid_before,
+            critical_options,
+            extensions,
+            reserved,
+            signature_key,
+            signature,
+        };
+        cert.signature_key.verify(&cert.signature, &cert.signed_data(), None)?;
+        Ok(cert)
+    }
+
+    /// Signature algorithm used by the CA.
+    pub fn signature_type(&self) -> Result<String, KeyError> {
+        super::signature_type(&self.signature)
+    }
+
+    /// Check type, validity window and principal (sshkey_cert_check_authority()).
+    /// `now` is in seconds since the epoch. With `wildcard_pattern` the
+    /// principals are patterns matched against `name`, as for host
+    /// certificates. Whether the CA is trusted is not checked here.
+    pub fn check_authority(
+        &self,
+        want_host: bool,
+        require_principal: bool,
+        wildcard_pattern: bool,
+        name: Option<&str>,
+        now: u64,
+    ) -> Result<(), KeyError> {
+        let invalid = |reason: &str| Err(KeyError::CertInvalid(reason.to_string()));
+        if want_host && self.cert_type != CertType::Host {
+            return invalid("Certificate invalid: not a host certificate");
+        }
+        if !want_host && self.cert_type != CertType::User {
+            return invalid("Certificate invalid: not a user certificate");
+        }
+        if now < self.valid_after {
+            return invalid("Certificate invalid: not yet valid");
+        }
+        if now >= self.valid_before {
+            return invalid("Certificate invalid: expired");
+        }
+        if self.principals.is_empty() {
+            if require_principal {
+                return invalid("Certificate lacks principal list");
+            }
+        } else if let Some(name) = name {
+            let listed = self
+                .principals
+                .iter()
+                .any(|p| if wildcard_pattern { match_pattern(name, p) } else { p == name });
+            if !listed {
+                return invalid("Certificate invalid: name is not a listed principal");
+            }
+        }
+        Ok(())
+    }
+
+    /// Accept the certificate for `principal` at time `now` if it was
+    /// signed by one of `ca_keys`. User certificates must list the
+    /// principal; host certificates may list wildcard patterns or none.
+    pub fn validate(&self, principal: &str, now: u64, ca_keys: &[PublicKey]) -> Result<(), KeyError> {
+        if !ca_keys.contains(&self.signature_key) {
+            return Err(KeyError::CertInvalid("Certificate signed by an untrusted CA".to_string()));
+        }
+        let host = self.cert_type == CertType::Host;
+        self.check_authority(host, !host, host, Some(principal), now)
+    }
+}
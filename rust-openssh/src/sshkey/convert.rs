@@ -0,0 +1,177 @@
+// sshkey/convert.rs
+//
+// Public key interchange formats for ssh-keygen -e and -i, after the
+// do_convert_to_*() and do_convert_from_*() functions of ssh-keygen.c:
+// the RFC 4716 "SSH2 PUBLIC KEY" block, and X.509 SubjectPublicKeyInfo
+// ("PKCS8") or PKCS#1 RSAPublicKey ("PEM") inside PEM armour.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use pkcs8::der::asn1::BitString;
+use pkcs8::der::{Any, Decode, Encode};
+use pkcs8::spki::{AlgorithmIdentifierOwned, SubjectPublicKeyInfoOwned, SubjectPublicKeyInfoRef};
+use pkcs8::ObjectIdentifier;
+use rsa::pkcs1::{DecodeRsaPublicKey, EncodeRsaPublicKey};
+use rsa::traits::PublicKeyParts;
+use rsa::RsaPublicKey;
+
+use super::format::{dearmor, Armor, OID_EC, OID_ED25519, OID_P256, OID_P384, OID_P521, OID_RSA};
+use super::{rsa_public, EcdsaCurve, KeyError, PrivateKeyFormat, PublicKey, ED25519_PK_SZ};
+
+pub const SSH_COM_PUBLIC_BEGIN: &str = "---- BEGIN SSH2 PUBLIC KEY ----";
+pub const SSH_COM_PUBLIC_END: &str = "---- END SSH2 PUBLIC KEY ----";
+const SSH_COM_PRIVATE_BEGIN: &str = "---- BEGIN SSH2 ENCRYPTED PRIVATE KEY ----";
+
+fn der_err(e: impl std::fmt::Display) -> KeyError {
+    KeyError::InvalidFormat(format!("DER: {}", e))
+}
+
+fn curve_oid(curve: EcdsaCurve) -> ObjectIdentifier {
+    match curve {
+        EcdsaCurve::NistP256 => OID_P256,
+        EcdsaCurve::NistP384 => OID_P384,
+        EcdsaCurve::NistP521 => OID_P521,
+    }
+}
+
+// PEM 封装，与 OpenSSL 一样每行 64 个字符
+fn armor(label: &str, der: &[u8]) -> String {
+    let b64 = BASE64.encode(der);
+    let mut out = format!("-----BEGIN {}-----\n", label);
+    for chunk in b64.as_bytes().chunks(64) {
+        out.push_str(std::str::from_utf8(chunk).unwrap());
+        out.push('\n');
+    }
+    out.push_str(&format!("-----END {}-----\n", label));
+    out
+}
+
+impl PublicKey {
+    /// The RFC 4716 form written by ssh-keygen -e (do_convert_to_ssh2()).
+    /// Certificates are exported whole; `comment` becomes the Comment
+    /// header.
+    pub fn to_rfc4716(&self, comment: &str) -> String {
+        let b64 = BASE64.encode(self.to_blob());
+        let mut out = format!("{}\nComment: \"{}\"\n", SSH_COM_PUBLIC_BEGIN, comment);
+        for chunk in b64.as_bytes().chunks(70) {
+            out.push_str(std::str::from_utf8(chunk).unwrap());
+            out.push('\n');
+        }
+        out.push_str(SSH_COM_PUBLIC_END);
+        out.push('\n');
+        out
+    }
+
+    /// Read an RFC 4716 public key (do_convert_from_ssh2()). Header lines,
+    /// including those continued with a trailing backslash, are skipped.
+    pub fn from_rfc4716(text: &str) -> Result<PublicKey, KeyError> {
+        let mut lines = text.lines().map(str::trim_end).skip_while(|l| l.trim().is_empty());
+        match lines.next() {
+            Some(SSH_COM_PUBLIC_BEGIN) => {}
+            Some(SSH_COM_PRIVATE_BEGIN) => {
+                return Err(KeyError::Unsupported("SSH2 private keys are not supported".to_string()))
+            }
+            _ => return Err(KeyError::InvalidFormat("missing SSH2 PUBLIC KEY header".to_string())),
+        }
+        let mut encoded = String::new();
+        let mut continued = false;
+        let mut ended = false;
+        for line in lines {
+            if line == SSH_COM_PUBLIC_END {
+                ended = true;
+                break;
+            }
+            // 头部行形如 "Tag: value"，以反斜杠结尾时下一行是它的延续
+            let header = continued || line.contains(':');
+            continued = header && line.ends_with('\\');
+            if !header {
+                encoded.push_str(line.trim());
+            }
+        }
+        if !ended {
+            return Err(KeyError::InvalidFormat("missing SSH2 PUBLIC KEY trailer".to_string()));
+        }
+        let blob = BASE64.decode(encoded).map_err(|_| KeyError::InvalidFormat("bad base64 in SSH2 key".to_string()))?;
+        PublicKey::from_blob(&blob)
+    }
+
+    /// SubjectPublicKeyInfo DER of the key, or of the key a certificate
+    /// certifies.
+    pub fn to_spki_der(&self) -> Result<Vec<u8>, KeyError> {
+        let (algorithm, bits) = match self.plain() {
+            PublicKey::Rsa(k) => {
+                let null = Any::encode_from(&pkcs8::der::asn1::Null).map_err(der_err)?;
+                let der = k.to_pkcs1_der().map_err(der_err)?;
+                (AlgorithmIdentifierOwned { oid: OID_RSA, parameters: Some(null) }, der.as_bytes().to_vec())
+            }
+            PublicKey::Ecdsa { curve, point } => {
+                let params = Any::encode_from(&curve_oid(*curve)).map_err(der_err)?;
+                (AlgorithmIdentifierOwned { oid: OID_EC, parameters: Some(params) }, point.clone())
+            }
+            PublicKey::Ed25519(k) => (AlgorithmIdentifierOwned { oid: OID_ED25519, parameters: None }, k.to_vec()),
+            k => return Err(KeyError::Unsupported(format!("cannot convert {} keys", k.type_name()))),
+        };
+        let info = SubjectPublicKeyInfoOwned { algorithm, subject_public_key: BitString::from_bytes(&bits).map_err(der_err)? };
+        info.to_der().map_err(der_err)
+    }
+
+    /// Parse a SubjectPublicKeyInfo.
+    pub fn from_spki_der(der: &[u8]) -> Result<PublicKey, KeyError> {
+        let info = SubjectPublicKeyInfoRef::from_der(der).map_err(der_err)?;
+        let bits = info
+            .subject_public_key
+            .as_bytes()
+            .ok_or_else(|| KeyError::InvalidFormat("unaligned public key".to_string()))?;
+        match info.algorithm.oid {
+            OID_RSA => rsa_from_pkcs1(bits),
+            OID_EC => {
+                let curve = match info.algorithm.parameters_oid().map_err(der_err)? {
+                    OID_P256 => EcdsaCurve::NistP256,
+                    OID_P384 => EcdsaCurve::NistP384,
+                    OID_P521 => EcdsaCurve::NistP521,
+                    oid => return Err(KeyError::UnknownKeyType(format!("EC curve {}", oid))),
+                };
+                PublicKey::ecdsa(curve, bits)
+            }
+            OID_ED25519 => {
+                let key: [u8; ED25519_PK_SZ] =
+                    bits.try_into().map_err(|_| KeyError::InvalidKey("bad Ed25519 public key".to_string()))?;
+                Ok(PublicKey::Ed25519(key))
+            }
+            oid => Err(KeyError::UnknownKeyType(format!("public key algorithm {}", oid))),
+        }
+    }
+
+    /// ssh-keygen -e -m PKCS8|PEM (do_convert_to_pkcs8(), do_convert_to_pem()).
+    /// PKCS8 writes a "PUBLIC KEY" block; PEM writes RSA keys as PKCS#1
+    /// "RSA PUBLIC KEY" and ECDSA keys as "PUBLIC KEY", as OpenSSL does.
+    pub fn to_pem_public(&self, format: PrivateKeyFormat) -> Result<String, KeyError> {
+        match (format, self.plain()) {
+            (PrivateKeyFormat::Pem, PublicKey::Rsa(k)) => {
+                Ok(armor("RSA PUBLIC KEY", k.to_pkcs1_der().map_err(der_err)?.as_bytes()))
+            }
+            (PrivateKeyFormat::Pem, PublicKey::Ed25519(_)) => {
+                Err(KeyError::Unsupported("ED25519 keys cannot be written as PEM".to_string()))
+            }
+            (PrivateKeyFormat::Pem | PrivateKeyFormat::Pkcs8, _) => Ok(armor("PUBLIC KEY", &self.to_spki_der()?)),
+            (PrivateKeyFormat::OpenSsh, _) => Err(KeyError::Unsupported("not a PEM format".to_string())),
+        }
+    }
+
+    /// ssh-keygen -i -m PKCS8|PEM: a "PUBLIC KEY" or "RSA PUBLIC KEY" block
+    /// (do_convert_from_pkcs8(), do_convert_from_pem()).
+    pub fn from_pem_public(text: &str) -> Result<PublicKey, KeyError> {
+        let Armor { label, data, .. } = dearmor(text)?;
+        match label.as_str() {
+            "PUBLIC KEY" => PublicKey::from_spki_der(&data),
+            "RSA PUBLIC KEY" => rsa_from_pkcs1(&data),
+            _ => Err(KeyError::InvalidFormat(format!("unsupported PEM type {}", label))),
+        }
+    }
+}
+
+// 经 rsa_public() 检查模数长度
+fn rsa_from_pkcs1(der: &[u8]) -> Result<PublicKey, KeyError> {
+    let key = RsaPublicKey::from_pkcs1_der(der).map_err(der_err)?;
+    Ok(PublicKey::Rsa(rsa_public(&key.n().to_bytes_be(), &key.e().to_bytes_be())?))
+}
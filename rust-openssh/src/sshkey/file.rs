@@ -0,0 +1,135 @@
+// sshkey/file.rs
+//
+// Reading and writing key files, after OpenSSH's authfile.c. A private
+// key file that its owner has left readable by others is refused with the
+// familiar "UNPROTECTED PRIVATE KEY FILE" warning; public keys are looked
+// for in the file itself, then in the matching .pub file.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+use log::error;
+
+use super::format::{public_from_private_text, private_format, PrivateKeyFormat};
+use super::{KeyError, PrivateKey, PublicKey};
+
+/// Key files larger than this are not read (MAX_KEY_FILE_SIZE).
+pub const MAX_KEY_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Refuse a file owned by the current user that group or others can
+/// access (sshkey_perm_ok()). Files owned by someone else are left to
+/// the usual access checks.
+pub fn perm_ok(file: &File, path: &Path) -> Result<(), KeyError> {
+    let meta = file.metadata().map_err(KeyError::Io)?;
+    let uid = unsafe { libc::getuid() };
+    if meta.uid() == uid && meta.mode() & 0o077 != 0 {
+        error!("@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@");
+        error!("@         WARNING: UNPROTECTED PRIVATE KEY FILE!          @");
+        error!("@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@");
+        error!("Permissions 0{:03o} for '{}' are too open.", meta.mode() & 0o777, path.display());
+        error!("It is required that your private key files are NOT accessible by others.");
+        error!("This private key will be ignored.");
+        return Err(KeyError::BadPermissions(meta.mode() & 0o777));
+    }
+    Ok(())
+}
+
+fn read_limited(mut file: File) -> Result<String, KeyError> {
+    let mut text = String::new();
+    (&mut file).take(MAX_KEY_FILE_SIZE + 1).read_to_string(&mut text).map_err(KeyError::Io)?;
+    if text.len() as u64 > MAX_KEY_FILE_SIZE {
+        return Err(KeyError::InvalidFormat("key file too large".to_string()));
+    }
+    Ok(text)
+}
+
+/// Load a private key and its comment (sshkey_load_private()).
+pub fn load_private(path: &Path, passphrase: &str) -> Result<(PrivateKey, String), KeyError> {
+    let file = File::open(path).map_err(KeyError::Io)?;
+    perm_ok(&file, path)?;
+    PrivateKey::from_private_text(&read_limited(file)?, passphrase)
+}
+
+// 文件中第一条可解析的公钥行，跳过空行与注释（sshkey_try_load_public()）
+fn parse_public_lines(text: &str) -> Result<(PublicKey, String), KeyError> {
+    let mut last = KeyError::InvalidFormat("no public key found".to_string());
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match PublicKey::from_openssh_line(line) {
+            Ok(found) => return Ok(found),
+            Err(e) => last = e,
+        }
+    }
+    Err(last)
+}
+
+/// Path of the public key belonging to a private key file.
+pub fn public_path(path: &Path) -> PathBuf {
+    let mut p = path.as_os_str().to_owned();
+    p.push(".pub");
+    PathBuf::from(p)
+}
+
+/// Load a public key (sshkey_load_public()). `path` may be a public key
+/// file or a private key file; for the latter `path`.pub is read, falling
+/// back to the public half kept unencrypted in an openssh-key-v1 file.
+pub fn load_public(path: &Path) -> Result<(PublicKey, String), KeyError> {
+    let text = read_limited(File::open(path).map_err(KeyError::Io)?)?;
+    let format = match private_format(&text) {
+        Some(format) => format,
+        None => return parse_public_lines(&text),
+    };
+    match File::open(public_path(path)) {
+        Ok(f) => parse_public_lines(&read_limited(f)?),
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(KeyError::Io(e)),
+        Err(_) if format == PrivateKeyFormat::OpenSsh => Ok((public_from_private_text(&text)?, String::new())),
+        Err(_) => Err(KeyError::InvalidFormat("PEM private key without a .pub file".to_string())),
+    }
+}
+
+// 以给定权限创建或截断文件后写入
+fn write_file(path: &Path, data: &str, mode: u32) -> Result<(), KeyError> {
+    let mut f = OpenOptions::new().write(true).create(true).truncate(true).mode(mode).open(path).map_err(KeyError::Io)?;
+    f.write_all(data.as_bytes()).map_err(KeyError::Io)?;
+    f.sync_all().map_err(KeyError::Io)
+}
+
+/// Write a private key in openssh-key-v1 format with mode 0600
+/// (sshkey_save_private()).
+pub fn save_private(
+    path: &Path,
+    key: &PrivateKey,
+    passphrase: &str,
+    comment: &str,
+    ciphername: &str,
+    rounds: u32,
+) -> Result<(), KeyError> {
+    let text = key.to_openssh_private(comment, passphrase, ciphername, rounds)?;
+    write_file(path, &text, 0o600)
+}
+
+/// Write "type base64 comment" to a .pub file with mode 0644.
+pub fn save_public(path: &Path, key: &PublicKey, comment: &str) -> Result<(), KeyError> {
+    write_file(path, &format!("{}\n", key.to_openssh_line(comment)), 0o644)
+}
+
+/// Re-encrypt a private key under a new passphrase, as ssh-keygen -p
+/// does. Keys in PEM or PKCS#8 files are rewritten as openssh-key-v1.
+/// The new file replaces the old one atomically; returns the comment.
+pub fn change_passphrase(path: &Path, old: &str, new: &str, ciphername: &str, rounds: u32) -> Result<String, KeyError> {
+    let (key, comment) = load_private(path, old)?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let _ = fs::remove_file(&tmp);
+    save_private(&tmp, &key, new, &comment, ciphername, rounds)?;
+    if let Err(e) = fs::rename(&tmp, path) {
+        let _ = fs::remove_file(&tmp);
+        return Err(KeyError::Io(e));
+    }
+    Ok(comment)
+}
@@ -0,0 +1,113 @@
+// sshkey/fingerprint.rs
+//
+// Key fingerprints as printed by ssh-keygen -l and ssh: a digest of the
+// public key blob shown as unpadded base64 (SHA256) or colon-separated
+// hex (MD5), and the "drunken bishop" randomart picture of -lv.
+
+use base64::engine::general_purpose::STANDARD_NO_PAD as BASE64_NO_PAD;
+use base64::Engine;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use super::PublicKey;
+
+const FLDBASE: usize = 8;
+const FLDSIZE_Y: usize = FLDBASE + 1;
+const FLDSIZE_X: usize = FLDBASE * 2 + 1;
+// 访问次数越多，字符越“重”；S 与 E 标记起点和终点
+const AUGMENTATION: &[u8] = b" .o+=*BOX@%&#/^SE";
+
+/// Fingerprint hash (FingerprintHash).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FpHash {
+    Md5,
+    Sha256,
+}
+
+impl FpHash {
+    pub fn name(self) -> &'static str {
+        match self {
+            FpHash::Md5 => "MD5",
+            FpHash::Sha256 => "SHA256",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FpHash> {
+        match name.to_ascii_lowercase().as_str() {
+            "md5" => Some(FpHash::Md5),
+            "sha256" => Some(FpHash::Sha256),
+            _ => None,
+        }
+    }
+}
+
+impl PublicKey {
+    /// Digest of the key blob (sshkey_fingerprint_raw()). Certificates
+    /// have the fingerprint of the key they certify.
+    pub fn fingerprint_raw(&self, hash: FpHash) -> Vec<u8> {
+        let blob = self.plain().to_blob();
+        match hash {
+            FpHash::Md5 => Md5::digest(&blob).to_vec(),
+            FpHash::Sha256 => Sha256::digest(&blob).to_vec(),
+        }
+    }
+
+    /// "SHA256:base64" or "MD5:hex:hex:..." (sshkey_fingerprint()).
+    pub fn fingerprint(&self, hash: FpHash) -> String {
+        let raw = self.fingerprint_raw(hash);
+        let body = match hash {
+            FpHash::Md5 => raw.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(":"),
+            FpHash::Sha256 => BASE64_NO_PAD.encode(&raw),
+        };
+        format!("{}:{}", hash.name(), body)
+    }
+
+    /// The randomart picture (fingerprint_randomart()), without a trailing
+    /// newline.
+    pub fn randomart(&self, hash: FpHash) -> String {
+        let raw = self.fingerprint_raw(hash);
+        let len = AUGMENTATION.len() - 1;
+        let mut field = [[0usize; FLDSIZE_Y]; FLDSIZE_X];
+        let (mut x, mut y) = (FLDSIZE_X / 2, FLDSIZE_Y / 2);
+
+        // 每个字节给出四步移动，每步两位：第 0 位左右，第 1 位上下
+        for &byte in &raw {
+            let mut input = byte;
+            for _ in 0..4 {
+                x = if input & 0x1 != 0 { (x + 1).min(FLDSIZE_X - 1) } else { x.saturating_sub(1) };
+                y = if input & 0x2 != 0 { (y + 1).min(FLDSIZE_Y - 1) } else { y.saturating_sub(1) };
+                if field[x][y] < len - 2 {
+                    field[x][y] += 1;
+                }
+                input >>= 2;
+            }
+        }
+        field[FLDSIZE_X / 2][FLDSIZE_Y / 2] = len - 1;
+        field[x][y] = len;
+
+        let ktype = self.type_name();
+        let mut title = format!("[{} {}]", ktype, self.bits());
+        // 放不下时只显示类型
+        if title.len() > FLDSIZE_X {
+            title = format!("[{}]", ktype);
+        }
+        title.truncate(FLDSIZE_X - 1);
+        let footer = format!("[{}]", hash.name());
+
+        let border = |label: &str| {
+            let left = (FLDSIZE_X - label.len()) / 2;
+            format!("+{}{}{}+", "-".repeat(left), label, "-".repeat(FLDSIZE_X - left - label.len()))
+        };
+        let mut out = border(&title);
+        out.push('\n');
+        for y in 0..FLDSIZE_Y {
+            out.push('|');
+            for column in &field {
+                out.push(AUGMENTATION[column[y].min(len)] as char);
+            }
+            out.push_str("|\n");
+        }
+        out.push_str(&border(&footer));
+        out
+    }
+}
@@ -0,0 +1,990 @@
+// sshkey/mod.rs
+//
+// Public and private keys, after OpenSSH's sshkey.c: the key types in use
+// today (Ed25519, ECDSA over the NIST curves, RSA with SHA-2 signatures and
+// the FIDO security key variants), their wire encoding (RFC 4253 §6.6,
+// RFC 5656 §3.1, PROTOCOL.u2f), certificates, signatures, private key
+// serialisation and the single-line "type base64 comment" form used by
+// .pub files.
+
+pub mod cert;
+pub mod convert;
+pub mod file;
+pub mod fingerprint;
+pub mod format;
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ed25519_dalek::{Signer, Verifier};
+use rand_core::{CryptoRng, RngCore};
+use rsa::traits::{PrivateKeyParts, PublicKeyParts};
+use rsa::{BigUint, Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::packet::random_bytes;
+use crate::sshbuf::{SshBuf, SshBufError, SSHBUF_MAX_BIGNUM};
+
+pub use self::cert::{CertOption, CertRequest, CertType, Certificate};
+pub use self::fingerprint::FpHash;
+pub use self::format::PrivateKeyFormat;
+
+/// SSH_RSA_MINIMUM_MODULUS_SIZE: smaller RSA keys are refused outright.
+pub const RSA_MIN_MODULUS_SIZE: usize = 1024;
+pub const RSA_MAX_MODULUS_SIZE: usize = SSHBUF_MAX_BIGNUM * 8;
+pub const ED25519_PK_SZ: usize = 32;
+pub const ED25519_SK_SZ: usize = 64;
+const ED25519_SIG_SZ: usize = 64;
+
+/// Security key flag: the authenticator tested for user presence.
+pub const SSH_SK_USER_PRESENCE_REQD: u8 = 0x01;
+/// Security key flag: the user was verified (PIN or biometric).
+pub const SSH_SK_USER_VERIFICATION_REQD: u8 = 0x04;
+pub const SSH_SK_RESIDENT_KEY: u8 = 0x20;
+
+#[derive(Debug)]
+pub enum KeyError {
+    Format(SshBufError),
+    /// Structurally valid encoding with invalid contents.
+    InvalidFormat(String),
+    UnknownKeyType(String),
+    /// A signature or blob of one key type used with another.
+    TypeMismatch,
+    /// The key itself is unusable, e.g. an EC point not on the curve.
+    InvalidKey(String),
+    /// RSA modulus outside the permitted range.
+    KeyLength(usize),
+    SignatureInvalid,
+    /// A certificate that is malformed or not valid for the use at hand;
+    /// the message is the reason OpenSSH would log.
+    CertInvalid(String),
+    /// An encrypted private key with a wrong or missing passphrase.
+    WrongPassphrase,
+    UnknownCipher(String),
+    /// A private key file readable by others; holds the file mode.
+    BadPermissions(u32),
+    Unsupported(String),
+    Rng(io::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyError::Format(e) => write!(f, "invalid key encoding: {}", e),
+            KeyError::InvalidFormat(msg) => write!(f, "invalid format: {}", msg),
+            KeyError::UnknownKeyType(t) => write!(f, "unknown or unsupported key type {}", t),
+            KeyError::TypeMismatch => f.write_str("key type does not match"),
+            KeyError::InvalidKey(msg) => write!(f, "invalid key: {}", msg),
+            KeyError::KeyLength(bits) => write!(f, "invalid key length {}", bits),
+            KeyError::SignatureInvalid => f.write_str("incorrect signature"),
+            KeyError::CertInvalid(reason) => f.write_str(reason),
+            KeyError::WrongPassphrase => f.write_str("incorrect passphrase supplied to decrypt private key"),
+            KeyError::UnknownCipher(name) => write!(f, "unknown or unsupported key cipher {}", name),
+            KeyError::BadPermissions(mode) => write!(f, "bad permissions 0{:03o}", mode),
+            KeyError::Unsupported(msg) => write!(f, "unsupported: {}", msg),
+            KeyError::Rng(e) => write!(f, "random number generator: {}", e),
+            KeyError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for KeyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            KeyError::Format(e) => Some(e),
+            KeyError::Rng(e) | KeyError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<SshBufError> for KeyError {
+    fn from(e: SshBufError) -> Self {
+        KeyError::Format(e)
+    }
+}
+
+/// Key types (the KEY_* constants of sshkey.h).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyType {
+    Ed25519,
+    Ecdsa(EcdsaCurve),
+    Rsa,
+    SkEd25519,
+    SkEcdsa,
+}
+
+impl KeyType {
+    /// Wire name of the key (sshkey_ssh_name()).
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyType::Ed25519 => "ssh-ed25519",
+            KeyType::Ecdsa(curve) => curve.key_name(),
+            KeyType::Rsa => "ssh-rsa",
+            KeyType::SkEd25519 => "sk-ssh-ed25519@openssh.com",
+            KeyType::SkEcdsa => "sk-ecdsa-sha2-nistp256@openssh.com",
+        }
+    }
+
+    /// Short name shown by ssh-keygen (sshkey_type()).
+    pub fn short_name(self) -> &'static str {
+        match self {
+            KeyType::Ed25519 => "ED25519",
+            KeyType::Ecdsa(_) => "ECDSA",
+            KeyType::Rsa => "RSA",
+            KeyType::SkEd25519 => "ED25519-SK",
+            KeyType::SkEcdsa => "ECDSA-SK",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<KeyType> {
+        Some(match name {
+            "ssh-ed25519" => KeyType::Ed25519,
+            "ecdsa-sha2-nistp256" => KeyType::Ecdsa(EcdsaCurve::NistP256),
+            "ecdsa-sha2-nistp384" => KeyType::Ecdsa(EcdsaCurve::NistP384),
+            "ecdsa-sha2-nistp521" => KeyType::Ecdsa(EcdsaCurve::NistP521),
+            "ssh-rsa" => KeyType::Rsa,
+            "sk-ssh-ed25519@openssh.com" => KeyType::SkEd25519,
+            "sk-ecdsa-sha2-nistp256@openssh.com" => KeyType::SkEcdsa,
+            _ => return None,
+        })
+    }
+
+    /// Key type for a signature algorithm name, e.g. rsa-sha2-256 → RSA.
+    pub fn from_sig_alg(alg: &str) -> Option<KeyType> {
+        match alg {
+            "rsa-sha2-256" | "rsa-sha2-512" => Some(KeyType::Rsa),
+            _ => KeyType::from_name(alg),
+        }
+    }
+
+    pub fn is_sk(self) -> bool {
+        matches!(self, KeyType::SkEd25519 | KeyType::SkEcdsa)
+    }
+
+    /// Wire name of a certificate for this key type.
+    pub fn cert_name(self) -> &'static str {
+        match self {
+            KeyType::Ed25519 => "ssh-ed25519-cert-v01@openssh.com",
+            KeyType::Ecdsa(EcdsaCurve::NistP256) => "ecdsa-sha2-nistp256-cert-v01@openssh.com",
+            KeyType::Ecdsa(EcdsaCurve::NistP384) => "ecdsa-sha2-nistp384-cert-v01@openssh.com",
+            KeyType::Ecdsa(EcdsaCurve::NistP521) => "ecdsa-sha2-nistp521-cert-v01@openssh.com",
+            KeyType::Rsa => "ssh-rsa-cert-v01@openssh.com",
+            KeyType::SkEd25519 => "sk-ssh-ed25519-cert-v01@openssh.com",
+            KeyType::SkEcdsa => "sk-ecdsa-sha2-nistp256-cert-v01@openssh.com",
+        }
+    }
+
+    pub fn cert_short_name(self) -> &'static str {
+        match self {
+            KeyType::Ed25519 => "ED25519-CERT",
+            KeyType::Ecdsa(_) => "ECDSA-CERT",
+            KeyType::Rsa => "RSA-CERT",
+            KeyType::SkEd25519 => "ED25519-SK-CERT",
+            KeyType::SkEcdsa => "ECDSA-SK-CERT",
+        }
+    }
+
+    /// Key type certified by a certificate of the given wire name.
+    pub fn from_cert_name(name: &str) -> Option<KeyType> {
+        let plain = name.strip_suffix("-cert-v01@openssh.com")?;
+        let ktype = KeyType::from_name(plain).or_else(|| KeyType::from_name(&format!("{}@openssh.com", plain)))?;
+        (ktype.cert_name() == name).then_some(ktype)
+    }
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EcdsaCurve {
+    NistP256,
+    NistP384,
+    NistP521,
+}
+
+impl EcdsaCurve {
+    /// Curve identifier inside the key blob (sshkey_curve_nid_to_name()).
+    pub fn name(self) -> &'static str {
+        match self {
+            EcdsaCurve::NistP256 => "nistp256",
+            EcdsaCurve::NistP384 => "nistp384",
+            EcdsaCurve::NistP521 => "nistp521",
+        }
+    }
+
+    fn key_name(self) -> &'static str {
+        match self {
+            EcdsaCurve::NistP256 => "ecdsa-sha2-nistp256",
+            EcdsaCurve::NistP384 => "ecdsa-sha2-nistp384",
+            EcdsaCurve::NistP521 => "ecdsa-sha2-nistp521",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<EcdsaCurve> {
+        match name {
+            "nistp256" => Some(EcdsaCurve::NistP256),
+            "nistp384" => Some(EcdsaCurve::NistP384),
+            "nistp521" => Some(EcdsaCurve::NistP521),
+            _ => None,
+        }
+    }
+
+    pub fn from_bits(bits: usize) -> Option<EcdsaCurve> {
+        match bits {
+            256 => Some(EcdsaCurve::NistP256),
+            384 => Some(EcdsaCurve::NistP384),
+            521 => Some(EcdsaCurve::NistP521),
+            _ => None,
+        }
+    }
+
+    pub fn bits(self) -> usize {
+        match self {
+            EcdsaCurve::NistP256 => 256,
+            EcdsaCurve::NistP384 => 384,
+            EcdsaCurve::NistP521 => 521,
+        }
+    }
+
+    fn field_len(self) -> usize {
+        self.bits().div_ceil(8)
+    }
+}
+
+/// Security key details from a verified sk signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SkDetails {
+    pub flags: u8,
+    pub counter: u32,
+}
+
+/// A public key (struct sshkey without private parts).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublicKey {
+    Ed25519([u8; ED25519_PK_SZ]),
+    /// Uncompressed SEC1 point, validated on construction.
+    Ecdsa { curve: EcdsaCurve, point: Vec<u8> },
+    Rsa(RsaPublicKey),
+    SkEd25519 { key: [u8; ED25519_PK_SZ], application: String },
+    SkEcdsa { point: Vec<u8>, application: String },
+    Cert(Box<Certificate>),
+}
+
+// 校验 EC 公钥点：必须为未压缩格式且位于曲线上（sshkey_ec_validate_public()）
+fn check_ec_point(curve: EcdsaCurve, point: &[u8]) -> Result<(), KeyError> {
+    if point.len() != 1 + 2 * curve.field_len() || point[0] != 0x04 {
+        return Err(KeyError::InvalidKey("EC point is not in uncompressed form".to_string()));
+    }
+    let ok = match curve {
+        EcdsaCurve::NistP256 => p256::ecdsa::VerifyingKey::from_sec1_bytes(point).is_ok(),
+        EcdsaCurve::NistP384 => p384::ecdsa::VerifyingKey::from_sec1_bytes(point).is_ok(),
+        EcdsaCurve::NistP521 => p521::ecdsa::VerifyingKey::from_sec1_bytes(point).is_ok(),
+    };
+    if !ok {
+        return Err(KeyError::InvalidKey("EC point is not on the curve".to_string()));
+    }
+    Ok(())
+}
+
+fn rsa_public(n: &[u8], e: &[u8]) -> Result<RsaPublicKey, KeyError> {
+    let n = BigUint::from_bytes_be(n);
+    let bits = n.bits();
+    if !(RSA_MIN_MODULUS_SIZE..=RSA_MAX_MODULUS_SIZE).contains(&bits) {
+        return Err(KeyError::KeyLength(bits));
+    }
+    RsaPublicKey::new_with_max_size(n, BigUint::from_bytes_be(e), RSA_MAX_MODULUS_SIZE)
+        .map_err(|e| KeyError::InvalidKey(e.to_string()))
+}
+
+pub(crate) fn get_cstring(b: &mut SshBuf) -> Result<String, KeyError> {
+    Ok(b.get_cstring()?)
+}
+
+fn get_array<const N: usize>(b: &mut SshBuf) -> Result<[u8; N], KeyError> {
+    let v = b.get_string()?;
+    v.try_into().map_err(|_| KeyError::InvalidFormat(format!("expected {} bytes, got {}", N, v.len())))
+}
+
+fn get_curve(b: &mut SshBuf, expect: EcdsaCurve) -> Result<(), KeyError> {
+    let name = get_cstring(b)?;
+    if EcdsaCurve::from_name(&name) != Some(expect) {
+        return Err(KeyError::InvalidFormat(format!("curve {} does not match key type", name)));
+    }
+    Ok(())
+}
+
+impl PublicKey {
+    /// Type of the key; for a certificate, that of the certified key
+    /// (sshkey_type_plain()).
+    pub fn key_type(&self) -> KeyType {
+        match self {
+            PublicKey::Ed25519(_) => KeyType::Ed25519,
+            PublicKey::Ecdsa { curve, .. } => KeyType::Ecdsa(*curve),
+            PublicKey::Rsa(_) => KeyType::Rsa,
+            PublicKey::SkEd25519 { .. } => KeyType::SkEd25519,
+            PublicKey::SkEcdsa { .. } => KeyType::SkEcdsa,
+            PublicKey::Cert(c) => c.key_type(),
+        }
+    }
+
+    pub fn is_cert(&self) -> bool {
+        matches!(self, PublicKey::Cert(_))
+    }
+
+    pub fn cert(&self) -> Option<&Certificate> {
+        match self {
+            PublicKey::Cert(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    /// The key without any certificate (sshkey_drop_cert()).
+    pub fn plain(&self) -> &PublicKey {
+        match self {
+            PublicKey::Cert(c) => &c.key,
+            key => key,
+        }
+    }
+
+    /// Wire name, the certificate name for certificates (sshkey_ssh_name()).
+    pub fn name(&self) -> &'static str {
+        match self {
+            PublicKey::Cert(c) => c.key_type().cert_name(),
+            key => key.key_type().name(),
+        }
+    }
+
+    /// Short name as shown by ssh-keygen, e.g. "ED25519-CERT" (sshkey_type()).
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            PublicKey::Cert(c) => c.key_type().cert_short_name(),
+            key => key.key_type().short_name(),
+        }
+    }
+
+    /// Key size in bits (sshkey_size()).
+    pub fn bits(&self) -> usize {
+        match self {
+            PublicKey::Ed25519(_) | PublicKey::SkEd25519 { .. } => 256,
+            PublicKey::Ecdsa { curve, .. } => curve.bits(),
+            PublicKey::Rsa(k) => k.n().bits(),
+            PublicKey::SkEcdsa { .. } => 256,
+            PublicKey::Cert(c) => c.key.bits(),
+        }
+    }
+
+    /// FIDO application (relying party) of a security key.
+    pub fn sk_application(&self) -> Option<&str> {
+        match self.plain() {
+            PublicKey::SkEd25519 { application, .. } | PublicKey::SkEcdsa { application, .. } => Some(application),
+            _ => None,
+        }
+    }
+
+    pub fn ecdsa(curve: EcdsaCurve, point: &[u8]) -> Result<PublicKey, KeyError> {
+        check_ec_point(curve, point)?;
+        Ok(PublicKey::Ecdsa { curve, point: point.to_vec() })
+    }
+
+    /// Encode as a key blob (sshkey_to_blob()).
+    pub fn to_blob(&self) -> Vec<u8> {
+        if let PublicKey::Cert(c) = self {
+            return c.to_blob();
+        }
+        let mut b = SshBuf::new();
+        b.put_cstring(self.key_type().name());
+        self.put_body(&mut b);
+        b.into_vec()
+    }
+
+    // 类型名之后的公钥字段；证书只写出被认证密钥的字段
+    fn put_body(&self, b: &mut SshBuf) {
+        match self {
+            PublicKey::Ed25519(k) => b.put_string(k),
+            PublicKey::Ecdsa { curve, point } => {
+                b.put_cstring(curve.name());
+                b.put_string(point);
+            }
+            PublicKey::Rsa(k) => {
+                b.put_bignum2_bytes(&k.e().to_bytes_be());
+                b.put_bignum2_bytes(&k.n().to_bytes_be());
+            }
+            PublicKey::SkEd25519 { key, application } => {
+                b.put_string(key);
+                b.put_cstring(application);
+            }
+            PublicKey::SkEcdsa { point, application } => {
+                b.put_cstring(EcdsaCurve::NistP256.name());
+                b.put_string(point);
+                b.put_cstring(application);
+            }
+            PublicKey::Cert(c) => c.key.put_body(b),
+        }
+    }
+
+    /// Decode a key blob (sshkey_from_blob()); trailing data is an error.
+    pub fn from_blob(blob: &[u8]) -> Result<PublicKey, KeyError> {
+        let mut b = SshBuf::from_vec(blob.to_vec());
+        let key = Self::decode(&mut b)?;
+        if !b.is_empty() {
+            return Err(KeyError::InvalidFormat("trailing data after public key".to_string()));
+        }
+        Ok(key)
+    }
+
+    /// Read a key blob's fields from `b`, leaving anything after them.
+    pub fn decode(b: &mut SshBuf) -> Result<PublicKey, KeyError> {
+        let name = get_cstring(b)?;
+        if let Some(ktype) = KeyType::from_cert_name(&name) {
+            return Ok(PublicKey::Cert(Box::new(Certificate::decode_body(ktype, b)?)));
+        }
+        let ktype = KeyType::from_name(&name).ok_or(KeyError::UnknownKeyType(name))?;
+        Self::decode_body(ktype, b)
+    }
+
+    fn decode_body(ktype: KeyType, b: &mut SshBuf) -> Result<PublicKey, KeyError> {
+        Ok(match ktype {
+            KeyType::Ed25519 => PublicKey::Ed25519(get_array(b)?),
+            KeyType::Ecdsa(curve) => {
+                get_curve(b, curve)?;
+                PublicKey::ecdsa(curve, b.get_string()?)?
+            }
+            KeyType::Rsa => {
+                let e = b.get_bignum2_bytes()?.to_vec();
+                let n = b.get_bignum2_bytes()?.to_vec();
+                PublicKey::Rsa(rsa_public(&n, &e)?)
+            }
+            KeyType::SkEd25519 => {
+                let key = get_array(b)?;
+                PublicKey::SkEd25519 { key, application: get_cstring(b)? }
+            }
+            KeyType::SkEcdsa => {
+                get_curve(b, EcdsaCurve::NistP256)?;
+                let point = b.get_string()?.to_vec();
+                check_ec_point(EcdsaCurve::NistP256, &point)?;
+                PublicKey::SkEcdsa { point, application: get_cstring(b)? }
+            }
+        })
+    }
+
+    /// Parse "type base64 [comment]" as found in .pub files, returning
+    /// the key and its comment (sshkey_read()).
+    pub fn from_openssh_line(line: &str) -> Result<(PublicKey, String), KeyError> {
+        let line = line.trim_start();
+        let (name, rest) = line.split_once([' ', '\t']).ok_or_else(|| KeyError::InvalidFormat("missing key data".to_string()))?;
+        if KeyType::from_name(name).or_else(|| KeyType::from_cert_name(name)).is_none() {
+            return Err(KeyError::UnknownKeyType(name.to_string()));
+        }
+        let rest = rest.trim_start_matches([' ', '\t']);
+        let (data, comment) = rest.split_once([' ', '\t']).unwrap_or((rest, ""));
+        let blob = BASE64
+            .decode(data.trim_end())
+            .map_err(|_| KeyError::InvalidFormat("bad base64 key data".to_string()))?;
+        let key = Self::from_blob(&blob)?;
+        if key.name() != name {
+            return Err(KeyError::TypeMismatch);
+        }
+        Ok((key, comment.trim().to_string()))
+    }
+
+    /// Format as "type base64 comment" (sshkey_write()); an empty comment
+    /// is left out.
+    pub fn to_openssh_line(&self, comment: &str) -> String {
+        let mut line = format!("{} {}", self.name(), BASE64.encode(self.to_blob()));
+        if !comment.is_empty() {
+            line.push(' ');
+            line.push_str(comment);
+        }
+        line
+    }
+
+    /// Verify `sig` over `data` (sshkey_verify()). If `alg` is given the
+    /// signature must use exactly that algorithm. Security key signatures
+    /// return their flags and counter; checking user presence against
+    /// policy is up to the caller. A certificate verifies with the key it
+    /// certifies.
+    pub fn verify(&self, sig: &[u8], data: &[u8], alg: Option<&str>) -> Result<Option<SkDetails>, KeyError> {
+        if let PublicKey::Cert(c) = self {
+            return c.key.verify(sig, data, alg);
+        }
+        let mut b = SshBuf::from_vec(sig.to_vec());
+        let sigtype = get_cstring(&mut b)?;
+        if let Some(alg) = alg {
+            if alg != sigtype {
+                return Err(KeyError::SignatureInvalid);
+            }
+        }
+        if KeyType::from_sig_alg(&sigtype) != Some(self.key_type()) {
+            return Err(KeyError::TypeMismatch);
+        }
+        let raw = b.get_string()?.to_vec();
+        let details = if self.key_type().is_sk() {
+            Some(SkDetails { flags: b.get_u8()?, counter: b.get_u32()? })
+        } else {
+            None
+        };
+        if !b.is_empty() {
+            return Err(KeyError::InvalidFormat("trailing data after signature".to_string()));
+        }
+        match self {
+            PublicKey::Ed25519(k) => ed25519_verify(k, &raw, data)?,
+            PublicKey::Ecdsa { curve, point } => ecdsa_verify(*curve, point, &raw, data)?,
+            PublicKey::Rsa(k) => rsa_verify(k, &sigtype, &raw, data)?,
+            PublicKey::SkEd25519 { key, application } => {
+                let d = details.expect("sk signature");
+                ed25519_verify(key, &raw, &sk_signed_data(application, d, data))?
+            }
+            PublicKey::SkEcdsa { point, application } => {
+                let d = details.expect("sk signature");
+                ecdsa_verify(EcdsaCurve::NistP256, point, &raw, &sk_signed_data(application, d, data))?
+            }
+            PublicKey::Cert(_) => unreachable!("certificates verify with their key"),
+        }
+        Ok(details)
+    }
+}
+
+/// Signature algorithm name of a signature blob (sshkey_get_sigtype()).
+pub fn signature_type(sig: &[u8]) -> Result<String, KeyError> {
+    let mut b = SshBuf::from_vec(sig.to_vec());
+    get_cstring(&mut b)
+}
+
+/// Skip the options field at the start of an authorized_keys style line,
+/// honouring double quotes, and return what follows it
+/// (sshkey_advance_past_options()). None for an unterminated quote.
+pub fn advance_past_options(line: &str) -> Option<&str> {
+    let mut quoted = false;
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            // \" 不改变引号状态
+            '\\' if chars.peek().is_some_and(|&(_, n)| n == '"') => {
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ' ' | '\t' if !quoted => return Some(&line[i..]),
+            _ => {}
+        }
+    }
+    (!quoted).then_some("")
+}
+
+// 安全密钥实际签名的内容（PROTOCOL.u2f）
+fn sk_signed_data(application: &str, details: SkDetails, data: &[u8]) -> Vec<u8> {
+    let mut m = Vec::with_capacity(32 + 1 + 4 + 32);
+    m.extend_from_slice(&Sha256::digest(application.as_bytes()));
+    m.push(details.flags);
+    m.extend_from_slice(&details.counter.to_be_bytes());
+    m.extend_from_slice(&Sha256::digest(data));
+    m
+}
+
+fn ed25519_verify(key: &[u8; ED25519_PK_SZ], sig: &[u8], data: &[u8]) -> Result<(), KeyError> {
+    let vk = ed25519_dalek::VerifyingKey::from_bytes(key).map_err(|_| KeyError::InvalidKey("bad Ed25519 key".to_string()))?;
+    let sig: [u8; ED25519_SIG_SZ] = sig.try_into().map_err(|_| KeyError::SignatureInvalid)?;
+    vk.verify(data, &ed25519_dalek::Signature::from_bytes(&sig)).map_err(|_| KeyError::SignatureInvalid)
+}
+
+// ECDSA 签名体为 mpint r, mpint s，转换为定长的 r || s
+fn ecdsa_raw_sig(curve: EcdsaCurve, sig: &[u8]) -> Result<Vec<u8>, KeyError> {
+    let mut b = SshBuf::from_vec(sig.to_vec());
+    let len = curve.field_len();
+    let mut out = vec![0u8; 2 * len];
+    for i in 0..2 {
+        let v = b.get_bignum2_bytes()?;
+        if v.len() > len {
+            return Err(KeyError::SignatureInvalid);
+        }
+        out[(i + 1) * len - v.len()..(i + 1) * len].copy_from_slice(v);
+    }
+    if !b.is_empty() {
+        return Err(KeyError::InvalidFormat("trailing data after ECDSA signature".to_string()));
+    }
+    Ok(out)
+}
+
+fn ecdsa_verify(curve: EcdsaCurve, point: &[u8], sig: &[u8], data: &[u8]) -> Result<(), KeyError> {
+    let rs = ecdsa_raw_sig(curve, sig)?;
+    let bad_key = |_| KeyError::InvalidKey("bad EC key".to_string());
+    let ok = match curve {
+        EcdsaCurve::NistP256 => {
+            let vk = p256::ecdsa::VerifyingKey::from_sec1_bytes(point).map_err(bad_key)?;
+            let sig = p256::ecdsa::Signature::from_slice(&rs).map_err(|_| KeyError::SignatureInvalid)?;
+            vk.verify(data, &sig).is_ok()
+        }
+        EcdsaCurve::NistP384 => {
+            let vk = p384::ecdsa::VerifyingKey::from_sec1_bytes(point).map_err(bad_key)?;
+            let sig = p384::ecdsa::Signature::from_slice(&rs).map_err(|_| KeyError::SignatureInvalid)?;
+            vk.verify(data, &sig).is_ok()
+        }
+        EcdsaCurve::NistP521 => {
+            let vk = p521::ecdsa::VerifyingKey::from_sec1_bytes(point).map_err(bad_key)?;
+            let sig = p521::ecdsa::Signature::from_slice(&rs).map_err(|_| KeyError::SignatureInvalid)?;
+            vk.verify(data, &sig).is_ok()
+        }
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(KeyError::SignatureInvalid)
+    }
+}
+
+fn ecdsa_encode_sig(rs: &[u8]) -> Vec<u8> {
+    let (r, s) = rs.split_at(rs.len() / 2);
+    let mut b = SshBuf::new();
+    b.put_bignum2_bytes(r);
+    b.put_bignum2_bytes(s);
+    b.into_vec()
+}
+
+fn rsa_hash(alg: &str, data: &[u8]) -> Result<(Pkcs1v15Sign, Vec<u8>), KeyError> {
+    Ok(match alg {
+        "ssh-rsa" => (Pkcs1v15Sign::new::<Sha1>(), Sha1::digest(data).to_vec()),
+        "rsa-sha2-256" => (Pkcs1v15Sign::new::<Sha256>(), Sha256::digest(data).to_vec()),
+        "rsa-sha2-512" => (Pkcs1v15Sign::new::<Sha512>(), Sha512::digest(data).to_vec()),
+        _ => return Err(KeyError::Unsupported(format!("RSA signature algorithm {}", alg))),
+    })
+}
+
+fn rsa_verify(key: &RsaPublicKey, alg: &str, sig: &[u8], data: &[u8]) -> Result<(), KeyError> {
+    let modlen = key.size();
+    if sig.len() > modlen {
+        return Err(KeyError::SignatureInvalid);
+    }
+    // 较短的签名左侧补零（与 ssh_rsa_verify() 相同）
+    let mut padded = vec![0u8; modlen - sig.len()];
+    padded.extend_from_slice(sig);
+    let (scheme, hash) = rsa_hash(alg, data)?;
+    key.verify(scheme, &hash, &padded).map_err(|_| KeyError::SignatureInvalid)
+}
+
+/// Private half of an ECDSA key.
+#[derive(Clone)]
+pub enum EcdsaPrivate {
+    P256(p256::ecdsa::SigningKey),
+    P384(p384::ecdsa::SigningKey),
+    P521(p521::ecdsa::SigningKey),
+}
+
+impl EcdsaPrivate {
+    fn from_scalar(curve: EcdsaCurve, d: &[u8]) -> Result<EcdsaPrivate, KeyError> {
+        let len = curve.field_len();
+        if d.len() > len {
+            return Err(KeyError::InvalidKey("EC private scalar too large".to_string()));
+        }
+        let mut raw = vec![0u8; len - d.len()];
+        raw.extend_from_slice(d);
+        let bad = |_| KeyError::InvalidKey("bad EC private scalar".to_string());
+        Ok(match curve {
+            EcdsaCurve::NistP256 => EcdsaPrivate::P256(p256::ecdsa::SigningKey::from_slice(&raw).map_err(bad)?),
+            EcdsaCurve::NistP384 => EcdsaPrivate::P384(p384::ecdsa::SigningKey::from_slice(&raw).map_err(bad)?),
+            EcdsaCurve::NistP521 => EcdsaPrivate::P521(p521::ecdsa::SigningKey::from_slice(&raw).map_err(bad)?),
+        })
+    }
+
+    fn curve(&self) -> EcdsaCurve {
+        match self {
+            EcdsaPrivate::P256(_) => EcdsaCurve::NistP256,
+            EcdsaPrivate::P384(_) => EcdsaCurve::NistP384,
+            EcdsaPrivate::P521(_) => EcdsaCurve::NistP521,
+        }
+    }
+
+    fn scalar(&self) -> Vec<u8> {
+        match self {
+            EcdsaPrivate::P256(k) => k.to_bytes().to_vec(),
+            EcdsaPrivate::P384(k) => k.to_bytes().to_vec(),
+            EcdsaPrivate::P521(k) => k.to_bytes().to_vec(),
+        }
+    }
+
+    fn point(&self) -> Vec<u8> {
+        match self {
+            EcdsaPrivate::P256(k) => k.verifying_key().to_encoded_point(false).as_bytes().to_vec(),
+            EcdsaPrivate::P384(k) => k.verifying_key().to_encoded_point(false).as_bytes().to_vec(),
+            EcdsaPrivate::P521(k) => {
+                p521::ecdsa::VerifyingKey::from(k).to_encoded_point(false).as_bytes().to_vec()
+            }
+        }
+    }
+
+    // 返回定长的 r || s
+    fn sign(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            EcdsaPrivate::P256(k) => Signer::<p256::ecdsa::Signature>::sign(k, data).to_bytes().to_vec(),
+            EcdsaPrivate::P384(k) => Signer::<p384::ecdsa::Signature>::sign(k, data).to_bytes().to_vec(),
+            EcdsaPrivate::P521(k) => Signer::<p521::ecdsa::Signature>::sign(k, data).to_bytes().to_vec(),
+        }
+    }
+}
+
+/// A security key credential: the private part lives in the
+/// authenticator, the file only holds a handle to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkCredential {
+    pub flags: u8,
+    pub key_handle: Vec<u8>,
+    pub reserved: Vec<u8>,
+}
+
+/// A private key together with its public half.
+#[derive(Clone)]
+pub enum PrivateKey {
+    Ed25519(ed25519_dalek::SigningKey),
+    Ecdsa(EcdsaPrivate),
+    Rsa(RsaPrivateKey),
+    SkEd25519 { key: [u8; ED25519_PK_SZ], application: String, credential: SkCredential },
+    SkEcdsa { point: Vec<u8>, application: String, credential: SkCredential },
+}
+
+impl fmt::Debug for PrivateKey {
+    // 不输出私钥内容
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PrivateKey({} {})", self.key_type().short_name(), self.public().bits())
+    }
+}
+
+// 以 random_bytes() 为熵源的 RNG，供 RSA 密钥生成使用
+struct SysRng;
+
+impl RngCore for SysRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut b = [0u8; 4];
+        self.fill_bytes(&mut b);
+        u32::from_le_bytes(b)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut b = [0u8; 8];
+        self.fill_bytes(&mut b);
+        u64::from_le_bytes(b)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        random_bytes(dest).expect("random_bytes")
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        random_bytes(dest).map_err(rand_core::Error::new)
+    }
+}
+
+impl CryptoRng for SysRng {}
+
+impl PrivateKey {
+    /// Generate a new key (sshkey_generate()). `bits` selects the RSA
+    /// modulus size (default 3072) or the ECDSA curve (default 256).
+    pub fn generate(ktype: KeyType, bits: Option<usize>) -> Result<PrivateKey, KeyError> {
+        match ktype {
+            KeyType::Ed25519 => {
+                let mut seed = [0u8; 32];
+                random_bytes(&mut seed).map_err(KeyError::Rng)?;
+                Ok(PrivateKey::Ed25519(ed25519_dalek::SigningKey::from_bytes(&seed)))
+            }
+            KeyType::Ecdsa(curve) => {
+                let curve = match bits {
+                    Some(bits) => EcdsaCurve::from_bits(bits).ok_or(KeyError::KeyLength(bits))?,
+                    None => curve,
+                };
+                let mut raw = vec![0u8; curve.field_len()];
+                // 标量需落在 [1, n) 内，超出则重新取随机数
+                for _ in 0..64 {
+                    random_bytes(&mut raw).map_err(KeyError::Rng)?;
+                    raw[0] &= 0xff >> (raw.len() * 8 - curve.bits());
+                    if let Ok(k) = EcdsaPrivate::from_scalar(curve, &raw) {
+                        return Ok(PrivateKey::Ecdsa(k));
+                    }
+                }
+                Err(KeyError::InvalidKey("could not generate EC key".to_string()))
+            }
+            KeyType::Rsa => {
+                let bits = bits.unwrap_or(3072);
+                if !(RSA_MIN_MODULUS_SIZE..=RSA_MAX_MODULUS_SIZE).contains(&bits) {
+                    return Err(KeyError::KeyLength(bits));
+                }
+                let key = RsaPrivateKey::new(&mut SysRng, bits).map_err(|e| KeyError::InvalidKey(e.to_string()))?;
+                Ok(PrivateKey::Rsa(key))
+            }
+            KeyType::SkEd25519 | KeyType::SkEcdsa => {
+                Err(KeyError::Unsupported("security keys are enrolled by a FIDO authenticator".to_string()))
+            }
+        }
+    }
+
+    pub fn key_type(&self) -> KeyType {
+        match self {
+            PrivateKey::Ed25519(_) => KeyType::Ed25519,
+            PrivateKey::Ecdsa(k) => KeyType::Ecdsa(k.curve()),
+            PrivateKey::Rsa(_) => KeyType::Rsa,
+            PrivateKey::SkEd25519 { .. } => KeyType::SkEd25519,
+            PrivateKey::SkEcdsa { .. } => KeyType::SkEcdsa,
+        }
+    }
+
+    /// The public half (sshkey_from_private()).
+    pub fn public(&self) -> PublicKey {
+        match self {
+            PrivateKey::Ed25519(k) => PublicKey::Ed25519(k.verifying_key().to_bytes()),
+            PrivateKey::Ecdsa(k) => PublicKey::Ecdsa { curve: k.curve(), point: k.point() },
+            PrivateKey::Rsa(k) => PublicKey::Rsa(k.to_public_key()),
+            PrivateKey::SkEd25519 { key, application, .. } => {
+                PublicKey::SkEd25519 { key: *key, application: application.clone() }
+            }
+            PrivateKey::SkEcdsa { point, application, .. } => {
+                PublicKey::SkEcdsa { point: point.clone(), application: application.clone() }
+            }
+        }
+    }
+
+    /// Sign `data` (sshkey_sign()), returning a signature blob. RSA keys
+    /// sign with `alg` if given, else rsa-sha2-512; legacy ssh-rsa (SHA-1)
+    /// signatures are only verified, never made.
+    pub fn sign(&self, data: &[u8], alg: Option<&str>) -> Result<Vec<u8>, KeyError> {
+        let sigtype = match (self, alg) {
+            (PrivateKey::Rsa(_), None) => "rsa-sha2-512",
+            (PrivateKey::Rsa(_), Some("ssh-rsa")) => {
+                return Err(KeyError::Unsupported("ssh-rsa (SHA-1) signatures".to_string()));
+            }
+            (_, Some(alg)) if KeyType::from_sig_alg(alg) != Some(self.key_type()) => return Err(KeyError::TypeMismatch),
+            (_, Some(alg)) => alg,
+            (_, None) => self.key_type().name(),
+        };
+        let raw = match self {
+            PrivateKey::Ed25519(k) => k.sign(data).to_bytes().to_vec(),
+            PrivateKey::Ecdsa(k) => ecdsa_encode_sig(&k.sign(data)),
+            PrivateKey::Rsa(k) => {
+                let (scheme, hash) = rsa_hash(sigtype, data)?;
+                k.sign(scheme, &hash).map_err(|e| KeyError::InvalidKey(e.to_string()))?
+            }
+            PrivateKey::SkEd25519 { .. } | PrivateKey::SkEcdsa { .. } => {
+                return Err(KeyError::Unsupported("signing requires the FIDO authenticator".to_string()));
+            }
+        };
+        let mut b = SshBuf::new();
+        b.put_cstring(sigtype);
+        b.put_string(&raw);
+        Ok(b.into_vec())
+    }
+
+    /// Append the private key fields as used inside openssh-key-v1 files
+    /// and by ssh-agent (sshkey_private_serialize()).
+    pub fn encode_private(&self, b: &mut SshBuf) {
+        b.put_cstring(self.key_type().name());
+        match self {
+            PrivateKey::Ed25519(k) => {
+                let pk = k.verifying_key().to_bytes();
+                b.put_string(&pk);
+                let mut sk = k.to_bytes().to_vec();
+                sk.extend_from_slice(&pk);
+                b.put_string(&sk);
+            }
+            PrivateKey::Ecdsa(k) => {
+                b.put_cstring(k.curve().name());
+                b.put_string(&k.point());
+                b.put_bignum2_bytes(&k.scalar());
+            }
+            PrivateKey::Rsa(k) => {
+                b.put_bignum2_bytes(&k.n().to_bytes_be());
+                b.put_bignum2_bytes(&k.e().to_bytes_be());
+                b.put_bignum2_bytes(&k.d().to_bytes_be());
+                let iqmp = k.crt_coefficient().expect("RSA key with two primes");
+                b.put_bignum2_bytes(&iqmp.to_bytes_be());
+                b.put_bignum2_bytes(&k.primes()[0].to_bytes_be());
+                b.put_bignum2_bytes(&k.primes()[1].to_bytes_be());
+            }
+            PrivateKey::SkEd25519 { key, application, credential } => {
+                b.put_string(key);
+                b.put_cstring(application);
+                put_sk_credential(b, credential);
+            }
+            PrivateKey::SkEcdsa { point, application, credential } => {
+                b.put_cstring(EcdsaCurve::NistP256.name());
+                b.put_string(point);
+                b.put_cstring(application);
+                put_sk_credential(b, credential);
+            }
+        }
+    }
+
+    /// Read private key fields written by `encode_private`
+    /// (sshkey_private_deserialize()).
+    pub fn decode_private(b: &mut SshBuf) -> Result<PrivateKey, KeyError> {
+        let name = get_cstring(b)?;
+        let ktype = KeyType::from_name(&name).ok_or(KeyError::UnknownKeyType(name))?;
+        let key = match ktype {
+            KeyType::Ed25519 => {
+                let pk: [u8; ED25519_PK_SZ] = get_array(b)?;
+                let sk: [u8; ED25519_SK_SZ] = get_array(b)?;
+                let seed: [u8; 32] = sk[..32].try_into().expect("32 bytes");
+                let key = ed25519_dalek::SigningKey::from_bytes(&seed);
+                if key.verifying_key().to_bytes() != pk || sk[32..] != pk {
+                    return Err(KeyError::InvalidKey("Ed25519 public and private halves differ".to_string()));
+                }
+                PrivateKey::Ed25519(key)
+            }
+            KeyType::Ecdsa(curve) => {
+                get_curve(b, curve)?;
+                let point = b.get_string()?.to_vec();
+                check_ec_point(curve, &point)?;
+                let key = EcdsaPrivate::from_scalar(curve, b.get_bignum2_bytes()?)?;
+                if key.point() != point {
+                    return Err(KeyError::InvalidKey("EC public and private halves differ".to_string()));
+                }
+                PrivateKey::Ecdsa(key)
+            }
+            KeyType::Rsa => {
+                let mut v = Vec::with_capacity(6);
+                for _ in 0..6 {
+                    v.push(BigUint::from_bytes_be(b.get_bignum2_bytes()?));
+                }
+                let [n, e, d, _iqmp, p, q]: [BigUint; 6] = v.try_into().expect("six values");
+                let bits = n.bits();
+                if !(RSA_MIN_MODULUS_SIZE..=RSA_MAX_MODULUS_SIZE).contains(&bits) {
+                    return Err(KeyError::KeyLength(bits));
+                }
+                let mut key =
+                    RsaPrivateKey::from_components(n, e, d, vec![p, q]).map_err(|e| KeyError::InvalidKey(e.to_string()))?;
+                key.validate().map_err(|e| KeyError::InvalidKey(e.to_string()))?;
+                key.precompute().map_err(|e| KeyError::InvalidKey(e.to_string()))?;
+                PrivateKey::Rsa(key)
+            }
+            KeyType::SkEd25519 => {
+                let key = get_array(b)?;
+                let application = get_cstring(b)?;
+                PrivateKey::SkEd25519 { key, application, credential: get_sk_credential(b)? }
+            }
+            KeyType::SkEcdsa => {
+                get_curve(b, EcdsaCurve::NistP256)?;
+                let point = b.get_string()?.to_vec();
+                check_ec_point(EcdsaCurve::NistP256, &point)?;
+                let application = get_cstring(b)?;
+                PrivateKey::SkEcdsa { point, application, credential: get_sk_credential(b)? }
+            }
+        };
+        Ok(key)
+    }
+}
+
+fn put_sk_credential(b: &mut SshBuf, c: &SkCredential) {
+    b.put_u8(c.flags);
+    b.put_string(&c.key_handle);
+    b.put_string(&c.reserved);
+}
+
+fn get_sk_credential(b: &mut SshBuf) -> Result<SkCredential, KeyError> {
+    let flags = b.get_u8()?;
+    let key_handle = b.get_string()?.to_vec();
+    let reserved = b.get_string()?.to_vec();
+    Ok(SkCredential { flags, key_handle, reserved })
+}
@@ -0,0 +1,505 @@
+// sshsig.rs
+//
+// Signing and verifying files with SSH keys (PROTOCOL.sshsig), after
+// OpenSSH's sshsig.c: the armoured "SSH SIGNATURE" format that binds a
+// signature to a namespace such as "git" or "file", and the
+// allowed_signers files that say which keys may sign for which
+// principals, in which namespaces and for how long.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use log::{debug, error};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::addrmatch::match_pattern_list;
+use crate::auth_options::{opt_dequote, opt_flag, opt_match};
+use crate::misc::{format_absolute_time, parse_absolute_time};
+use crate::sshbuf::{SshBuf, SshBufError};
+use crate::sshkey::{advance_past_options, signature_type, KeyError, KeyType, PrivateKey, PublicKey, SkDetails};
+
+pub const SIG_VERSION: u32 = 1;
+const MAGIC_PREAMBLE: &[u8] = b"SSHSIG";
+const BEGIN_SIGNATURE: &str = "-----BEGIN SSH SIGNATURE-----";
+const END_SIGNATURE: &str = "-----END SSH SIGNATURE-----";
+// base64 每行 70 个字符，与 ssh-keygen 相同
+const LINE_LEN: usize = 70;
+/// RSA keys always sign with SHA-512.
+const RSA_SIGN_ALG: &str = "rsa-sha2-512";
+pub const HASHALG_DEFAULT: &str = "sha512";
+
+#[derive(Debug)]
+pub enum SigError {
+    Format(SshBufError),
+    InvalidFormat(String),
+    UnsupportedVersion(u32),
+    UnsupportedHash(String),
+    /// The signature was made for a different namespace.
+    NamespaceMismatch { expected: String, found: String },
+    Key(KeyError),
+    /// No allowed_signers line accepts the key.
+    KeyNotFound,
+    Io(io::Error),
+}
+
+impl fmt::Display for SigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SigError::Format(e) => write!(f, "invalid signature encoding: {}", e),
+            SigError::InvalidFormat(msg) => write!(f, "invalid format: {}", msg),
+            SigError::UnsupportedVersion(v) => write!(f, "unsupported signature version {}", v),
+            SigError::UnsupportedHash(alg) => write!(f, "unsupported hash algorithm \"{}\"", alg),
+            SigError::NamespaceMismatch { expected, found } => {
+                write!(f, "signature namespace \"{}\" does not match required \"{}\"", found, expected)
+            }
+            SigError::Key(e) => write!(f, "{}", e),
+            SigError::KeyNotFound => f.write_str("key not found"),
+            SigError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for SigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SigError::Format(e) => Some(e),
+            SigError::Key(e) => Some(e),
+            SigError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<SshBufError> for SigError {
+    fn from(e: SshBufError) -> Self {
+        SigError::Format(e)
+    }
+}
+
+impl From<KeyError> for SigError {
+    fn from(e: KeyError) -> Self {
+        SigError::Key(e)
+    }
+}
+
+/// Wrap a signature blob in BEGIN/END SSH SIGNATURE lines (sshsig_armor()).
+pub fn armor(blob: &[u8]) -> String {
+    let b64 = BASE64.encode(blob);
+    let mut out = String::from(BEGIN_SIGNATURE);
+    out.push('\n');
+    for chunk in b64.as_bytes().chunks(LINE_LEN) {
+        out.push_str(std::str::from_utf8(chunk).unwrap());
+        out.push('\n');
+    }
+    out.push_str(END_SIGNATURE);
+    out.push('\n');
+    out
+}
+
+/// Undo armor() (sshsig_dearmor()). Text after the END line is ignored.
+pub fn dearmor(text: &str) -> Result<Vec<u8>, SigError> {
+    let mut lines = text.lines().map(str::trim_end);
+    if lines.next() != Some(BEGIN_SIGNATURE) {
+        return Err(SigError::InvalidFormat("missing BEGIN SSH SIGNATURE".to_string()));
+    }
+    let mut b64 = String::new();
+    for line in lines {
+        if line == END_SIGNATURE {
+            return BASE64.decode(b64).map_err(|_| SigError::InvalidFormat("bad base64 in signature".to_string()));
+        }
+        b64.push_str(line);
+    }
+    Err(SigError::InvalidFormat("missing END SSH SIGNATURE".to_string()))
+}
+
+/// Digest of the message being signed, read to the end
+/// (hash_file()).
+fn hash_message<R: Read>(hashalg: &str, mut reader: R) -> Result<Vec<u8>, SigError> {
+    fn consume<D: Digest, R: Read>(mut d: D, reader: &mut R) -> Result<Vec<u8>, SigError> {
+        let mut buf = [0u8; 8192];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => return Ok(d.finalize().to_vec()),
+                Ok(n) => d.update(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(SigError::Io(e)),
+            }
+        }
+    }
+    match hashalg {
+        "sha256" => consume(Sha256::new(), &mut reader),
+        "sha512" => consume(Sha512::new(), &mut reader),
+        alg => Err(SigError::UnsupportedHash(alg.to_string())),
+    }
+}
+
+// 实际被签名的数据：MAGIC || namespace || reserved || hashalg || H(message)
+fn signed_data(namespace: &str, hashalg: &str, hash: &[u8]) -> Vec<u8> {
+    let mut b = SshBuf::new();
+    b.put(MAGIC_PREAMBLE);
+    b.put_cstring(namespace);
+    b.put_string(b"");
+    b.put_cstring(hashalg);
+    b.put_string(hash);
+    b.into_vec()
+}
+
+/// Sign everything `reader` yields (sshsig_sign_fd()). `signer` is the
+/// public key recorded in the signature: the key itself, or a
+/// certificate for it. Returns the signature blob; armor() it for
+/// output.
+pub fn sign<R: Read>(
+    key: &PrivateKey,
+    signer: Option<&PublicKey>,
+    hashalg: Option<&str>,
+    namespace: &str,
+    reader: R,
+) -> Result<Vec<u8>, SigError> {
+    if namespace.is_empty() {
+        return Err(SigError::InvalidFormat("empty namespace".to_string()));
+    }
+    let public = key.public();
+    let signer = signer.unwrap_or(&public);
+    if signer.plain() != &public {
+        return Err(SigError::Key(KeyError::TypeMismatch));
+    }
+    let hashalg = hashalg.unwrap_or(HASHALG_DEFAULT);
+    let hash = hash_message(hashalg, reader)?;
+    let alg = (key.key_type() == KeyType::Rsa).then_some(RSA_SIGN_ALG);
+    let sig = key.sign(&signed_data(namespace, hashalg, &hash), alg)?;
+
+    let mut b = SshBuf::new();
+    b.put(MAGIC_PREAMBLE);
+    b.put_u32(SIG_VERSION);
+    b.put_string(&signer.to_blob());
+    b.put_cstring(namespace);
+    b.put_string(b"");
+    b.put_cstring(hashalg);
+    b.put_string(&sig);
+    Ok(b.into_vec())
+}
+
+/// The fields of a signature blob.
+struct Parsed {
+    key: PublicKey,
+    namespace: String,
+    hashalg: String,
+    signature: Vec<u8>,
+}
+
+fn parse(blob: &[u8]) -> Result<Parsed, SigError> {
+    if !blob.starts_with(MAGIC_PREAMBLE) {
+        return Err(SigError::InvalidFormat("missing SSHSIG preamble".to_string()));
+    }
+    let mut b = SshBuf::from_vec(blob[MAGIC_PREAMBLE.len()..].to_vec());
+    let version = b.get_u32()?;
+    if version > SIG_VERSION {
+        return Err(SigError::UnsupportedVersion(version));
+    }
+    let key = PublicKey::from_blob(b.get_string()?)?;
+    let namespace = b.get_cstring()?;
+    b.get_string()?; // reserved
+    let hashalg = b.get_cstring()?;
+    let signature = b.get_string()?.to_vec();
+    if !b.is_empty() {
+        return Err(SigError::InvalidFormat("trailing data after signature".to_string()));
+    }
+    Ok(Parsed { key, namespace, hashalg, signature })
+}
+
+/// The public key that made a signature (sshsig_get_pubkey()).
+pub fn signature_key(blob: &[u8]) -> Result<PublicKey, SigError> {
+    Ok(parse(blob)?.key)
+}
+
+/// Check a signature over everything `reader` yields, made in namespace
+/// `namespace` (sshsig_verify_fd()). Returns the signing key, which the
+/// caller must still check against its policy, e.g. with
+/// check_allowed_keys().
+pub fn verify<R: Read>(blob: &[u8], reader: R, namespace: &str) -> Result<(PublicKey, Option<SkDetails>), SigError> {
+    let sig = parse(blob)?;
+    if sig.namespace != namespace {
+        return Err(SigError::NamespaceMismatch { expected: namespace.to_string(), found: sig.namespace });
+    }
+    let hash = hash_message(&sig.hashalg, reader)?;
+    let alg = signature_type(&sig.signature)?;
+    // 不接受 SHA-1 的 RSA 签名
+    if sig.key.key_type() == KeyType::Rsa && alg != "rsa-sha2-256" && alg != "rsa-sha2-512" {
+        return Err(SigError::Key(KeyError::Unsupported(format!("RSA signature algorithm {}", alg))));
+    }
+    let details = sig.key.verify(&sig.signature, &signed_data(namespace, &sig.hashalg, &hash), Some(&alg))?;
+    Ok((sig.key, details))
+}
+
+/// Options of an allowed_signers line (struct sshsigopt).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SigOpts {
+    /// The key is a CA trusted to certify signers.
+    pub ca: bool,
+    pub namespaces: Option<String>,
+    /// Zero when unset.
+    pub valid_after: u64,
+    /// Zero when unset.
+    pub valid_before: u64,
+}
+
+impl SigOpts {
+    /// Parse a comma-separated option list, ending at the first unquoted
+    /// blank (sshsigopt_parse()); the flag and "name=" matching is shared
+    /// with authorized_keys.
+    pub fn parse(opts: &str) -> Result<SigOpts, String> {
+        let mut ret = SigOpts::default();
+        let mut opts = opts;
+        let time = |opts: &mut &str, name: &str| -> Result<u64, String> {
+            let value = opt_dequote(opts).map_err(str::to_string)?;
+            match parse_absolute_time(&value) {
+                Some(t) if t != 0 => Ok(t),
+                _ => Err(format!("invalid \"{}\" time", name)),
+            }
+        };
+        while !opts.is_empty() {
+            if opt_flag("cert-authority", false, &mut opts) == Some(true) {
+                ret.ca = true;
+            } else if opt_match(&mut opts, "namespaces") {
+                if ret.namespaces.is_some() {
+                    return Err("multiple \"namespaces\" clauses".to_string());
+                }
+                ret.namespaces = Some(opt_dequote(&mut opts).map_err(str::to_string)?);
+            } else if opt_match(&mut opts, "valid-after") {
+                if ret.valid_after != 0 {
+                    return Err("multiple \"valid-after\" clauses".to_string());
+                }
+                ret.valid_after = time(&mut opts, "valid-after")?;
+            } else if opt_match(&mut opts, "valid-before") {
+                if ret.valid_before != 0 {
+                    return Err("multiple \"valid-before\" clauses".to_string());
+                }
+                ret.valid_before = time(&mut opts, "valid-before")?;
+            }
+            // 跳过逗号，进入下一个选项
+            if opts.is_empty() || opts.starts_with([' ', '\t']) {
+                break;
+            }
+            opts = opts.strip_prefix(',').ok_or("unknown key option")?;
+            if opts.is_empty() {
+                return Err("unexpected end-of-options".to_string());
+            }
+        }
+        if ret.valid_after != 0 && ret.valid_before != 0 && ret.valid_before <= ret.valid_after {
+            return Err("\"valid-before\" time is before \"valid-after\"".to_string());
+        }
+        Ok(ret)
+    }
+}
+
+/// One line of an allowed_signers file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllowedSigner {
+    /// Comma-separated principal patterns.
+    pub principals: String,
+    pub opts: SigOpts,
+    pub key: PublicKey,
+}
+
+// 第一个字段，可以用双引号括起来（strdelimw()）
+fn split_principals(line: &str) -> Option<(&str, &str)> {
+    if let Some(rest) = line.strip_prefix('"') {
+        let (principals, rest) = rest.split_once('"')?;
+        return Some((principals, rest.trim_start()));
+    }
+    let (principals, rest) = line.split_once([' ', '\t']).unwrap_or((line, ""));
+    Some((principals, rest.trim_start()))
+}
+
+/// Parse "principals [options] key" (parse_principals_key_and_options()).
+/// Returns None for blank and comment lines and, when `required_principal`
+/// is given, for lines whose principals do not match it. Errors are logged
+/// against `path`:`linenum`.
+pub fn parse_allowed_signer(
+    path: &str,
+    linenum: usize,
+    line: &str,
+    required_principal: Option<&str>,
+) -> Result<Option<AllowedSigner>, SigError> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let bad = |what: &str| {
+        error!("{}:{}: {}", path, linenum, what);
+        SigError::InvalidFormat(format!("{}:{}: {}", path, linenum, what))
+    };
+    let (principals, rest) = split_principals(line).ok_or_else(|| bad("invalid line"))?;
+    if principals.is_empty() {
+        return Err(bad("invalid line"));
+    }
+    // 需要特定 principal 时尽早跳过不相关的行
+    if let Some(principal) = required_principal {
+        if match_pattern_list(principal, principals, false) != 1 {
+            return Ok(None);
+        }
+        debug!("{}:{}: matched principal \"{}\"", path, linenum, principal);
+    }
+    if rest.is_empty() {
+        return Err(bad("missing key"));
+    }
+
+    let (opts, key) = match PublicKey::from_openssh_line(rest) {
+        Ok((key, _)) => ("", key),
+        Err(_) => {
+            let after = advance_past_options(rest).ok_or_else(|| bad("invalid options"))?;
+            if after.trim_start().is_empty() {
+                return Err(bad("missing key"));
+            }
+            let opts = &rest[..rest.len() - after.len()];
+            let (key, _) = PublicKey::from_openssh_line(after).map_err(|_| bad("invalid key"))?;
+            (opts, key)
+        }
+    };
+    let opts = SigOpts::parse(opts).map_err(|reason| bad(&format!("bad options: {}", reason)))?;
+    Ok(Some(AllowedSigner { principals: principals.to_string(), opts, key }))
+}
+
+// 逐行解析，出错的行记录日志后跳过
+fn allowed_signers(path: &Path, required_principal: Option<&str>) -> Result<Vec<(usize, AllowedSigner)>, SigError> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        error!("open {}: {}", path.display(), e);
+        SigError::Io(e)
+    })?;
+    let name = path.display().to_string();
+    Ok(text
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| match parse_allowed_signer(&name, i + 1, line, required_principal) {
+            Ok(Some(signer)) => Some((i + 1, signer)),
+            _ => None,
+        })
+        .collect())
+}
+
+// valid-after / valid-before
+fn check_times(path: &Path, linenum: usize, opts: &SigOpts, verify_time: u64) -> bool {
+    if opts.valid_after != 0 && verify_time < opts.valid_after {
+        error!(
+            "{}:{}: key is not yet valid: verify time {} < valid-after {}",
+            path.display(),
+            linenum,
+            format_absolute_time(verify_time),
+            format_absolute_time(opts.valid_after)
+        );
+        return false;
+    }
+    if opts.valid_before != 0 && verify_time > opts.valid_before {
+        error!(
+            "{}:{}: key has expired: verify time {} > valid-before {}",
+            path.display(),
+            linenum,
+            format_absolute_time(verify_time),
+            format_absolute_time(opts.valid_before)
+        );
+        return false;
+    }
+    true
+}
+
+// 签名密钥是由该行的 CA 签发的证书
+fn signed_by_ca<'a>(signer: &AllowedSigner, sign_key: &'a PublicKey) -> Option<&'a crate::sshkey::Certificate> {
+    sign_key.cert().filter(|cert| signer.opts.ca && cert.signature_key == signer.key)
+}
+
+/// Whether `path` allows `sign_key` to sign for `principal` in
+/// `namespace` at `verify_time` (sshsig_check_allowed_keys()). A key is
+/// accepted by a line listing it, or, if it is a certificate, by a
+/// cert-authority line for its CA.
+pub fn check_allowed_keys(
+    path: &Path,
+    sign_key: &PublicKey,
+    principal: &str,
+    namespace: &str,
+    verify_time: u64,
+) -> Result<(), SigError> {
+    for (linenum, signer) in allowed_signers(path, Some(principal))? {
+        let found = if !signer.opts.ca && &signer.key == sign_key {
+            debug!("{}:{}: matched key", path.display(), linenum);
+            true
+        } else if let Some(cert) = signed_by_ca(&signer, sign_key) {
+            match cert.check_authority(false, true, false, Some(principal), verify_time) {
+                Ok(()) => {
+                    debug!("{}:{}: matched certificate CA key", path.display(), linenum);
+                    true
+                }
+                Err(reason) => {
+                    error!("{}:{}: certificate not authorized: {}", path.display(), linenum, reason);
+                    false
+                }
+            }
+        } else {
+            false
+        };
+        if !found {
+            continue;
+        }
+        // 只对匹配的密钥检查 namespace，避免为无关的行报错
+        if let Some(namespaces) = &signer.opts.namespaces {
+            if match_pattern_list(namespace, namespaces, false) != 1 {
+                error!("{}:{}: key is not permitted for use in signature namespace \"{}\"", path.display(), linenum, namespace);
+                continue;
+            }
+        }
+        if check_times(path, linenum, &signer.opts, verify_time) {
+            return Ok(());
+        }
+    }
+    Err(SigError::KeyNotFound)
+}
+
+/// The principals `sign_key` may sign as, from the first line of `path`
+/// that accepts it (sshsig_find_principals()). For a certificate these
+/// are its own principals that the line's patterns allow.
+pub fn find_principals(path: &Path, sign_key: &PublicKey, verify_time: u64) -> Result<String, SigError> {
+    for (linenum, signer) in allowed_signers(path, None)? {
+        let principals = if !signer.opts.ca && &signer.key == sign_key {
+            debug!("{}:{}: matched key", path.display(), linenum);
+            signer.principals.clone()
+        } else if let Some(cert) = signed_by_ca(&signer, sign_key) {
+            if let Err(reason) = cert.check_authority(false, true, false, None, verify_time) {
+                error!("{}:{}: certificate not authorized: {}", path.display(), linenum, reason);
+                continue;
+            }
+            // 只保留同时出现在证书与该行中的 principal（cert_filter_principals()）
+            let allowed: Vec<&str> = cert
+                .principals
+                .iter()
+                .filter(|p| match_pattern_list(p, &signer.principals, false) == 1)
+                .map(String::as_str)
+                .collect();
+            if allowed.is_empty() {
+                error!("{}:{}: no valid principals found", path.display(), linenum);
+                continue;
+            }
+            debug!("{}:{}: matched certificate CA key", path.display(), linenum);
+            allowed.join(",")
+        } else {
+            continue;
+        };
+        if check_times(path, linenum, &signer.opts, verify_time) {
+            return Ok(principals);
+        }
+    }
+    Err(SigError::KeyNotFound)
+}
+
+/// The principals fields of every line of `path` whose patterns match
+/// `principal` (sshsig_match_principals()).
+pub fn match_principals(path: &Path, principal: &str) -> Result<Vec<String>, SigError> {
+    let found: Vec<String> = allowed_signers(path, Some(principal))?.into_iter().map(|(_, s)| s.principals).collect();
+    if found.is_empty() {
+        return Err(SigError::KeyNotFound);
+    }
+    Ok(found)
+}
@@ -0,0 +1,162 @@
+// umac.rs
+//
+// UMAC (RFC 4418) with 64- and 128-bit tags, as used by umac-64@openssh.com
+// and umac-128@openssh.com. A straight port of the reference umac.c that
+// OpenSSH ships, restricted to one-shot messages: NH (L1) over 1024-byte
+// chunks, a polynomial hash (L2) for longer messages, an inner-product
+// hash (L3) and an AES-based pad keyed by the 8-byte nonce.
+
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::Aes128;
+
+pub const UMAC_KEY_LEN: usize = 16;
+const L1_KEY_LEN: usize = 1024;
+const L1_KEY_SHIFT: usize = 16;
+const L1_PAD_BOUNDARY: usize = 32;
+const P36: u64 = 0x0000_000F_FFFF_FFFB;
+const P64: u64 = 0xFFFF_FFFF_FFFF_FFC5;
+
+fn aes_block(key: &Aes128, input: &[u8; 16]) -> [u8; 16] {
+    let mut block = (*input).into();
+    key.encrypt_block(&mut block);
+    block.into()
+}
+
+// 由 AES 生成第 ndx 组子密钥
+fn kdf(key: &Aes128, ndx: u8, nbytes: usize) -> Vec<u8> {
+    let mut input = [0u8; 16];
+    input[16 - 9] = ndx;
+    let mut out = Vec::with_capacity(nbytes + 16);
+    let mut i = 1u8;
+    while out.len() < nbytes {
+        input[15] = i;
+        out.extend_from_slice(&aes_block(key, &input));
+        i = i.wrapping_add(1);
+    }
+    out.truncate(nbytes);
+    out
+}
+
+fn be64(b: &[u8]) -> u64 {
+    let mut v = [0u8; 8];
+    v.copy_from_slice(&b[..8]);
+    u64::from_be_bytes(v)
+}
+
+/// One UMAC key; `taglen` is 8 for UMAC-64 or 16 for UMAC-128.
+pub struct Umac {
+    streams: usize,
+    nh_key: Vec<u32>,
+    poly_key: Vec<u64>,
+    ip_keys: Vec<u64>,
+    ip_trans: Vec<u32>,
+    pdf_key: Aes128,
+    pdf_nonce: [u8; 16],
+    pdf_cache: [u8; 16],
+}
+
+impl Umac {
+    pub fn new(key: &[u8; UMAC_KEY_LEN], taglen: usize) -> Umac {
+        assert!(taglen == 8 || taglen == 16, "unsupported UMAC tag length");
+        let streams = taglen / 4;
+        let prf = Aes128::new(key.into());
+
+        let pdf_key = Aes128::new_from_slice(&kdf(&prf, 0, UMAC_KEY_LEN)).expect("16-byte key");
+        let pdf_nonce = [0u8; 16];
+        let pdf_cache = aes_block(&pdf_key, &pdf_nonce);
+
+        let nh_bytes = kdf(&prf, 1, L1_KEY_LEN + L1_KEY_SHIFT * (streams - 1));
+        let nh_key = nh_bytes.chunks(4).map(|w| u32::from_be_bytes([w[0], w[1], w[2], w[3]])).collect();
+
+        // L2 与 L3 密钥缓冲区按 8 个 64 位字一组取用，多余部分跳过
+        let buf = kdf(&prf, 2, (8 * streams + 4) * 8);
+        let poly_key = (0..streams).map(|i| be64(&buf[24 * i..]) & 0x01ff_ffff_01ff_ffff).collect();
+        let buf = kdf(&prf, 3, (8 * streams + 4) * 8);
+        let ip_keys = (0..streams)
+            .flat_map(|i| (0..4).map(move |j| (i, j)))
+            .map(|(i, j)| be64(&buf[(8 * i + 4 + j) * 8..]) % P36)
+            .collect();
+        let ip_trans = kdf(&prf, 4, streams * 4)
+            .chunks(4)
+            .map(|w| u32::from_be_bytes([w[0], w[1], w[2], w[3]]))
+            .collect();
+
+        Umac { streams, nh_key, poly_key, ip_keys, ip_trans, pdf_key, pdf_nonce, pdf_cache }
+    }
+
+    // L1：对一个不超过 1024 字节的分片做 NH，并加上其比特长度
+    fn nh(&self, chunk: &[u8]) -> Vec<u64> {
+        let padded_len = chunk.len().div_ceil(L1_PAD_BOUNDARY).max(1) * L1_PAD_BOUNDARY;
+        let mut data = chunk.to_vec();
+        data.resize(padded_len, 0);
+        let words: Vec<u32> = data.chunks(4).map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]])).collect();
+        let bits = (chunk.len() as u64) << 3;
+        (0..self.streams)
+            .map(|s| {
+                let k = &self.nh_key[4 * s..];
+                let mut sum = 0u64;
+                for (blk, m) in words.chunks(8).enumerate() {
+                    let k = &k[blk * 8..];
+                    for j in 0..4 {
+                        let a = m[j].wrapping_add(k[j]) as u64;
+                        let b = m[j + 4].wrapping_add(k[j + 4]) as u64;
+                        sum = sum.wrapping_add(a * b);
+                    }
+                }
+                sum.wrapping_add(bits)
+            })
+            .collect()
+    }
+
+    fn poly(acc: u64, key: u64, m: u64) -> u64 {
+        ((acc as u128 * key as u128 + m as u128) % P64 as u128) as u64
+    }
+
+    // L3：16 位分段与密钥做内积，模 2^36-5 后取低 32 位
+    fn ip(&self, stream: usize, data: u64) -> u32 {
+        let k = &self.ip_keys[4 * stream..];
+        let t = k[0] * (data >> 48 & 0xffff)
+            + k[1] * (data >> 32 & 0xffff)
+            + k[2] * (data >> 16 & 0xffff)
+            + k[3] * (data & 0xffff);
+        ((t % P36) as u32) ^ self.ip_trans[stream]
+    }
+
+    fn uhash(&self, msg: &[u8]) -> Vec<u8> {
+        let l2: Vec<u64> = if msg.len() <= L1_KEY_LEN {
+            self.nh(msg)
+        } else {
+            let mut acc = vec![1u64; self.streams];
+            for chunk in msg.chunks(L1_KEY_LEN) {
+                for (s, y) in self.nh(chunk).into_iter().enumerate() {
+                    let key = self.poly_key[s];
+                    // 过大的值先写入标记再写入 y-59，使其落在 Z_p64 内
+                    acc[s] = if y >> 32 == 0xffff_ffff {
+                        Self::poly(Self::poly(acc[s], key, P64 - 1), key, y - 59)
+                    } else {
+                        Self::poly(acc[s], key, y)
+                    };
+                }
+            }
+            acc
+        };
+        l2.iter().enumerate().flat_map(|(s, &v)| self.ip(s, v).to_be_bytes()).collect()
+    }
+
+    /// Tag for `msg` under `nonce` (umac_final()).
+    pub fn mac(&mut self, msg: &[u8], nonce: &[u8; 8]) -> Vec<u8> {
+        let mut tag = self.uhash(msg);
+        // UMAC-64 的两个输出共用一次 AES，由 nonce 的最低位选择
+        let low_bit_mask = if self.streams == 2 { 1 } else { 0 };
+        let mut block = [0u8; 16];
+        block[..8].copy_from_slice(nonce);
+        block[7] &= !low_bit_mask;
+        if block != self.pdf_nonce {
+            self.pdf_nonce = block;
+            self.pdf_cache = aes_block(&self.pdf_key, &block);
+        }
+        let off = if self.streams == 2 { 8 * (nonce[7] & low_bit_mask) as usize } else { 0 };
+        tag.iter_mut().zip(&self.pdf_cache[off..]).for_each(|(t, p)| *t ^= p);
+        tag
+    }
+}
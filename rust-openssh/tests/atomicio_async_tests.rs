@@ -0,0 +1,82 @@
+#![cfg(feature = "async")]
+
+use std::io::{self, IoSlice};
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+use tokio::io::AsyncReadExt;
+
+use rust_openssh::atomicio_async::*;
+
+#[tokio::test]
+async fn test_async_exact_transfer_with_callback() {
+    // 小缓冲区迫使读写分多次完成
+    let (mut a, mut b) = tokio::io::duplex(7);
+    let payload: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+    let expected = payload.clone();
+
+    let writer = tokio::spawn(async move {
+        let mut chunks = 0;
+        let mut cb = |_n: usize| {
+            chunks += 1;
+            ControlFlow::Continue(())
+        };
+        let header = [0xaa; 5];
+        let mut iov = [IoSlice::new(&header), IoSlice::new(&payload)];
+        let n = writev_all_async(&mut a, &mut iov, Some(&mut cb)).await.unwrap();
+        (n, chunks)
+    });
+
+    let mut header = [0u8; 5];
+    let mut body = vec![0u8; 1000];
+    let mut counted = 0;
+    let mut cb = |n: usize| {
+        counted += n;
+        ControlFlow::Continue(())
+    };
+    assert_eq!(read_exact_async(&mut b, &mut header, None).await.unwrap(), 5);
+    assert_eq!(read_exact_async(&mut b, &mut body, Some(&mut cb)).await.unwrap(), 1000);
+    assert_eq!(counted, 1000);
+    assert_eq!(header, [0xaa; 5]);
+    assert_eq!(body, expected);
+
+    let (n, chunks) = writer.await.unwrap();
+    assert_eq!(n, 1005);
+    assert!(chunks > 1);
+
+    // 对端已关闭，读取不足时报告已读字节数
+    let mut rest = [0u8; 4];
+    let err = read_exact_async(&mut b, &mut rest, None).await.unwrap_err();
+    assert_eq!((err.kind(), err.transferred), (io::ErrorKind::UnexpectedEof, 0));
+}
+
+#[tokio::test]
+async fn test_async_callback_abort() {
+    let (mut a, mut b) = tokio::io::duplex(64);
+    let mut cb = |_n: usize| ControlFlow::Break(());
+    let err = write_all_async(&mut a, &[1u8; 16], Some(&mut cb)).await.unwrap_err();
+    assert_eq!((err.kind(), err.transferred), (io::ErrorKind::Interrupted, 16));
+
+    let mut buf = [0u8; 16];
+    b.read_exact(&mut buf).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_async_deadlines() {
+    let (mut a, mut b) = tokio::io::duplex(8);
+
+    // 对端不读取：写满 8 字节后超时
+    let deadline = Instant::now() + Duration::from_millis(50);
+    let err = write_all_async_until(&mut a, &[0u8; 32], deadline, None).await.unwrap_err();
+    assert!(err.timed_out());
+    assert_eq!(err.transferred, 8);
+
+    let mut buf = [0u8; 12];
+    let deadline = Instant::now() + Duration::from_millis(50);
+    let err = read_exact_async_until(&mut b, &mut buf, deadline, None).await.unwrap_err();
+    assert!(err.timed_out());
+    assert_eq!(err.transferred, 8);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    assert_eq!(writev_all_async_until(&mut a, &mut [IoSlice::new(b"ok")], deadline, None).await.unwrap(), 2);
+}
@@ -0,0 +1,292 @@
+use std::io::{self, Cursor, IoSlice, IoSliceMut, Read};
+use std::ops::ControlFlow;
+use std::os::unix::io::{AsFd, FromRawFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rust_openssh::atomicio::*;
+
+fn pipe() -> (OwnedFd, OwnedFd) {
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) }
+}
+
+// 每次只返回一个字节，并穿插 EINTR
+struct Trickle<'a> {
+    data: &'a [u8],
+    interrupt: bool,
+}
+
+impl Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.interrupt = !self.interrupt;
+        if self.interrupt {
+            return Err(io::ErrorKind::Interrupted.into());
+        }
+        if self.data.is_empty() || buf.is_empty() {
+            return Ok(0);
+        }
+        buf[0] = self.data[0];
+        self.data = &self.data[1..];
+        Ok(1)
+    }
+}
+
+#[test]
+fn test_generic_read_retries_eintr() {
+    let mut r = Trickle { data: b"hello", interrupt: false };
+    let mut buf = [0u8; 5];
+    assert_eq!(read_exact_atomic(&mut r, &mut buf).unwrap(), 5);
+    assert_eq!(&buf, b"hello");
+}
+
+#[test]
+fn test_generic_short_read() {
+    let mut buf = [0u8; 8];
+    let err = read_exact_atomic(&mut Cursor::new(b"abc"), &mut buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    assert_eq!(err.transferred, 3);
+}
+
+#[test]
+fn test_pipe_short_read_does_not_close_fd() {
+    let (r, w) = pipe();
+    assert_eq!(write_all_fd(w.as_fd(), b"partial").unwrap(), 7);
+    assert_eq!(write_all_fd(w.as_fd(), b"!").unwrap(), 1);
+    drop(w);
+
+    let mut buf = [0u8; 16];
+    let err = read_exact_fd(r.as_fd(), &mut buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    assert_eq!(err.transferred, 8);
+    assert_eq!(&buf[..8], b"partial!");
+    // fd 仍然有效
+    assert_ne!(unsafe { libc::fcntl(std::os::unix::io::AsRawFd::as_raw_fd(&r), libc::F_GETFD) }, -1);
+}
+
+#[test]
+fn test_nonblocking_socket_read_polls() {
+    let (a, mut b) = UnixStream::pair().unwrap();
+    a.set_nonblocking(true).unwrap();
+    let writer = thread::spawn(move || {
+        for chunk in [&b"abc"[..], b"def", b"gh"] {
+            thread::sleep(Duration::from_millis(20));
+            io::Write::write_all(&mut b, chunk).unwrap();
+        }
+        b
+    });
+
+    let mut buf = [0u8; 8];
+    assert_eq!(read_exact_fd(a.as_fd(), &mut buf).unwrap(), 8);
+    assert_eq!(&buf, b"abcdefgh");
+    let _b = writer.join().unwrap();
+
+    // 通用版本无法 poll，遇到 EAGAIN 时直接返回
+    let err = read_exact_atomic(&mut &a, &mut buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+}
+
+#[test]
+fn test_nonblocking_socket_write_polls_pollout() {
+    let (a, mut b) = UnixStream::pair().unwrap();
+    a.set_nonblocking(true).unwrap();
+    let data = vec![0x5au8; 4 << 20];
+    let reader = thread::spawn(move || {
+        let mut total = 0;
+        let mut buf = [0u8; 65536];
+        loop {
+            match b.read(&mut buf).unwrap() {
+                0 => return total,
+                n => total += n,
+            }
+        }
+    });
+
+    assert_eq!(write_all_fd(a.as_fd(), &data).unwrap(), data.len());
+    drop(a);
+    assert_eq!(reader.join().unwrap(), data.len());
+}
+
+#[test]
+fn test_writev_advances_across_partial_writes() {
+    let (a, mut b) = UnixStream::pair().unwrap();
+    a.set_nonblocking(true).unwrap();
+    let header = [1u8, 2, 3, 4, 5];
+    let payload = vec![0xa5u8; 3 << 20];
+    let mac = [9u8; 32];
+    let reader = thread::spawn(move || {
+        let mut out = Vec::new();
+        b.read_to_end(&mut out).unwrap();
+        out
+    });
+
+    let mut calls = 0;
+    let mut counted = 0;
+    let mut cb = |n: usize| {
+        calls += 1;
+        counted += n;
+        ControlFlow::Continue(())
+    };
+    let mut iov = [IoSlice::new(&header), IoSlice::new(&[]), IoSlice::new(&payload), IoSlice::new(&mac)];
+    let total = header.len() + payload.len() + mac.len();
+    assert_eq!(writev_all_fd(a.as_fd(), &mut iov, Some(&mut cb)).unwrap(), total);
+    drop(a);
+
+    assert!(calls > 1);
+    assert_eq!(counted, total);
+    let out = reader.join().unwrap();
+    assert_eq!(out.len(), total);
+    assert_eq!(&out[..5], &header);
+    assert_eq!(&out[total - 32..], &mac);
+}
+
+// 每次最多写 3 字节，并穿插 EINTR
+#[derive(Default)]
+struct Choppy {
+    out: Vec<u8>,
+    calls: usize,
+}
+
+impl io::Write for Choppy {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_vectored(&[IoSlice::new(buf)])
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.calls += 1;
+        if self.calls.is_multiple_of(2) {
+            return Err(io::ErrorKind::Interrupted.into());
+        }
+        let mut n = 0;
+        for b in bufs {
+            let take = b.len().min(3 - n);
+            self.out.extend_from_slice(&b[..take]);
+            n += take;
+            if n == 3 {
+                break;
+            }
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_generic_writev_partial_and_eintr() {
+    let mut w = Choppy::default();
+    let mut iov = [IoSlice::new(b"ab"), IoSlice::new(&[]), IoSlice::new(b"cdefg"), IoSlice::new(b"hij")];
+    assert_eq!(writev_all_atomic(&mut w, &mut iov).unwrap(), 10);
+    assert_eq!(w.out, b"abcdefghij");
+
+    let err = writev_all_atomic(&mut Cursor::new([0u8; 4].as_mut_slice()), &mut [IoSlice::new(b"abcdef")]).unwrap_err();
+    assert_eq!((err.kind(), err.transferred), (io::ErrorKind::WriteZero, 4));
+}
+
+#[test]
+fn test_readv_splits_into_buffers() {
+    let (r, w) = pipe();
+    let writer = thread::spawn(move || {
+        for chunk in [&b"ab"[..], b"cdefg", b"hij"] {
+            thread::sleep(Duration::from_millis(10));
+            write_all_fd(w.as_fd(), chunk).unwrap();
+        }
+    });
+
+    let (mut x, mut y, mut z) = ([0u8; 3], [0u8; 4], [0u8; 3]);
+    let mut iov = [IoSliceMut::new(&mut x), IoSliceMut::new(&mut y), IoSliceMut::new(&mut z)];
+    assert_eq!(readv_exact_fd(r.as_fd(), &mut iov, None).unwrap(), 10);
+    writer.join().unwrap();
+    assert_eq!((&x, &y, &z), (b"abc", b"defg", b"hij"));
+
+    // 写端已关闭，剩余缓冲区无法填满
+    let mut rest = [0u8; 4];
+    let err = readv_exact_fd(r.as_fd(), &mut [IoSliceMut::new(&mut rest)], None).unwrap_err();
+    assert_eq!((err.kind(), err.transferred), (io::ErrorKind::UnexpectedEof, 0));
+}
+
+#[test]
+fn test_writev_callback_abort() {
+    let (r, w) = pipe();
+    let data = [7u8; 16];
+    let mut cb = |_n: usize| ControlFlow::Break(());
+    let err = writev_all_fd(w.as_fd(), &mut [IoSlice::new(&data)], Some(&mut cb)).unwrap_err();
+    assert_eq!((err.kind(), err.transferred), (io::ErrorKind::Interrupted, 16));
+    drop(r);
+}
+
+#[test]
+fn test_atomicio6_callback_sees_every_chunk() {
+    let (r, w) = pipe();
+    let writer = thread::spawn(move || {
+        for chunk in [&b"abc"[..], b"de", b"fghij"] {
+            thread::sleep(Duration::from_millis(10));
+            write_all_fd(w.as_fd(), chunk).unwrap();
+        }
+    });
+
+    let mut seen = Vec::new();
+    let mut cb = |n: usize| {
+        seen.push(n);
+        ControlFlow::Continue(())
+    };
+    let mut buf = [0u8; 10];
+    assert_eq!(atomicio6_read(r.as_fd(), &mut buf, Some(&mut cb)).unwrap(), 10);
+    writer.join().unwrap();
+    assert_eq!(&buf, b"abcdefghij");
+    assert_eq!(seen, [3, 2, 5]);
+}
+
+
+#[test]
+fn test_read_deadline_reports_partial() {
+    let (r, w) = pipe();
+    write_all_fd(w.as_fd(), b"abc").unwrap();
+
+    // 阻塞 fd 上也不能越过截止时间
+    let started = Instant::now();
+    let mut buf = [0u8; 8];
+    let err = read_exact_fd_until(r.as_fd(), &mut buf, &Deadline::after(Duration::from_millis(100)), None).unwrap_err();
+    assert!(err.timed_out());
+    assert_eq!(err.transferred, 3);
+    assert_eq!(&buf[..3], b"abc");
+    assert!(started.elapsed() >= Duration::from_millis(100));
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    // 截止时间前数据到齐则正常返回
+    let writer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        write_all_fd(w.as_fd(), b"defgh").unwrap();
+    });
+    let mut buf = [0u8; 5];
+    let mut iov = [IoSliceMut::new(&mut buf)];
+    assert_eq!(readv_exact_fd_until(r.as_fd(), &mut iov, &Deadline::after(Duration::from_secs(5)), None).unwrap(), 5);
+    writer.join().unwrap();
+    assert_eq!(&buf, b"defgh");
+}
+
+#[test]
+fn test_write_deadline_with_sigmask() {
+    let (a, _b) = UnixStream::pair().unwrap();
+    a.set_nonblocking(true).unwrap();
+    let mut mask: libc::sigset_t = unsafe { std::mem::zeroed() };
+    unsafe {
+        libc::sigemptyset(&mut mask);
+        libc::sigaddset(&mut mask, libc::SIGALRM);
+    }
+
+    // 对端不读取，写满缓冲区后超时；阻塞 fd 会卡在 write(2) 内部
+    let data = vec![0u8; 8 << 20];
+    let deadline = Deadline::new(Instant::now() + Duration::from_millis(100)).with_sigmask(mask);
+    let err = write_all_fd_until(a.as_fd(), &data, &deadline, None).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert!(err.transferred > 0 && err.transferred < data.len());
+
+    let err = writev_all_fd_until(a.as_fd(), &mut [IoSlice::new(&data)], &deadline, None).unwrap_err();
+    assert!(err.timed_out());
+    assert_eq!(err.transferred, 0);
+}
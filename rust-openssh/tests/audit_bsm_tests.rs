@@ -0,0 +1,88 @@
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, UNIX_EPOCH};
+
+use rust_openssh::audit::{AuditBackend, AuditRecord};
+use rust_openssh::audit_bsm::*;
+
+mod common;
+use common::scratch;
+
+fn record(event: &'static str, user: Option<&str>) -> AuditRecord {
+    AuditRecord {
+        event,
+        code: None,
+        euid: 0,
+        user: user.map(str::to_string),
+        remote_addr: Some("127.0.0.1".to_string()),
+        remote_port: Some(40022),
+        method: None,
+        session_id: None,
+        tty: None,
+        command: None,
+    }
+}
+
+#[test]
+fn test_record_roundtrip() {
+    let body = [
+        BsmToken::Text("invalid password for user alice".to_string()),
+        BsmToken::Return32 { status: 4, ret: u32::MAX },
+    ];
+    let when = UNIX_EPOCH + Duration::from_millis(1_000_250);
+    let record = bsm_build_record(AUE_OPENSSH, when, &body);
+    let tokens = bsm_parse_record(&record).unwrap();
+    let lines: Vec<String> = tokens.iter().map(BsmToken::praudit).collect();
+    assert_eq!(
+        lines,
+        vec![
+            format!("header,{},11,OpenSSH login,0,1000.250", record.len()),
+            "text,invalid password for user alice".to_string(),
+            "return,failure : 4,4294967295".to_string(),
+            format!("trailer,{}", record.len()),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_rejects_size_mismatch() {
+    let mut record = bsm_build_record(AUE_LOGOUT, UNIX_EPOCH, &[BsmToken::Text(String::new())]);
+    record.push(0x13);
+    assert!(bsm_parse_record(&record).is_err());
+}
+
+#[test]
+fn test_user_mask_selection() {
+    let mut config = BsmConfig::new("/dev/null");
+    config.parse_audit_control("flags:+aa\nnaflags:lo\n").unwrap();
+    config.parse_audit_user("alice:lo:no\nbob:no:-lo\n").unwrap();
+
+    assert!(config.selected(Some("alice"), AUE_OPENSSH, true));
+    assert!(!config.selected(Some("bob"), AUE_OPENSSH, true));
+    assert!(!config.selected(Some("carol"), AUE_LOGOUT, false));
+    assert!(config.selected(None, AUE_OPENSSH, false));
+    assert!(AuditMask::from_flags("lo,bogus").is_err());
+}
+
+#[test]
+fn test_trail_records() {
+    let dir = scratch("trail");
+    let trail = dir.join("trail");
+    let mut backend = BsmAuditBackend::open(BsmConfig::new(&trail)).unwrap();
+
+    backend.record(&record("CONNECTION_FROM", None)).unwrap();
+    backend.record(&record("INVALID_USER", Some("mallory"))).unwrap();
+    // 未认证的连接关闭不产生 logout 记录
+    backend.record(&record("CONNECTION_CLOSE", Some("mallory"))).unwrap();
+
+    let data = fs::read(&trail).unwrap();
+    let tokens = bsm_parse_record(&data).unwrap();
+    match &tokens[1] {
+        BsmToken::Subject32Ex { tid, .. } => {
+            assert_eq!(tid.addr, IpAddr::V4(Ipv4Addr::LOCALHOST));
+            assert_eq!(tid.port, 40022);
+        }
+        other => panic!("unexpected token {:?}", other),
+    }
+    assert_eq!(tokens[2], BsmToken::Text("invalid user for user mallory".to_string()));
+}
@@ -0,0 +1,115 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+
+use rust_openssh::audit::{self, AuditBackend, AuditRecord, AuthContext, SshAuditEvent};
+use rust_openssh::audit_json::{audit_record_json, format_timestamp, JsonAuditOptions, JsonAuditSink};
+
+mod common;
+use common::scratch;
+
+#[test]
+fn test_format_timestamp() {
+    let t = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+    assert_eq!(format_timestamp(t), "2023-11-14T22:13:20.123Z");
+    assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+}
+
+#[test]
+fn test_record_fields() {
+    let rec = AuditRecord {
+        event: "AUTH_FAIL_PASSWD",
+        code: Some(SshAuditEvent::AuthFailPasswd.code()),
+        euid: 0,
+        user: Some("alice".to_string()),
+        remote_addr: Some("192.0.2.1".to_string()),
+        remote_port: Some(50022),
+        method: Some("password".to_string()),
+        session_id: None,
+        tty: None,
+        command: None,
+    };
+    let v = audit_record_json(&rec, UNIX_EPOCH);
+    let unknown = audit_record_json(&AuditRecord { user: None, ..rec.clone() }, UNIX_EPOCH);
+    assert_eq!(unknown["user"], "(unknown user)");
+    assert_eq!(v["event"], "AUTH_FAIL_PASSWD");
+    assert_eq!(v["code"], 4);
+    assert_eq!(v["user"], "alice");
+    assert_eq!(v["remote_port"], 50022);
+    assert!(v["session_id"].is_null());
+}
+
+#[test]
+fn test_sink_rotation() {
+    let dir = scratch("rotate");
+    let path = dir.join("rotate.jsonl");
+    let opts = JsonAuditOptions { fsync: true, max_size: Some(300), keep: 2 };
+    audit::audit_set_backend(Some(Box::new(JsonAuditSink::open(&path, opts).unwrap())));
+    audit::audit_connection_from("198.51.100.7", 2222);
+
+    let ctxt = AuthContext {
+        user: Some("bob".to_string()),
+        valid: true,
+        method: Some("publickey".to_string()),
+    };
+    for _ in 0..4 {
+        audit::audit_event(Some(&ctxt), &SshAuditEvent::AuthSuccess);
+    }
+    audit::audit_run_command(Some(&ctxt), &"x".repeat(500));
+    audit::audit_set_backend(None);
+
+    let current = fs::read_to_string(&path).unwrap();
+    let last: serde_json::Value = serde_json::from_str(current.lines().last().unwrap()).unwrap();
+    assert_eq!(last["event"], "RUN_COMMAND");
+    assert_eq!(last["command"].as_str().unwrap().len(), audit::AUDIT_COMMAND_MAX);
+
+    let rotated = fs::read_to_string(format!("{}.1", path.display())).unwrap();
+    let first: serde_json::Value = serde_json::from_str(rotated.lines().next().unwrap()).unwrap();
+    assert_eq!(first["user"], "bob");
+    assert_eq!(first["remote_addr"], "198.51.100.7");
+    assert!(!PathBuf::from(format!("{}.3", path.display())).exists());
+}
+
+#[test]
+fn test_sinks_sharing_file() {
+    // 模拟 sshd 的多个子进程：各自持有一个 sink，写同一个文件
+    let dir = scratch("shared");
+    let path = dir.join("shared.jsonl");
+    let rec = |n: usize| AuditRecord {
+        event: "AUTH_SUCCESS",
+        code: None,
+        euid: 0,
+        user: Some("bob".to_string()),
+        remote_addr: None,
+        remote_port: None,
+        method: None,
+        session_id: Some(n.to_string()),
+        tty: None,
+        command: None,
+    };
+    let line_len = serde_json::to_vec(&audit_record_json(&rec(0), UNIX_EPOCH)).unwrap().len() as u64 + 1;
+    let opts = JsonAuditOptions { fsync: false, max_size: Some(line_len * 7 / 2), keep: 5 };
+    let mut sinks = [JsonAuditSink::open(&path, opts.clone()).unwrap(), JsonAuditSink::open(&path, opts).unwrap()];
+    for n in 0..9 {
+        sinks[n % 2].record(&rec(n)).unwrap();
+    }
+
+    // 每个文件三条记录，没有记录写入已轮转的文件或被多余的轮转删除
+    let sessions = |name: &PathBuf| -> Vec<String> {
+        fs::read_to_string(name)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap()["session_id"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let rotated = |n: usize| PathBuf::from(format!("{}.{}", path.display(), n));
+    assert_eq!(sessions(&rotated(2)), ["0", "1", "2"]);
+    assert_eq!(sessions(&rotated(1)), ["3", "4", "5"]);
+    assert_eq!(sessions(&path), ["6", "7", "8"]);
+    assert!(!rotated(3).exists());
+
+    // 文件被外部删除后重新创建
+    fs::remove_file(&path).unwrap();
+    sinks[0].record(&rec(9)).unwrap();
+    assert_eq!(sessions(&path), ["9"]);
+}
@@ -0,0 +1,128 @@
+#![cfg(target_os = "linux")]
+
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::thread;
+
+use rust_openssh::audit::{AuditBackend, AuditRecord};
+use rust_openssh::audit_linux::*;
+
+// 用 AF_UNIX 数据报 socketpair 模拟 NETLINK_AUDIT
+fn fake_netlink() -> (OwnedFd, OwnedFd) {
+    let mut fds = [0; 2];
+    let rc = unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_DGRAM, 0, fds.as_mut_ptr()) };
+    assert_eq!(rc, 0);
+    unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) }
+}
+
+// 读取一条请求并以给定错误码应答，返回 (消息类型, 文本负载)
+fn kernel_reply(fd: &OwnedFd, error: i32) -> (u16, String) {
+    let mut buf = [0u8; 9000];
+    let n = unsafe { libc::recv(fd.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0) };
+    assert!(n >= 16);
+    let len = u32::from_ne_bytes(buf[0..4].try_into().unwrap()) as usize;
+    let msg_type = u16::from_ne_bytes([buf[4], buf[5]]);
+    let payload = String::from_utf8(buf[16..len - 1].to_vec()).unwrap();
+
+    let mut ack = [0u8; 36];
+    ack[0..4].copy_from_slice(&36u32.to_ne_bytes());
+    ack[4..6].copy_from_slice(&2u16.to_ne_bytes());
+    ack[8..12].copy_from_slice(&buf[8..12]);
+    ack[16..20].copy_from_slice(&error.to_ne_bytes());
+    ack[20..36].copy_from_slice(&buf[0..16]);
+    unsafe { libc::send(fd.as_raw_fd(), ack.as_ptr() as *const libc::c_void, ack.len(), 0) };
+    (msg_type, payload)
+}
+
+fn record(event: &'static str, user: Option<&str>) -> AuditRecord {
+    AuditRecord {
+        event,
+        code: None,
+        euid: 0,
+        user: user.map(str::to_string),
+        remote_addr: Some("192.0.2.10".to_string()),
+        remote_port: Some(22),
+        method: None,
+        session_id: None,
+        tty: Some("/dev/pts/3".to_string()),
+        command: None,
+    }
+}
+
+#[test]
+fn test_encode_value() {
+    assert_eq!(audit_encode_value("alice"), "\"alice\"");
+    assert_eq!(audit_encode_value("a b"), "612062");
+    assert_eq!(audit_encode_value("x\"y"), "782279");
+}
+
+#[test]
+fn test_format_acct_message() {
+    let msg = format_acct_message("login", None, 1000, "/usr/sbin/sshd", None, Some("::1"), Some("sshd"), false);
+    assert_eq!(msg, "op=login id=1000 exe=\"/usr/sbin/sshd\" hostname=? addr=::1 terminal=sshd res=failed");
+}
+
+#[test]
+fn test_session_open_records() {
+    let (ours, kernel) = fake_netlink();
+    let mut backend = LinuxAuditBackend::with_socket(LinuxAudit::from_fd(ours));
+    let peer = thread::spawn(move || (kernel_reply(&kernel, 0), kernel_reply(&kernel, 0)));
+
+    backend.record(&record("SESSION_OPEN", Some("alice"))).unwrap();
+    let ((t1, p1), (t2, p2)) = peer.join().unwrap();
+    assert_eq!(t1, AUDIT_USER_LOGIN);
+    assert!(p1.starts_with("op=login acct=\"alice\" exe="));
+    assert!(p1.ends_with("addr=192.0.2.10 terminal=/dev/pts/3 res=success"));
+    assert_eq!(t2, AUDIT_USER_START);
+    assert!(p2.starts_with("op=PAM:session_open "));
+}
+
+#[test]
+fn test_unknown_user() {
+    let (ours, kernel) = fake_netlink();
+    let mut backend = LinuxAuditBackend::with_socket(LinuxAudit::from_fd(ours));
+    let peer = thread::spawn(move || (kernel_reply(&kernel, 0), kernel_reply(&kernel, 0), kernel_reply(&kernel, 0)));
+
+    // 没有用户时记录 euid，认证失败时则与 OpenSSH 一样记录占位名称
+    backend.record(&record("NOLOGIN", None)).unwrap();
+    backend.record(&record("AUTH_FAIL_PASSWD", None)).unwrap();
+    let ((_, p1), (_, p2), (t3, p3)) = peer.join().unwrap();
+    assert!(p1.starts_with("op=PAM:accounting id="), "{}", p1);
+    assert!(p2.starts_with(&format!("op=PAM:authentication acct={} ", audit_encode_value("(unknown user)"))), "{}", p2);
+    assert_eq!(t3, AUDIT_USER_LOGIN);
+    assert!(p3.starts_with("op=login acct="), "{}", p3);
+}
+
+#[test]
+fn test_kernel_error_is_reported() {
+    let (ours, kernel) = fake_netlink();
+    let mut backend = LinuxAuditBackend::with_socket(LinuxAudit::from_fd(ours));
+    let peer = thread::spawn(move || kernel_reply(&kernel, -libc::EIO));
+
+    let err = backend.record(&record("SESSION_CLOSE", Some("alice"))).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EIO));
+    assert_eq!(peer.join().unwrap().0, AUDIT_USER_END);
+}
+
+#[test]
+fn test_eperm_ignored_when_not_root() {
+    let (ours, kernel) = fake_netlink();
+    let mut backend = LinuxAuditBackend::with_socket(LinuxAudit::from_fd(ours));
+    let peer = thread::spawn(move || kernel_reply(&kernel, -libc::EPERM));
+
+    let res = backend.record(&record("NOLOGIN", Some("alice")));
+    peer.join().unwrap();
+    if unsafe { libc::geteuid() } == 0 {
+        assert_eq!(res.unwrap_err().raw_os_error(), Some(libc::EPERM));
+    } else {
+        assert!(res.is_ok());
+    }
+}
+
+#[test]
+fn test_connection_refused_is_not_an_error() {
+    // 对端关闭后 send 返回 ECONNREFUSED，相当于系统中没有审计守护进程
+    let (ours, kernel) = fake_netlink();
+    drop(kernel);
+    let mut backend = LinuxAuditBackend::with_socket(LinuxAudit::from_fd(ours));
+    backend.cred_acquired("alice", None).unwrap();
+}
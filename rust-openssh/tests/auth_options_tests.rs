@@ -0,0 +1,228 @@
+use std::fs;
+
+use rust_openssh::addrmatch::{addr_match_cidr_list, addr_match_list, match_host_and_ip};
+use rust_openssh::auth_options::*;
+use rust_openssh::sshkey::cert::encode_options;
+use rust_openssh::sshkey::{CertOption, PublicKey};
+
+fn cert(name: &str) -> PublicKey {
+    PublicKey::from_openssh_line(&fs::read_to_string(format!("tests/data/sshkey/{}", name)).unwrap()).unwrap().0
+}
+
+fn apply(options: &[CertOption], which: u32, crit: bool) -> Result<SshAuthOpt, ParseError> {
+    let mut opts = SshAuthOpt::new();
+    cert_option_list(&mut opts, &encode_options(options), which, crit)?;
+    Ok(opts)
+}
+
+#[test]
+fn test_options_from_openssh_certificate() {
+    let opts = SshAuthOpt::from_cert(cert("user-cert.pub").cert().unwrap()).unwrap();
+    assert_eq!(opts.force_command.as_deref(), Some("/usr/bin/backup --daily"));
+    assert_eq!(opts.required_from_host_cert.as_deref(), Some("10.0.0.0/8,192.168.1.1"));
+    assert!(opts.permit_agent_forwarding_flag && opts.permit_port_forwarding_flag);
+    assert!(opts.permit_pty_flag && opts.permit_user_rc);
+    // 签发时使用了 -O no-x11-forwarding
+    assert!(!opts.permit_x11_forwarding_flag);
+    assert_eq!(opts.force_tun_device, -1);
+
+    // 没有扩展的证书什么也不允许
+    let opts = SshAuthOpt::from_cert(cert("host-cert.pub").cert().unwrap()).unwrap();
+    assert_eq!(opts, SshAuthOpt::new());
+}
+
+#[test]
+fn test_cert_option_list() {
+    let opts = apply(
+        &[CertOption::flag("verify-required"), CertOption::flag("no-touch-required"), CertOption::flag("permit-pty")],
+        OPTIONS_CRITICAL | OPTIONS_EXTENSIONS,
+        true,
+    )
+    .unwrap();
+    assert!(opts.require_verify && opts.no_require_user_presence && opts.permit_pty_flag);
+
+    // 未知扩展被忽略，未知关键选项导致拒绝
+    let unknown = [CertOption::flag("permit-everything@example.com")];
+    assert_eq!(apply(&unknown, OPTIONS_EXTENSIONS, false).unwrap(), SshAuthOpt::new());
+    assert_eq!(
+        apply(&unknown, OPTIONS_CRITICAL, true),
+        Err(ParseError::InvalidOption("permit-everything@example.com".to_string()))
+    );
+    // 扩展名出现在关键选项中同样不被接受
+    assert!(matches!(apply(&[CertOption::flag("permit-pty")], OPTIONS_CRITICAL, true), Err(ParseError::InvalidOption(_))));
+
+    let twice = [CertOption::string("force-command", "/bin/a"), CertOption::string("force-command", "/bin/b")];
+    assert_eq!(apply(&twice, OPTIONS_CRITICAL, true), Err(ParseError::MultipleOptions("force-command".to_string())));
+    let twice = [CertOption::string("source-address", "::1"), CertOption::string("source-address", "::1")];
+    assert_eq!(apply(&twice, OPTIONS_CRITICAL, true), Err(ParseError::MultipleOptions("source-address".to_string())));
+
+    let flag_with_data = [CertOption { name: "permit-pty".to_string(), data: b"\0".to_vec() }];
+    assert_eq!(apply(&flag_with_data, OPTIONS_EXTENSIONS, false), Err(ParseError::OptionCorruption("permit-pty".to_string())));
+    let mut trailing = CertOption::string("force-command", "/bin/true");
+    trailing.data.push(0);
+    assert!(matches!(apply(&[trailing], OPTIONS_CRITICAL, true), Err(ParseError::OptionCorruption(_))));
+    let empty = [CertOption::flag("force-command")];
+    assert!(matches!(apply(&empty, OPTIONS_CRITICAL, true), Err(ParseError::SyntaxError(_))));
+    let bad_addr = [CertOption::string("source-address", "10.0.0.0/8,example.com")];
+    assert!(matches!(apply(&bad_addr, OPTIONS_CRITICAL, true), Err(ParseError::SyntaxError(_))));
+
+    let mut opts = SshAuthOpt::new();
+    assert_eq!(cert_option_list(&mut opts, b"\0\0\0\x05abc", OPTIONS_CRITICAL, true), Err(ParseError::InvalidBuffer));
+}
+
+#[test]
+fn test_source_address_lists() {
+    let list = "10.0.0.0/8,192.168.1.1,2001:db8::/32";
+    assert_eq!(addr_match_cidr_list(None, list), 0);
+    assert_eq!(addr_match_cidr_list(Some("10.1.2.3"), list), 1);
+    assert_eq!(addr_match_cidr_list(Some("192.168.1.1"), list), 1);
+    assert_eq!(addr_match_cidr_list(Some("192.168.1.2"), list), 0);
+    assert_eq!(addr_match_cidr_list(Some("2001:db8::1"), list), 1);
+    assert_eq!(addr_match_cidr_list(Some("2001:db9::1"), list), 0);
+    assert_eq!(addr_match_cidr_list(Some("203.0.113.9"), "0.0.0.0/0"), 1);
+    assert_eq!(addr_match_cidr_list(None, "10.0.0.0/33"), -1);
+    assert_eq!(addr_match_cidr_list(None, "10.0.0.0/8,"), -1);
+    // 主机部分不为零的网络和过长的条目
+    assert_eq!(addr_match_cidr_list(None, "10.0.0.1/8"), -1);
+    assert_eq!(addr_match_cidr_list(None, &format!("{}/128", "0:".repeat(24))), -1);
+}
+
+#[test]
+fn test_defaults_and_permits() {
+    let opts = SshAuthOpt::new_with_keys_defaults();
+    assert!(opts.permit_port_forwarding_flag && opts.permit_agent_forwarding_flag && opts.permit_x11_forwarding_flag);
+    assert!(opts.permit_pty_flag && opts.permit_user_rc && !opts.require_verify);
+
+    let mut permits = Vec::new();
+    handle_permit("db.internal:5432", false, &mut permits).unwrap();
+    handle_permit("8080", true, &mut permits).unwrap();
+    handle_permit("[2001:db8::1]:*", false, &mut permits).unwrap();
+    assert_eq!(permits, ["db.internal:5432", "*:8080", "[2001:db8::1]:*"]);
+    assert!(matches!(handle_permit("8080", false, &mut permits), Err(SshAuthError::InvalidPermissionHostname)));
+    assert!(matches!(handle_permit("host:0", false, &mut permits), Err(SshAuthError::InvalidPermissionPort)));
+    assert!(matches!(handle_permit("host:http", false, &mut permits), Err(SshAuthError::InvalidPermissionPort)));
+    assert_eq!(permits.len(), 3);
+}
+
+#[test]
+fn test_option_helpers() {
+    let mut opts = "No-Pty,pty";
+    assert_eq!(opt_flag("pty", true, &mut opts), Some(false));
+    assert_eq!(opts, ",pty");
+    assert_eq!(opt_flag("pty", false, &mut opts), None);
+    let mut opts = "no-pty";
+    assert_eq!(opt_flag("pty", false, &mut opts), None);
+    assert_eq!(opts, "no-pty");
+
+    let mut opts = "Command=\"echo \\\"hi\\\"\",restrict ssh-ed25519";
+    assert!(!opt_match(&mut opts, "comm"));
+    assert!(opt_match(&mut opts, "command"));
+    assert_eq!(opt_dequote(&mut opts).unwrap(), "echo \"hi\"");
+    assert_eq!(opts, ",restrict ssh-ed25519");
+    assert_eq!(opt_dequote(&mut "unquoted"), Err("missing start quote"));
+    assert_eq!(opt_dequote(&mut "\"open"), Err("missing end quote"));
+}
+
+#[test]
+fn test_host_and_ip_lists() {
+    let list = "10.0.0.0/8,!10.9.0.0/16,192.168.1.*,*.example.com,!bad.example.com";
+    assert_eq!(addr_match_list(Some("10.1.2.3"), list), 1);
+    assert_eq!(addr_match_list(Some("10.9.2.3"), list), -1);
+    assert_eq!(addr_match_list(Some("192.168.1.77"), list), 1);
+    // 通配符要匹配整个地址
+    assert_eq!(addr_match_list(Some("10.192.168.1"), "192.168.1.*"), 0);
+    assert_eq!(addr_match_list(Some("not an address"), list), 0);
+    assert_eq!(addr_match_list(None, "10.0.0.0/8,,::1"), -2);
+    // 掩码长度与地址不一致时整个列表无效，无法解析的掩码按通配符处理
+    assert_eq!(addr_match_list(Some("10.0.0.1"), "10.0.0.1/33"), -2);
+    assert_eq!(addr_match_list(Some("10.0.0.1"), "10.0.0.0/8,!10.0.0.1/8"), -2);
+    assert_eq!(addr_match_list(None, "2001:db8::/129"), 0);
+    assert_eq!(addr_match_list(Some("10.0.0.1"), "10.0.0.1/x,10.0.0.1/32"), 1);
+    assert_eq!(addr_match_list(Some("10.0.0.1/x"), "10.0.0.1/x"), 0);
+
+    assert_eq!(match_host_and_ip(Some("host.Example.com"), Some("203.0.113.1"), list), 1);
+    assert_eq!(match_host_and_ip(Some("bad.example.com"), Some("10.1.2.3"), list), 0);
+    assert_eq!(match_host_and_ip(Some("host.example.com"), Some("10.9.2.3"), list), 0);
+    assert_eq!(match_host_and_ip(Some("other.org"), Some("203.0.113.1"), list), 0);
+    assert_eq!(match_host_and_ip(None, Some("10.1.2.3"), list), 0);
+    assert_eq!(match_host_and_ip(Some("h"), Some("10.1.2.3"), "!"), -1);
+    assert_eq!(match_host_and_ip(Some("h"), Some("10.1.2.3"), "h,10.0.0.0/33"), -1);
+    // 长主机名模式不受地址长度限制
+    let long = "a.very-long-internal-subdomain.datacenter.example.com";
+    assert_eq!(match_host_and_ip(Some(long), Some("10.0.0.1"), "*.very-long-internal-subdomain.datacenter.example.com"), 1);
+    assert_eq!(addr_match_list(Some("10.0.0.1"), &format!("!{}", long)), 0);
+}
+
+#[test]
+fn test_parse_key_options() {
+    assert_eq!(SshAuthOpt::parse("").unwrap(), SshAuthOpt::new_with_keys_defaults());
+    // 选项在第一个未加引号的空白处结束
+    let opts = SshAuthOpt::parse(
+        "restrict,Pty,command=\"echo a,b\",from=\"10.0.0.0/8\",environment=\"PATH=/bin\",environment=\"PATH=/x\",\
+         permitopen=\"db:5432\",permitlisten=\"8080\",tunnel=\"any\",expiry-time=\"20300101Z\",\
+         expiry-time=\"20290101Z\",no-touch-required,verify-required ssh-ed25519 AAAA",
+    )
+    .unwrap();
+    assert!(opts.restricted && opts.permit_pty_flag);
+    assert!(!opts.permit_port_forwarding_flag && !opts.permit_agent_forwarding_flag && !opts.permit_x11_forwarding_flag);
+    assert!(!opts.permit_user_rc && !opts.cert_authority);
+    assert_eq!(opts.force_command.as_deref(), Some("echo a,b"));
+    assert_eq!(opts.required_from_host_keys.as_deref(), Some("10.0.0.0/8"));
+    assert_eq!(opts.env, ["PATH=/bin"]);
+    assert_eq!((opts.permitopen, opts.permitlisten), (vec!["db:5432".to_string()], vec!["*:8080".to_string()]));
+    assert_eq!(opts.force_tun_device, SSH_TUNID_ANY);
+    assert_eq!(opts.valid_before, 1_861_920_000);
+    assert!(opts.no_require_user_presence && opts.require_verify);
+
+    let opts = SshAuthOpt::parse("cert-authority,principals=\"a,b\",no-agent-forwarding,tunnel=\"3\"").unwrap();
+    assert!(opts.cert_authority && !opts.permit_agent_forwarding_flag && opts.permit_pty_flag);
+    assert_eq!(opts.cert_principals.as_deref(), Some("a,b"));
+    assert_eq!(opts.force_tun_device, 3);
+
+    for (bad, reason) in [
+        ("command=\"a\",command=\"b\"", "multiple \"command\" clauses"),
+        ("principals=\"a\",principals=\"b\"", "multiple \"principals\" clauses"),
+        ("from=\"a\",from=\"b\"", "multiple \"from\" clauses"),
+        ("command=unquoted", "missing start quote"),
+        ("command=\"open", "missing end quote"),
+        ("expiry-time=\"tomorrow\"", "invalid expires time"),
+        ("environment=\"NOEQUALS\"", "invalid environment string"),
+        ("environment=\"BAD-NAME=1\"", "invalid environment string"),
+        ("permitopen=\"8080\"", "invalid permitopen hostname"),
+        ("permitlisten=\"host:0\"", "invalid permitlisten port"),
+        ("tunnel=\"eth0\"", "invalid tun device"),
+        ("bogus", "unknown key option"),
+        ("no-restrict", "unknown key option"),
+        ("pty,", "unexpected end-of-options"),
+    ] {
+        assert_eq!(SshAuthOpt::parse(bad), Err(ParseError::SyntaxError(reason.to_string())), "{}", bad);
+    }
+}
+
+#[test]
+fn test_merge_options() {
+    let keyopts = SshAuthOpt::parse("no-pty,command=\"/bin/a\",from=\"10.0.0.0/8\",expiry-time=\"20300101Z\"").unwrap();
+    let certopts = SshAuthOpt::from_cert(cert("user-cert.pub").cert().unwrap()).unwrap();
+    assert_eq!(
+        SshAuthOpt::merge(&keyopts, &certopts),
+        Err(ParseError::SyntaxError("forced command options do not match".to_string()))
+    );
+
+    let keyopts = SshAuthOpt::parse("no-pty,principals=\"x\",from=\"10.0.0.0/8\",expiry-time=\"20300101Z\"").unwrap();
+    let merged = SshAuthOpt::merge(&keyopts, &certopts).unwrap();
+    // 标志取两者的交集
+    assert!(!merged.permit_pty_flag && !merged.permit_x11_forwarding_flag && merged.permit_agent_forwarding_flag);
+    assert_eq!(merged.force_command.as_deref(), Some("/usr/bin/backup --daily"));
+    assert_eq!(merged.required_from_host_keys.as_deref(), Some("10.0.0.0/8"));
+    assert_eq!(merged.required_from_host_cert.as_deref(), Some("10.0.0.0/8,192.168.1.1"));
+    assert_eq!(merged.valid_before, 1_893_456_000);
+    assert_eq!(merged.cert_principals, None);
+    assert_eq!(merged.force_tun_device, -1);
+
+    let mut verify = SshAuthOpt::new();
+    verify.require_verify = true;
+    verify.valid_before = 100;
+    let merged = SshAuthOpt::merge(&keyopts, &verify).unwrap();
+    assert!(merged.require_verify && !merged.permit_agent_forwarding_flag);
+    assert_eq!(merged.valid_before, 100);
+}
@@ -0,0 +1,456 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use rust_openssh::auth_options::SshAuthOpt;
+use rust_openssh::authfile::*;
+use rust_openssh::misc::Passwd;
+use rust_openssh::sshkey::*;
+
+mod common;
+use common::scratch;
+
+const KEYS: &str = "tests/data/authfile/authorized_keys";
+
+fn public(name: &str) -> PublicKey {
+    PublicKey::from_openssh_line(&fs::read_to_string(format!("tests/data/sshkey/{}.pub", name)).unwrap()).unwrap().0
+}
+
+fn private(name: &str) -> PrivateKey {
+    PrivateKey::from_private_text(&fs::read_to_string(format!("tests/data/sshkey/{}", name)).unwrap(), "").unwrap().0
+}
+
+// 当前用户，但名字固定为 alice，主目录为 home
+fn alice(home: &Path) -> Passwd {
+    let me = Passwd::from_uid(unsafe { libc::getuid() }).unwrap();
+    Passwd { name: "alice".to_string(), dir: home.to_path_buf(), ..me }
+}
+
+fn conn(ip: &str, host: &str) -> ConnInfo {
+    ConnInfo { remote_ip: ip.to_string(), remote_host: host.to_string(), ..Default::default() }
+}
+
+// ecdsa521 用户密钥的证书
+fn cert(ca: &str, principals: &[&str], critical: &[CertOption]) -> PublicKey {
+    let mut req = CertRequest::new(public("ecdsa521"), CertType::User, "test");
+    req.principals = principals.iter().map(|p| p.to_string()).collect();
+    req.critical_options = critical.to_vec();
+    PublicKey::Cert(Box::new(req.sign(&private(ca), None).unwrap()))
+}
+
+// 在 authorized_keys 测试文件中查找
+fn check(key: &PublicKey, conn: &ConnInfo) -> (Option<AuthorizedKey>, Vec<String>) {
+    let mut auth_debug = Vec::new();
+    let pw = alice(Path::new("/nonexistent"));
+    let found = check_authkeys_file(&pw, fs::read(KEYS).unwrap().as_slice(), KEYS, key, conn, &mut auth_debug);
+    (found, auth_debug)
+}
+
+fn chmod(path: &Path, mode: u32) {
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+}
+
+#[test]
+fn test_expand_authorized_keys() {
+    let pw = alice(Path::new("/home/alice"));
+    let uid = pw.uid;
+    assert_eq!(expand_authorized_keys(".ssh/authorized_keys", &pw).unwrap(), Path::new("/home/alice/.ssh/authorized_keys"));
+    assert_eq!(expand_authorized_keys("%h/.ssh/keys", &pw).unwrap(), Path::new("/home/alice/.ssh/keys"));
+    assert_eq!(expand_authorized_keys("/etc/ssh/keys/%u", &pw).unwrap(), Path::new("/etc/ssh/keys/alice"));
+    assert_eq!(expand_authorized_keys("/keys/%U-100%%", &pw).unwrap(), PathBuf::from(format!("/keys/{}-100%", uid)));
+    assert!(expand_authorized_keys("/keys/%x", &pw).is_err());
+    assert!(expand_authorized_keys("/keys/%", &pw).is_err());
+}
+
+#[test]
+fn test_plain_keys() {
+    let client = conn("10.1.2.3", "client.example.com");
+
+    let (found, auth_debug) = check(&public("ed25519"), &client);
+    let found = found.unwrap();
+    assert_eq!(found.loc, format!("{}:2", KEYS));
+    assert_eq!(found.key, public("ed25519"));
+    assert_eq!(found.opts, SshAuthOpt::new_with_keys_defaults());
+    assert!(auth_debug.is_empty());
+
+    let found = check(&public("ecdsa256"), &client).0.unwrap();
+    assert_eq!(found.loc, format!("{}:3", KEYS));
+    assert!(found.opts.restricted && found.opts.permit_pty_flag);
+    assert!(!found.opts.permit_port_forwarding_flag && !found.opts.permit_user_rc);
+    assert_eq!(found.opts.force_command.as_deref(), Some("/usr/bin/backup --daily"));
+    assert_eq!(found.opts.env, ["A=1"]);
+
+    // expiry-time="20000101"
+    let (found, auth_debug) = check(&public("rsa2048"), &client);
+    assert_eq!(found, None);
+    assert!(auth_debug[0].starts_with(&format!("{}:6: entry expired at 2000-01-01T", KEYS)));
+    assert_eq!(auth_debug[1], "Refused by key options");
+
+    // 普通密钥不能使用 principals=
+    let (found, auth_debug) = check(&public("rsa1024"), &client);
+    assert_eq!(found, None);
+    assert_eq!(auth_debug[0], format!("{}:7: principals on non-CA key", KEYS));
+}
+
+#[test]
+fn test_from_option() {
+    // from="10.0.0.0/8,*.example.com,!bad.example.com"
+    let key = public("ecdsa384");
+    assert_eq!(check(&key, &conn("10.1.2.3", "unknown")).0.unwrap().loc, format!("{}:5", KEYS));
+    assert!(check(&key, &conn("192.0.2.1", "Client.Example.COM")).0.is_some());
+    for (ip, host) in [("192.0.2.1", "client.example.net"), ("10.1.2.3", "bad.example.com")] {
+        let (found, auth_debug) = check(&key, &conn(ip, host));
+        assert_eq!(found, None, "{} {}", ip, host);
+        assert_eq!(
+            auth_debug[0],
+            format!("{}:5: Your host '{}' is not permitted to use this key for login.", KEYS, host)
+        );
+    }
+}
+
+#[test]
+fn test_malformed_lines() {
+    let (found, auth_debug) = check(&public("ecdsa521"), &conn("10.1.2.3", "client"));
+    assert_eq!(found, None);
+    assert_eq!(auth_debug.len(), 3);
+    assert_eq!(auth_debug[0], format!("{}:10: bad key options: unknown key option", KEYS));
+    assert_eq!(auth_debug[1], format!("{}:11: invalid key option string", KEYS));
+    assert!(auth_debug[2].starts_with(&format!("{}:12: invalid key: ", KEYS)));
+
+    // 空行、注释与行尾的 \r 都被忽略
+    let pw = alice(Path::new("/nonexistent"));
+    let text = format!("\n# x\r\n\t{}\r\n", fs::read_to_string("tests/data/sshkey/ed25519.pub").unwrap().trim());
+    let mut auth_debug = Vec::new();
+    let found = check_authkeys_file(&pw, text.as_bytes(), "text", &public("ed25519"), &ConnInfo::default(), &mut auth_debug);
+    assert_eq!(found.unwrap().loc, "text:3");
+    assert!(auth_debug.is_empty());
+}
+
+#[test]
+fn test_certificates() {
+    let client = conn("10.1.2.3", "client.example.com");
+
+    // principals= 优先于用户名
+    let found = check(&cert("ed25519", &["deploy"], &[]), &client).0.unwrap();
+    assert_eq!(found.loc, format!("{}:8", KEYS));
+    assert_eq!(found.key, public("ed25519"));
+    assert!(found.opts.permit_pty_flag && found.opts.cert_principals.is_none());
+    let (found, auth_debug) = check(&cert("ed25519", &["alice"], &[]), &client);
+    assert_eq!(found, None);
+    assert_eq!(auth_debug[0], "Certificate does not contain an authorized principal");
+
+    // 没有 principals= 时必须包含用户名；选项与证书的合并
+    let found = check(&cert("ecdsa384", &["alice", "bob"], &[]), &client).0.unwrap();
+    assert_eq!(found.loc, format!("{}:9", KEYS));
+    assert_eq!(found.key, public("ecdsa384"));
+    assert_eq!(found.opts.force_command.as_deref(), Some("/bin/true"));
+    assert!(!found.opts.permit_pty_flag && found.opts.permit_agent_forwarding_flag);
+    let (found, auth_debug) = check(&cert("ecdsa384", &["bob"], &[]), &client);
+    assert_eq!(found, None);
+    assert_eq!(auth_debug[0], "Certificate invalid: name is not a listed principal");
+
+    let force = [CertOption::string("force-command", "/bin/false")];
+    assert_eq!(check(&cert("ecdsa384", &["alice"], &force), &client).1[0], "forced command options do not match");
+    let source = [CertOption::string("source-address", "192.168.0.0/16")];
+    let (found, auth_debug) = check(&cert("ecdsa384", &["alice"], &source), &client);
+    assert_eq!(found, None);
+    assert_eq!(
+        auth_debug[0],
+        format!("{}:9: Your address '10.1.2.3' is not permitted to use this certificate for login.", KEYS)
+    );
+    assert!(check(&cert("ecdsa384", &["alice"], &source), &conn("192.168.1.1", "client")).0.is_some());
+
+    // 没有 cert-authority 的行不接受证书
+    let (found, auth_debug) = check(&cert("ecdsa256", &["alice"], &[]), &client);
+    assert_eq!(found, None);
+    assert!(auth_debug.iter().all(|m| m.contains(": invalid key") || m.contains(": bad key options")));
+}
+
+#[test]
+fn test_user_key_allowed() {
+    let home = scratch("home");
+    let ssh_dir = home.join(".ssh");
+    fs::create_dir(&ssh_dir).unwrap();
+    let keys = ssh_dir.join("authorized_keys");
+    fs::copy(KEYS, &keys).unwrap();
+    chmod(&home, 0o755);
+    chmod(&ssh_dir, 0o700);
+    chmod(&keys, 0o600);
+
+    let pw = alice(&home);
+    let client = conn("10.1.2.3", "client");
+    let mut cfg = AuthKeysConfig::default();
+    let mut auth_debug = Vec::new();
+    let found = user_key_allowed(&cfg, &pw, &public("ed25519"), &client, &mut auth_debug).unwrap();
+    assert_eq!(found.loc, format!("{}:2", keys.display()));
+    assert!(user_key_allowed(&cfg, &pw, &public("ecdsa521"), &client, &mut Vec::new()).is_none());
+
+    // StrictModes
+    chmod(&keys, 0o620);
+    let mut auth_debug = Vec::new();
+    assert!(user_key_allowed(&cfg, &pw, &public("ed25519"), &client, &mut auth_debug).is_none());
+    let canonical = fs::canonicalize(&keys).unwrap();
+    assert_eq!(auth_debug, [format!("Ignored authorized keys: bad ownership or modes for file {}", canonical.display())]);
+    chmod(&keys, 0o600);
+    chmod(&ssh_dir, 0o770);
+    let mut auth_debug = Vec::new();
+    assert!(user_key_allowed(&cfg, &pw, &public("ed25519"), &client, &mut auth_debug).is_none());
+    assert!(auth_debug[0].ends_with(&format!("bad ownership or modes for directory {}", canonical.parent().unwrap().display())));
+    cfg.strict_modes = false;
+    assert!(user_key_allowed(&cfg, &pw, &public("ed25519"), &client, &mut Vec::new()).is_some());
+    chmod(&ssh_dir, 0o700);
+    cfg.strict_modes = true;
+
+    // 模板依次尝试，"none" 与不存在的文件被跳过
+    fs::rename(&keys, ssh_dir.join("keys_alice")).unwrap();
+    assert!(user_key_allowed(&cfg, &pw, &public("ed25519"), &client, &mut Vec::new()).is_none());
+    cfg.authorized_keys_files = vec!["none".to_string(), ".ssh/missing".to_string(), "%h/.ssh/keys_%u".to_string()];
+    let found = user_key_allowed(&cfg, &pw, &public("ed25519"), &client, &mut Vec::new()).unwrap();
+    assert_eq!(found.loc, format!("{}/.ssh/keys_alice:2", home.display()));
+
+    // RevokedKeys：吊销 ed25519 也就吊销了它签发的证书
+    let revoked = home.join("revoked_keys");
+    fs::copy("tests/data/sshkey/ed25519.pub", &revoked).unwrap();
+    cfg.revoked_keys_file = Some(revoked);
+    assert!(user_key_allowed(&cfg, &pw, &public("ed25519"), &client, &mut Vec::new()).is_none());
+    assert!(user_key_allowed(&cfg, &pw, &cert("ed25519", &["deploy"], &[]), &client, &mut Vec::new()).is_none());
+    assert!(user_key_allowed(&cfg, &pw, &public("ecdsa384"), &client, &mut Vec::new()).is_some());
+    cfg.revoked_keys_file = Some(PathBuf::from("tests/data/krl/mixed.krl"));
+    assert!(user_key_allowed(&cfg, &pw, &public("ecdsa384"), &client, &mut Vec::new()).is_none());
+    assert!(user_key_allowed(&cfg, &pw, &cert("ecdsa384", &["alice"], &[]), &client, &mut Vec::new()).is_none());
+    cfg.revoked_keys_file = Some(PathBuf::from("tests/data/krl/serials.krl"));
+    assert!(user_key_allowed(&cfg, &pw, &cert("ecdsa384", &["alice"], &[]), &client, &mut Vec::new()).is_some());
+    // 无法读取的 RevokedKeys 文件使所有密钥被拒绝
+    cfg.revoked_keys_file = Some(home.join("missing"));
+    assert!(user_key_allowed(&cfg, &pw, &public("ecdsa384"), &client, &mut Vec::new()).is_none());
+}
+
+// 以当前用户身份通过 /bin/sh 运行的脚本，参数逐行写入 args
+fn command_script(dir: &Path, body: &str) -> String {
+    let script = dir.join("cmd.sh");
+    fs::write(&script, format!("printf '%s\\n' \"$@\" > {}/args\n{}\n", dir.display(), body)).unwrap();
+    format!("/bin/sh {}", script.display())
+}
+
+fn command_config() -> AuthKeysConfig {
+    let me = Passwd::from_uid(unsafe { libc::getuid() }).unwrap();
+    AuthKeysConfig {
+        authorized_keys_files: vec![],
+        authorized_keys_command_user: Some(me.name.clone()),
+        authorized_principals_command_user: Some(me.name),
+        command_timeout: std::time::Duration::from_secs(10),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_authorized_keys_command() {
+    let dir = scratch("keys_command");
+    let pw = alice(&dir);
+    let key = public("ed25519");
+    let client = ConnInfo {
+        remote_ip: "10.1.2.3".to_string(),
+        remote_host: "client.example.com".to_string(),
+        remote_port: 50022,
+        local_ip: "10.0.0.1".to_string(),
+        local_port: 22,
+    };
+    let mut cfg = command_config();
+    let keys = fs::canonicalize(KEYS).unwrap();
+    let command = command_script(&dir, &format!("cat {}", keys.display()));
+    cfg.authorized_keys_command = Some(format!("{} %u %U %h %t %f %k %C '%%u x'", command));
+    let found = user_key_allowed(&cfg, &pw, &key, &client, &mut Vec::new()).unwrap();
+    assert_eq!(found.loc, format!("{}:2", cfg.authorized_keys_command.as_ref().unwrap()));
+    assert_eq!(found.key, key);
+    let args = fs::read_to_string(dir.join("args")).unwrap();
+    let keytext = key.to_openssh_line("").split(' ').nth(1).unwrap().to_string();
+    let want = [
+        "alice".to_string(),
+        pw.uid.to_string(),
+        dir.display().to_string(),
+        "ssh-ed25519".to_string(),
+        key.fingerprint(FpHash::Sha256),
+        keytext,
+        "10.1.2.3 50022 10.0.0.1 22".to_string(),
+        "%u x".to_string(),
+    ];
+    assert_eq!(args.lines().collect::<Vec<_>>(), want);
+
+    // 输出按 authorized_keys 处理，选项照常生效
+    assert!(user_key_allowed(&cfg, &pw, &public("ecdsa384"), &client, &mut Vec::new()).is_some());
+    assert!(user_key_allowed(&cfg, &pw, &public("ecdsa384"), &conn("192.168.1.1", "client.example.net"), &mut Vec::new()).is_none());
+    assert!(user_key_allowed(&cfg, &pw, &cert("ed25519", &["deploy"], &[]), &client, &mut Vec::new()).is_some());
+
+    // 以失败状态退出、超时或输出过多时不接受任何密钥
+    for body in ["exit 1", "sleep 30", "yes"] {
+        let command = command_script(&dir, &format!("cat {}; {}", keys.display(), body));
+        cfg.authorized_keys_command = Some(command);
+        cfg.command_timeout = std::time::Duration::from_millis(500);
+        cfg.command_max_output = 4096;
+        assert!(user_key_allowed(&cfg, &pw, &key, &client, &mut Vec::new()).is_none(), "{}", body);
+    }
+    cfg.command_timeout = std::time::Duration::from_secs(10);
+    cfg.authorized_keys_command = Some(command_script(&dir, &format!("cat {}", keys.display())));
+    assert!(user_key_allowed(&cfg, &pw, &key, &client, &mut Vec::new()).is_some());
+
+    // 配置错误
+    let good = cfg.clone();
+    cfg.authorized_keys_command_user = None;
+    assert!(user_key_allowed(&cfg, &pw, &key, &client, &mut Vec::new()).is_none());
+    cfg.authorized_keys_command_user = Some("no such user here".to_string());
+    assert!(user_key_allowed(&cfg, &pw, &key, &client, &mut Vec::new()).is_none());
+    cfg = good.clone();
+    cfg.authorized_keys_command = Some(format!("{} 'unterminated", good.authorized_keys_command.as_ref().unwrap()));
+    assert!(user_key_allowed(&cfg, &pw, &key, &client, &mut Vec::new()).is_none());
+    cfg.authorized_keys_command = Some("sh -c true".to_string());
+    assert!(user_key_allowed(&cfg, &pw, &key, &client, &mut Vec::new()).is_none());
+    cfg.authorized_keys_command = Some("  ".to_string());
+    assert!(user_key_allowed(&cfg, &pw, &key, &client, &mut Vec::new()).is_none());
+    // 文件中已找到时不运行命令
+    fs::remove_file(dir.join("args")).unwrap();
+    cfg = good;
+    cfg.authorized_keys_files = vec![keys.display().to_string()];
+    cfg.strict_modes = false;
+    assert!(user_key_allowed(&cfg, &pw, &key, &client, &mut Vec::new()).is_some());
+    assert!(!dir.join("args").exists());
+}
+
+#[test]
+fn test_principals_command() {
+    let dir = scratch("principals_command");
+    let pw = alice(&dir);
+    let client = conn("10.1.2.3", "client.example.com");
+    let key = cert("ed25519", &["deploy", "backup"], &[]);
+    let c = key.cert().unwrap();
+    let mut cfg = command_config();
+    let lines = "# principals\n\n  other\nfrom=\"10.0.0.0/8\",no-pty\tbackup # comment\ndeploy\n";
+    let command = command_script(&dir, &format!("printf '{}'", lines.replace('\n', "\\n")));
+    cfg.authorized_principals_command = Some(format!("{} %u %i %s %t %T %F %K %f", command));
+
+    let opts = match_principals_command(&cfg, &pw, &key, &client, &mut Vec::new()).unwrap();
+    assert_eq!(opts.required_from_host_keys.as_deref(), Some("10.0.0.0/8"));
+    assert!(!opts.permit_pty_flag);
+    let args = fs::read_to_string(dir.join("args")).unwrap();
+    let catext = c.signature_key.to_openssh_line("").split(' ').nth(1).unwrap().to_string();
+    let want = [
+        "alice".to_string(),
+        "test".to_string(),
+        c.serial.to_string(),
+        "ecdsa-sha2-nistp521-cert-v01@openssh.com".to_string(),
+        "ssh-ed25519".to_string(),
+        c.signature_key.fingerprint(FpHash::Sha256),
+        catext,
+        key.fingerprint(FpHash::Sha256),
+    ];
+    assert_eq!(args.lines().collect::<Vec<_>>(), want);
+
+    let deploy_only = cert("ed25519", &["deploy"], &[]);
+    assert_eq!(match_principals_command(&cfg, &pw, &deploy_only, &client, &mut Vec::new()), Some(SshAuthOpt::parse("").unwrap()));
+    assert!(match_principals_command(&cfg, &pw, &cert("ed25519", &["alice"], &[]), &client, &mut Vec::new()).is_none());
+    // 只用于证书
+    assert!(match_principals_command(&cfg, &pw, &public("ed25519"), &client, &mut Vec::new()).is_none());
+
+    let mut auth_debug = Vec::new();
+    let found = process_principals("bogus-option deploy\nother\n".as_bytes(), "(command)", c, &mut auth_debug);
+    assert!(found.is_none());
+    assert_eq!(auth_debug, ["(command):1: bad principals options: unknown key option"]);
+
+    cfg.authorized_principals_command = Some(command_script(&dir, "echo deploy; exit 1"));
+    assert!(match_principals_command(&cfg, &pw, &key, &client, &mut Vec::new()).is_none());
+    cfg.authorized_principals_command = Some(command_script(&dir, "echo deploy"));
+    assert!(match_principals_command(&cfg, &pw, &key, &client, &mut Vec::new()).is_some());
+    cfg.authorized_principals_command_user = None;
+    assert!(match_principals_command(&cfg, &pw, &key, &client, &mut Vec::new()).is_none());
+}
+
+#[test]
+fn test_trusted_user_ca() {
+    let home = scratch("trusted_ca");
+    chmod(&home, 0o755);
+    let ssh_dir = home.join(".ssh");
+    fs::create_dir(&ssh_dir).unwrap();
+    chmod(&ssh_dir, 0o700);
+    let pw = alice(&home);
+    let client = conn("10.1.2.3", "client.example.com");
+    let ca_keys = home.join("trusted_ca");
+    fs::write(&ca_keys, format!("# user CAs\n{}", fs::read_to_string("tests/data/sshkey/ed25519.pub").unwrap())).unwrap();
+    let mut cfg = AuthKeysConfig {
+        authorized_keys_files: vec![],
+        trusted_user_ca_keys: Some(ca_keys.clone()),
+        ..Default::default()
+    };
+    let allowed = |cfg: &AuthKeysConfig, key: &PublicKey, client: &ConnInfo| {
+        let mut auth_debug = Vec::new();
+        let found = user_key_allowed(cfg, &pw, key, client, &mut auth_debug);
+        (found, auth_debug)
+    };
+
+    // 没有 AuthorizedPrincipals 时证书须列出用户名
+    let found = allowed(&cfg, &cert("ed25519", &["alice"], &[]), &client).0.unwrap();
+    assert_eq!(found.loc, ca_keys.display().to_string());
+    assert_eq!(found.key, public("ed25519"));
+    assert_eq!(found.opts, SshAuthOpt::parse("").unwrap());
+    let (found, auth_debug) = allowed(&cfg, &cert("ed25519", &["bob"], &[]), &client);
+    assert!(found.is_none());
+    assert_eq!(auth_debug, ["Certificate invalid: name is not a listed principal"]);
+    let (found, auth_debug) = allowed(&cfg, &cert("ed25519", &[], &[]), &client);
+    assert!(found.is_none());
+    assert_eq!(auth_debug, ["Certificate lacks principal list"]);
+    assert!(allowed(&cfg, &cert("ecdsa384", &["alice"], &[]), &client).0.is_none());
+    assert!(allowed(&cfg, &public("ed25519"), &client).0.is_none());
+    let source = [CertOption::string("source-address", "192.168.0.0/16")];
+    let (found, auth_debug) = allowed(&cfg, &cert("ed25519", &["alice"], &source), &client);
+    assert!(found.is_none());
+    assert_eq!(auth_debug.last().unwrap(), "Refused by certificate options");
+
+    // 主体文件中的名字代替用户名，行上的选项与证书选项合并
+    let principals = ssh_dir.join("principals_alice");
+    fs::write(&principals, "# principals\nfrom=\"10.0.0.0/8\",command=\"/usr/bin/backup\",no-pty backup\ndeploy\n").unwrap();
+    chmod(&principals, 0o600);
+    cfg.authorized_principals_file = Some("%h/.ssh/principals_%u".to_string());
+    let found = allowed(&cfg, &cert("ed25519", &["alice", "backup"], &[]), &client).0.unwrap();
+    assert_eq!(found.opts.force_command.as_deref(), Some("/usr/bin/backup"));
+    assert!(!found.opts.permit_pty_flag);
+    assert!(found.opts.permit_port_forwarding_flag);
+    let cert_opts = [CertOption::string("force-command", "/usr/bin/backup")];
+    assert!(allowed(&cfg, &cert("ed25519", &["backup"], &cert_opts), &client).0.is_some());
+    let (found, auth_debug) = allowed(&cfg, &cert("ed25519", &["backup"], &[]), &conn("192.168.1.1", "client"));
+    assert!(found.is_none());
+    assert_eq!(auth_debug.last().unwrap(), "Refused by certificate principals options");
+    let cert_opts = [CertOption::string("force-command", "/bin/sh")];
+    let (found, auth_debug) = allowed(&cfg, &cert("ed25519", &["backup"], &cert_opts), &client);
+    assert!(found.is_none());
+    assert_eq!(auth_debug, ["forced command options do not match"]);
+    assert_eq!(allowed(&cfg, &cert("ed25519", &["deploy"], &[]), &client).0.unwrap().opts, SshAuthOpt::parse("").unwrap());
+    let (found, auth_debug) = allowed(&cfg, &cert("ed25519", &["alice"], &[]), &client);
+    assert!(found.is_none());
+    assert_eq!(auth_debug, ["Certificate does not contain an authorized principal"]);
+
+    // StrictModes 同样适用于主体文件
+    chmod(&principals, 0o666);
+    let (found, auth_debug) = allowed(&cfg, &cert("ed25519", &["deploy"], &[]), &client);
+    assert!(found.is_none());
+    assert!(auth_debug[0].starts_with("Ignored authorized principals: bad ownership or modes for file"));
+    chmod(&principals, 0o600);
+
+    // 文件中没有匹配时再尝试 AuthorizedPrincipalsCommand
+    let me = Passwd::from_uid(unsafe { libc::getuid() }).unwrap();
+    let script = home.join("principals.sh");
+    fs::write(&script, "echo ops\n").unwrap();
+    cfg.authorized_principals_command = Some(format!("/bin/sh {}", script.display()));
+    cfg.authorized_principals_command_user = Some(me.name);
+    assert!(allowed(&cfg, &cert("ed25519", &["ops"], &[]), &client).0.is_some());
+    assert!(allowed(&cfg, &cert("ed25519", &["deploy"], &[]), &client).0.is_some());
+    cfg.authorized_principals_file = Some("none".to_string());
+    assert!(allowed(&cfg, &cert("ed25519", &["deploy"], &[]), &client).0.is_none());
+    cfg.authorized_principals_command = None;
+    assert!(allowed(&cfg, &cert("ed25519", &["alice"], &[]), &client).0.is_some());
+
+    // 未列出的 CA 仍可通过 authorized_keys 中的 cert-authority 行
+    cfg.trusted_user_ca_keys = Some(home.join("missing"));
+    assert!(allowed(&cfg, &cert("ed25519", &["alice"], &[]), &client).0.is_none());
+    cfg.authorized_keys_files = vec![fs::canonicalize(KEYS).unwrap().display().to_string()];
+    cfg.strict_modes = false;
+    let found = allowed(&cfg, &cert("ed25519", &["deploy"], &[]), &client).0.unwrap();
+    assert!(found.loc.ends_with("authorized_keys:8"));
+}
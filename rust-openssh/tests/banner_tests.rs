@@ -0,0 +1,136 @@
+use std::io::{self, Cursor, Read, Write};
+use std::os::unix::io::AsFd;
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+use rust_openssh::atomicio::Deadline;
+use rust_openssh::compat::*;
+use rust_openssh::kex::banner::*;
+
+// 读取预置数据，写入内容另行收集
+struct Duplex {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+impl Read for Duplex {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for Duplex {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn duplex(input: &[u8]) -> Duplex {
+    Duplex { input: Cursor::new(input.to_vec()), output: Vec::new() }
+}
+
+#[test]
+fn test_parse_banner() {
+    let peer = parse_banner("SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13").unwrap();
+    assert_eq!((peer.major, peer.minor), (2, 0));
+    assert_eq!(peer.software, "OpenSSH_9.6p1");
+    assert_eq!(peer.comments.as_deref(), Some("Ubuntu-3ubuntu13"));
+    assert_eq!(peer.compat, SSH_NEW_OPENSSH);
+
+    let peer = parse_banner("SSH-1.99-3.0 SecureCRT").unwrap();
+    assert_eq!(peer.software, "3.0");
+    assert_eq!(peer.compat, SSH_OLD_SESSIONID);
+
+    assert!(matches!(parse_banner("SSH-1.5-OpenSSH_2.0"), Err(BannerError::ProtocolMismatch { major: 1, minor: 5 })));
+    assert!(matches!(parse_banner("SSH-2.0-"), Err(BannerError::BadIdentification(_))));
+    assert!(matches!(parse_banner("SSH-x.0-foo"), Err(BannerError::BadIdentification(_))));
+}
+
+#[test]
+fn test_compat_table() {
+    assert_eq!(compat_banner("OpenSSH_7.4p1"), SSH_NEW_OPENSSH | SSH_BUG_SIGTYPE | SSH_BUG_SIGTYPE74);
+    assert_eq!(compat_banner("OpenSSH_6.6.1p1"), SSH_NEW_OPENSSH | SSH_BUG_SIGTYPE);
+    assert_eq!(compat_banner("OpenSSH_6.6p1"), SSH_NEW_OPENSSH | SSH_BUG_CURVE25519PAD | SSH_BUG_SIGTYPE);
+    assert_eq!(compat_banner("OpenSSH_3.0.2"), SSH_BUG_EXTEOF | SSH_OLD_FORWARD_ADDR | SSH_BUG_SIGTYPE);
+    assert_eq!(compat_banner("PuTTY_Release_0.63"), SSH_OLD_DHGEX);
+    assert_eq!(compat_banner("PuTTY_Release_0.80"), 0);
+    assert_eq!(compat_banner("WinSCP_release_5.7.4"), SSH_OLD_DHGEX);
+    assert_eq!(compat_banner("WinSCP_release_5.7.5"), 0);
+    assert_eq!(compat_banner("Twisted_22.10.0"), 0);
+    assert_eq!(compat_banner("Twisted"), SSH_BUG_DEBUG);
+}
+
+#[test]
+fn test_client_skips_preamble() {
+    let input = b"Welcome to example.org\nplease behave\r\nSSH-2.0-Cisco-1.25\r\nextra";
+    let mut s = duplex(input);
+    let ours = local_version(Some("test"));
+    assert_eq!(ours, format!("SSH-2.0-{} test", SSH_RELEASE));
+
+    let peer = exchange_identification(&mut s, false, &ours).unwrap();
+    assert_eq!(peer.banner, "SSH-2.0-Cisco-1.25");
+    assert_eq!(peer.compat, SSH_BUG_DHGEX_LARGE | SSH_BUG_HOSTKEYS);
+    assert_eq!(s.output, format!("{}\r\n", ours).into_bytes());
+    // 标识行之后的数据留给包协议层
+    let mut rest = Vec::new();
+    s.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b"extra");
+}
+
+#[test]
+fn test_banner_line_limits() {
+    // 标识行必须以 CR LF 结尾
+    let mut r = Cursor::new(b"SSH-2.0-OpenSSH_9.9\n".to_vec());
+    assert!(matches!(read_peer_banner(&mut r, false), Err(BannerError::BadIdentification(_))));
+
+    let mut r = Cursor::new(b"SSH-2.0-foo\rbar\r\n".to_vec());
+    assert!(matches!(read_peer_banner(&mut r, false), Err(BannerError::InvalidCharacters)));
+
+    let mut r = Cursor::new(b"hello\0\r\n".to_vec());
+    assert!(matches!(read_peer_banner(&mut r, false), Err(BannerError::InvalidCharacters)));
+
+    // 253 字节内容加 CR LF 正好 255 字节
+    let ok = format!("SSH-2.0-{}\r\n", "a".repeat(245));
+    assert_eq!(ok.len(), SSH_MAX_BANNER_LEN);
+    assert!(read_peer_banner(&mut Cursor::new(ok.into_bytes()), false).is_ok());
+    let long = format!("SSH-2.0-{}\r\n", "a".repeat(246));
+    assert!(matches!(read_peer_banner(&mut Cursor::new(long.into_bytes()), false), Err(BannerError::LineTooLong)));
+
+    let many = "junk\r\n".repeat(SSH_MAX_PRE_BANNER_LINES) + "SSH-2.0-x\r\n";
+    assert!(matches!(
+        read_peer_banner(&mut Cursor::new(many.into_bytes()), false),
+        Err(BannerError::TooManyPreambleLines)
+    ));
+
+    let mut r = Cursor::new(b"SSH-2.0-trunc".to_vec());
+    assert!(matches!(read_peer_banner(&mut r, false), Err(BannerError::ConnectionClosed)));
+}
+
+#[test]
+fn test_server_rejects_bad_clients() {
+    let mut s = duplex(b"GET / HTTP/1.1\r\n");
+    assert!(matches!(exchange_identification(&mut s, true, "SSH-2.0-test"), Err(BannerError::BadIdentification(_))));
+    assert_eq!(s.output, b"SSH-2.0-test\r\nInvalid SSH identification string.\r\n");
+
+    let mut s = duplex(b"SSH-1.5-old\r\n");
+    assert!(matches!(exchange_identification(&mut s, true, "SSH-2.0-test"), Err(BannerError::ProtocolMismatch { .. })));
+    assert!(s.output.ends_with(b"Protocol major versions differ.\r\n"));
+
+    let mut s = duplex(b"SSH-2.0-Probe-1.0\r\n");
+    assert!(matches!(exchange_identification(&mut s, true, "SSH-2.0-test"), Err(BannerError::Rejected(_))));
+    // 客户端不会因为 Probe 而拒绝
+    let mut s = duplex(b"SSH-2.0-Probe-1.0\r\n");
+    assert!(exchange_identification(&mut s, false, "SSH-2.0-test").is_ok());
+}
+
+#[test]
+fn test_banner_fd_deadline() {
+    let (a, mut b) = UnixStream::pair().unwrap();
+    b.write_all(b"SSH-2.0-partial").unwrap();
+    let err = read_peer_banner_fd(a.as_fd(), true, &Deadline::after(Duration::from_millis(50))).unwrap_err();
+    assert!(matches!(err, BannerError::TimedOut));
+}
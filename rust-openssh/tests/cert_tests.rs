@@ -0,0 +1,211 @@
+use std::fs;
+use std::slice;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+use rust_openssh::sshbuf::SshBuf;
+use rust_openssh::sshkey::*;
+
+// 2024-01-01T00:00:00Z 与 2034-01-01T00:00:00Z
+const JAN_2024: u64 = 1704067200;
+const JAN_2034: u64 = 2019686400;
+
+fn data(name: &str) -> String {
+    fs::read_to_string(format!("tests/data/sshkey/{}", name)).unwrap()
+}
+
+fn load_public(name: &str) -> (PublicKey, String) {
+    PublicKey::from_openssh_line(&data(name)).unwrap()
+}
+
+// 未加密的 openssh-key-v1 文件：只取出其中的私钥字段
+fn load_private(name: &str) -> PrivateKey {
+    let body: String = data(name).lines().filter(|l| !l.starts_with("-----")).collect();
+    let raw = BASE64.decode(body).unwrap();
+    let mut b = SshBuf::from_vec(raw[b"openssh-key-v1\0".len()..].to_vec());
+    for _ in 0..3 {
+        b.get_string().unwrap();
+    }
+    b.get_u32().unwrap();
+    b.get_string().unwrap();
+    let mut p = SshBuf::from_vec(b.get_string().unwrap().to_vec());
+    p.get_u32().unwrap();
+    p.get_u32().unwrap();
+    PrivateKey::decode_private(&mut p).unwrap()
+}
+
+fn names(options: &[CertOption]) -> Vec<&str> {
+    options.iter().map(|o| o.name.as_str()).collect()
+}
+
+// ssh-keygen -s ed25519 -I alice@example.com -n alice,admin -z 42
+//   -V 20240101000000Z:20340101000000Z -O force-command=... -O source-address=...
+//   -O no-x11-forwarding user.pub
+fn user_cert() -> Certificate {
+    let (key, _) = load_public("user-cert.pub");
+    key.cert().unwrap().clone()
+}
+
+// ssh-keygen -s rsa2048 -h -I host.example.com -n host.example.com,*.example.net
+//   -z 7 -V 20240101000000Z:forever host.pub
+fn host_cert() -> Certificate {
+    let (key, _) = load_public("host-cert.pub");
+    key.cert().unwrap().clone()
+}
+
+#[test]
+fn test_parse_openssh_certificates() {
+    let line = data("user-cert.pub");
+    let (key, comment) = PublicKey::from_openssh_line(&line).unwrap();
+    assert_eq!(key.to_openssh_line(&comment), line.trim_end());
+    assert_eq!(key.name(), "ecdsa-sha2-nistp256-cert-v01@openssh.com");
+    assert_eq!((key.type_name(), key.bits(), comment.as_str()), ("ECDSA-CERT", 256, "ecdsa 256"));
+    // 证书的指纹就是被认证密钥的指纹
+    let (plain, _) = load_public("ecdsa256.pub");
+    assert_eq!(key.key_type(), plain.key_type());
+    assert_eq!(key.plain(), &plain);
+    assert_eq!(key.fingerprint(FpHash::Sha256), "SHA256:4oGixbBvHYvO9lBfj/I5jMOS1rah7eI+9FBkuUfeBlo");
+
+    let cert = key.cert().unwrap();
+    assert_eq!(cert.nonce.len(), 32);
+    assert_eq!((cert.serial, cert.cert_type), (42, CertType::User));
+    assert_eq!(cert.key_id, "alice@example.com");
+    assert_eq!(cert.principals, ["alice", "admin"]);
+    assert_eq!((cert.valid_after, cert.valid_before), (JAN_2024, JAN_2034));
+    assert_eq!(names(&cert.critical_options), ["force-command", "source-address"]);
+    assert_eq!(cert.critical_options[0].value().unwrap(), "/usr/bin/backup --daily");
+    assert_eq!(cert.critical_options[1].value().unwrap(), "10.0.0.0/8,192.168.1.1");
+    assert_eq!(names(&cert.extensions), ["permit-agent-forwarding", "permit-port-forwarding", "permit-pty", "permit-user-rc"]);
+    assert_eq!(cert.extensions[0].value(), None);
+    assert_eq!(cert.signature_key, load_public("ed25519.pub").0);
+    assert_eq!(cert.signature_type().unwrap(), "ssh-ed25519");
+
+    let host = host_cert();
+    assert_eq!((host.serial, host.cert_type, host.key_id.as_str()), (7, CertType::Host, "host.example.com"));
+    assert_eq!(host.valid_before, u64::MAX);
+    assert!(host.critical_options.is_empty() && host.extensions.is_empty());
+    assert_eq!(host.signature_type().unwrap(), "rsa-sha2-512");
+    let key = PublicKey::Cert(Box::new(host));
+    assert_eq!(key.randomart(FpHash::Sha256), data("host-cert.art").trim_end());
+}
+
+#[test]
+fn test_sign_matches_ssh_keygen() {
+    // Ed25519 与 RSA PKCS#1 签名是确定性的：相同的字段与 nonce 应得到相同的证书
+    for (cert, ca, alg) in [(user_cert(), "ed25519", None), (host_cert(), "rsa2048", Some("rsa-sha2-512"))] {
+        let mut req = CertRequest::new(cert.key.clone(), cert.cert_type, &cert.key_id);
+        req.serial = cert.serial;
+        req.principals = cert.principals.clone();
+        req.valid_after = cert.valid_after;
+        req.valid_before = cert.valid_before;
+        req.critical_options = cert.critical_options.clone();
+        req.extensions = cert.extensions.clone();
+        req.nonce = cert.nonce.clone();
+        let ours = req.sign(&load_private(ca), alg).unwrap();
+        assert_eq!(ours.to_blob(), cert.to_blob(), "{}", cert.key_id);
+    }
+}
+
+#[test]
+fn test_issue_certificates() {
+    let ca = PrivateKey::generate(KeyType::Ecdsa(EcdsaCurve::NistP384), None).unwrap();
+    let user = PrivateKey::generate(KeyType::Ed25519, None).unwrap();
+
+    let mut req = CertRequest::new(user.public(), CertType::User, "ci-runner");
+    req.principals = vec!["deploy".to_string()];
+    req.critical_options = vec![CertOption::string("force-command", "/bin/true")];
+    let cert = req.sign(&ca, None).unwrap();
+    assert_eq!(names(&cert.extensions).len(), cert::DEFAULT_USER_EXTENSIONS.len());
+    assert_ne!(cert.nonce, CertRequest::new(user.public(), CertType::User, "x").sign(&ca, None).unwrap().nonce);
+
+    let key = PublicKey::Cert(Box::new(cert.clone()));
+    let parsed = PublicKey::from_blob(&key.to_blob()).unwrap();
+    assert_eq!(parsed, key);
+    let (again, _) = PublicKey::from_openssh_line(&key.to_openssh_line("")).unwrap();
+    assert_eq!(again, key);
+
+    // 证书验证签名时使用被认证的密钥
+    let sig = user.sign(b"session", None).unwrap();
+    assert_eq!(key.verify(&sig, b"session", None).unwrap(), None);
+
+    // 不能为证书再签发证书
+    let nested = CertRequest::new(key, CertType::User, "nested");
+    assert!(matches!(nested.sign(&ca, None), Err(KeyError::InvalidKey(_))));
+
+    // 安全密钥证书
+    let (sk, _) = load_public("sk-ed25519.pub");
+    let cert = CertRequest::new(sk.clone(), CertType::User, "fido").sign(&ca, None).unwrap();
+    let key = PublicKey::from_blob(&cert.to_blob()).unwrap();
+    assert_eq!((key.name(), key.type_name()), ("sk-ssh-ed25519-cert-v01@openssh.com", "ED25519-SK-CERT"));
+    assert_eq!(key.sk_application(), Some("ssh:"));
+    assert_eq!(key.plain(), &sk);
+}
+
+#[test]
+fn test_validate() {
+    let ca = load_public("ed25519.pub").0;
+    let other = load_public("ecdsa256.pub").0;
+    let now = 1_750_000_000;
+    let cert = user_cert();
+    cert.validate("alice", now, &[other.clone(), ca.clone()]).unwrap();
+    cert.validate("admin", JAN_2024, slice::from_ref(&ca)).unwrap();
+
+    let reason = |r: Result<(), KeyError>| match r {
+        Err(KeyError::CertInvalid(reason)) => reason,
+        r => panic!("unexpected {:?}", r),
+    };
+    assert_eq!(reason(cert.validate("bob", now, slice::from_ref(&ca))), "Certificate invalid: name is not a listed principal");
+    assert_eq!(reason(cert.validate("alice", JAN_2024 - 1, slice::from_ref(&ca))), "Certificate invalid: not yet valid");
+    assert_eq!(reason(cert.validate("alice", JAN_2034, slice::from_ref(&ca))), "Certificate invalid: expired");
+    assert_eq!(reason(cert.validate("alice", now, &[other])), "Certificate signed by an untrusted CA");
+    assert_eq!(reason(cert.check_authority(true, false, true, None, now)), "Certificate invalid: not a host certificate");
+
+    // 主机证书的 principal 可以是通配符
+    let host = host_cert();
+    let rsa_ca = load_public("rsa2048.pub").0;
+    host.validate("host.example.com", now, slice::from_ref(&rsa_ca)).unwrap();
+    host.validate("web1.example.net", u64::MAX - 1, slice::from_ref(&rsa_ca)).unwrap();
+    assert!(host.validate("example.net", now, slice::from_ref(&rsa_ca)).is_err());
+    assert_eq!(reason(host.check_authority(false, true, false, Some("alice"), now)), "Certificate invalid: not a user certificate");
+
+    // 没有 principal 列表：用户证书被拒绝，主机证书可用于任何主机名
+    let ca_key = load_private("ed25519");
+    let user = CertRequest::new(load_public("rsa1024.pub").0, CertType::User, "nobody").sign(&ca_key, None).unwrap();
+    assert_eq!(reason(user.validate("alice", now, slice::from_ref(&ca))), "Certificate lacks principal list");
+    user.check_authority(false, false, false, Some("alice"), now).unwrap();
+    let host = CertRequest::new(load_public("rsa1024.pub").0, CertType::Host, "any").sign(&ca_key, None).unwrap();
+    host.validate("anything.example.org", now, &[ca]).unwrap();
+}
+
+#[test]
+fn test_invalid_certificates() {
+    let blob = user_cert().to_blob();
+    let find = |needle: &[u8]| blob.windows(needle.len()).position(|w| w == needle).unwrap();
+
+    // 篡改 key id
+    let mut bad = blob.clone();
+    bad[find(b"alice@example.com")] ^= 0x20;
+    assert!(matches!(PublicKey::from_blob(&bad), Err(KeyError::SignatureInvalid)));
+
+    // 未知的证书类型
+    let mut bad = blob.clone();
+    let at = find(&[0, 0, 0, 0, 0, 0, 0, 42, 0, 0, 0, 1]);
+    bad[at + 11] = 3;
+    assert!(matches!(PublicKey::from_blob(&bad), Err(KeyError::CertInvalid(_))));
+
+    // 截断与尾随数据
+    assert!(matches!(PublicKey::from_blob(&blob[..blob.len() - 1]), Err(KeyError::Format(_))));
+    let mut long = blob.clone();
+    long.push(0);
+    assert!(matches!(PublicKey::from_blob(&long), Err(KeyError::InvalidFormat(_))));
+
+    // 行首的类型名必须与证书一致
+    let line = data("user-cert.pub").replacen("ecdsa-sha2-nistp256-cert-v01@openssh.com", "ecdsa-sha2-nistp256", 1);
+    assert!(matches!(PublicKey::from_openssh_line(&line), Err(KeyError::TypeMismatch)));
+
+    assert_eq!(KeyType::from_cert_name("ssh-rsa-cert-v01@openssh.com"), Some(KeyType::Rsa));
+    assert_eq!(KeyType::from_cert_name("sk-ecdsa-sha2-nistp256-cert-v01@openssh.com"), Some(KeyType::SkEcdsa));
+    assert_eq!(KeyType::from_cert_name("ssh-ed25519@openssh.com-cert-v01@openssh.com"), None);
+    assert_eq!(KeyType::from_cert_name("ssh-dss-cert-v01@openssh.com"), None);
+}
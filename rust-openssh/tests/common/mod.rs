@@ -0,0 +1,38 @@
+// Helpers shared by the integration tests.
+
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// A scratch directory under the system temp dir, removed when dropped so
+/// that failing tests do not leave it behind.
+pub struct ScratchDir(PathBuf);
+
+impl Deref for ScratchDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for ScratchDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Create an empty scratch directory named after the test binary, the
+/// process and `name`.
+pub fn scratch(name: &str) -> ScratchDir {
+    let dir = std::env::temp_dir().join(format!("{}.{}.{}", env!("CARGO_CRATE_NAME"), std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    ScratchDir(dir)
+}
@@ -0,0 +1,139 @@
+use std::fs;
+
+use rust_openssh::cipher::cipher_init;
+use rust_openssh::compress::*;
+use rust_openssh::mac::mac_init;
+use rust_openssh::packet::{Mode, PacketError, PacketState, PACKET_MAX_SIZE};
+use rust_openssh::ssh2::*;
+
+fn unhex(s: &str) -> Vec<u8> {
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+}
+
+// OpenSSH 9.2 客户端（ssh -C）的真实会话：USERAUTH_SUCCESS 之后的包经过压缩，
+// 服务器方向的包由本实现压缩并已被客户端正确解出
+#[test]
+fn test_openssh_sessions() {
+    let mut seen = 0;
+    for entry in fs::read_dir("tests/data/compress").unwrap() {
+        let path = entry.unwrap().path();
+        let t: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let cipher = t["cipher"].as_str().unwrap();
+        let comp = Compression::from_name(t["compression"].as_str().unwrap()).unwrap();
+        for dir in ["c2s", "s2c"] {
+            let keys = &t["keys"][dir];
+            let key = |k: &str| unhex(keys[k].as_str().unwrap());
+            let mac = if cipher.contains("poly1305") { None } else { Some(mac_init(t["mac"].as_str().unwrap(), &key("mac_key")).unwrap()) };
+            let mut state = PacketState::new();
+            state.set_newkeys(Mode::In, cipher_init(cipher, &key("key"), &key("iv"), Mode::In).unwrap(), mac);
+            state.set_compression(Mode::In, comp);
+            let auth_after = t["authenticated_after"][dir].as_u64().unwrap() as usize;
+            for (i, p) in t[dir].as_array().unwrap().iter().enumerate() {
+                if i == auth_after {
+                    state.set_authenticated();
+                }
+                state.feed(&unhex(p["packet"].as_str().unwrap()));
+                assert_eq!(state.decode().unwrap().unwrap(), unhex(p["payload"].as_str().unwrap()), "{:?} {}", path, dir);
+            }
+            let stats = state.info().inbound.compression_stats.unwrap();
+            assert!(stats.raw > 0 && stats.compressed > 0);
+        }
+        seen += 1;
+    }
+    assert_eq!(seen, 2);
+}
+
+#[test]
+fn test_delayed_compression() {
+    let (mut a, mut b) = (PacketState::new(), PacketState::new());
+    a.set_compression(Mode::Out, Compression::Delayed);
+    b.set_compression(Mode::In, Compression::Delayed);
+    assert_eq!(a.info().outbound.compression_stats, None);
+
+    // 认证之前以明文发送
+    let text = b"\x05ssh-userauth".to_vec();
+    let packet = a.encode(&text).unwrap().into_vec();
+    assert_eq!(&packet[5..5 + text.len()], &text[..]);
+    b.feed(&packet);
+    assert_eq!(b.decode().unwrap().unwrap(), text);
+
+    a.set_authenticated();
+    b.set_authenticated();
+    let data: Vec<u8> = [SSH2_MSG_CHANNEL_DATA].iter().chain(b"all work and no play ".repeat(200).iter()).copied().collect();
+    let mut wire = 0;
+    for _ in 0..5 {
+        let packet = a.encode(&data).unwrap().into_vec();
+        wire += packet.len();
+        b.feed(&packet);
+        assert_eq!(b.decode().unwrap().unwrap(), data);
+    }
+    assert!(wire < data.len());
+
+    let out = a.info().outbound;
+    assert_eq!(out.compression, Compression::Delayed);
+    let stats = out.compression_stats.unwrap();
+    assert_eq!(stats.raw, 5 * data.len() as u64);
+    assert!(stats.factor() < 0.1, "factor {}", stats.factor());
+    // 两端统计同一条流
+    assert_eq!(b.info().inbound.compression_stats, Some(stats));
+    assert!(b.info().authenticated);
+}
+
+#[test]
+fn test_zlib_survives_rekey() {
+    let (mut a, mut b) = (PacketState::new(), PacketState::new());
+    let key = [9u8; 64];
+    for round in 0..3 {
+        a.set_newkeys(Mode::Out, cipher_init("aes128-ctr", &key, &key, Mode::Out).unwrap(), Some(mac_init("hmac-sha1", &key).unwrap()));
+        b.set_newkeys(Mode::In, cipher_init("aes128-ctr", &key, &key, Mode::In).unwrap(), Some(mac_init("hmac-sha1", &key).unwrap()));
+        a.set_compression(Mode::Out, Compression::Zlib);
+        b.set_compression(Mode::In, Compression::Zlib);
+        let payload = vec![SSH2_MSG_IGNORE; 100 + round];
+        b.feed(&a.encode(&payload).unwrap().into_vec());
+        assert_eq!(b.decode().unwrap().unwrap(), payload);
+    }
+    // 压缩流跨越重新协商密钥，不重新开始
+    assert_eq!(a.info().outbound.compression_stats.unwrap().raw, 303);
+}
+
+#[test]
+fn test_decompression_bomb() {
+    // 1 MiB 的零压缩后只有 1 KiB 左右，解压时必须在上限处停止
+    let mut c = Compressor::new();
+    let bomb = c.compress(&vec![0u8; 1 << 20]).unwrap();
+    assert!(bomb.len() < 4096);
+    let mut d = Decompressor::new(PACKET_MAX_SIZE);
+    assert!(matches!(d.decompress(&bomb), Err(PacketError::TooLarge(n)) if n > PACKET_MAX_SIZE));
+
+    // 经由 PacketState 同样被拒绝
+    let (mut a, mut b) = (PacketState::new(), PacketState::new());
+    a.set_compression(Mode::Out, Compression::Zlib);
+    b.set_compression(Mode::In, Compression::Zlib);
+    b.feed(&a.encode(&vec![SSH2_MSG_IGNORE; PACKET_MAX_SIZE + 1]).unwrap().into_vec());
+    assert!(matches!(b.decode(), Err(PacketError::TooLarge(_))));
+
+    // 恰好在上限以内的可以解出
+    let mut c = Compressor::new();
+    let mut d = Decompressor::new(64 * 1024);
+    let data = vec![7u8; 64 * 1024 - 1];
+    assert_eq!(d.decompress(&c.compress(&data).unwrap()).unwrap(), data);
+}
+
+#[test]
+fn test_corrupt_stream() {
+    let mut d = Decompressor::new(PACKET_MAX_SIZE);
+    assert!(matches!(d.decompress(b"\x00\x01garbage"), Err(PacketError::Protocol(_))));
+}
+
+#[test]
+fn test_compression_names() {
+    assert_eq!(Compression::from_name("zlib@openssh.com"), Some(Compression::Delayed));
+    assert_eq!(Compression::from_name("zlib"), Some(Compression::Zlib));
+    assert_eq!(Compression::from_name("lz4"), None);
+    assert_eq!(Compression::Delayed.name(), "zlib@openssh.com");
+    assert!(!Compression::Delayed.active(false));
+    assert!(Compression::Zlib.active(false));
+    assert!(compression_valid("none,zlib@openssh.com"));
+    assert!(!compression_valid("none,lz4"));
+    assert_eq!(CompressionStats::default().factor(), 0.0);
+}
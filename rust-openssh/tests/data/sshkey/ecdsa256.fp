@@ -0,0 +1,24 @@
+256 SHA256:4oGixbBvHYvO9lBfj/I5jMOS1rah7eI+9FBkuUfeBlo ecdsa 256 (ECDSA)
+256 MD5:0c:06:0e:15:50:db:e2:b9:82:8c:2b:64:7d:65:d6:4a ecdsa 256 (ECDSA)
++---[ECDSA 256]---+
+|       .         |
+|      + E        |
+|.    o * o       |
+| +   .+ o o      |
+|. + +.o.S.       |
+| + =o= + o       |
+|. =.o*=o. .      |
+| +..*oB+o.       |
+| .o==*+oo.       |
++----[SHA256]-----+
++---[ECDSA 256]---+
+|  o+=.           |
+|   o +           |
+|    + + .        |
+|   . + E .       |
+|  . o = S        |
+|o+ . o .         |
+|=.. o            |
+|.. .             |
+|o                |
++------[MD5]------+
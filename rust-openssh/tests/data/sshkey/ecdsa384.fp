@@ -0,0 +1,24 @@
+384 SHA256:V4LMOk7p8LSO46Rx3pbDknvLjOJLwdJyGpgjRUHPAqI ecdsa 384 (ECDSA)
+384 MD5:29:df:38:b8:d1:35:2b:80:08:e9:41:a7:45:a4:8f:d6 ecdsa 384 (ECDSA)
++---[ECDSA 384]---+
+|o.+.             |
+|oo o   o .       |
+|E o o   + . .    |
+|.= .   o   o     |
+|O = . * S .      |
+|.B . B o .       |
+|. o oo=.         |
+| ..*+B*          |
+| .++***o         |
++----[SHA256]-----+
++---[ECDSA 384]---+
+| .o=             |
+|..=              |
+|o+               |
+|..=. .   .       |
+| +.E. o S o      |
+|.      * + o     |
+|      o * o      |
+|       o o       |
+|      .          |
++------[MD5]------+
@@ -0,0 +1,24 @@
+521 SHA256:Qg4TjmtxsCWA0lVbhIEcTqQBb0r98flAA1qfHY7Eag8 ecdsa 521 (ECDSA)
+521 MD5:59:42:a0:c3:da:88:56:e6:a9:6e:83:0a:c3:91:73:43 ecdsa 521 (ECDSA)
++---[ECDSA 521]---+
+|++++B*+=o.       |
+|o.o%=.=+= .      |
+|..Bo*.+* o       |
+|.o +.E+ o        |
+|. o ..=+S        |
+| .     oo        |
+|         .       |
+|                 |
+|                 |
++----[SHA256]-----+
++---[ECDSA 521]---+
+|      ...        |
+|   . . .         |
+|   E+   . .      |
+| .*+..   +       |
+|.=o=.   S        |
+|o = .            |
+|+o               |
+|++               |
+|+..              |
++------[MD5]------+
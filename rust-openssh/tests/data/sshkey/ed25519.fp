@@ -0,0 +1,24 @@
+256 SHA256:g/2kls0vDzQeYmo2Ie6YSpqpoRxzbQWZ4WkeHLy2zzc ed25519 test key (ED25519)
+256 MD5:e8:c1:5b:12:c0:99:c8:b8:7f:c0:28:84:0e:f5:6c:c3 ed25519 test key (ED25519)
++--[ED25519 256]--+
+|    .o           |
+|    o.*          |
+|     X.          |
+|    oooo         |
+|    o.+.S =      |
+|   ..o.+ % o     |
+|.+ ..oB + *      |
+|=+++.o = E.o     |
+|Ooo .   . .oo    |
++----[SHA256]-----+
++--[ED25519 256]--+
+|.+.o.o           |
+|+.o++.           |
+|++  E .          |
+|+.o. o o         |
+|.. .  = S        |
+|  . .. =         |
+|   .  o          |
+|                 |
+|                 |
++------[MD5]------+
@@ -0,0 +1,24 @@
+1024 SHA256:sYaUpM+zT74a7/eIE2LyY3q+7PQRDvz8p5Lv9pciX4Y no comment (RSA)
+1024 MD5:6d:f0:79:80:44:ac:29:ad:1e:4a:78:a9:a6:71:59:69 no comment (RSA)
++---[RSA 1024]----+
+|      .          |
+|     o .         |
+|    . o .        |
+|     = . o       |
+|      B S        |
+|    . oO..   .   |
+|     ++.*o  E o. |
+|     o=B+++.ooo  |
+|    .**=OO*B+o   |
++----[SHA256]-----+
++---[RSA 1024]----+
+|       oo        |
+|       ...       |
+|     . oo .      |
+|    ..+  + o     |
+| . .Eo  S = .    |
+|. ++o    . .     |
+|.+oo .           |
+|.+. .            |
+|+                |
++------[MD5]------+
@@ -0,0 +1,24 @@
+2048 SHA256:EH77CwKLSk6zFfCkN2wy/5xHVpl7/IC+ADsr6Y5c4bs rsa test key (RSA)
+2048 MD5:38:33:ca:ec:d8:b4:3d:be:eb:a6:d0:35:0a:a1:d8:c1 rsa test key (RSA)
++---[RSA 2048]----+
+|      .          |
+|     . .         |
+| . .  o . o      |
+|  *    o =       |
+| + O..  S +      |
+|  B.+ooo + +     |
+| +.=oo+.o o o    |
+|=.*o+ +o.o . .   |
+|.=oEo=.  .o      |
++----[SHA256]-----+
++---[RSA 2048]----+
+|                 |
+| .               |
+|  E              |
+|.o o   .         |
+|o o   B S        |
+|   = + =         |
+|  . B            |
+|   * oo          |
+|  . ==*+         |
++------[MD5]------+
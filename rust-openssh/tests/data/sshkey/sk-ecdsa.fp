@@ -0,0 +1,24 @@
+256 SHA256:ja77Lsl03QY81vXMrrLhrw8JeWYQSLP46lejCYdESx8 sk ecdsa (ECDSA-SK)
+256 MD5:c4:9e:d9:45:a5:42:0f:3e:a4:51:c7:f6:fe:d7:77:b5 sk ecdsa (ECDSA-SK)
++-[ECDSA-SK 256]--+
+|       .o..      |
+|      o.Eo .  .  |
+|     o.o.o.. . + |
+|      o..o=o.   +|
+|     . .So+++  . |
+|      +oo +=o.  .|
+|     o.=.+ o+  . |
+|     .+.+  ..o.  |
+|      +*o   +*o  |
++----[SHA256]-----+
++-[ECDSA-SK 256]--+
+|        ..=.o..  |
+|       . * =o.   |
+|        + +.+.   |
+|       o + +  .  |
+|        S .  .   |
+|              . .|
+|               .+|
+|               E*|
+|                +|
++------[MD5]------+
@@ -0,0 +1,24 @@
+256 SHA256:mWgwmi9R85ICEzSk8quQs7ms0XDRlmR1Mt9y0uv0OV0 sk ed25519 (ED25519-SK)
+256 MD5:48:5b:d9:dc:a5:95:53:35:19:5d:e4:a5:15:9c:88:c3 sk ed25519 (ED25519-SK)
++[ED25519-SK 256]-+
+|++  o.+ .        |
+|...+ . = o       |
+|= . O   + +      |
+|.+ * * . * .     |
+|. B o + S o     E|
+| = = o   o . o . |
+|= + .     . + .  |
+|o* .         .   |
+|Bo               |
++----[SHA256]-----+
++[ED25519-SK 256]-+
+|          . . =B/|
+|         + E =oB+|
+|      . o o + ...|
+|     . +         |
+|      o S        |
+|                 |
+|                 |
+|                 |
+|                 |
++------[MD5]------+
@@ -0,0 +1,216 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use rust_openssh::hostfile::*;
+use rust_openssh::sshkey::*;
+
+mod common;
+use common::scratch;
+
+const KNOWN_HOSTS: &str = "tests/data/hostfile/known_hosts";
+
+fn public(name: &str) -> PublicKey {
+    PublicKey::from_openssh_line(&fs::read_to_string(format!("tests/data/sshkey/{}.pub", name)).unwrap()).unwrap().0
+}
+
+fn private(name: &str) -> PrivateKey {
+    PrivateKey::from_private_text(&fs::read_to_string(format!("tests/data/sshkey/{}", name)).unwrap(), "").unwrap().0
+}
+
+// ca 签发的主机证书
+fn host_cert(key: &str, ca: &str, principals: &[&str]) -> PublicKey {
+    let mut req = CertRequest::new(public(key), CertType::Host, "host");
+    req.principals = principals.iter().map(|p| p.to_string()).collect();
+    PublicKey::Cert(Box::new(req.sign(&private(ca), None).unwrap()))
+}
+
+fn load(host: &str) -> HostKeys {
+    let mut hostkeys = HostKeys::new();
+    hostkeys.load(host, Path::new(KNOWN_HOSTS)).unwrap();
+    hostkeys
+}
+
+#[test]
+fn test_host_hash() {
+    // 由 OpenSSH 的 ssh-keygen -H 生成
+    let hashed = "|1|6Z0H0XeuqUlcGpu11j29OUDYzgc=|4R8cadzSMwANDw3B6gk0pJ2ajzQ=";
+    let salt = [
+        0xe9, 0x9d, 0x07, 0xd1, 0x77, 0xae, 0xa9, 0x49, 0x5c, 0x1a, 0x9b, 0xb5, 0xd6, 0x3d, 0xbd, 0x39, 0x40, 0xd8, 0xce,
+        0x07,
+    ];
+    assert_eq!(host_hash("hashed.example.com", Some(&salt)).unwrap(), hashed);
+    assert_eq!(match_maybe_hashed("hashed.example.com", hashed), Some(true));
+    assert_eq!(match_maybe_hashed("other.example.com", hashed), Some(false));
+    let fresh = host_hash("hashed.example.com", None).unwrap();
+    assert!(fresh.starts_with(HASH_MAGIC));
+    assert_ne!(fresh, hashed);
+    assert_eq!(match_maybe_hashed("hashed.example.com", &fresh), Some(true));
+    assert_eq!(match_maybe_hashed("x", "|1|bm90IGEgc2FsdA==|AAAA"), None);
+    assert_eq!(match_maybe_hashed("x", "|2|6Z0H0XeuqUlcGpu11j29OUDYzgc=|AAAA"), None);
+
+    assert_eq!(match_maybe_hashed("WWW.Example.com", "*.example.com,!bad.example.com"), Some(true));
+    assert_eq!(match_maybe_hashed("bad.example.com", "*.example.com,!bad.example.com"), Some(false));
+    assert_eq!(match_maybe_hashed("[server.example.com]:2222", "[server.example.com]:2222"), Some(true));
+    assert_eq!(put_host_port("server.example.com", 22), "server.example.com");
+    assert_eq!(put_host_port("server.example.com", 0), "server.example.com");
+    assert_eq!(put_host_port("2001:db8::1", 2222), "[2001:db8::1]:2222");
+}
+
+#[test]
+fn test_hostkeys_foreach() {
+    let mut lines = Vec::new();
+    hostkeys_foreach(Path::new(KNOWN_HOSTS), Some("server.example.com"), Some("192.0.2.7"), |l| {
+        lines.push((l.linenum, l.status, l.matched, l.marker));
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(lines, [
+        (1, LineStatus::Comment, 0, Marker::None),
+        (2, LineStatus::Matched, MATCH_HOST, Marker::None),
+        (3, LineStatus::Matched, MATCH_HOST, Marker::None),
+        (4, LineStatus::Ok, 0, Marker::None),
+        (5, LineStatus::Comment, 0, Marker::None),
+        (6, LineStatus::Ok, 0, Marker::None),
+        (7, LineStatus::Matched, MATCH_IP | MATCH_IP_HASHED, Marker::None),
+        (8, LineStatus::Ok, 0, Marker::CertAuthority),
+        (9, LineStatus::Matched, MATCH_HOST | MATCH_IP, Marker::Revoked),
+        (10, LineStatus::Invalid, 0, Marker::None),
+        (11, LineStatus::Invalid, 0, Marker::None),
+        (12, LineStatus::Invalid, MATCH_HOST, Marker::None),
+    ]);
+
+    // 不指定主机时只解析
+    let mut lines = Vec::new();
+    hostkeys_foreach(Path::new(KNOWN_HOSTS), None, None, |l| {
+        lines.push((l.status, l.hosts.clone(), l.key.clone(), l.comment.clone()));
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(lines[1], (LineStatus::Ok, "server.example.com,10.0.0.5".to_string(), Some(public("ed25519")), "server".to_string()));
+    assert_eq!(lines[10].0, LineStatus::Ok);
+    assert_eq!(lines[11].0, LineStatus::Invalid);
+    assert_eq!(
+        hostkeys_foreach(Path::new("tests/data/hostfile/missing"), None, None, |_| Ok(())).unwrap_err().kind(),
+        std::io::ErrorKind::NotFound
+    );
+}
+
+#[test]
+fn test_check_key() {
+    let hostkeys = load("server.example.com");
+    assert_eq!(hostkeys.entries.iter().map(|e| e.line).collect::<Vec<_>>(), [2, 3, 9]);
+    let (status, found) = hostkeys.check_key(&public("ed25519"));
+    assert_eq!(status, HostStatus::Ok);
+    assert_eq!(found.unwrap().line, 2);
+    assert_eq!(found.unwrap().host, "server.example.com");
+    assert_eq!(hostkeys.check_key(&public("ecdsa256")).0, HostStatus::Ok);
+    // 同类型的其他密钥视为已更改，ECDSA 不区分曲线
+    let (status, found) = hostkeys.check_key(&public("ecdsa384"));
+    assert_eq!(status, HostStatus::Changed);
+    assert_eq!(found.unwrap().line, 3);
+    let other = PrivateKey::generate(KeyType::Ed25519, None).unwrap().public();
+    assert_eq!(hostkeys.check_key(&other).0, HostStatus::Changed);
+    assert_eq!(hostkeys.check_key(&public("rsa2048")), (HostStatus::New, None));
+    assert_eq!(hostkeys.check_key(&public("rsa1024")), (HostStatus::Revoked, None));
+    assert!(hostkeys.lookup_by_type(KeyType::Ecdsa(EcdsaCurve::NistP256), Marker::None).is_some());
+    assert!(hostkeys.lookup_by_type(KeyType::Ecdsa(EcdsaCurve::NistP384), Marker::None).is_none());
+    assert_eq!(hostkeys.lookup_marker(Marker::Revoked).unwrap().line, 9);
+    assert!(hostkeys.lookup_marker(Marker::CertAuthority).is_none());
+
+    assert_eq!(load("bad.example.com").check_key(&public("ecdsa256")).0, HostStatus::New);
+    let hostkeys = load("[server.example.com]:2222");
+    assert_eq!(hostkeys.check_key(&public("ecdsa384")).0, HostStatus::Ok);
+    assert_eq!(hostkeys.check_key(&public("ed25519")).0, HostStatus::New);
+    assert_eq!(load("hashed.example.com").check_key(&public("rsa2048")).0, HostStatus::Ok);
+    assert_eq!(load("192.0.2.7").check_key(&public("rsa2048")).0, HostStatus::Ok);
+
+    // 证书由 @cert-authority 列出的 CA 签发即可
+    let hostkeys = load("www.example.org");
+    let (status, found) = hostkeys.check_key(&host_cert("ecdsa521", "ed25519", &["www.example.org"]));
+    assert_eq!(status, HostStatus::Ok);
+    assert_eq!(found.unwrap().marker, Marker::CertAuthority);
+    assert_eq!(hostkeys.check_key(&host_cert("ecdsa521", "ecdsa256", &["www.example.org"])).0, HostStatus::New);
+    assert_eq!(hostkeys.check_key(&public("ed25519")).0, HostStatus::New);
+    assert_eq!(hostkeys.check_key(&host_cert("ecdsa521", "rsa1024", &[])).0, HostStatus::Revoked);
+    assert_eq!(hostkeys.check_key(&host_cert("rsa1024", "ed25519", &[])).0, HostStatus::Revoked);
+    assert_eq!(load("server.example.com").check_key(&host_cert("ecdsa521", "ed25519", &[])).0, HostStatus::New);
+
+    let mut hostkeys = HostKeys::new();
+    hostkeys.load("server.example.com", Path::new("tests/data/hostfile/missing")).unwrap();
+    assert!(hostkeys.entries.is_empty());
+}
+
+#[test]
+fn test_add_host_to_hostfile() {
+    let dir = scratch("add");
+    let path = dir.join("known_hosts");
+    fs::write(&path, "# no newline").unwrap();
+    add_host_to_hostfile(&path, "Server.Example.COM", &public("ed25519"), false).unwrap();
+    add_host_to_hostfile(&path, "[hashed.example.com]:2222", &public("ecdsa256"), true).unwrap();
+    let text = fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[1], format!("server.example.com {}", public("ed25519").to_openssh_line("")));
+    assert!(lines[2].starts_with(HASH_MAGIC));
+    assert!(text.ends_with('\n'));
+
+    let mut hostkeys = HostKeys::new();
+    hostkeys.load("server.example.com", &path).unwrap();
+    hostkeys.load("[hashed.example.com]:2222", &path).unwrap();
+    assert_eq!(hostkeys.entries.iter().map(|e| e.line).collect::<Vec<_>>(), [2, 3]);
+    assert_eq!(hostkeys.entries[1].host, "[hashed.example.com]:2222");
+
+    let created = dir.join("created");
+    add_host_to_hostfile(&created, "host", &public("ed25519"), false).unwrap();
+    assert_eq!(fs::read_to_string(&created).unwrap(), format!("host {}\n", public("ed25519").to_openssh_line("")));
+}
+
+#[test]
+fn test_hostfile_replace_entries() {
+    let dir = scratch("replace");
+    let path = dir.join("known_hosts");
+    let original = fs::read_to_string(KNOWN_HOSTS).unwrap();
+    fs::write(&path, &original).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+    let keys = [public("ed25519"), public("rsa2048")];
+
+    // 删除该主机的其他密钥，保留标记行与无效行，追加新密钥
+    assert!(hostfile_replace_entries(&path, "server.example.com", Some("10.0.0.5"), &keys, false).unwrap());
+    assert_eq!(fs::read_to_string(dir.join("known_hosts.old")).unwrap(), original);
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+    let text = fs::read_to_string(&path).unwrap();
+    let mut want: Vec<&str> = original.lines().filter(|l| !l.starts_with("*.example.com")).collect();
+    let added = format!("server.example.com,10.0.0.5 {}", public("rsa2048").to_openssh_line(""));
+    want.push(&added);
+    assert_eq!(text.lines().collect::<Vec<_>>(), want);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+    // 没有变化时不改写文件
+    fs::remove_file(dir.join("known_hosts.old")).unwrap();
+    assert!(!hostfile_replace_entries(&path, "server.example.com", Some("10.0.0.5"), &keys, false).unwrap());
+    assert_eq!(fs::read_to_string(&path).unwrap(), text);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    // 只缺地址时补上地址，散列时单独成行
+    assert!(hostfile_replace_entries(&path, "server.example.com", Some("10.9.9.9"), &keys, true).unwrap());
+    let updated = fs::read_to_string(&path).unwrap();
+    let new_lines: Vec<&str> = updated.lines().skip(text.lines().count()).collect();
+    assert_eq!(new_lines.len(), 2);
+    assert!(new_lines.iter().all(|l| l.starts_with(HASH_MAGIC)));
+    let mut hostkeys = HostKeys::new();
+    hostkeys.load("10.9.9.9", &path).unwrap();
+    assert_eq!(hostkeys.check_key(&public("ed25519")).0, HostStatus::Ok);
+    assert_eq!(hostkeys.check_key(&public("rsa2048")).0, HostStatus::Ok);
+
+    // 散列的主机名与地址行都算匹配
+    fs::write(&path, &original).unwrap();
+    assert!(hostfile_replace_entries(&path, "hashed.example.com", Some("192.0.2.7"), &keys[1..], true).unwrap());
+    let text = fs::read_to_string(&path).unwrap();
+    assert_eq!(text.lines().count(), original.lines().count() - 1);
+    assert!(!text.contains("*.example.com"));
+
+    let missing = dir.join("missing");
+    assert!(hostfile_replace_entries(&missing, "host", None, &keys, false).is_err());
+    assert!(!missing.exists());
+}
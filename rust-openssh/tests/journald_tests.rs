@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+
+use log::{Level, LevelFilter, Log, Record};
+use rust_openssh::audit::{AuditBackend, AuditRecord};
+use rust_openssh::journald::*;
+
+mod common;
+use common::{scratch, ScratchDir};
+
+// 套接字放在临时目录中，测试结束后随目录一起删除
+fn bind_journal(name: &str) -> (UnixDatagram, ScratchDir, PathBuf) {
+    let dir = scratch(name);
+    let path = dir.join("socket");
+    (UnixDatagram::bind(&path).unwrap(), dir, path)
+}
+
+// 解析 journal 原生协议，包括二进制长度前缀格式
+fn parse_entry(mut buf: &[u8]) -> HashMap<String, String> {
+    let mut fields = HashMap::new();
+    while !buf.is_empty() {
+        let nl = buf.iter().position(|&b| b == b'\n').unwrap();
+        let line = &buf[..nl];
+        if let Some(eq) = line.iter().position(|&b| b == b'=') {
+            let key = String::from_utf8(line[..eq].to_vec()).unwrap();
+            fields.insert(key, String::from_utf8(line[eq + 1..].to_vec()).unwrap());
+            buf = &buf[nl + 1..];
+        } else {
+            let key = String::from_utf8(line.to_vec()).unwrap();
+            let len = u64::from_le_bytes(buf[nl + 1..nl + 9].try_into().unwrap()) as usize;
+            let value = &buf[nl + 9..nl + 9 + len];
+            fields.insert(key, String::from_utf8(value.to_vec()).unwrap());
+            buf = &buf[nl + 10 + len..];
+        }
+    }
+    fields
+}
+
+fn receive(socket: &UnixDatagram) -> HashMap<String, String> {
+    let mut buf = [0u8; 4096];
+    let n = socket.recv(&mut buf).unwrap();
+    parse_entry(&buf[..n])
+}
+
+#[test]
+fn test_multiline_field_encoding() {
+    let mut buf = Vec::new();
+    journal_append_field(&mut buf, "MESSAGE", "a\nb");
+    assert_eq!(buf, b"MESSAGE\n\x03\x00\x00\x00\x00\x00\x00\x00a\nb\n");
+}
+
+#[test]
+fn test_audit_event_fields() {
+    let (server, _dir, path) = bind_journal("audit");
+    let mut backend = JournalAuditBackend::new(JournalSink::connect(&path));
+    backend
+        .record(&AuditRecord {
+            event: "AUTH_FAIL_PASSWD",
+            code: Some(4),
+            euid: 0,
+            user: Some("alice".to_string()),
+            remote_addr: Some("203.0.113.5".to_string()),
+            remote_port: Some(51000),
+            method: Some("password".to_string()),
+            session_id: None,
+            tty: None,
+            command: None,
+        })
+        .unwrap();
+
+    let fields = receive(&server);
+    assert_eq!(fields["SSH_EVENT"], "AUTH_FAIL_PASSWD");
+    assert_eq!(fields["SSH_USER"], "alice");
+    assert_eq!(fields["SSH_REMOTE_ADDR"], "203.0.113.5");
+    assert_eq!(fields["SYSLOG_IDENTIFIER"], "sshd");
+    assert_eq!(fields["PRIORITY"], "5");
+    assert!(!fields.contains_key("SSH_SESSION_ID"));
+}
+
+#[test]
+fn test_logger_fields() {
+    let (server, _dir, path) = bind_journal("log");
+    let logger = JournalLogger::new(JournalSink::connect(&path), LevelFilter::Info);
+    logger.log(&Record::builder().level(Level::Warn).args(format_args!("bad line\nhere")).build());
+    logger.log(&Record::builder().level(Level::Debug).args(format_args!("filtered")).build());
+
+    let fields = receive(&server);
+    assert_eq!(fields["MESSAGE"], "bad line\nhere");
+    assert_eq!(fields["PRIORITY"], "4");
+    server.set_nonblocking(true).unwrap();
+    assert!(server.recv(&mut [0u8; 16]).is_err());
+}
+
+#[test]
+fn test_fallback_without_journal() {
+    let sink = JournalSink::connect("/nonexistent/journal/socket");
+    assert!(!sink.is_connected());
+    sink.send(&[("MESSAGE", "written to stderr")]).unwrap();
+}
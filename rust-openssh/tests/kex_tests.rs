@@ -0,0 +1,390 @@
+use std::fs;
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use rust_openssh::kex::dh::*;
+use rust_openssh::kex::*;
+use rust_openssh::packet::{Mode, PacketStream};
+use rust_openssh::ssh2::*;
+
+const CLIENT_VERSION: &str = "SSH-2.0-OpenSSH_9.9p1";
+const SERVER_VERSION: &str = "SSH-2.0-OpenSSH_9.9p1 test";
+
+fn unhex(s: &str) -> Vec<u8> {
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+}
+
+// 测试用主机密钥：“签名”为数据本身的哈希，由客户端重新计算比对
+struct TestSigner;
+
+impl HostKeySigner for TestSigner {
+    fn public_key(&self, alg: &str) -> Option<Vec<u8>> {
+        (alg == "ssh-ed25519").then(|| b"test host key".to_vec())
+    }
+
+    fn sign(&mut self, _alg: &str, data: &[u8]) -> Result<Vec<u8>, KexError> {
+        Ok(KexHash::Sha256.digest(data))
+    }
+}
+
+struct TestVerifier;
+
+impl HostKeyVerifier for TestVerifier {
+    fn verify(&mut self, alg: &str, host_key: &[u8], signature: &[u8], data: &[u8]) -> Result<(), KexError> {
+        assert_eq!(alg, "ssh-ed25519");
+        assert_eq!(host_key, b"test host key");
+        if signature != KexHash::Sha256.digest(data) {
+            return Err(KexError::HostKey("incorrect signature".to_string()));
+        }
+        Ok(())
+    }
+}
+
+fn proposal(kex: &str) -> Proposal {
+    let mut p = default_proposal();
+    p[PROPOSAL_KEX_ALGS] = kex.to_string();
+    p[PROPOSAL_SERVER_HOST_KEY_ALGS] = "ssh-ed25519".to_string();
+    p
+}
+
+// 回放 OpenSSH 9.2 客户端与本实现服务器端的真实会话：
+// 复用记录下的随机数与签名，服务器的每条回复、H 与派生密钥都应逐字节一致
+struct RecordedSigner {
+    host_key: Vec<u8>,
+    signature: Vec<u8>,
+    expect: Vec<u8>,
+}
+
+impl HostKeySigner for RecordedSigner {
+    fn public_key(&self, alg: &str) -> Option<Vec<u8>> {
+        (alg == "ssh-ed25519").then(|| self.host_key.clone())
+    }
+
+    fn sign(&mut self, _alg: &str, data: &[u8]) -> Result<Vec<u8>, KexError> {
+        assert_eq!(data, &self.expect[..], "exchange hash");
+        Ok(self.signature.clone())
+    }
+}
+
+#[test]
+fn test_openssh_transcripts() {
+    let mut seen = 0;
+    for entry in fs::read_dir("tests/data/kex").unwrap() {
+        let path = entry.unwrap().path();
+        let t: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let field = |k: &str| unhex(t[k].as_str().unwrap());
+        let kex_name = t["kex"].as_str().unwrap();
+
+        let mut server = Kex::server(
+            t["client_version"].as_str().unwrap(),
+            t["server_version"].as_str().unwrap(),
+            proposal(kex_name),
+            Box::new(RecordedSigner {
+                host_key: field("host_key"),
+                signature: field("signature"),
+                expect: field("exchange_hash"),
+            }),
+        );
+        let mut rng = field("rng").into_iter();
+        server.set_rng(Box::new(move |buf: &mut [u8]| {
+            buf.iter_mut().for_each(|b| *b = rng.next().expect("recorded randomness"));
+            Ok(())
+        }));
+
+        let mut sent = vec![server.kexinit().unwrap()];
+        let mut expected = Vec::new();
+        let mut seqnr = 0;
+        for m in t["messages"].as_array().unwrap() {
+            let payload = unhex(m["payload"].as_str().unwrap());
+            if m["dir"] == "s2c" {
+                expected.push(payload);
+            } else {
+                sent.extend(server.input(seqnr, &payload).unwrap());
+                seqnr += 1;
+            }
+        }
+        assert_eq!(sent, expected, "{}", kex_name);
+
+        let out = server.take_output().unwrap();
+        assert_eq!(out.negotiated.strict, t["strict"].as_bool().unwrap());
+        assert_eq!(out.exchange_hash, field("exchange_hash"));
+        assert_eq!(out.session_id, out.exchange_hash);
+        assert_eq!(out.shared_secret, field("shared_secret"));
+        for (mode, dir) in [(Mode::In, "c2s"), (Mode::Out, "s2c")] {
+            let keys = out.keys(mode);
+            assert_eq!(keys.iv, unhex(t["keys"][dir]["iv"].as_str().unwrap()));
+            assert_eq!(keys.key, unhex(t["keys"][dir]["key"].as_str().unwrap()));
+            assert_eq!(keys.mac_key, unhex(t["keys"][dir]["mac_key"].as_str().unwrap()));
+        }
+        seen += 1;
+    }
+    assert_eq!(seen, 10);
+}
+
+#[test]
+fn test_mlkem768x25519_known_answer() {
+    // OpenSSH 9.9 之前的客户端不支持该方法，向量由独立实现（OpenSSL）生成
+    let t: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string("tests/data/pq/mlkem768x25519-sha256.json").unwrap()).unwrap();
+    let field = |k: &str| unhex(t[k].as_str().unwrap());
+    let mut recorded = field("client_rng").into_iter();
+    let mut rng = move |buf: &mut [u8]| {
+        buf.iter_mut().for_each(|b| *b = recorded.next().expect("recorded randomness"));
+        Ok(())
+    };
+    let (key, public) = pq::mlkem768x25519_keypair(&mut rng).unwrap();
+    assert_eq!(public, field("client_pub"));
+    assert_eq!(pq::mlkem768x25519_dec(&key, &field("server_pub")).unwrap(), field("shared_secret"));
+
+    // 篡改密文时 ML-KEM 隐式拒绝，得到不同的 K
+    let mut bad = field("server_pub");
+    bad[0] ^= 1;
+    assert_ne!(pq::mlkem768x25519_dec(&key, &bad).unwrap(), field("shared_secret"));
+
+    // 服务端对同一公钥封装，客户端解出相同的 K
+    let mut rng = |buf: &mut [u8]| {
+        buf.fill(0x42);
+        Ok(())
+    };
+    let (server_pub, k) = pq::mlkem768x25519_enc(&public, &mut rng).unwrap();
+    assert_eq!(server_pub.len(), pq::MLKEM768_CIPHERTEXT_SIZE + 32);
+    assert_eq!(pq::mlkem768x25519_dec(&key, &server_pub).unwrap(), k);
+}
+
+fn run_pair(client_p: Proposal, server_p: Proposal) -> (Result<KexOutput, KexError>, Result<KexOutput, KexError>) {
+    let (a, b) = UnixStream::pair().unwrap();
+    let server = thread::spawn(move || {
+        let mut conn = PacketStream::new(b);
+        let mut kex = Kex::server(CLIENT_VERSION, SERVER_VERSION, server_p, Box::new(TestSigner));
+        kex.set_moduli(parse_moduli(MODULI));
+        let res = run_kex(&mut conn, &mut kex);
+        if res.is_ok() {
+            assert_eq!(conn.state().seqnr(Mode::In), 0);
+            assert_eq!(conn.state().seqnr(Mode::Out), 0);
+        }
+        res
+    });
+    let mut conn = PacketStream::new(a);
+    let mut kex = Kex::client(CLIENT_VERSION, SERVER_VERSION, client_p, Box::new(TestVerifier));
+    let client = run_kex(&mut conn, &mut kex);
+    drop(conn);
+    (client, server.join().unwrap())
+}
+
+// ssh-keygen -M generate/screen 产出的一个 2048 位安全素数（RFC 3526 group14）
+const MODULI: &str = "# Time Type Tests Tries Size Generator Modulus
+20240101000000 2 6 100 2047 2 \
+FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F14374FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7EDEE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3DC2007CB8A163BF0598DA48361C55D39A69163FA8FD24CF5F83655D23DCA3AD961C62F356208552BB9ED529077096966D670C354E4ABC9804F1746C08CA18217C32905E462E36CE3BE39E772C180E86039B2783A2EC07A28FB5C55DF06F4C52C9DE2BCBF6955817183995497CEA956AE515D2261898FA051015728E5A8AACAA68FFFFFFFFFFFFFFFF
+";
+
+#[test]
+fn test_all_methods_client_server() {
+    for name in KEX_DEFAULT_KEX.split(',') {
+        let (client, server) = run_pair(proposal(name), proposal(name));
+        let (client, server) = (client.unwrap(), server.unwrap());
+        assert_eq!(client.negotiated.kex, KexAlg::from_name(name).unwrap(), "{}", name);
+        assert!(client.negotiated.strict && client.negotiated.ext_info_c && client.negotiated.ext_info_s);
+        assert_eq!(client.exchange_hash, server.exchange_hash, "{}", name);
+        assert_eq!(client.shared_secret, server.shared_secret, "{}", name);
+        assert_eq!(client.keys(Mode::Out), server.keys(Mode::In));
+        assert_eq!(client.keys(Mode::In), server.keys(Mode::Out));
+        assert_ne!(client.keys(Mode::Out), client.keys(Mode::In));
+    }
+}
+
+#[test]
+fn test_negotiation() {
+    assert_eq!(match_list("a,b,c", "c,b"), Some("b".to_string()));
+    assert_eq!(match_list("a", "b"), None);
+
+    let init = |p: Proposal| KexInit { cookie: [0; 16], proposal: p, first_kex_follows: false };
+    let mut c = proposal("curve25519-sha256,ecdh-sha2-nistp256");
+    let mut s = proposal("ecdh-sha2-nistp256,curve25519-sha256");
+    c[PROPOSAL_ENC_ALGS_CTOS] = "aes256-gcm@openssh.com,aes128-ctr".to_string();
+    c[PROPOSAL_MAC_ALGS_CTOS] = "nonexistent".to_string();
+    s[PROPOSAL_ENC_ALGS_STOC] = "aes192-ctr".to_string();
+    s[PROPOSAL_MAC_ALGS_STOC] = "hmac-sha2-512,hmac-sha2-256".to_string();
+    let n = kex_choose(&init(c.clone()), &init(s.clone())).unwrap();
+    // 以客户端的偏好顺序为准；AEAD 不协商 MAC
+    assert_eq!(n.kex, KexAlg::Curve25519Sha256);
+    assert_eq!(n.c2s, NewKeys { enc: "aes256-gcm@openssh.com".into(), mac: None, comp: "none".into() });
+    assert_eq!(n.s2c.enc, "aes192-ctr");
+    assert_eq!(n.s2c.mac.as_deref(), Some("hmac-sha2-256"));
+    assert_eq!(n.we_need, 32);
+    assert!(!n.strict && !n.ext_info_c);
+
+    c[PROPOSAL_ENC_ALGS_CTOS] = "aes128-ctr".to_string();
+    match kex_choose(&init(c.clone()), &init(s.clone())) {
+        Err(KexError::NoMatch { what: "MAC", .. }) => {}
+        other => panic!("{:?}", other),
+    }
+    c[PROPOSAL_KEX_ALGS] = "diffie-hellman-group1-sha1".to_string();
+    assert!(matches!(kex_choose(&init(c), &init(s)), Err(KexError::NoMatch { what: "key exchange method", .. })));
+
+    // 伪算法仅在双方都提供时生效
+    let mut c = proposal("curve25519-sha256,ext-info-c,kex-strict-c-v00@openssh.com");
+    let s = proposal("curve25519-sha256,kex-strict-s-v00@openssh.com");
+    assert!(kex_choose(&init(c.clone()), &init(s.clone())).unwrap().strict);
+    c[PROPOSAL_KEX_ALGS] = "curve25519-sha256,ext-info-c".to_string();
+    let n = kex_choose(&init(c), &init(s)).unwrap();
+    assert!(!n.strict && n.ext_info_c && !n.ext_info_s);
+
+    let list = "curve25519-sha256,curve25519-sha256@libssh.org,diffie-hellman-group-exchange-sha256";
+    assert_eq!(
+        compat_kex_proposal(list, rust_openssh::compat::SSH_BUG_CURVE25519PAD | rust_openssh::compat::SSH_OLD_DHGEX),
+        "curve25519-sha256"
+    );
+}
+
+#[test]
+fn test_kexinit_and_ext_info_encoding() {
+    let init = KexInit { cookie: [7; 16], proposal: default_proposal(), first_kex_follows: true };
+    let payload = init.to_payload();
+    assert_eq!(payload[0], SSH2_MSG_KEXINIT);
+    assert_eq!(KexInit::parse(&payload).unwrap(), init);
+    assert!(KexInit::parse(&payload[..payload.len() - 1]).is_err());
+
+    let payload = ext_info_payload(&[("server-sig-algs", b"ssh-ed25519,rsa-sha2-256"), ("ping@openssh.com", b"0")]);
+    let exts = parse_ext_info(&payload).unwrap();
+    assert_eq!(exts.len(), 2);
+    assert_eq!(exts[0], ("server-sig-algs".to_string(), b"ssh-ed25519,rsa-sha2-256".to_vec()));
+
+    // 超出一个哈希长度时按 K || H || 已有密钥 继续扩展
+    let long = derive_key(KexHash::Sha256, b"K", b"H", b"sid", b'C', 64);
+    assert_eq!(&long[..32], &derive_key(KexHash::Sha256, b"K", b"H", b"sid", b'C', 32)[..]);
+    assert_eq!(&long[32..], &KexHash::Sha256.digest(&[&b"KH"[..], &long[..32]].concat())[..]);
+}
+
+#[test]
+fn test_strict_kex_violations() {
+    // 严格模式下 KEXINIT 必须是第一个包
+    let mut server = Kex::server(CLIENT_VERSION, SERVER_VERSION, proposal("curve25519-sha256"), Box::new(TestSigner));
+    let mut client = Kex::client(CLIENT_VERSION, SERVER_VERSION, proposal("curve25519-sha256"), Box::new(TestVerifier));
+    let ckexinit = client.kexinit().unwrap();
+    server.input(0, &[SSH2_MSG_IGNORE, 0, 0, 0, 0]).unwrap();
+    assert!(matches!(server.input(1, &ckexinit), Err(KexError::Strict(_))));
+
+    // 首次交换期间的 IGNORE 同样是违规
+    let mut server = Kex::server(CLIENT_VERSION, SERVER_VERSION, proposal("curve25519-sha256"), Box::new(TestSigner));
+    server.input(0, &ckexinit).unwrap();
+    assert!(server.strict());
+    assert!(matches!(server.input(1, &[SSH2_MSG_IGNORE, 0, 0, 0, 0]), Err(KexError::Strict(_))));
+
+    // 非严格模式下忽略 IGNORE，对其他消息回复 UNIMPLEMENTED
+    let mut p = proposal("curve25519-sha256");
+    p[PROPOSAL_KEX_ALGS] = "curve25519-sha256".to_string();
+    let mut server = Kex::server(CLIENT_VERSION, SERVER_VERSION, p, Box::new(TestSigner));
+    let plain = KexInit { cookie: [0; 16], proposal: proposal("curve25519-sha256"), first_kex_follows: false };
+    server.input(0, &[SSH2_MSG_IGNORE, 0, 0, 0, 0]).unwrap();
+    let out = server.input(1, &plain.to_payload()).unwrap();
+    assert_eq!(out.len(), 1);
+    assert!(!server.strict());
+    assert_eq!(server.input(2, &[SSH2_MSG_SERVICE_REQUEST]).unwrap(), vec![vec![SSH2_MSG_UNIMPLEMENTED, 0, 0, 0, 2]]);
+    assert!(matches!(server.input(3, &[SSH2_MSG_NEWKEYS]), Err(KexError::Protocol(_))));
+}
+
+#[test]
+fn test_first_kex_packet_follows_wrong_guess() {
+    let mut server = Kex::server(
+        CLIENT_VERSION,
+        SERVER_VERSION,
+        proposal("ecdh-sha2-nistp256,curve25519-sha256"),
+        Box::new(TestSigner),
+    );
+    let guess = KexInit {
+        cookie: [0; 16],
+        proposal: proposal("curve25519-sha256,ecdh-sha2-nistp256"),
+        first_kex_follows: true,
+    };
+    server.input(0, &guess.to_payload()).unwrap();
+    assert_eq!(server.negotiated().unwrap().kex, KexAlg::Curve25519Sha256);
+    // 猜测的 INIT 被丢弃，之后的 INIT 才被处理
+    assert!(server.input(1, &[SSH2_MSG_KEX_ECDH_INIT, 0, 0, 0, 1, 9]).unwrap().is_empty());
+    assert!(matches!(server.input(2, &[SSH2_MSG_KEX_ECDH_INIT, 0, 0, 0, 1, 9]), Err(KexError::InvalidPublicKey)));
+}
+
+#[test]
+fn test_bad_host_signature_and_no_match() {
+    struct BadSigner;
+    impl HostKeySigner for BadSigner {
+        fn public_key(&self, _alg: &str) -> Option<Vec<u8>> {
+            Some(b"test host key".to_vec())
+        }
+        fn sign(&mut self, _alg: &str, _data: &[u8]) -> Result<Vec<u8>, KexError> {
+            Ok(vec![0; 32])
+        }
+    }
+    let (a, b) = UnixStream::pair().unwrap();
+    let server = thread::spawn(move || {
+        let mut conn = PacketStream::new(b);
+        let mut kex = Kex::server(CLIENT_VERSION, SERVER_VERSION, proposal("curve25519-sha256"), Box::new(BadSigner));
+        let _ = run_kex(&mut conn, &mut kex);
+    });
+    let mut conn = PacketStream::new(a);
+    let mut kex = Kex::client(CLIENT_VERSION, SERVER_VERSION, proposal("curve25519-sha256"), Box::new(TestVerifier));
+    assert!(matches!(run_kex(&mut conn, &mut kex), Err(KexError::HostKey(_))));
+    drop(conn);
+    server.join().unwrap();
+
+    let (client, server) = run_pair(proposal("curve25519-sha256"), proposal("ecdh-sha2-nistp256"));
+    assert!(matches!(client, Err(KexError::NoMatch { .. })));
+    assert!(matches!(server, Err(KexError::NoMatch { .. })));
+}
+
+#[test]
+fn test_rekey_keeps_session_id() {
+    let (a, b) = UnixStream::pair().unwrap();
+    let ids = Arc::new(Mutex::new(Vec::new()));
+    let server_ids = ids.clone();
+    let server = thread::spawn(move || {
+        let mut conn = PacketStream::new(b);
+        let mut kex = Kex::server(CLIENT_VERSION, SERVER_VERSION, proposal("curve25519-sha256"), Box::new(TestSigner));
+        for _ in 0..2 {
+            let out = run_kex(&mut conn, &mut kex).unwrap();
+            server_ids.lock().unwrap().push((out.session_id, out.exchange_hash));
+            kex.rekey();
+        }
+    });
+    let mut conn = PacketStream::new(a);
+    let mut kex = Kex::client(CLIENT_VERSION, SERVER_VERSION, proposal("curve25519-sha256"), Box::new(TestVerifier));
+    let first = run_kex(&mut conn, &mut kex).unwrap();
+    kex.rekey();
+    let second = run_kex(&mut conn, &mut kex).unwrap();
+    server.join().unwrap();
+
+    // 重协商时不再附加伪算法，但严格模式保持有效
+    assert!(first.negotiated.ext_info_c && !second.negotiated.ext_info_c);
+    assert!(!second.negotiated.strict);
+
+    assert_eq!(second.session_id, first.exchange_hash);
+    assert_ne!(second.exchange_hash, first.exchange_hash);
+    assert!(kex.strict());
+    let ids = ids.lock().unwrap();
+    assert_eq!(ids[1], (second.session_id.clone(), second.exchange_hash.clone()));
+}
+
+#[test]
+fn test_dh_groups_and_moduli() {
+    let moduli = parse_moduli(MODULI);
+    assert_eq!(moduli.len(), 1);
+    assert_eq!(moduli[0].size, 2048);
+    assert_eq!(moduli[0].group, group14());
+    // 非安全素数类型与未测试条目被忽略
+    assert!(parse_moduli_line("20240101000000 1 6 100 2047 2 FF").is_none());
+    assert!(parse_moduli_line("20240101000000 2 1 100 2047 2 FF").is_none());
+
+    let mut first = |_: usize| 0;
+    assert_eq!(choose_dh(&moduli, 2048, 3072, 8192, &mut first), group14());
+    assert_eq!(choose_dh(&moduli, 3072, 4096, 8192, &mut first).bits(), 8192);
+    assert_eq!(choose_dh(&[], 2048, 2048, 4096, &mut first), group16());
+    assert_eq!(fallback_group(2048), group14());
+    assert_eq!(group18().bits(), 8192);
+    assert_eq!(dh_estimate(128), 3072);
+
+    let g = group14();
+    assert!(!dh_pub_is_valid(&g, &1u32.into()));
+    assert!(!dh_pub_is_valid(&g, &(&g.p - 1u32)));
+    assert!(!dh_pub_is_valid(&g, &(num_bigint::BigUint::from(1u32) << 1000)));
+    assert!(dh_pub_is_valid(&g, &3u32.into()));
+}
@@ -0,0 +1,255 @@
+use std::fs;
+use std::path::Path;
+
+use sha1::{Digest, Sha1};
+
+use rust_openssh::krl::*;
+use rust_openssh::sshkey::*;
+
+mod common;
+use common::scratch;
+
+const DATA: &str = "tests/data";
+
+fn data(name: &str) -> Vec<u8> {
+    fs::read(format!("{}/{}", DATA, name)).unwrap()
+}
+
+fn public(name: &str) -> PublicKey {
+    PublicKey::from_openssh_line(&String::from_utf8(data(&format!("sshkey/{}.pub", name))).unwrap()).unwrap().0
+}
+
+fn private(name: &str) -> PrivateKey {
+    PrivateKey::from_private_text(&String::from_utf8(data(&format!("sshkey/{}", name))).unwrap(), "").unwrap().0
+}
+
+// 由 ca 签发、序列号为 serial 的证书
+fn cert(ca: &PrivateKey, serial: u64, key_id: &str) -> PublicKey {
+    let mut req = CertRequest::new(public("ecdsa521"), CertType::User, key_id);
+    req.serial = serial;
+    PublicKey::Cert(Box::new(req.sign(ca, None).unwrap()))
+}
+
+// 去掉 "# Generated at" 一行，它依赖本地时区
+fn without_date(dump: &str) -> String {
+    dump.lines().filter(|l| !l.starts_with("# Generated at")).map(|l| format!("{}\n", l)).collect()
+}
+
+// mixed.txt 的内容
+fn mixed() -> Krl {
+    let ca = public("ed25519");
+    let mut krl = Krl::new();
+    krl.krl_version = 3;
+    for (lo, hi) in [(1, 1), (5, 9), (32, 32)] {
+        krl.revoke_cert_by_serial_range(Some(&ca), lo, hi).unwrap();
+    }
+    krl.revoke_cert_by_key_id(Some(&ca), "alice@example.com");
+    krl.revoke_key_explicit(&public("ed25519"));
+    krl.revoke_key_sha256(&public("ecdsa256").fingerprint_raw(FpHash::Sha256)).unwrap();
+    krl.revoke_key_sha1(&Sha1::digest(public("ecdsa384").to_blob())).unwrap();
+    krl.revoke_key(&public("rsa2048")).unwrap();
+    krl
+}
+
+// serials.txt 的内容
+fn serials() -> Krl {
+    let mut krl = Krl::new();
+    for (lo, hi) in [
+        (1, 1),
+        (3, 3),
+        (10, 1000),
+        (1002, 1002),
+        (1004, 1010),
+        (5000, 5000),
+        (100000, 100000),
+        (100002, 100002),
+        (0x200000, 0x2000ff),
+        (9000000000, 9000000000),
+    ] {
+        krl.revoke_cert_by_serial_range(None, lo, hi).unwrap();
+    }
+    krl.revoke_cert_by_key_id(None, "bob@example.com");
+    krl.revoke_cert_by_key_id(None, "carol@example.com");
+    krl
+}
+
+#[test]
+fn test_parse_openssh_krls() {
+    // ssh-keygen -k，见 mixed.txt 与 serials.txt 的第一行
+    for (name, ours) in [("mixed", mixed()), ("serials", serials())] {
+        let blob = data(&format!("krl/{}.krl", name));
+        let mut krl = Krl::from_blob(&blob, &[]).unwrap();
+        assert_ne!(krl.generated_date, 0);
+        let expected = String::from_utf8(data(&format!("krl/{}.dump", name))).unwrap();
+        assert_eq!(without_date(&krl.dump()), without_date(&expected), "{}", name);
+
+        krl.generated_date = 0;
+        assert_eq!(krl, ours, "{}", name);
+    }
+}
+
+#[test]
+fn test_generate_matches_ssh_keygen() {
+    for (name, mut ours) in [("mixed", mixed()), ("serials", serials())] {
+        let blob = data(&format!("krl/{}.krl", name));
+        // 生成时间位于魔数、格式版本与 KRL 版本之后
+        ours.generated_date = u64::from_be_bytes(blob[20..28].try_into().unwrap());
+        assert_eq!(ours.to_blob(&[]).unwrap(), blob, "{}", name);
+    }
+
+    let mut krl = Krl::new();
+    krl.comment = "test krl".to_string();
+    let blob = krl.to_blob(&[]).unwrap();
+    assert!(blob.starts_with(KRL_MAGIC));
+    let parsed = Krl::from_blob(&blob, &[]).unwrap();
+    assert_ne!(parsed.generated_date, 0);
+    assert_eq!(parsed.comment, "test krl");
+}
+
+#[test]
+fn test_serial_ranges() {
+    let ca = private("ed25519");
+    let mut krl = Krl::new();
+    krl.revoke_cert_by_serial_range(Some(&ca.public()), 10, 20).unwrap();
+    // 重叠与相邻的区间会合并
+    krl.revoke_cert_by_serial_range(Some(&ca.public()), 15, 30).unwrap();
+    krl.revoke_cert_by_serial(Some(&ca.public()), 31).unwrap();
+    krl.revoke_cert_by_serial_range(Some(&ca.public()), 5, 9).unwrap();
+    krl.revoke_cert_by_serial(Some(&ca.public()), u64::MAX).unwrap();
+    assert!(without_date(&krl.dump()).ends_with("serial: 5-31\nserial: 18446744073709551615\n"));
+
+    for (serial, revoked) in [(4, false), (5, true), (20, true), (31, true), (32, false), (u64::MAX, true)] {
+        assert_eq!(krl.is_revoked(&cert(&ca, serial, "x")), revoked, "{}", serial);
+    }
+    // 序列号 0 从不被吊销，也不能吊销
+    assert!(!krl.is_revoked(&cert(&ca, 0, "x")));
+    assert!(matches!(krl.revoke_cert_by_serial(None, 0), Err(KrlError::InvalidArgument(_))));
+    assert!(krl.revoke_cert_by_serial_range(None, 9, 8).is_err());
+
+    let parsed = Krl::from_blob(&krl.to_blob(&[]).unwrap(), &[]).unwrap();
+    assert!(parsed.is_revoked(&cert(&ca, u64::MAX, "x")));
+    assert!(!parsed.is_revoked(&cert(&ca, 32, "x")));
+}
+
+#[test]
+fn test_is_revoked() {
+    let ca = private("ed25519");
+    let other_ca = private("ecdsa256");
+    let krl = Krl::from_blob(&data("krl/mixed.krl"), &[]).unwrap();
+
+    assert!(krl.is_revoked(&public("ed25519")));
+    assert!(krl.is_revoked(&public("ecdsa256")));
+    assert!(krl.is_revoked(&public("ecdsa384")));
+    assert!(krl.is_revoked(&public("rsa2048")));
+    assert!(!krl.is_revoked(&public("ecdsa521")));
+    assert!(!krl.is_revoked(&public("rsa1024")));
+
+    // 证书所认证的密钥或 CA 本身被吊销时，证书也被吊销
+    assert!(krl.is_revoked(&cert(&ca, 10, "x")));
+    let mut req = CertRequest::new(public("rsa2048"), CertType::User, "x");
+    req.serial = 100;
+    assert!(krl.is_revoked(&PublicKey::Cert(Box::new(req.sign(&private("ecdsa521"), None).unwrap()))));
+    assert!(krl.is_revoked(&cert(&private("rsa2048"), 100, "x")));
+    let user_cert = public("user-cert");
+    assert!(krl.is_revoked(&user_cert));
+
+    // 按序列号与 key ID 吊销只对该 CA 签发的证书有效
+    let mut by_ca = Krl::new();
+    by_ca.revoke_cert_by_serial_range(Some(&ca.public()), 5, 9).unwrap();
+    by_ca.revoke_cert_by_key_id(Some(&ca.public()), "alice@example.com");
+    assert!(by_ca.is_revoked(&cert(&ca, 7, "x")));
+    assert!(!by_ca.is_revoked(&cert(&ca, 10, "x")));
+    assert!(by_ca.is_revoked(&cert(&ca, 0, "alice@example.com")));
+    assert!(!by_ca.is_revoked(&cert(&other_ca, 7, "alice@example.com")));
+
+    // 通配 CA 适用于任何 CA 签发的证书
+    let krl = Krl::from_blob(&data("krl/serials.krl"), &[]).unwrap();
+    for ca in [&ca, &other_ca] {
+        assert!(krl.is_revoked(&cert(ca, 500, "x")));
+        assert!(krl.is_revoked(&cert(ca, 0x2000ff, "x")));
+        assert!(!krl.is_revoked(&cert(ca, 1001, "x")));
+        assert!(krl.is_revoked(&cert(ca, 1001, "carol@example.com")));
+    }
+    // 不是证书的密钥不受证书吊销影响
+    assert!(!krl.is_revoked(&public("ed25519")));
+}
+
+#[test]
+fn test_signed_krl() {
+    let signer = private("ecdsa521");
+    let other = private("rsa2048");
+    let blob = mixed().to_blob(&[signer.clone(), other.clone()]).unwrap();
+    assert_eq!(Krl::from_blob(&blob, &[]).unwrap(), Krl::from_blob(&blob, &[signer.public()]).unwrap());
+    assert!(matches!(
+        Krl::from_blob(&blob, &[public("ecdsa256")]),
+        Err(KrlError::Key(KeyError::SignatureInvalid))
+    ));
+    // mixed() 吊销了 rsa2048，它的签名不算数
+    assert!(matches!(
+        Krl::from_blob(&blob, &[other.public()]),
+        Err(KrlError::Key(KeyError::SignatureInvalid))
+    ));
+    let only_revoked = mixed().to_blob(std::slice::from_ref(&other)).unwrap();
+    assert!(matches!(Krl::from_blob(&only_revoked, &[]), Err(KrlError::Revoked)));
+    // 未签名的 KRL 不受信任签名密钥的限制
+    Krl::from_blob(&mixed().to_blob(&[]).unwrap(), &[public("ecdsa256")]).unwrap();
+
+    // 篡改被签名的内容
+    let signed = serials().to_blob(std::slice::from_ref(&other)).unwrap();
+    let at = signed.windows(3).position(|w| w == b"bob").unwrap();
+    let mut tampered = signed.clone();
+    tampered[at] = b'B';
+    assert!(Krl::from_blob(&tampered, &[]).is_err());
+
+    // 签名之后不能再有其他段
+    let mut trailing = signed.clone();
+    trailing.extend_from_slice(&[2, 0, 0, 0, 0]);
+    assert!(matches!(Krl::from_blob(&trailing, &[]), Err(KrlError::InvalidFormat(_))));
+    // 同一密钥不能签名两次
+    assert!(serials().to_blob(&[other.clone(), other]).and_then(|b| Krl::from_blob(&b, &[])).is_err());
+}
+
+#[test]
+fn test_malformed_krls() {
+    let blob = data("krl/serials.krl");
+    assert!(matches!(Krl::from_blob(b"ssh-ed25519 AAAA", &[]), Err(KrlError::BadMagic)));
+    assert!(Krl::from_blob(&blob[..blob.len() - 1], &[]).is_err());
+    let mut version = blob.clone();
+    version[11] = 2;
+    assert!(matches!(Krl::from_blob(&version, &[]), Err(KrlError::InvalidFormat(_))));
+    let mut section = blob.clone();
+    section.extend_from_slice(&[9, 0, 0, 0, 0]);
+    assert!(matches!(Krl::from_blob(&section, &[]), Err(KrlError::InvalidFormat(_))));
+    // SHA256 段中长度不对的哈希
+    let mut krl = Krl::new();
+    assert!(krl.revoke_key_sha256(&[0; 20]).is_err());
+    assert!(krl.revoke_key_sha1(&[0; 32]).is_err());
+}
+
+#[test]
+fn test_revoked_keys_file() {
+    let dir = scratch("revoked_keys");
+
+    let krl = Path::new("tests/data/krl/mixed.krl");
+    assert!(matches!(RevokedKeys::load(krl).unwrap(), RevokedKeys::Krl(_)));
+    assert!(matches!(check_revoked(&public("ed25519"), krl), Err(KrlError::Revoked)));
+    check_revoked(&public("ecdsa521"), krl).unwrap();
+
+    // 旧式的公钥列表
+    let list = dir.join("revoked_keys");
+    let text = String::from_utf8(data("sshkey/ecdsa521.pub")).unwrap() + "\n# comment\n"
+        + &String::from_utf8(data("sshkey/ed25519.pub")).unwrap();
+    fs::write(&list, text).unwrap();
+    let revoked = RevokedKeys::load(&list).unwrap();
+    assert!(matches!(revoked, RevokedKeys::Keys(ref keys) if keys.len() == 2));
+    assert!(revoked.is_revoked(&public("ecdsa521")));
+    assert!(!revoked.is_revoked(&public("ecdsa256")));
+    // 列出的 CA 签发的证书，以及列出的密钥的证书
+    assert!(revoked.is_revoked(&public("user-cert")));
+    assert!(revoked.is_revoked(&cert(&private("ecdsa256"), 1, "x")));
+
+    // 无法解析的行使整个文件无效，而不是被跳过
+    fs::write(&list, "ssh-ed25519 garbage\n").unwrap();
+    assert!(matches!(check_revoked(&public("ecdsa521"), &list), Err(KrlError::InvalidFormat(_))));
+    assert!(matches!(check_revoked(&public("ecdsa521"), &dir.join("missing")), Err(KrlError::Io(_))));
+}
@@ -0,0 +1,137 @@
+use std::os::unix::net::UnixDatagram;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use rust_openssh::log::*;
+
+mod common;
+use common::scratch;
+
+#[test]
+fn test_level_and_facility_names() {
+    assert_eq!(LogLevel::from_name("debug"), Some(LogLevel::Debug1));
+    assert_eq!(LogLevel::from_name("VERBOSE"), Some(LogLevel::Verbose));
+    assert_eq!(LogLevel::from_name("loud"), None);
+    assert!(LogLevel::Debug3 > LogLevel::Info);
+    assert_eq!(SyslogFacility::from_name("authpriv").unwrap().code(), 10);
+    assert_eq!(SyslogFacility::Local7.name(), "LOCAL7");
+}
+
+#[test]
+fn test_level_prefix() {
+    assert_eq!(LogLevel::Fatal.prefix(false), "fatal: ");
+    assert_eq!(LogLevel::Error.prefix(false), "error: ");
+    assert_eq!(LogLevel::Info.prefix(false), "");
+    assert_eq!(LogLevel::Debug2.prefix(false), "debug2: ");
+    // 输出到 stderr 时错误不加标签，调试信息仍然加
+    assert_eq!(LogLevel::Fatal.prefix(true), "");
+    assert_eq!(LogLevel::Error.prefix(true), "");
+    assert_eq!(LogLevel::Debug1.prefix(true), "debug1: ");
+}
+
+#[test]
+fn test_rfc5424_format() {
+    let config = LogConfig { format: SyslogFormat::Rfc5424, ..LogConfig::default() };
+    let line = syslog_format(&config, LogLevel::Error, "bastion", UNIX_EPOCH + Duration::from_secs(86400), "x");
+    let expected = format!("<35>1 1970-01-02T00:00:00.000000Z bastion sshd {} - - x", std::process::id());
+    assert_eq!(line, expected);
+}
+
+#[test]
+fn test_log_verbose_match() {
+    let config = LogConfig {
+        verbose: vec![
+            "kex.rs:*:1000,*:kex_exchange_identification():*".to_string(),
+            "packet.rs:*send*".to_string(),
+            "auth.rs:rust_openssh::auth():12".to_string(),
+        ],
+        ..LogConfig::default()
+    };
+    // 每个条目作为整体与 "文件:函数():行号" 匹配，逗号分隔的是多个模式
+    assert!(config.log_verbose_match("src/kex.rs", "rust_openssh::kex", 1000));
+    assert!(!config.log_verbose_match("src/kex.rs", "rust_openssh::kex", 999));
+    assert!(config.log_verbose_match("src/sshd.rs", "kex_exchange_identification", 55));
+    assert!(!config.log_verbose_match("src/sshd.rs", "kex_exchange", 55));
+    assert!(config.log_verbose_match("packet.rs", "packet_send2", 7));
+    assert!(!config.log_verbose_match("auth.rs", "packet_send2", 7));
+    assert!(config.log_verbose_match("src/auth.rs", "rust_openssh::auth", 12));
+    assert!(!config.log_verbose_match("src/auth.rs", "rust_openssh::auth", 120));
+    assert!(!LogConfig::default().log_verbose_match("kex.rs", "f", 1));
+}
+
+#[test]
+fn test_syslog_socket_and_verbose_override() {
+    let dir = scratch("syslog");
+    let path = dir.join("log");
+    let server = UnixDatagram::bind(&path).unwrap();
+    let config = LogConfig {
+        facility: SyslogFacility::Local0,
+        verbose: vec!["kex.rs:*".to_string()],
+        ..LogConfig::default()
+    };
+    let logger = SshLogger::with_syslog_socket(config, &path).unwrap();
+
+    logger.sshlog("auth.rs", "f", 1, LogLevel::Debug1, "dropped");
+    logger.sshlog("kex.rs", "f", 1, LogLevel::Debug2, "forced\nline");
+
+    let mut buf = [0u8; 512];
+    let n = server.recv(&mut buf).unwrap();
+    let msg = String::from_utf8_lossy(&buf[..n]).into_owned();
+    assert!(msg.starts_with("<134>"), "{}", msg);
+    assert!(msg.ends_with(&format!("sshd[{}]: forced?line", std::process::id())), "{}", msg);
+    server.set_nonblocking(true).unwrap();
+    assert!(server.recv(&mut buf).is_err());
+}
+
+#[test]
+fn test_ratelimit() {
+    let mut rl = LogRateLimitCtx::new(2, 10, 5, 3);
+    let t0 = Instant::now();
+    let at = |s: u64| t0 + Duration::from_secs(s);
+
+    assert_eq!(rl.consider(at(0)), RateLimit::Log);
+    assert_eq!(rl.consider(at(0)), RateLimit::Log);
+    assert_eq!(rl.consider(at(0)), RateLimit::LogStart);
+    assert_eq!(rl.consider(at(1)), RateLimit::Suppress);
+    assert_eq!(rl.consider(at(2)), RateLimit::Suppress);
+    assert_eq!(rl.consider(at(3)), RateLimit::LogSummary(2));
+    // 事件停止后经过 hysteresis 秒才解除限流
+    assert_eq!(rl.consider(at(5)), RateLimit::Suppress);
+    assert_eq!(rl.consider(at(12)), RateLimit::LogEnd(1));
+    assert_eq!(rl.consider(at(30)), RateLimit::Log);
+
+    let mut off = LogRateLimitCtx::new(0, 0, 0, 0);
+    assert!((0..100).all(|_| off.consider(t0) == RateLimit::Log));
+}
+
+#[test]
+fn test_ratelimit_verbose_uses_caller() {
+    let dir = scratch("ratelimit");
+    let path = dir.join("log");
+    let server = UnixDatagram::bind(&path).unwrap();
+    let config = LogConfig { verbose: vec!["log_tests.rs:log_tests():*".to_string()], ..LogConfig::default() };
+    let logger = SshLogger::with_syslog_socket(config, &path).unwrap();
+
+    // LogVerbose 按调用方的文件和模块匹配，而不是 log.rs
+    let mut rl = LogRateLimitCtx::new(2, 10, 5, 3);
+    let now = Instant::now();
+    rust_openssh::log_ratelimit!(&logger, &mut rl, now, LogLevel::Debug1, "first");
+    rust_openssh::log_ratelimit!(&logger, &mut rl, now, LogLevel::Debug1, "second");
+    rust_openssh::log_ratelimit!(&logger, &mut rl, now, LogLevel::Debug1, "third");
+    rust_openssh::log_ratelimit!(&logger, &mut rl, now, LogLevel::Debug1, "fourth");
+
+    let mut buf = [0u8; 512];
+    let mut got = Vec::new();
+    for _ in 0..3 {
+        let n = server.recv(&mut buf).unwrap();
+        got.push(String::from_utf8_lossy(&buf[..n]).into_owned());
+    }
+    assert!(got[0].ends_with(": first"), "{}", got[0]);
+    assert!(got[1].ends_with(": second"), "{}", got[1]);
+    assert!(got[2].ends_with(": third (rate limiting messages)"), "{}", got[2]);
+    server.set_nonblocking(true).unwrap();
+    assert!(server.recv(&mut buf).is_err());
+
+    // 不匹配的调用位置仍按级别过滤
+    log_ratelimit(&logger, &mut LogRateLimitCtx::new(0, 0, 0, 0), now, "log.rs", "rust_openssh::log", 1, LogLevel::Debug1, "x");
+    assert!(server.recv(&mut buf).is_err());
+}
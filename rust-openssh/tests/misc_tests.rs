@@ -0,0 +1,174 @@
+use rust_openssh::misc::*;
+
+mod common;
+use common::scratch;
+
+#[test]
+fn test_convtime() {
+    assert_eq!(convtime("90"), Some(90));
+    assert_eq!(convtime("1h30m"), Some(5400));
+    assert_eq!(convtime("2W"), Some(1_209_600));
+    assert_eq!(convtime("1d1s"), Some(86_401));
+    assert_eq!(convtime(""), None);
+    assert_eq!(convtime("h"), None);
+    assert_eq!(convtime("1y"), None);
+    assert_eq!(convtime("-5"), None);
+    assert_eq!(convtime("5000w"), None);
+}
+
+#[test]
+fn test_absolute_time() {
+    assert_eq!(parse_absolute_time("20240101Z"), Some(1_704_067_200));
+    assert_eq!(parse_absolute_time("202401011230z"), Some(1_704_112_200));
+    assert_eq!(parse_absolute_time("20240229235959Z"), Some(1_709_251_199));
+    assert_eq!(parse_absolute_time("20230229Z"), None);
+    assert_eq!(parse_absolute_time("2024010Z"), None);
+    assert_eq!(parse_absolute_time("20241301"), None);
+    assert_eq!(parse_absolute_time("2024-01-01"), None);
+    // 本地时间与 UTC 之差不超过一天
+    let local = parse_absolute_time("20240101").unwrap();
+    assert!(local.abs_diff(1_704_067_200) <= 86_400);
+    assert_eq!(format_absolute_time(local), "2024-01-01T00:00:00");
+}
+
+#[test]
+fn test_percent_expand() {
+    let keys = [('h', "/home/alice"), ('u', "alice")];
+    assert_eq!(percent_expand("%h/.ssh/%u_keys", &keys).unwrap(), "/home/alice/.ssh/alice_keys");
+    assert_eq!(percent_expand("100%% %u", &keys).unwrap(), "100% alice");
+    assert_eq!(percent_expand("plain", &[]).unwrap(), "plain");
+    assert!(percent_expand("%x", &keys).unwrap_err().contains("unknown key %x"));
+    assert!(percent_expand("trailing %", &keys).is_err());
+}
+
+#[test]
+fn test_passwd() {
+    let root = Passwd::from_name("root").unwrap();
+    assert_eq!((root.uid, root.gid), (0, 0));
+    assert_eq!(Passwd::from_uid(0).unwrap(), root);
+    assert_eq!(Passwd::from_name("no such user here"), None);
+    assert_eq!(Passwd::from_name("nul\0byte"), None);
+}
+
+#[test]
+fn test_safe_path() {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    let home = scratch("safe_path");
+    let dir = home.join("sub");
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("file");
+    fs::write(&file, "x").unwrap();
+    let chmod = |p: &std::path::Path, mode| fs::set_permissions(p, fs::Permissions::from_mode(mode)).unwrap();
+    chmod(&home, 0o755);
+    chmod(&dir, 0o755);
+    chmod(&file, 0o644);
+    let uid = unsafe { libc::getuid() };
+    let check = |home: Option<&std::path::Path>| safe_path(&file, &fs::metadata(&file).unwrap(), home, uid);
+    let canonical = fs::canonicalize(&file).unwrap();
+
+    check(Some(&home)).unwrap();
+    chmod(&file, 0o664);
+    assert_eq!(check(Some(&home)).unwrap_err(), format!("bad ownership or modes for file {}", canonical.display()));
+    chmod(&file, 0o600);
+    chmod(&dir, 0o757);
+    assert_eq!(
+        check(Some(&home)).unwrap_err(),
+        format!("bad ownership or modes for directory {}", canonical.parent().unwrap().display())
+    );
+    chmod(&dir, 0o755);
+    // 没有主目录时一直检查到根目录，而临时目录通常所有人可写
+    if fs::metadata(std::env::temp_dir()).unwrap().permissions().mode() & 0o022 != 0 {
+        assert!(check(None).unwrap_err().starts_with("bad ownership or modes for directory"));
+    }
+    assert!(safe_path(&dir, &fs::metadata(&dir).unwrap(), Some(&home), uid).unwrap_err().ends_with("is not a regular file"));
+    assert!(safe_path(&home.join("missing"), &fs::metadata(&file).unwrap(), None, uid).unwrap_err().starts_with("realpath"));
+}
+
+#[test]
+fn test_argv_split() {
+    let split = |s: &str| argv_split(s).map(|v| v.iter().map(|a| a.to_string()).collect::<Vec<_>>());
+    assert_eq!(split("  /bin/cmd\t%u  x "), Some(vec!["/bin/cmd".into(), "%u".into(), "x".into()]));
+    assert_eq!(split(r#"a "b c" 'd "e"' f\ g h\"i \x"#), Some(vec![
+        "a".into(), "b c".into(), "d \"e\"".into(), "f g".into(), "h\"i".into(), "\\x".into()
+    ]));
+    assert_eq!(split(r#"a"b"c ''"#), Some(vec!["abc".into(), "".into()]));
+    assert_eq!(split(""), Some(vec![]));
+    assert_eq!(split("a 'b"), None);
+
+    let argv: Vec<String> = vec!["/bin/cmd".into(), "b c".into(), "d\"e".into()];
+    assert_eq!(argv_assemble(&argv), r#"/bin/cmd "b c" d\"e"#);
+    assert_eq!(argv_split(&argv_assemble(&argv)).unwrap(), argv);
+}
+
+#[test]
+fn test_subprocess() {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, Instant};
+
+    let me = Passwd::from_uid(unsafe { libc::getuid() }).unwrap();
+    let sh = |script: &str| vec!["/bin/sh".to_string(), "-c".to_string(), script.to_string(), "sh".to_string(), "arg 1".to_string()];
+    let run = |script: &str| subprocess("Test", &sh(script), &me, Duration::from_secs(10), 1000);
+
+    // 最小环境，stdin 为 /dev/null
+    let out = String::from_utf8(run("echo \"$1\"; cat; echo \"$PATH:$USER:$LOGNAME:$HOME\"; env | grep -c CARGO || true").unwrap()).unwrap();
+    assert_eq!(out, format!("arg 1\n{}:{}:{}:{}\n0\n", PATH_STDPATH, me.name, me.name, me.dir.display()));
+    // 标准错误只写入日志
+    assert_eq!(run("echo out; echo err >&2").unwrap(), b"out\n");
+
+    assert_eq!(run("echo x; exit 3").unwrap_err(), "Test /bin/sh -c \"echo x; exit 3\" sh \"arg 1\" failed, status 3");
+    assert!(run("kill -TERM $$").unwrap_err().ends_with("exited on signal 15"));
+    assert!(run("yes").unwrap_err().ends_with("output exceeds 1000 bytes"));
+    assert_eq!(run("head -c 1000 /dev/zero").unwrap().len(), 1000);
+    // 超时后连同子进程一起杀掉
+    let start = Instant::now();
+    let err = subprocess("Test", &sh("sleep 30 & sleep 30"), &me, Duration::from_millis(300), 1000).unwrap_err();
+    assert!(err.contains("timed out"), "{}", err);
+    let err = subprocess("Test", &sh("exec >&-; sleep 30"), &me, Duration::from_millis(300), 1000).unwrap_err();
+    assert!(err.contains("timed out"), "{}", err);
+    assert!(start.elapsed() < Duration::from_secs(10));
+
+    let argv = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    let err = subprocess("Test", &argv(&["sh", "-c", "true"]), &me, Duration::from_secs(10), 1000).unwrap_err();
+    assert_eq!(err, "Test path is not absolute");
+    let err = subprocess("Test", &argv(&["/nonexistent/cmd"]), &me, Duration::from_secs(10), 1000).unwrap_err();
+    assert!(err.starts_with("Could not stat Test \"/nonexistent/cmd\""));
+    // 程序本身须属于 root 且所在目录不可被他人写入
+    let dir = scratch("subprocess");
+    let script = dir.join("cmd");
+    fs::write(&script, "#!/bin/sh\necho hi\n").unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    let result = subprocess("Test", &[script.display().to_string()], &me, Duration::from_secs(10), 1000);
+    if fs::metadata(std::env::temp_dir()).unwrap().permissions().mode() & 0o022 != 0 || me.uid != 0 {
+        let err = result.unwrap_err();
+        assert!(err.starts_with(&format!("Unsafe Test \"{}\": bad ownership or modes", script.display())), "{}", err);
+    }
+}
+
+#[test]
+fn test_subprocess_concurrent() {
+    use std::time::Duration;
+
+    // 多个线程同时 fork 时子进程不应继承被占用的锁
+    let me = Passwd::from_uid(unsafe { libc::getuid() }).unwrap();
+    let threads: Vec<_> = (0..2)
+        .map(|t| {
+            let me = me.clone();
+            std::thread::spawn(move || {
+                for i in 0..20 {
+                    let argv: Vec<String> = ["/bin/sh", "-c", "echo \"$1\"; echo err >&2", "sh", &format!("{}.{}", t, i)]
+                        .iter()
+                        .map(|s| s.to_string())
+                        .collect();
+                    let out = subprocess("Test", &argv, &me, Duration::from_secs(30), 1000).unwrap();
+                    assert_eq!(out, format!("{}.{}\n", t, i).as_bytes());
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+}
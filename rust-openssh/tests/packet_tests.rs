@@ -0,0 +1,328 @@
+use std::io::{self, IoSlice, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rust_openssh::packet::*;
+use rust_openssh::ssh2::*;
+use rust_openssh::sshbuf::SshBuf;
+
+// 测试用的位置无关 XOR “密码”，分组 16 字节
+struct XorCipher(u8);
+
+impl PacketCipher for XorCipher {
+    fn block_size(&self) -> usize {
+        16
+    }
+
+    fn crypt(&mut self, _seqnr: u32, buf: &mut [u8], aadlen: usize, len: usize) -> Result<(), PacketError> {
+        buf[aadlen..aadlen + len].iter_mut().for_each(|b| *b ^= self.0);
+        Ok(())
+    }
+}
+
+// 测试用 AEAD：XOR 加密，标签为密文与 AAD 的简单校验和
+struct ToyAead {
+    key: u8,
+    encrypt: bool,
+}
+
+impl ToyAead {
+    fn tag(seqnr: u32, data: &[u8]) -> [u8; 4] {
+        let sum = data.iter().fold(seqnr.wrapping_mul(31), |acc, &b| acc.wrapping_mul(257).wrapping_add(b as u32));
+        sum.to_be_bytes()
+    }
+}
+
+impl PacketCipher for ToyAead {
+    fn block_size(&self) -> usize {
+        8
+    }
+
+    fn auth_len(&self) -> usize {
+        4
+    }
+
+    fn crypt(&mut self, seqnr: u32, buf: &mut [u8], aadlen: usize, len: usize) -> Result<(), PacketError> {
+        let end = aadlen + len;
+        if !self.encrypt && buf[end..end + 4] != Self::tag(seqnr, &buf[..end]) {
+            return Err(PacketError::MacInvalid);
+        }
+        buf[aadlen..end].iter_mut().for_each(|b| *b ^= self.key);
+        if self.encrypt {
+            let tag = Self::tag(seqnr, &buf[..end]);
+            buf[end..end + 4].copy_from_slice(&tag);
+        }
+        Ok(())
+    }
+}
+
+struct ToyMac {
+    etm: bool,
+}
+
+impl PacketMac for ToyMac {
+    fn mac_len(&self) -> usize {
+        8
+    }
+
+    fn is_etm(&self) -> bool {
+        self.etm
+    }
+
+    fn compute(&mut self, seqnr: u32, data: &[u8]) -> Vec<u8> {
+        let mut h: u64 = 0xcbf29ce484222325 ^ seqnr as u64;
+        for &b in data {
+            h = (h ^ b as u64).wrapping_mul(0x100000001b3);
+        }
+        h.to_be_bytes().to_vec()
+    }
+}
+
+// 记录每次 write_vectored 调用的切片长度
+#[derive(Default)]
+struct Recorder {
+    calls: Vec<Vec<usize>>,
+    data: Vec<u8>,
+}
+
+impl Read for Recorder {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Ok(0)
+    }
+}
+
+impl Write for Recorder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_vectored(&[IoSlice::new(buf)])
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.calls.push(bufs.iter().map(|b| b.len()).collect());
+        for b in bufs {
+            self.data.extend_from_slice(b);
+        }
+        Ok(bufs.iter().map(|b| b.len()).sum())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn keyed(state: &mut PacketState, etm: bool) {
+    for mode in [Mode::In, Mode::Out] {
+        state.set_newkeys(mode, Box::new(XorCipher(0x5a)), Some(Box::new(ToyMac { etm })));
+    }
+}
+
+#[test]
+fn test_plaintext_framing() {
+    let mut state = PacketState::new();
+    let packet = state.encode(&[SSH2_MSG_KEXINIT, 1, 2, 3]).unwrap().into_vec();
+    // 长度 + 填充长度 + 负载 + 至少 4 字节填充，总长为 8 的倍数
+    assert_eq!(packet.len() % 8, 0);
+    let packlen = u32::from_be_bytes([packet[0], packet[1], packet[2], packet[3]]) as usize;
+    assert_eq!(packlen + 4, packet.len());
+    let padlen = packet[4] as usize;
+    assert!(padlen >= 4);
+    assert_eq!(&packet[5..9], &[SSH2_MSG_KEXINIT, 1, 2, 3]);
+    // 明文阶段填充为零
+    assert!(packet[9..].iter().all(|&b| b == 0));
+    assert_eq!(state.seqnr(Mode::Out), 1);
+
+    // 逐字节输入也能解出完整的包
+    let mut peer = PacketState::new();
+    for (i, b) in packet.iter().enumerate() {
+        let res = peer.decode().unwrap();
+        assert!(res.is_none(), "early packet at byte {}", i);
+        peer.feed(&[*b]);
+    }
+    assert_eq!(peer.decode().unwrap().unwrap(), &[SSH2_MSG_KEXINIT, 1, 2, 3]);
+    assert_eq!(peer.seqnr(Mode::In), 1);
+    assert_eq!(peer.pending_input(), 0);
+}
+
+#[test]
+fn test_encrypted_roundtrip_mac_and_etm() {
+    for etm in [false, true] {
+        let (mut a, mut b) = (PacketState::new(), PacketState::new());
+        keyed(&mut a, etm);
+        keyed(&mut b, etm);
+
+        let payloads: Vec<Vec<u8>> = (0..20).map(|n| vec![SSH2_MSG_CHANNEL_DATA; n * 37 + 1]).collect();
+        let mut wire = Vec::new();
+        for p in &payloads {
+            let packet = a.encode(p).unwrap().into_vec();
+            // EtM 模式下 packet_length 以明文发送，其余部分按分组对齐
+            let body = packet.len() - 8 - if etm { 4 } else { 0 };
+            assert_eq!(body % 16, 0);
+            if etm {
+                assert_eq!(u32::from_be_bytes([packet[0], packet[1], packet[2], packet[3]]) as usize, body);
+            }
+            wire.extend_from_slice(&packet);
+        }
+        b.feed(&wire);
+        for p in &payloads {
+            assert_eq!(&b.decode().unwrap().unwrap(), p);
+        }
+        assert!(b.decode().unwrap().is_none());
+        assert_eq!(b.seqnr(Mode::In), 20);
+        assert_eq!(b.counters(Mode::In).0, 20);
+    }
+}
+
+#[test]
+fn test_mac_failure_and_bad_length() {
+    for etm in [false, true] {
+        let (mut a, mut b) = (PacketState::new(), PacketState::new());
+        keyed(&mut a, etm);
+        keyed(&mut b, etm);
+        let mut packet = a.encode(&[SSH2_MSG_IGNORE, 0, 0, 0, 0]).unwrap().into_vec();
+        let n = packet.len();
+        packet[n - 12] ^= 1;
+        b.feed(&packet);
+        assert!(matches!(b.decode(), Err(PacketError::MacInvalid)));
+    }
+
+    let mut state = PacketState::new();
+    state.feed(&[0x00, 0x10, 0x00, 0x01, 4, 0, 0, 0]);
+    assert!(matches!(state.decode(), Err(PacketError::BadLength(0x100001))));
+
+    // 长度不是分组大小的整数倍
+    let mut state = PacketState::new();
+    state.feed(&[0, 0, 0, 9, 4, 0, 0, 0]);
+    assert!(matches!(state.decode(), Err(PacketError::BadLength(9))));
+
+    // 填充长度小于 4
+    let mut state = PacketState::new();
+    state.feed(&[0, 0, 0, 12, 3, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+    assert!(matches!(state.decode(), Err(PacketError::BadPadding)));
+}
+
+#[test]
+fn test_aead_roundtrip() {
+    let (mut a, mut b) = (PacketState::new(), PacketState::new());
+    a.set_newkeys(Mode::Out, Box::new(ToyAead { key: 7, encrypt: true }), Some(Box::new(ToyMac { etm: false })));
+    b.set_newkeys(Mode::In, Box::new(ToyAead { key: 7, encrypt: false }), None);
+
+    let packet = a.encode(b"\x5ehello").unwrap().into_vec();
+    // AEAD 模式忽略 MAC，只追加 4 字节标签
+    assert_eq!((packet.len() - 4 - 4) % 8, 0);
+    b.feed(&packet);
+    assert_eq!(b.decode().unwrap().unwrap(), b"\x5ehello");
+
+    let mut packet = a.encode(b"\x5eworld").unwrap().into_vec();
+    packet[0] ^= 0x80;
+    b.feed(&packet);
+    assert!(matches!(b.decode(), Err(PacketError::BadLength(_))));
+}
+
+#[test]
+fn test_max_packet_size() {
+    let mut state = PacketState::new();
+    assert!(state.set_max_packet_size(1024).is_err());
+    state.set_max_packet_size(8 * 1024).unwrap();
+    assert!(state.encode(&vec![SSH2_MSG_CHANNEL_DATA; 8000]).is_ok());
+    assert!(matches!(state.encode(&vec![SSH2_MSG_CHANNEL_DATA; 9000]), Err(PacketError::TooLarge(_))));
+}
+
+#[test]
+fn test_rekey_limits() {
+    let mut state = PacketState::new();
+    let now = Instant::now();
+    assert!(!state.need_rekeying(1 << 20, now));
+
+    // RekeyLimit 1K：16 字节分组的密码在 64 个分组后需要重新协商
+    state.set_rekey_limits(1024, Some(Duration::from_secs(3600)));
+    keyed(&mut state, false);
+    let mut sent = 0;
+    while !state.need_rekeying(64, now) {
+        state.encode(&[SSH2_MSG_CHANNEL_DATA; 40]).unwrap();
+        sent += 64;
+    }
+    assert_eq!(sent, 1024);
+    assert_eq!(state.counters(Mode::Out), (16, 64, 1024));
+
+    // 时间间隔到期同样触发
+    let mut state = PacketState::new();
+    state.set_rekey_limits(0, Some(Duration::from_secs(60)));
+    keyed(&mut state, false);
+    state.encode(&[SSH2_MSG_IGNORE]).unwrap();
+    assert!(!state.need_rekeying(16, Instant::now()));
+    assert!(state.need_rekeying(16, Instant::now() + Duration::from_secs(61)));
+
+    // 新密钥重置计数器但不重置序列号
+    keyed(&mut state, false);
+    assert_eq!(state.counters(Mode::Out), (0, 0, 0));
+    assert_eq!(state.seqnr(Mode::Out), 1);
+    state.reset_seqnr(Mode::Out);
+    assert_eq!(state.seqnr(Mode::Out), 0);
+}
+
+#[test]
+fn test_encoded_parts_and_vectored_send() {
+    for etm in [false, true] {
+        let mut state = PacketState::new();
+        keyed(&mut state, etm);
+        let packet = state.encode(&[SSH2_MSG_CHANNEL_DATA; 40]).unwrap();
+        assert_eq!(packet.header().len(), 4);
+        assert_eq!(packet.mac().len(), 8);
+        assert_eq!(packet.len(), 4 + packet.body().len() + 8);
+        let joined = [packet.header(), packet.body(), packet.mac()].concat();
+        assert_eq!(packet.into_vec(), joined);
+
+        // 长度、包体和 MAC 在同一次 writev 中发出
+        let mut conn = PacketStream::new(Recorder::default());
+        keyed(conn.state_mut(), etm);
+        conn.send(&[SSH2_MSG_CHANNEL_DATA; 40]).unwrap();
+        let rec = conn.into_inner();
+        assert_eq!(rec.calls.len(), 1);
+        assert_eq!(rec.calls[0].len(), 3);
+        assert_eq!(rec.calls[0][0], 4);
+        assert_eq!(rec.calls[0][2], 8);
+        assert_eq!(rec.data.len(), joined.len());
+        let mut peer = PacketState::new();
+        keyed(&mut peer, etm);
+        peer.feed(&rec.data);
+        assert_eq!(peer.decode().unwrap().unwrap(), &[SSH2_MSG_CHANNEL_DATA; 40]);
+    }
+
+    // 明文阶段没有 MAC
+    let mut conn = PacketStream::new(Recorder::default());
+    conn.send(&[SSH2_MSG_IGNORE]).unwrap();
+    let rec = conn.into_inner();
+    assert_eq!(rec.calls.len(), 1);
+    assert_eq!(rec.data.len() % 8, 0);
+}
+
+#[test]
+fn test_stream_filters_transport_messages() {
+    let (a, b) = UnixStream::pair().unwrap();
+    let sender = thread::spawn(move || {
+        let mut conn = PacketStream::new(a);
+        conn.send_ignore(b"padding").unwrap();
+        conn.send_debug(false, "remote says hi").unwrap();
+        conn.send(&[SSH2_MSG_SERVICE_REQUEST, 0, 0, 0, 0]).unwrap();
+        let mut unimpl = SshBuf::new();
+        unimpl.put_u8(SSH2_MSG_UNIMPLEMENTED);
+        unimpl.put_u32(3);
+        conn.send(unimpl.as_slice()).unwrap();
+        conn.send_disconnect(SSH2_DISCONNECT_BY_APPLICATION, "bye").unwrap();
+        conn
+    });
+
+    let mut conn = PacketStream::new(b);
+    assert_eq!(conn.read_packet().unwrap(), &[SSH2_MSG_SERVICE_REQUEST, 0, 0, 0, 0]);
+    match conn.read_packet() {
+        Err(PacketError::Disconnected { reason, description }) => {
+            assert_eq!(reason, SSH2_DISCONNECT_BY_APPLICATION);
+            assert_eq!(description, "bye");
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(conn.state().seqnr(Mode::In), 5);
+
+    drop(sender.join().unwrap());
+    assert!(matches!(conn.read_raw(), Err(PacketError::ConnectionClosed)));
+}
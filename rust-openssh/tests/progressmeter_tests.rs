@@ -0,0 +1,70 @@
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+use rust_openssh::bwlimit::BwLimit;
+use rust_openssh::progressmeter::*;
+
+#[test]
+fn test_format_size_and_rate() {
+    assert_eq!(format_size(512), " 512  ");
+    assert_eq!(format_size(9999), "9999  ");
+    assert_eq!(format_size(10_000), "  10KB");
+    assert_eq!(format_size(5 << 20), "5120KB");
+    assert_eq!(format_size(50 << 20), "  50MB");
+    assert_eq!(format_rate(0.0), "  0.0KB");
+    assert_eq!(format_rate(1536.0), "  1.5KB");
+    assert_eq!(format_rate(2.5 * 1024.0 * 1024.0), "  2.5MB");
+}
+
+#[test]
+fn test_progress_meter_lines() {
+    let start = Instant::now();
+    let at = |ms: u64| start + Duration::from_millis(ms);
+    let mut meter = ProgressMeter::with_writer("file.bin", 4 << 20, Vec::new(), 60, start);
+
+    // 一秒内的更新不会重绘
+    assert_eq!(meter.update_at(1 << 20, at(500)), ControlFlow::Continue(()));
+    assert_eq!(meter.update_at(1 << 20, at(1000)), ControlFlow::Continue(()));
+    let _ = meter.update_at(2 << 20, at(2000));
+    meter.finish_at(at(2000));
+    assert_eq!(meter.transferred(), 4 << 20);
+
+    let out = String::from_utf8(meter.into_inner().unwrap()).unwrap();
+    let lines: Vec<&str> = out.trim_end_matches('\n').split('\r').skip(1).collect();
+    assert_eq!(lines.len(), 3);
+    for line in &lines {
+        assert_eq!(line.len(), 59);
+        assert!(line.starts_with("file.bin "));
+    }
+    assert!(lines[0].ends_with(" 50% 2048KB   2.0MB/s    00:01 ETA"), "{}", lines[0]);
+    assert!(lines[2].ends_with("100% 4096KB   2.0MB/s    00:02    "), "{}", lines[2]);
+    assert!(out.ends_with('\n'));
+}
+
+#[test]
+fn test_progress_meter_truncates_name() {
+    let start = Instant::now();
+    let name = "x".repeat(100);
+    let mut meter = ProgressMeter::with_writer(&name, 0, Vec::new(), 50, start);
+    meter.finish_at(start);
+    let out = String::from_utf8(meter.into_inner().unwrap()).unwrap();
+    assert_eq!(out.trim_end().len(), 50);
+    assert!(out.contains("100%"));
+}
+
+#[test]
+fn test_bwlimit_token_bucket() {
+    let start = Instant::now();
+    let at = |ms: u64| start + Duration::from_millis(ms);
+    // 8 Kbit/s = 1024 字节/秒，突发 1024 字节
+    let mut bw = BwLimit::new(8, 1024);
+    assert_eq!(bw.rate(), 1024.0);
+
+    assert_eq!(bw.delay(1024, at(0)), Duration::ZERO);
+    assert_eq!(bw.delay(512, at(0)), Duration::from_millis(500));
+    // 等待结束后令牌补足到 0，再发送 256 字节需 250ms
+    assert_eq!(bw.delay(256, at(500)), Duration::from_millis(250));
+    // 空闲很久后最多累积 burst 个令牌
+    assert_eq!(bw.delay(1024, at(60_000)), Duration::ZERO);
+    assert_eq!(bw.delay(1, at(60_000)), Duration::from_secs_f64(1.0 / 1024.0));
+}
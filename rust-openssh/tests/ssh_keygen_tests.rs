@@ -0,0 +1,347 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use rust_openssh::auth_options::SshAuthOpt;
+use rust_openssh::sshkey::*;
+
+mod common;
+use common::scratch;
+
+const KEYGEN: &str = env!("CARGO_BIN_EXE_ssh-keygen");
+const DATA: &str = "tests/data/sshkey";
+
+fn data(name: &str) -> String {
+    fs::read_to_string(format!("{}/{}", DATA, name)).unwrap()
+}
+
+fn keygen(args: &[&str]) -> Output {
+    Command::new(KEYGEN).args(args).env("TZ", "UTC").output().unwrap()
+}
+
+// 把 input 写到标准输入
+fn keygen_stdin(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(KEYGEN)
+        .args(args)
+        .env("TZ", "UTC")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+// 成功时返回标准输出
+fn run(args: &[&str]) -> String {
+    let out = keygen(args);
+    assert!(out.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&out.stderr));
+    String::from_utf8(out.stdout).unwrap()
+}
+
+fn fail(args: &[&str]) -> String {
+    let out = keygen(args);
+    assert_eq!(out.status.code(), Some(255), "{:?}", args);
+    String::from_utf8(out.stderr).unwrap()
+}
+
+// 复制测试密钥；私钥文件必须是 0600
+fn copy_key(dir: &Path, name: &str) -> String {
+    let path = dir.join(name);
+    fs::copy(format!("{}/{}", DATA, name), &path).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn test_generate_and_print() {
+    let dir = scratch("generate");
+    for (ktype, bits, expect) in [("ed25519", None, "256"), ("ecdsa", Some("384"), "384"), ("rsa", Some("1024"), "1024")] {
+        let path = dir.join(format!("id_{}", ktype));
+        let path = path.to_str().unwrap();
+        let mut args = vec!["-q", "-t", ktype, "-N", "", "-C", "test key", "-f", path];
+        if let Some(bits) = bits {
+            args.extend(["-b", bits]);
+        }
+        assert_eq!(run(&args), "");
+        assert_eq!(fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        let public = fs::read_to_string(format!("{}.pub", path)).unwrap();
+        assert_eq!(run(&["-y", "-f", path]), public);
+        let (key, comment) = PublicKey::from_openssh_line(&public).unwrap();
+        assert_eq!(comment, "test key");
+        let fp = run(&["-l", "-f", path]);
+        assert_eq!(fp, format!("{} {} test key ({})\n", expect, key.fingerprint(FpHash::Sha256), key.type_name()));
+    }
+
+    // 不带 -q 时输出指纹与 randomart
+    let path = dir.join("verbose");
+    let out = run(&["-t", "ed25519", "-N", "", "-C", "me@example.com", "-f", path.to_str().unwrap()]);
+    let key = PublicKey::from_openssh_line(&fs::read_to_string(dir.join("verbose.pub")).unwrap()).unwrap().0;
+    assert!(out.starts_with("Generating public/private ed25519 key pair.\n"));
+    assert!(out.contains(&format!("The key fingerprint is:\n{} me@example.com\n", key.fingerprint(FpHash::Sha256))));
+    assert!(out.ends_with(&format!("{}\n", key.randomart(FpHash::Sha256))));
+
+    assert!(fail(&["-t", "dsa", "-N", "", "-f", dir.join("dsa").to_str().unwrap()]).contains("unknown key type dsa"));
+    assert!(fail(&["-q", "-t", "rsa", "-b", "512", "-N", "", "-f", dir.join("small").to_str().unwrap()]).contains("512"));
+}
+
+#[test]
+fn test_fingerprints() {
+    // 与 ssh-keygen -l [-v] [-E md5] 的输出一致
+    for name in ["ed25519", "ecdsa384", "rsa2048"] {
+        let fp = data(&format!("{}.fp", name));
+        let lines: Vec<&str> = fp.lines().collect();
+        let path = format!("{}/{}.pub", DATA, name);
+        assert_eq!(run(&["-l", "-f", &path]), format!("{}\n", lines[0]));
+        assert_eq!(run(&["-l", "-E", "md5", "-f", &path]), format!("{}\n", lines[1]));
+        assert_eq!(run(&["-lv", "-f", &path]), format!("{}\n{}\n", lines[0], lines[2..13].join("\n")));
+    }
+
+    // authorized_keys 中带选项的行
+    let dir = scratch("fingerprints");
+    let keys = dir.join("authorized_keys");
+    let ed25519 = data("ed25519.pub");
+    fs::write(&keys, format!("# comment\n\ncommand=\"echo hi there\",no-pty {}{}", ed25519, data("rsa2048.pub"))).unwrap();
+    let out = run(&["-l", "-f", keys.to_str().unwrap()]);
+    assert_eq!(out.lines().count(), 2);
+    assert!(out.starts_with(data("ed25519.fp").lines().next().unwrap()));
+    fs::write(&keys, "not a key\n").unwrap();
+    assert!(fail(&["-l", "-f", keys.to_str().unwrap()]).contains("is not a public key file"));
+}
+
+#[test]
+fn test_change_passphrase() {
+    let dir = scratch("passphrase");
+    let path = copy_key(&dir, "ed25519");
+    assert_eq!(run(&["-p", "-P", "", "-N", "s3cret", "-a", "2", "-f", &path]), "Your identification has been saved with the new passphrase.\n");
+    let public = data("ed25519.pub");
+    assert_eq!(run(&["-y", "-P", "s3cret", "-f", &path]), public);
+    assert!(fail(&["-y", "-P", "wrong", "-f", &path]).contains("incorrect passphrase supplied to decrypt private key"));
+    assert!(fail(&["-p", "-P", "wrong", "-N", "", "-f", &path]).contains("Failed to load key"));
+
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+    assert!(fail(&["-y", "-P", "s3cret", "-f", &path]).contains("UNPROTECTED PRIVATE KEY FILE"));
+}
+
+#[test]
+fn test_convert() {
+    let rsa = format!("{}/rsa2048.pub", DATA);
+    let formats = format!("{}/formats", DATA);
+    assert_eq!(run(&["-e", "-m", "PKCS8", "-f", &rsa]), data("formats/rsa2048.spki"));
+    assert_eq!(run(&["-e", "-m", "pem", "-f", &rsa]), data("formats/rsa2048.pkcs1"));
+    let ssh2 = run(&["-e", "-f", &rsa]);
+    assert!(ssh2.starts_with("---- BEGIN SSH2 PUBLIC KEY ----\nComment: \"2048-bit RSA, converted by "));
+    assert!(ssh2.lines().nth(1).unwrap().len() <= "Comment: \"\"".len() + 60);
+
+    let plain = |line: &str| line.split_whitespace().take(2).collect::<Vec<_>>().join(" ") + "\n";
+    assert_eq!(run(&["-i", "-f", &format!("{}/rsa2048.rfc4716", formats)]), plain(&data("rsa2048.pub")));
+    assert_eq!(run(&["-i", "-m", "PKCS8", "-f", &format!("{}/ecdsa256.spki", formats)]), plain(&data("ecdsa256.pub")));
+    assert_eq!(run(&["-i", "-m", "PEM", "-f", &format!("{}/rsa2048.pkcs1", formats)]), plain(&data("rsa2048.pub")));
+
+    assert!(fail(&["-i", "-f", &rsa]).contains("SSH2 PUBLIC KEY"));
+    assert!(fail(&["-e", "-m", "PEM", "-f", &format!("{}/ed25519.pub", DATA)]).contains("unsupported"));
+    assert!(fail(&["-e", "-m", "DER", "-f", &rsa]).contains("Unsupported conversion format"));
+}
+
+#[test]
+fn test_sign_certificates() {
+    let dir = scratch("certs");
+    let ca = copy_key(&dir, "ed25519");
+    let rsa_ca = copy_key(&dir, "rsa2048");
+    let user = copy_key(&dir, "ecdsa256.pub");
+    let host = copy_key(&dir, "rsa1024.pub");
+
+    let out = keygen(&[
+        "-s", &ca, "-I", "alice@example.com", "-n", "alice,admin", "-z", "42",
+        "-V", "20240101Z:20340101Z", "-O", "no-x11-forwarding", "-O", "force-command=/usr/bin/backup --daily",
+        "-O", "source-address=10.0.0.0/8,192.168.1.1", "-O", "extension:login@example.com=alice", &user,
+    ]);
+    assert!(out.status.success());
+    let cert_path = dir.join("ecdsa256-cert.pub");
+    assert_eq!(
+        String::from_utf8(out.stderr).unwrap(),
+        format!(
+            "Signed user key {}: id \"alice@example.com\" serial 42 for alice,admin valid from 2024-01-01T00:00:00 to 2034-01-01T00:00:00\n",
+            cert_path.display()
+        )
+    );
+    let (key, comment) = PublicKey::from_openssh_line(&fs::read_to_string(&cert_path).unwrap()).unwrap();
+    assert_eq!(comment, "ecdsa 256");
+    // 与 ssh-keygen 签发的 user-cert.pub 字段相同，只多了一个自定义扩展
+    let cert = key.cert().unwrap();
+    let reference = PublicKey::from_openssh_line(&data("user-cert.pub")).unwrap().0;
+    let reference = reference.cert().unwrap();
+    assert_eq!(
+        (&cert.key, &cert.key_id, cert.serial, &cert.principals, cert.valid_after, cert.valid_before),
+        (&reference.key, &reference.key_id, reference.serial, &reference.principals, reference.valid_after, reference.valid_before)
+    );
+    assert_eq!(cert.critical_options, reference.critical_options);
+    let names: Vec<&str> = cert.extensions.iter().map(|o| o.name.as_str()).collect();
+    assert_eq!(names, ["login@example.com", "permit-agent-forwarding", "permit-port-forwarding", "permit-pty", "permit-user-rc"]);
+    assert_eq!(cert.extensions[0].value().unwrap(), "alice");
+    assert_eq!(SshAuthOpt::from_cert(cert).unwrap(), SshAuthOpt::from_cert(reference).unwrap());
+    cert.validate("admin", 1_750_000_000, &[PublicKey::from_openssh_line(&data("ed25519.pub")).unwrap().0]).unwrap();
+
+    // 主机证书，RSA CA 使用 -t 指定的签名算法；主机证书忽略 -O
+    run(&["-s", &rsa_ca, "-t", "rsa-sha2-256", "-h", "-I", "web", "-n", "web.example.com", "-O", "no-pty", "-q", &host]);
+    let key = PublicKey::from_openssh_line(&fs::read_to_string(dir.join("rsa1024-cert.pub")).unwrap()).unwrap().0;
+    let cert = key.cert().unwrap();
+    assert_eq!((cert.cert_type, cert.valid_after, cert.valid_before), (CertType::Host, 0, u64::MAX));
+    assert_eq!(cert.signature_type().unwrap(), "rsa-sha2-256");
+    assert!(cert.critical_options.is_empty() && cert.extensions.is_empty());
+
+    // -O clear 去掉所有扩展；+1d 的有效期从一分钟前开始
+    run(&["-s", &ca, "-I", "bare", "-O", "clear", "-O", "verify-required", "-V", "+1d", "-q", &user]);
+    let key = PublicKey::from_openssh_line(&fs::read_to_string(&cert_path).unwrap()).unwrap().0;
+    let cert = key.cert().unwrap();
+    assert_eq!(cert.extensions, []);
+    assert_eq!(cert.critical_options, [CertOption::flag("verify-required")]);
+    assert_eq!(cert.valid_after % 60, 0);
+    assert!((86_400..86_400 + 120).contains(&(cert.valid_before - cert.valid_after)));
+
+    assert!(fail(&["-s", &ca, &user]).contains("Must specify key id (-I)"));
+    assert!(fail(&["-s", &ca, "-I", "x", "-O", "permit-everything", &user]).contains("Unsupported certificate option"));
+    assert!(fail(&["-s", &ca, "-I", "x", "-O", "source-address=example.com", &user]).contains("Invalid source-address list"));
+    assert!(fail(&["-s", &ca, "-I", "x", "-V", "20340101:20240101", &user]).contains("Empty certificate validity"));
+    assert!(fail(&["-s", &ca, "-I", "x", "-V", "soon", &user]).contains("Invalid certificate life specification"));
+    assert!(fail(&["-s", &ca, "-I", "x", "-t", "rsa-sha2-256", &user]).contains("doesn't match"));
+    assert!(fail(&["-s", &ca, "-I", "x", "-O", "critical:a", "-O", "critical:a", &user]).contains("Duplicate"));
+}
+
+#[test]
+fn test_sshsig() {
+    let dir = scratch("sshsig");
+    let key = copy_key(&dir, "ed25519");
+    copy_key(&dir, "ed25519.pub");
+    let message = data("message.txt");
+    let msg_path = dir.join("message.txt");
+    fs::write(&msg_path, &message).unwrap();
+    let msg_path = msg_path.to_str().unwrap();
+    let allowed = "tests/data/sshsig/allowed_signers";
+
+    let out = keygen(&["-Y", "sign", "-f", &key, "-n", "file", msg_path]);
+    assert!(out.status.success());
+    let sig_path = format!("{}.sig", msg_path);
+    assert_eq!(
+        String::from_utf8(out.stderr).unwrap(),
+        format!("Signing file {}\nWrite signature to {}\n", msg_path, sig_path)
+    );
+    // Ed25519 签名是确定性的
+    assert_eq!(fs::read_to_string(&sig_path).unwrap(), data("ed25519.sshsig"));
+
+    // 未指定文件时签名标准输入；-f 也可以是 .pub 文件
+    let pub_path = format!("{}.pub", key);
+    let out = keygen_stdin(&["-Y", "sign", "-f", &pub_path, "-n", "git", "-O", "hashalg=sha256"], message.as_bytes());
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), fs::read_to_string("tests/data/sshsig/ed25519-sha256.sig").unwrap());
+
+    let verify = |principal: &str, sig: &str, extra: &[&str]| {
+        let mut args = vec!["-Y", "verify", "-f", allowed, "-I", principal, "-n", "file", "-s", sig];
+        args.extend(extra);
+        keygen_stdin(&args, message.as_bytes())
+    };
+    let fp = "SHA256:g/2kls0vDzQeYmo2Ie6YSpqpoRxzbQWZ4WkeHLy2zzc";
+    let out = verify("alice@example.com", &sig_path, &[]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        format!("Good \"file\" signature for alice@example.com with ED25519 key {}\n", fp)
+    );
+    let out = verify("bob@example.com", &sig_path, &[]);
+    assert_eq!(out.status.code(), Some(255));
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "Could not verify signature.\n");
+    let out = verify("admin", "tests/data/sshsig/cert.sig", &["-O", "verify-time=20250101"]);
+    assert!(String::from_utf8(out.stdout).unwrap().starts_with("Good \"file\" signature for admin with ECDSA-CERT key"));
+    assert_eq!(verify("admin", "tests/data/sshsig/cert.sig", &["-O", "verify-time=20230101"]).status.code(), Some(255));
+
+    let out = keygen_stdin(
+        &["-Y", "check-novalidate", "-n", "git", "-s", "tests/data/sshsig/ed25519-sha256.sig", "-O", "print-pubkey"],
+        message.as_bytes(),
+    );
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        format!("Good \"git\" signature with ED25519 key {}\n{}\n", fp, PublicKey::from_openssh_line(&data("ed25519.pub")).unwrap().0.to_openssh_line(""))
+    );
+
+    assert_eq!(run(&["-Y", "find-principals", "-f", allowed, "-s", &format!("{}/ecdsa256.sshsig", DATA)]), "bob@example.com\n*@example.org\n");
+    assert_eq!(run(&["-Y", "find-principals", "-f", allowed, "-s", "tests/data/sshsig/cert.sig"]), "admin\n");
+    assert!(fail(&["-Y", "find-principals", "-f", allowed, "-s", &format!("{}/rsa1024.sshsig", DATA)]).contains("No principal matched."));
+    assert_eq!(run(&["-Y", "match-principals", "-f", allowed, "-I", "admin"]), "erin@example.com,admin\n");
+
+    assert_eq!(keygen(&["-Y", "sign", "-f", &key, msg_path]).status.code(), Some(1));
+    assert_eq!(keygen(&["-Y", "verify", "-n", "file", "-s", &sig_path]).status.code(), Some(1));
+    assert!(fail(&["-Y", "sign", "-f", &key, "-n", "file", "-O", "bogus", msg_path]).contains("Invalid option \"bogus\""));
+    assert_eq!(keygen(&["-Y", "frobnicate"]).status.code(), Some(1));
+}
+
+#[test]
+fn test_krl() {
+    let dir = scratch("krl");
+    let krl = dir.join("krl");
+    let krl = krl.to_str().unwrap();
+    let spec = "tests/data/krl/mixed.txt";
+    let ca = format!("{}/ed25519.pub", DATA);
+
+    let out = run(&["-k", "-f", krl, "-s", &ca, "-z", "3", spec]);
+    assert_eq!(out, format!("Revoking from {}\n", spec));
+    // 除生成时间外与 ssh-keygen 的输出逐字节一致
+    let (ours, theirs) = (fs::read(krl).unwrap(), fs::read("tests/data/krl/mixed.krl").unwrap());
+    assert_eq!((&ours[..20], &ours[28..]), (&theirs[..20], &theirs[28..]));
+
+    let pub_path = |name: &str| format!("{}/{}.pub", DATA, name);
+    let out = keygen(&["-Q", "-f", krl, &pub_path("ecdsa521"), &pub_path("rsa2048")]);
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        format!("{} (ecdsa 521): ok\n{} (rsa test key): REVOKED\n", pub_path("ecdsa521"), pub_path("rsa2048"))
+    );
+    let out = run(&["-Q", "-l", "-f", krl, &pub_path("ecdsa521")]);
+    assert!(out.starts_with("# KRL version 3\n# Generated at "));
+    assert!(out.ends_with("serial: 5-9\nserial: 32\nid: alice@example.com\ntests/data/sshkey/ecdsa521.pub (ecdsa 521): ok\n"));
+
+    // -u 在原有 KRL 上追加，-s none 为通配 CA；"-" 为标准输入
+    let out = keygen_stdin(&["-k", "-u", "-f", krl, "-s", "none", "-"], b"serial: 0x10-021 # octal\nid: bob\n");
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "Revoking from (standard input)\n");
+    let out = run(&["-Q", "-l", "-f", krl]);
+    assert!(out.contains("# Wildcard CA\nserial: 16-17\nid: bob\n"));
+    assert!(out.contains("hash: SHA256:EH77CwKLSk6zFfCkN2wy/5xHVpl7/IC+ADsr6Y5c4bs # ssh-rsa\n"));
+
+    let bad = dir.join("bad");
+    for (line, err) in [
+        ("serial: 5", "requires specification of a CA key"),
+        ("serial: 9-8", "invalid serial range 9:8"),
+        ("serial: five", "invalid serial \"five\""),
+        ("hash: MD5:aa", "unsupported hash algorithm"),
+        ("ssh-ed25519 garbage", "invalid key"),
+    ] {
+        fs::write(&bad, line).unwrap();
+        let ca_args: &[&str] = if line == "serial: 5" { &[] } else { &["-s", "none"] };
+        let mut args = vec!["-k", "-f", krl];
+        args.extend(ca_args);
+        args.push(bad.to_str().unwrap());
+        assert!(fail(&args).contains(err), "{}", line);
+    }
+    assert!(fail(&["-k", "-u", "-f", &format!("{}.missing", krl), spec]).contains("does not exist"));
+    assert!(fail(&["-Q", "-f", spec]).contains("Invalid KRL file"));
+
+    // -Y verify -r：RevokedKeys 可以是 KRL 或公钥列表
+    let verify = |revoked: &str| {
+        let args = ["-Y", "verify", "-f", "tests/data/sshsig/allowed_signers", "-I", "admin", "-n", "file"];
+        let mut args = args.to_vec();
+        args.extend(["-s", "tests/data/sshsig/cert.sig", "-r", revoked]);
+        keygen_stdin(&args, data("message.txt").as_bytes())
+    };
+    // 序列号 42 在通配 CA 的 10-1000 之内
+    assert_eq!(verify("tests/data/krl/serials.krl").status.code(), Some(255));
+    assert_eq!(verify(&pub_path("ecdsa256")).status.code(), Some(255));
+    let out = verify(&pub_path("ecdsa521"));
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let out = verify(krl);
+    assert_eq!(out.status.code(), Some(255));
+    assert!(String::from_utf8(out.stderr).unwrap().contains("Key is revoked"));
+}
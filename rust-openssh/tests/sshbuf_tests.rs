@@ -0,0 +1,46 @@
+use rust_openssh::sshbuf::*;
+
+#[test]
+fn test_sshbuf_roundtrip() {
+    let mut b = SshBuf::new();
+    b.put_u8(20);
+    b.put_bool(true);
+    b.put_u32(0xdeadbeef);
+    b.put_u64(1 << 40);
+    b.put_cstring("ssh-ed25519");
+    b.put_string(b"");
+    assert_eq!(&b.as_slice()[..6], &[20, 1, 0xde, 0xad, 0xbe, 0xef]);
+
+    assert_eq!(b.get_u8().unwrap(), 20);
+    assert!(b.get_bool().unwrap());
+    assert_eq!(b.get_u32().unwrap(), 0xdeadbeef);
+    assert_eq!(b.get_u64().unwrap(), 1 << 40);
+    assert_eq!(b.get_cstring().unwrap(), "ssh-ed25519");
+    assert_eq!(b.get_string().unwrap(), b"");
+    assert!(b.is_empty());
+    assert_eq!(b.get_u8(), Err(SshBufError::MessageIncomplete));
+}
+
+#[test]
+fn test_sshbuf_bignum_and_errors() {
+    // RFC 4251 §5 中的 mpint 示例
+    let mut b = SshBuf::new();
+    b.put_bignum2_bytes(&[0x00, 0x00, 0x80]);
+    b.put_bignum2_bytes(&[0x09, 0xa3, 0x78, 0xf9, 0xb2, 0xe3, 0x32, 0xa7]);
+    b.put_bignum2_bytes(&[0, 0]);
+    assert_eq!(&b.as_slice()[..7], &[0, 0, 0, 2, 0x00, 0x80, 0]);
+    assert_eq!(b.get_bignum2_bytes().unwrap(), &[0x80]);
+    assert_eq!(b.get_bignum2_bytes().unwrap(), &[0x09, 0xa3, 0x78, 0xf9, 0xb2, 0xe3, 0x32, 0xa7]);
+    assert_eq!(b.get_bignum2_bytes().unwrap(), b"");
+
+    let mut neg = SshBuf::from_vec(vec![0, 0, 0, 1, 0xff]);
+    assert_eq!(neg.get_bignum2_bytes(), Err(SshBufError::BignumIsNegative));
+
+    let mut short = SshBuf::from_vec(vec![0, 0, 0, 5, b'a']);
+    assert_eq!(short.get_string(), Err(SshBufError::MessageIncomplete));
+    // 失败的读取不消费数据
+    assert_eq!(short.len(), 5);
+
+    let mut nul = SshBuf::from_vec(vec![0, 0, 0, 2, b'a', 0]);
+    assert_eq!(nul.get_cstring(), Err(SshBufError::InvalidFormat));
+}
@@ -0,0 +1,250 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use rust_openssh::misc::parse_absolute_time;
+use rust_openssh::sshbuf::SshBuf;
+use rust_openssh::sshkey::*;
+use rust_openssh::sshsig::*;
+
+mod common;
+use common::scratch;
+
+const KEYS: &[&str] = &["ed25519", "ecdsa256", "ecdsa384", "ecdsa521", "rsa2048", "rsa1024"];
+const ALLOWED: &str = "tests/data/sshsig/allowed_signers";
+
+fn data(name: &str) -> String {
+    fs::read_to_string(format!("tests/data/{}", name)).unwrap()
+}
+
+fn message() -> String {
+    data("sshkey/message.txt")
+}
+
+fn public(name: &str) -> PublicKey {
+    PublicKey::from_openssh_line(&data(&format!("sshkey/{}.pub", name))).unwrap().0
+}
+
+fn private(name: &str) -> PrivateKey {
+    PrivateKey::from_private_text(&data(&format!("sshkey/{}", name)), "").unwrap().0
+}
+
+fn time(s: &str) -> u64 {
+    parse_absolute_time(s).unwrap()
+}
+
+// 收集日志，用于检查诊断信息
+static LOGGED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct Capture;
+
+impl log::Log for Capture {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        LOGGED.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+#[test]
+fn test_verify_openssh_signatures() {
+    // ssh-keygen -Y sign -n file message.txt
+    for name in KEYS {
+        let blob = dearmor(&data(&format!("sshkey/{}.sshsig", name))).unwrap();
+        let (key, details) = verify(&blob, message().as_bytes(), "file").unwrap();
+        assert_eq!(key, public(name), "{}", name);
+        assert_eq!(details, None);
+        assert_eq!(signature_key(&blob).unwrap(), key);
+
+        assert!(matches!(
+            verify(&blob, message().as_bytes(), "git"),
+            Err(SigError::NamespaceMismatch { ref expected, ref found }) if expected == "git" && found == "file"
+        ));
+        assert!(matches!(
+            verify(&blob, &b"something else"[..], "file"),
+            Err(SigError::Key(KeyError::SignatureInvalid))
+        ));
+    }
+
+    // ssh-keygen -Y sign -n git -O hashalg=sha256
+    let blob = dearmor(&data("sshsig/ed25519-sha256.sig")).unwrap();
+    assert_eq!(verify(&blob, message().as_bytes(), "git").unwrap().0, public("ed25519"));
+}
+
+#[test]
+fn test_sign() {
+    for name in KEYS {
+        let key = private(name);
+        for hashalg in [None, Some("sha256"), Some("sha512")] {
+            let blob = sign(&key, None, hashalg, "file", message().as_bytes()).unwrap();
+            let armored = armor(&blob);
+            assert!(armored.lines().all(|l| l.len() <= 70));
+            assert_eq!(dearmor(&armored).unwrap(), blob);
+            assert_eq!(verify(&blob, message().as_bytes(), "file").unwrap().0, key.public());
+        }
+    }
+
+    // Ed25519 签名是确定性的，应与 ssh-keygen 的输出逐字节一致
+    let ours = sign(&private("ed25519"), None, Some("sha256"), "git", message().as_bytes()).unwrap();
+    assert_eq!(armor(&ours), data("sshsig/ed25519-sha256.sig"));
+    // RSA 总是使用 rsa-sha2-512
+    let blob = sign(&private("rsa2048"), None, None, "file", message().as_bytes()).unwrap();
+    assert_eq!(armor(&blob), data("sshkey/rsa2048.sshsig"));
+
+    let key = private("ed25519");
+    assert!(matches!(sign(&key, None, Some("md5"), "file", &b""[..]), Err(SigError::UnsupportedHash(_))));
+    assert!(sign(&key, None, None, "", &b""[..]).is_err());
+    assert!(matches!(
+        sign(&key, Some(&public("ecdsa256")), None, "file", &b""[..]),
+        Err(SigError::Key(KeyError::TypeMismatch))
+    ));
+}
+
+#[test]
+fn test_malformed_signatures() {
+    assert!(dearmor("-----BEGIN SSH SIGNATURE-----\nU1NIU0lH\n").is_err());
+    assert!(dearmor("U1NIU0lH\n-----END SSH SIGNATURE-----\n").is_err());
+
+    let blob = dearmor(&data("sshkey/ed25519.sshsig")).unwrap();
+    let mut trailing = blob.clone();
+    trailing.push(0);
+    assert!(matches!(verify(&trailing, message().as_bytes(), "file"), Err(SigError::InvalidFormat(_))));
+    let mut version = blob.clone();
+    version[9] = 2;
+    assert!(matches!(verify(&version, message().as_bytes(), "file"), Err(SigError::UnsupportedVersion(2))));
+    assert!(matches!(verify(&blob[1..], message().as_bytes(), "file"), Err(SigError::InvalidFormat(_))));
+
+    // ssh-rsa（SHA-1）签名一律拒绝，不管签名本身是否正确
+    let mut sig = SshBuf::new();
+    sig.put_cstring("ssh-rsa");
+    sig.put_string(&[0u8; 256]);
+    let mut b = SshBuf::new();
+    b.put(b"SSHSIG");
+    b.put_u32(SIG_VERSION);
+    b.put_string(&public("rsa2048").to_blob());
+    b.put_cstring("file");
+    b.put_string(b"");
+    b.put_cstring("sha512");
+    b.put_string(&sig.into_vec());
+    assert!(matches!(
+        verify(&b.into_vec(), message().as_bytes(), "file"),
+        Err(SigError::Key(KeyError::Unsupported(_)))
+    ));
+}
+
+#[test]
+fn test_certificate_signatures() {
+    // ssh-keygen -Y sign -f user-cert.pub：签名中带的是证书
+    let blob = dearmor(&data("sshsig/cert.sig")).unwrap();
+    let (key, _) = verify(&blob, message().as_bytes(), "file").unwrap();
+    assert_eq!(key, public("user-cert"));
+
+    let now = time("20250601");
+    let allowed = Path::new(ALLOWED);
+    check_allowed_keys(allowed, &key, "admin", "file", now).unwrap();
+    // 该行允许 erin@example.com，但证书中没有这个 principal
+    assert!(matches!(check_allowed_keys(allowed, &key, "erin@example.com", "file", now), Err(SigError::KeyNotFound)));
+    // cert-authority 行只允许 file 命名空间
+    assert!(check_allowed_keys(allowed, &key, "admin", "git", now).is_err());
+    // 证书有效期为 2024-2034
+    assert!(check_allowed_keys(allowed, &key, "admin", "file", time("20230101")).is_err());
+    // 证书本身不能当作普通密钥匹配 bob 的行
+    assert!(check_allowed_keys(allowed, &key, "bob@example.com", "file", now).is_err());
+
+    assert_eq!(find_principals(allowed, &key, now).unwrap(), "admin");
+    sign(&private("ecdsa256"), Some(&key), None, "file", &b""[..]).unwrap();
+}
+
+#[test]
+fn test_sig_opts() {
+    assert_eq!(SigOpts::parse("").unwrap(), SigOpts::default());
+    let opts = SigOpts::parse("cert-authority,namespaces=\"git,file\" ssh-ed25519 AAAA").unwrap();
+    assert!(opts.ca);
+    assert_eq!(opts.namespaces.as_deref(), Some("git,file"));
+    let opts = SigOpts::parse("Valid-After=\"20240101\",valid-before=\"20250101Z\"").unwrap();
+    assert_eq!(opts.valid_after, time("20240101"));
+    assert_eq!(opts.valid_before, time("20250101Z"));
+
+    for (bad, reason) in [
+        ("namespaces=\"a\",namespaces=\"b\"", "multiple \"namespaces\" clauses"),
+        ("bogus", "unknown key option"),
+        ("cert-authority,", "unexpected end-of-options"),
+        ("namespaces=git", "missing start quote"),
+        ("namespaces=\"git", "missing end quote"),
+        ("valid-after=\"yesterday\"", "invalid \"valid-after\" time"),
+        ("valid-after=\"20250101\",valid-before=\"20240101\"", "\"valid-before\" time is before \"valid-after\""),
+    ] {
+        assert_eq!(SigOpts::parse(bad).unwrap_err(), reason, "{}", bad);
+    }
+}
+
+#[test]
+fn test_parse_allowed_signer() {
+    let key = data("sshkey/ed25519.pub");
+    let line = format!("\"alice@example.com,bob two\" namespaces=\"git\" {}", key);
+    let signer = parse_allowed_signer("f", 1, &line, None).unwrap().unwrap();
+    assert_eq!(signer.principals, "alice@example.com,bob two");
+    assert_eq!(signer.opts.namespaces.as_deref(), Some("git"));
+    assert_eq!(signer.key, public("ed25519"));
+
+    assert_eq!(parse_allowed_signer("f", 1, "  # comment", None).unwrap(), None);
+    assert_eq!(parse_allowed_signer("f", 1, "", None).unwrap(), None);
+    // 不匹配所需 principal 的行直接跳过，不再解析
+    assert_eq!(parse_allowed_signer("f", 1, "carol garbage", Some("alice")).unwrap(), None);
+    for bad in ["alice", "alice garbage", "alice namespaces=\"git ssh-ed25519", "\"alice ssh-ed25519 AAAA"] {
+        assert!(parse_allowed_signer("f", 1, bad, None).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn test_allowed_signers() {
+    let allowed = Path::new(ALLOWED);
+    let now = time("20250601");
+    check_allowed_keys(allowed, &public("ed25519"), "alice@example.com", "git", now).unwrap();
+    assert!(check_allowed_keys(allowed, &public("ed25519"), "alice@example.com", "ssh", now).is_err());
+    assert!(check_allowed_keys(allowed, &public("ed25519"), "bob@example.com", "file", now).is_err());
+    check_allowed_keys(allowed, &public("ecdsa256"), "anyone@example.org", "anything", now).unwrap();
+
+    // valid-before="20200101"
+    let rsa = public("rsa2048");
+    check_allowed_keys(allowed, &rsa, "carol@example.com", "file", time("20191231")).unwrap();
+    assert!(check_allowed_keys(allowed, &rsa, "carol@example.com", "file", now).is_err());
+    // valid-after="20990101Z"
+    assert!(check_allowed_keys(allowed, &public("ecdsa384"), "dave@example.com", "file", now).is_err());
+    check_allowed_keys(allowed, &public("ecdsa384"), "dave@example.com", "file", time("20990102Z")).unwrap();
+    // 选项无效的行被忽略
+    assert!(check_allowed_keys(allowed, &public("ecdsa521"), "frank@example.com", "file", now).is_err());
+
+    assert_eq!(find_principals(allowed, &public("ed25519"), now).unwrap(), "alice@example.com");
+    assert_eq!(find_principals(allowed, &public("ecdsa256"), now).unwrap(), "bob@example.com,*@example.org");
+    assert!(matches!(find_principals(allowed, &rsa, now), Err(SigError::KeyNotFound)));
+    assert!(matches!(find_principals(Path::new("/nonexistent"), &rsa, now), Err(SigError::Io(_))));
+
+    assert_eq!(match_principals(allowed, "admin").unwrap(), ["erin@example.com,admin"]);
+    assert_eq!(match_principals(allowed, "x@example.org").unwrap(), ["bob@example.com,*@example.org"]);
+    assert!(matches!(match_principals(allowed, "nobody"), Err(SigError::KeyNotFound)));
+}
+
+#[test]
+fn test_namespace_checked_after_key_match() {
+    let _ = log::set_logger(&Capture);
+    log::set_max_level(log::LevelFilter::Trace);
+    let dir = scratch("namespaces");
+    let path = dir.join("allowed_signers");
+    let line = |ns: &str, key: &str| format!("alice@example.com namespaces=\"{}\" {}", ns, data(&format!("sshkey/{}.pub", key)).trim());
+    fs::write(&path, format!("{}\n{}\n", line("ssh", "ecdsa256"), line("git", "ed25519"))).unwrap();
+
+    // 第一行的密钥不同，不应报告 namespace 不允许
+    check_allowed_keys(&path, &public("ed25519"), "alice@example.com", "git", time("20250601")).unwrap();
+    let prefix = format!("{}:1:", path.display());
+    assert!(!LOGGED.lock().unwrap().iter().any(|m| m.starts_with(&prefix) && m.contains("not permitted")));
+
+    // 密钥匹配但 namespace 不允许
+    assert!(check_allowed_keys(&path, &public("ecdsa256"), "alice@example.com", "git", time("20250601")).is_err());
+    let expected = format!("{} key is not permitted for use in signature namespace \"git\"", prefix);
+    assert!(LOGGED.lock().unwrap().contains(&expected));
+}